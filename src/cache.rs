@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use crate::{config::ResultCacheConfig, intent::Intent, util::TtlLruMap};
+
+/// 已落盘的生成结果，命中时直接复用文件而不再调用提供方
+#[derive(Clone)]
+pub struct CachedArtifact {
+    pub path: PathBuf,
+    pub description: String,
+    pub media_type: String,
+    pub file_extension: String,
+    pub details: Map<String, Value>,
+}
+
+/// 按（意图、规范化提示词、模型）缓存生成结果的 LRU，条目只保存在内存中
+pub struct ResultCache {
+    entries: TtlLruMap<String, CachedArtifact>,
+}
+
+impl ResultCache {
+    pub fn new(config: &ResultCacheConfig) -> Self {
+        Self {
+            entries: TtlLruMap::new(config.max_entries, config.ttl),
+        }
+    }
+
+    /// 命中且文件仍存在时返回缓存结果；文件已被清理的条目会被移除
+    pub async fn get(
+        &mut self,
+        intent: Intent,
+        prompt: &str,
+        model: Option<&str>,
+    ) -> Option<CachedArtifact> {
+        let key = Self::key(intent, prompt, model);
+        let artifact = self.entries.get(&key)?.clone();
+
+        if !tokio::fs::try_exists(&artifact.path).await.unwrap_or(false) {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        Some(artifact)
+    }
+
+    pub fn insert(
+        &mut self,
+        intent: Intent,
+        prompt: &str,
+        model: Option<&str>,
+        artifact: CachedArtifact,
+    ) {
+        self.entries
+            .insert(Self::key(intent, prompt, model), artifact);
+    }
+
+    /// 忽略大小写与多余空白，避免仅格式不同的提示词重复生成
+    fn key(intent: Intent, prompt: &str, model: Option<&str>) -> String {
+        let normalized = prompt
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        format!(
+            "{intent}\u{1f}{}\u{1f}{normalized}",
+            model.unwrap_or_default()
+        )
+    }
+}
@@ -1,37 +1,78 @@
 use crate::{
-    config::ZhipuConfig,
-    errors::Result,
-    providers::zhipu::ZhipuCompletionModel,
+    config::{DEFAULT_HISTORY_TOKEN_BUDGET, DeepSeekConfig, SamplingParams, ZhipuConfig},
+    errors::{AgentError, Result},
+    providers::{
+        apply_sampling, deepseek::DeepSeekCompletionModel, mock::MockCompletionModel,
+        zhipu::ZhipuCompletionModel,
+    },
+    usage::{PromptCacheUsage, TokenUsage},
+    util::estimate_tokens,
 };
 use rig::{
-    agent::Agent,
+    agent::{Agent, AgentBuilder},
     client::CompletionClient,
-    completion::{Chat, Message, request::CompletionModel},
+    completion::{
+        Completion, Message, ToolDefinition,
+        message::{AssistantContent, ToolCall, ToolResult, ToolResultContent, UserContent},
+        request::CompletionModel,
+    },
     one_or_many::OneOrMany,
     providers::openai,
 };
+use serde_json::{Map, Value, json};
 
 type OpenAiCompletionModel = <openai::Client as CompletionClient>::CompletionModel;
 
-const MAX_HISTORY_MESSAGES: usize = 24;
-
 pub struct ConversationAgent {
     backend: ConversationBackend,
     history: Vec<ConversationMessage>,
+    /// 历史记录的估算 token 上限，见 `trim_history`
+    history_budget: usize,
 }
 
 enum ConversationBackend {
     OpenAi(OpenAiConversation),
     Zhipu(ZhipuConversation),
+    DeepSeek(Agent<DeepSeekCompletionModel>),
+    Mock(Agent<MockCompletionModel>),
 }
 
 struct OpenAiConversation {
     agent: Agent<OpenAiCompletionModel>,
+    /// 开启工具调用后随每次请求发送的生成工具定义
+    tools: Vec<ToolDefinition>,
+    /// 信息查询工具定义：模型调用后回填查询结果，再请求一次得到最终回复
+    lookup_tools: Vec<ToolDefinition>,
+}
+
+/// 一次对话请求的结果
+pub struct ChatReply {
+    pub text: String,
+    pub usage: TokenUsage,
+    /// 模型在本轮请求调用的生成工具，按返回顺序排列；未开启工具调用时为空
+    pub tool_calls: Vec<ToolRequest>,
+    /// 模型在本轮请求查询的信息及回填的结果
+    pub lookups: Vec<ToolLookup>,
+}
+
+/// 模型查询的信息工具及回填给模型的结果
+#[derive(Debug, Clone)]
+pub struct ToolLookup {
+    pub name: String,
+    pub result: Value,
+}
+
+/// 模型请求调用的生成工具及其提示词
+#[derive(Debug, Clone)]
+pub struct ToolRequest {
+    pub name: String,
+    pub prompt: String,
 }
 
 struct ZhipuConversation {
     model: ZhipuCompletionModel,
     preamble: String,
+    sampling: SamplingParams,
 }
 
 #[derive(Clone)]
@@ -72,87 +113,533 @@ impl ConversationMessage {
 impl ConversationAgent {
     pub fn with_openai(agent: Agent<OpenAiCompletionModel>) -> Self {
         Self {
-            backend: ConversationBackend::OpenAi(OpenAiConversation { agent }),
+            backend: ConversationBackend::OpenAi(OpenAiConversation {
+                agent,
+                tools: Vec::new(),
+                lookup_tools: Vec::new(),
+            }),
             history: Vec::new(),
+            history_budget: DEFAULT_HISTORY_TOKEN_BUDGET,
         }
     }
 
     // 使用 rig CompletionModel API
-    pub fn with_zhipu(config: &ZhipuConfig, model_override: Option<&str>) -> Result<Self> {
+    pub fn with_zhipu(
+        config: &ZhipuConfig,
+        model_override: Option<&str>,
+        preamble: &str,
+        sampling: &SamplingParams,
+    ) -> Result<Self> {
         let model = ZhipuCompletionModel::from_config(config, model_override)?;
         Ok(Self {
             backend: ConversationBackend::Zhipu(ZhipuConversation {
                 model,
-                preamble: config.agent_preamble.clone(),
+                preamble: preamble.to_string(),
+                sampling: *sampling,
             }),
             history: Vec::new(),
+            history_budget: DEFAULT_HISTORY_TOKEN_BUDGET,
+        })
+    }
+
+    /// 用于用量统计的提供方名称
+    pub fn provider(&self) -> &'static str {
+        match &self.backend {
+            ConversationBackend::OpenAi(_) => "openai",
+            ConversationBackend::Zhipu(_) => "zhipu",
+            ConversationBackend::DeepSeek(_) => "deepseek",
+            ConversationBackend::Mock(_) => "mock",
+        }
+    }
+
+    /// 开启工具调用，`tools` 为 `(名称, 描述)`，每个工具只接受一个 `prompt` 参数；
+    /// 目前只有 OpenAI 后端支持，其它后端返回 `false` 并保持纯文本对话
+    pub fn enable_tools(&mut self, tools: &[(&str, &str)]) -> bool {
+        let ConversationBackend::OpenAi(openai) = &mut self.backend else {
+            return false;
+        };
+        openai.tools = tools
+            .iter()
+            .map(|(name, description)| ToolDefinition {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "prompt": {
+                            "type": "string",
+                            "description": "交给生成服务的完整描述",
+                        },
+                    },
+                    "required": ["prompt"],
+                }),
+            })
+            .collect();
+        true
+    }
+
+    /// 设置历史记录的估算 token 上限（`providers.conversation.history_token_budget`）
+    pub fn set_history_budget(&mut self, tokens: usize) {
+        self.history_budget = tokens;
+        self.trim_history();
+    }
+
+    /// 替换人设提示词，对话历史保留；rig 的 `Agent` 每次请求都会读取 `preamble`，直接替换即可生效
+    pub fn set_preamble(&mut self, preamble: &str) {
+        match &mut self.backend {
+            ConversationBackend::OpenAi(openai) => {
+                openai.agent.preamble = Some(preamble.to_string())
+            }
+            ConversationBackend::Zhipu(zhipu) => zhipu.preamble = preamble.to_string(),
+            ConversationBackend::DeepSeek(agent) => agent.preamble = Some(preamble.to_string()),
+            ConversationBackend::Mock(agent) => agent.preamble = Some(preamble.to_string()),
+        }
+    }
+
+    /// 开启信息查询工具，`tools` 为 `(名称, 描述)`，不带参数；查询结果在每次 `chat` 时由调用方提供。
+    /// 目前只有 OpenAI 后端支持，其它后端返回 `false`
+    pub fn enable_lookup_tools(&mut self, tools: &[(&str, &str)]) -> bool {
+        let ConversationBackend::OpenAi(openai) = &mut self.backend else {
+            return false;
+        };
+        openai.lookup_tools = tools
+            .iter()
+            .map(|(name, description)| ToolDefinition {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters: json!({ "type": "object", "properties": {} }),
+            })
+            .collect();
+        true
+    }
+
+    /// 当前生效的人设提示词
+    pub fn preamble(&self) -> Option<&str> {
+        match &self.backend {
+            ConversationBackend::OpenAi(openai) => openai.agent.preamble.as_deref(),
+            ConversationBackend::Zhipu(zhipu) => Some(zhipu.preamble.as_str()),
+            ConversationBackend::DeepSeek(agent) => agent.preamble.as_deref(),
+            ConversationBackend::Mock(agent) => agent.preamble.as_deref(),
+        }
+        .filter(|preamble| !preamble.is_empty())
+    }
+
+    /// 与 `other` 交换模型后端，历史记录留在各自代理中；用于单次请求临时换用其它模型，再交换一次即可恢复。
+    /// 双方都是 OpenAI 时工具定义跟随代理而不是后端，换上的模型同样可以调用工具
+    pub fn swap_backend(&mut self, other: &mut ConversationAgent) {
+        std::mem::swap(&mut self.backend, &mut other.backend);
+        if let (ConversationBackend::OpenAi(ours), ConversationBackend::OpenAi(theirs)) =
+            (&mut self.backend, &mut other.backend)
+        {
+            std::mem::swap(&mut ours.tools, &mut theirs.tools);
+            std::mem::swap(&mut ours.lookup_tools, &mut theirs.lookup_tools);
+        }
+    }
+
+    pub fn with_deepseek(
+        config: &DeepSeekConfig,
+        model_override: Option<&str>,
+        preamble: &str,
+        sampling: &SamplingParams,
+    ) -> Result<Self> {
+        let model = DeepSeekCompletionModel::from_config(config, model_override)?;
+        let builder = AgentBuilder::new(model)
+            .name("vutber-conversation")
+            .preamble(preamble);
+        let agent = apply_sampling(builder, sampling, None).build();
+        Ok(Self {
+            backend: ConversationBackend::DeepSeek(agent),
+            history: Vec::new(),
+            history_budget: DEFAULT_HISTORY_TOKEN_BUDGET,
+        })
+    }
+
+    /// 离线调试用，不请求任何模型，回复为输入的回显
+    pub fn with_mock(preamble: &str) -> Self {
+        let agent = AgentBuilder::new(MockCompletionModel::echo())
+            .name("vutber-conversation")
+            .preamble(preamble)
+            .build();
+        Self {
+            backend: ConversationBackend::Mock(agent),
+            history: Vec::new(),
+            history_budget: DEFAULT_HISTORY_TOKEN_BUDGET,
+        }
+    }
+
+    /// 直接走 Completion 接口而不是 `Chat`，以便拿到本次请求的 token 用量；
+    /// `sampling` 中配置了的项覆盖构建 Agent 时的采样参数
+    async fn complete<M>(
+        agent: &Agent<M>,
+        prompt: impl Into<Message> + Send,
+        history: Vec<Message>,
+        preamble: Option<&str>,
+        sampling: Option<&SamplingParams>,
+        tools: &[ToolDefinition],
+    ) -> Result<(ChatReply, Vec<ToolCall>)>
+    where
+        M: PromptCacheUsage + 'static,
+    {
+        let mut builder = agent.completion(prompt, history).await?;
+        if let Some(preamble) = preamble {
+            builder = builder.preamble(preamble.to_string());
+        }
+        if let Some(sampling) = sampling {
+            if let Some(temperature) = sampling.temperature {
+                builder = builder.temperature(temperature);
+            }
+            if let Some(max_tokens) = sampling.max_tokens {
+                builder = builder.max_tokens(max_tokens);
+            }
+            if let Some(top_p) = sampling.top_p {
+                builder = builder.additional_params(json!({ "top_p": top_p }));
+            }
+        }
+        if !tools.is_empty() {
+            builder = builder.tools(tools.to_vec());
+        }
+        let response = builder.send().await?;
+
+        let mut text = Vec::new();
+        let mut tool_calls = Vec::new();
+        let mut raw_calls = Vec::new();
+        for content in response.choice.iter() {
+            match content {
+                AssistantContent::Text(t) => text.push(t.text.as_str()),
+                AssistantContent::ToolCall(call) => {
+                    tool_calls.push(ToolRequest {
+                        name: call.function.name.clone(),
+                        prompt: call.function.arguments["prompt"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    });
+                    raw_calls.push(call.clone());
+                }
+                _ => {}
+            }
+        }
+        let reply = ChatReply {
+            text: text.join("\n"),
+            usage: TokenUsage::of::<M>(&response),
+            tool_calls,
+            lookups: Vec::new(),
+        };
+        Ok((reply, raw_calls))
+    }
+
+    /// 模型调用了信息查询工具时，把调用与查询结果追加到对话中再请求一次，得到最终回复；
+    /// 第二轮只带生成工具，不再查询。生成工具的调用与两轮的 token 用量合并返回
+    async fn complete_with_lookups(
+        openai: &OpenAiConversation,
+        user_input: &str,
+        mut history: Vec<Message>,
+        preamble: Option<&str>,
+        sampling: Option<&SamplingParams>,
+        lookups: &Map<String, Value>,
+    ) -> Result<ChatReply> {
+        let tools = [openai.tools.as_slice(), openai.lookup_tools.as_slice()].concat();
+        let (mut reply, calls) = Self::complete(
+            &openai.agent,
+            user_input,
+            history.clone(),
+            preamble,
+            sampling,
+            &tools,
+        )
+        .await?;
+        let is_lookup = |name: &str| openai.lookup_tools.iter().any(|tool| tool.name == name);
+        let lookup_calls: Vec<ToolCall> = calls
+            .into_iter()
+            .filter(|call| is_lookup(&call.function.name))
+            .collect();
+        if lookup_calls.is_empty() {
+            return Ok(reply);
+        }
+        reply.tool_calls.retain(|call| !is_lookup(&call.name));
+
+        let mut results = Vec::with_capacity(lookup_calls.len());
+        for call in &lookup_calls {
+            let result = lookups
+                .get(&call.function.name)
+                .cloned()
+                .unwrap_or_else(|| json!({ "error": "暂无数据" }));
+            results.push(UserContent::ToolResult(ToolResult {
+                id: call.id.clone(),
+                call_id: call.call_id.clone(),
+                content: OneOrMany::one(ToolResultContent::text(result.to_string())),
+            }));
+            reply.lookups.push(ToolLookup {
+                name: call.function.name.clone(),
+                result,
+            });
+        }
+        let to_many = |error| AgentError::other(format!("构造工具调用消息失败: {error}"));
+        history.push(Message::user(user_input));
+        history.push(Message::Assistant {
+            id: None,
+            content: OneOrMany::many(lookup_calls.into_iter().map(AssistantContent::ToolCall))
+                .map_err(to_many)?,
+        });
+        let results = Message::User {
+            content: OneOrMany::many(results).map_err(to_many)?,
+        };
+        let (answer, _) = Self::complete(
+            &openai.agent,
+            results,
+            history,
+            preamble,
+            sampling,
+            &openai.tools,
+        )
+        .await?;
+
+        Ok(ChatReply {
+            text: answer.text,
+            usage: reply.usage + answer.usage,
+            tool_calls: [reply.tool_calls, answer.tool_calls].concat(),
+            lookups: reply.lookups,
         })
     }
 
+    /// 从最近一轮往前保留历史，估算的 token 总数不超过 `history_budget`，最近一轮问答总是保留。
+    /// 历史按“用户 + 回复”成对写入，整轮丢弃，保留下来的历史总是从用户消息开始
     fn trim_history(&mut self) {
-        if self.history.len() > MAX_HISTORY_MESSAGES {
-            let overflow = self.history.len() - MAX_HISTORY_MESSAGES;
-            self.history.drain(0..overflow);
+        let mut used = 0;
+        let mut kept = 0;
+        for turn in self.history.rchunks(2) {
+            let tokens: usize = turn
+                .iter()
+                .map(|message| estimate_tokens(&message.content))
+                .sum();
+            if kept > 0 && used + tokens > self.history_budget {
+                break;
+            }
+            used += tokens;
+            kept += turn.len();
         }
+        let overflow = self.history.len() - kept;
+        self.history.drain(..overflow);
+    }
+
+    /// `preamble` 可临时替换人设提示词（例如按直播间切换人设），历史记录仍然共享；
+    /// `sampling` 可临时覆盖采样参数（例如弹幕回复使用更小的 `max_tokens`）；
+    /// `lookups` 为信息查询工具（按工具名）的结果，模型调用时回填。
+    /// 返回回复文本、本次请求的 token 用量、模型请求调用的生成工具与查询过的信息
+    pub async fn chat(
+        &mut self,
+        user_input: &str,
+        preamble: Option<&str>,
+        sampling: Option<&SamplingParams>,
+        lookups: &Map<String, Value>,
+    ) -> Result<ChatReply> {
+        let reply = self
+            .respond(user_input, &self.history, preamble, sampling, Some(lookups))
+            .await?;
+
+        // 历史中只保存文本，工具调用以简短说明代替，后续轮次仍能知道已经生成过什么
+        let mut remembered = reply.text.clone();
+        for call in &reply.tool_calls {
+            if !remembered.is_empty() {
+                remembered.push('\n');
+            }
+            remembered.push_str(&format!("（已调用 {}：{}）", call.name, call.prompt));
+        }
+        self.history.push(ConversationMessage::user(user_input));
+        self.history
+            .push(ConversationMessage::assistant(&remembered));
+        self.trim_history();
+
+        Ok(reply)
     }
 
-    pub async fn chat(&mut self, user_input: &str) -> Result<String> {
-        let history_snapshot = self.history.clone();
+    /// 撤回最近一轮的回复并以 `preamble` 重新生成（不带生成工具），用于回复被屏蔽词过滤拦下后换一种说法
+    pub async fn regenerate(
+        &mut self,
+        user_input: &str,
+        preamble: Option<&str>,
+        sampling: Option<&SamplingParams>,
+    ) -> Result<ChatReply> {
+        let kept = self.history.len().saturating_sub(2);
+        let reply = self
+            .respond(user_input, &self.history[..kept], preamble, sampling, None)
+            .await?;
 
-        let response = match &mut self.backend {
+        self.history.truncate(kept);
+        self.history.push(ConversationMessage::user(user_input));
+        self.history
+            .push(ConversationMessage::assistant(&reply.text));
+        self.trim_history();
+        Ok(reply)
+    }
+
+    /// 单次请求：不读写对话历史、不带生成工具，用于改写绘画提示词等辅助任务
+    pub async fn complete_once(&self, user_input: &str, preamble: &str) -> Result<ChatReply> {
+        self.respond(user_input, &[], Some(preamble), None, None)
+            .await
+    }
+
+    async fn respond(
+        &self,
+        user_input: &str,
+        history_snapshot: &[ConversationMessage],
+        preamble: Option<&str>,
+        sampling: Option<&SamplingParams>,
+        lookups: Option<&Map<String, Value>>,
+    ) -> Result<ChatReply> {
+        let reply = match &self.backend {
             ConversationBackend::OpenAi(openai) => {
                 let formatted_history: Vec<Message> =
                     history_snapshot.iter().map(|msg| msg.to_openai()).collect();
-                openai.agent.chat(user_input, formatted_history).await?
+                match lookups {
+                    Some(lookups) => {
+                        Self::complete_with_lookups(
+                            openai,
+                            user_input,
+                            formatted_history,
+                            preamble,
+                            sampling,
+                            lookups,
+                        )
+                        .await?
+                    }
+                    None => {
+                        Self::complete(
+                            &openai.agent,
+                            user_input,
+                            formatted_history,
+                            preamble,
+                            sampling,
+                            &[],
+                        )
+                            .await?
+                            .0
+                    }
+                }
+            }
+            ConversationBackend::DeepSeek(agent) => {
+                let formatted_history: Vec<Message> =
+                    history_snapshot.iter().map(|msg| msg.to_openai()).collect();
+                Self::complete(agent, user_input, formatted_history, preamble, sampling, &[])
+                    .await?
+                    .0
+            }
+            ConversationBackend::Mock(agent) => {
+                let formatted_history: Vec<Message> =
+                    history_snapshot.iter().map(|msg| msg.to_openai()).collect();
+                Self::complete(agent, user_input, formatted_history, preamble, sampling, &[])
+                    .await?
+                    .0
             }
             ConversationBackend::Zhipu(zhipu) => {
-                // 构建聊天历史消息
-                let mut messages = vec![];
-                
-                // 添加系统提示词作为用户消息
-                if !zhipu.preamble.is_empty() {
-                    messages.push(Message::User {
-                        content: OneOrMany::one(rig::completion::message::UserContent::Text(
-                            rig::completion::message::Text {
-                                text: zhipu.preamble.clone(),
-                            }
-                        )),
-                    });
-                }
-                
-                // 添加历史消息
-                for msg in &history_snapshot {
-                    messages.push(msg.to_openai());
-                }
-                
-                // 添加当前用户输入
-                messages.push(Message::user(user_input));
-                
+                // 系统提示词通过 preamble 以 system 角色发送
+                let preamble = preamble.unwrap_or(&zhipu.preamble);
+                let sampling = sampling.unwrap_or(&zhipu.sampling);
+                let messages: Vec<Message> =
+                    history_snapshot.iter().map(|msg| msg.to_openai()).collect();
+
                 // 构建请求
-                let request = zhipu.model
-                    .completion_request("")
+                let mut request = zhipu
+                    .model
+                    .completion_request(Message::user(user_input))
                     .messages(messages)
-                    .build();
+                    .temperature_opt(sampling.temperature)
+                    .max_tokens_opt(sampling.max_tokens);
+                if !preamble.is_empty() {
+                    request = request.preamble(preamble.to_string());
+                }
+                if let Some(top_p) = sampling.top_p {
+                    request = request.additional_params(json!({ "top_p": top_p }));
+                }
+                let request = request.build();
                 
                 // 调用模型
                 let response = zhipu.model.completion(request).await
                     .map_err(|e| crate::errors::AgentError::Unsupported(format!("智谱AI调用失败: {}", e)))?;
                 
                 // 提取响应文本
-                response.choice.iter()
+                let text = response.choice.iter()
                     .filter_map(|content| match content {
                         rig::completion::message::AssistantContent::Text(t) => Some(t.text.as_str()),
                         _ => None,
                     })
                     .collect::<Vec<_>>()
-                    .join("\n")
+                    .join("\n");
+                ChatReply {
+                    text,
+                    usage: response.usage.into(),
+                    tool_calls: Vec::new(),
+                    lookups: Vec::new(),
+                }
             }
         };
 
-        self.history.push(ConversationMessage::user(user_input));
-        self.history.push(ConversationMessage::assistant(&response));
-        self.trim_history();
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按“用户 + 回复”成对写入历史；构建 Agent 需要在 tokio 运行时中
+    fn agent_with_history(turns: &[(&str, &str)]) -> ConversationAgent {
+        let mut agent = ConversationAgent::with_mock("");
+        for (user, assistant) in turns {
+            agent.history.push(ConversationMessage::user(user));
+            agent
+                .history
+                .push(ConversationMessage::assistant(assistant));
+        }
+        agent
+    }
+
+    fn kept(agent: &ConversationAgent) -> Vec<&str> {
+        agent
+            .history
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect()
+    }
+
+    // 每轮 "hello world!"（3）+ "你好"（2）共 5 个 token
+    const TURNS: [(&str, &str); 3] = [
+        ("hello world!", "你好"),
+        ("hello world!", "你好"),
+        ("hello world!", "你好"),
+    ];
+
+    #[tokio::test]
+    async fn history_within_budget_is_kept() {
+        let mut agent = agent_with_history(&TURNS);
+        agent.set_history_budget(15);
+        assert_eq!(agent.history.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn oldest_turn_is_dropped_when_over_budget() {
+        let mut agent = agent_with_history(&TURNS);
+        agent.set_history_budget(14);
+        assert_eq!(agent.history.len(), 4);
+        assert!(matches!(agent.history[0].role, ConversationRole::User));
+    }
+
+    #[tokio::test]
+    async fn latest_turn_is_kept_even_if_over_budget() {
+        let mut agent = agent_with_history(&[("旧的问题", "旧的回答"), ("hello world!", "你好")]);
+        agent.set_history_budget(2);
+        assert_eq!(kept(&agent), ["hello world!", "你好"]);
+    }
 
-        Ok(response)
+    #[tokio::test]
+    async fn mixed_cjk_and_ascii_messages() {
+        // "hello world!晚上好呀！" 按 3 + 5/1.7 向上取整为 6，"ok" 为 1，共 7
+        let turns = [("hello world!晚上好呀！", "ok"), ("你好", "hello world!")];
+        let mut agent = agent_with_history(&turns);
+        agent.set_history_budget(12);
+        assert_eq!(agent.history.len(), 4);
+        agent.set_history_budget(11);
+        assert_eq!(kept(&agent), ["你好", "hello world!"]);
     }
 }
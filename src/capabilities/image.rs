@@ -1,54 +1,1039 @@
-use crate::{capabilities::BinaryArtifact, errors::Result};
-use rig::{
-    client::ImageGenerationClient, image_generation::ImageGenerationModel, providers::openai,
+use std::time::Duration;
+
+use crate::{
+    capabilities::BinaryArtifact,
+    config::{DEFAULT_OPENAI_BASE_URL, OpenAiConfig, SdWebUiConfig, ZhipuConfig},
+    errors::{AgentError, Result},
+    providers::{cogview::CogViewClient, sd_webui::SdWebUiClient},
+    util::{MAX_SOURCE_BYTES, fetch_bytes, fetch_source_bytes, send_with_retry},
 };
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
 use serde_json::{Map, Value, json};
+use tracing::debug;
+
+const GPT_IMAGE_1: &str = "gpt-image-1";
+
+/// CogView 系列共用的可选尺寸
+const COGVIEW_SIZES: &[(u32, u32)] = &[
+    (1024, 1024),
+    (768, 1344),
+    (864, 1152),
+    (1344, 768),
+    (1152, 864),
+    (1440, 720),
+    (720, 1440),
+];
+
+/// 内置的各模型可用尺寸，`[providers.image] sizes` 可覆盖；不在表中的模型不限制尺寸
+const MODEL_SIZES: &[(&str, &[(u32, u32)])] = &[
+    (GPT_IMAGE_1, &[(1024, 1024), (1024, 1536), (1536, 1024)]),
+    ("dall-e-3", &[(1024, 1024), (1792, 1024), (1024, 1792)]),
+    ("dall-e-2", &[(256, 256), (512, 512), (1024, 1024)]),
+    ("cogview-3-flash", COGVIEW_SIZES),
+    ("cogview-3-plus", COGVIEW_SIZES),
+    ("cogview-4", COGVIEW_SIZES),
+    ("cogview-4-250304", COGVIEW_SIZES),
+];
+
+/// 1x1 的 PNG，mock 提供方固定返回该图像
+const MOCK_PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
 
 pub struct ImageGenerator {
-    model: openai::image_generation::ImageGenerationModel,
+    backend: ImageBackend,
     model_name: String,
     default_width: u32,
     default_height: u32,
+    /// 模型接受的尺寸，为空时按请求的尺寸原样发送
+    sizes: Vec<(u32, u32)>,
+    /// 路由配置的默认可选参数，提示词中的子句优先
+    defaults: ImageOptions,
+    /// 参考图可以是 `file://` 或本地路径，见 `processing.allow_local_files`
+    allow_local_files: bool,
+}
+
+/// 图像生成的可选参数，模型不支持的参数在请求前丢弃
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageOptions {
+    pub quality: Option<String>,
+    pub style: Option<String>,
+    pub negative_prompt: Option<String>,
+}
+
+impl ImageOptions {
+    /// `other` 中已设置的参数覆盖当前值
+    fn merged(&self, other: ImageOptions) -> Self {
+        Self {
+            quality: other.quality.or_else(|| self.quality.clone()),
+            style: other.style.or_else(|| self.style.clone()),
+            negative_prompt: other
+                .negative_prompt
+                .or_else(|| self.negative_prompt.clone()),
+        }
+    }
+}
+
+enum ImageBackend {
+    /// 直接调用 OpenAI 兼容的 `/images/generations`，同时兼容 Base64 与 URL 两种返回形式
+    /// （rig 只处理 Base64，且解码失败时会 panic）
+    OpenAi(OpenAiImageClient),
+    /// 智谱 CogView，仅支持文生图
+    CogView(CogViewClient),
+    /// 本地 Stable Diffusion WebUI，仅支持文生图，可选参数中只接受反向提示词
+    SdWebUi(SdWebUiClient),
+    /// 离线调试用，不发起请求
+    Mock,
+}
+
+struct OpenAiImageClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    /// 返回的图像 URL 可以是 `file://` 或本地路径
+    allow_local_files: bool,
+}
+
+/// 图生图的参考图
+pub struct ImageSource {
+    pub bytes: Vec<u8>,
+    /// 写入结果元数据的来源字段（`source_artifact` / `source_url`）与取值
+    pub reference: (&'static str, String),
+}
+
+/// 参考图编辑的方式：有提示词时按提示词修改，否则生成相似的变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditOperation {
+    Edit,
+    Variation,
+}
+
+impl EditOperation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Edit => "edit",
+            Self::Variation => "variation",
+        }
+    }
+
+    fn endpoint(self) -> &'static str {
+        match self {
+            Self::Edit => "images/edits",
+            Self::Variation => "images/variations",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageResponse {
+    #[serde(default)]
+    data: Vec<ImageData>,
+    /// gpt-image-1 返回的实际尺寸
+    #[serde(default)]
+    size: Option<String>,
+    /// Azure OpenAI 对提示词的内容审核结果
+    #[serde(default)]
+    prompt_filter_results: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct ImageData {
+    #[serde(default)]
+    b64_json: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    /// DALL·E 3 改写后实际使用的提示词
+    #[serde(default)]
+    revised_prompt: Option<String>,
+    /// Azure OpenAI 对生成图像的内容审核结果
+    #[serde(default)]
+    content_filter_results: Option<Value>,
+}
+
+/// 接口返回的图像内容及其传输形式（`b64_json` 或 `url`）
+struct ImagePayload {
+    bytes: Vec<u8>,
+    response_format: &'static str,
+    /// 提供方返回的实际生成参数（如 SD WebUI 的种子），写入元数据
+    parameters: Map<String, Value>,
 }
 
 impl ImageGenerator {
-    pub fn new(client: openai::Client, model_name: &str) -> Self {
-        let model = client.image_generation_model(model_name);
+    /// `sizes` 为路由配置的可用尺寸，为空时使用内置表
+    pub fn new(config: &OpenAiConfig, model_name: &str, sizes: &[(u32, u32)]) -> Result<Self> {
+        let base_url = config
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_OPENAI_BASE_URL);
+        let backend = ImageBackend::OpenAi(OpenAiImageClient {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(180))
+                .build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+            allow_local_files: false,
+        });
+
+        Ok(Self::with_backend(backend, model_name, sizes))
+    }
+
+    /// 智谱 CogView，`model_name` 为空时使用 `zhipu.image_model`
+    pub fn zhipu(
+        config: &ZhipuConfig,
+        model_name: Option<&str>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Self> {
+        let model_name = model_name.unwrap_or(&config.image_model);
+        Ok(Self::with_backend(
+            ImageBackend::CogView(CogViewClient::from_config(config)?),
+            model_name,
+            sizes,
+        ))
+    }
+
+    /// 本地 SD WebUI，`model_name`（路由的 `model`）为要使用的模型，未配置时沿用 `sd_webui.checkpoint`
+    pub fn sd_webui(
+        config: &SdWebUiConfig,
+        model_name: Option<&str>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Self> {
+        let client = SdWebUiClient::from_config(config, model_name)?;
+        let model_name = client.checkpoint().unwrap_or("sd_webui").to_string();
+        let (width, height) = client.default_size();
+        let mut generator = Self::with_backend(ImageBackend::SdWebUi(client), &model_name, sizes);
+        generator.default_width = width;
+        generator.default_height = height;
+        Ok(generator)
+    }
+
+    /// 离线调试用，始终返回一张 1x1 的 PNG
+    pub fn mock(model_name: Option<&str>, sizes: &[(u32, u32)]) -> Self {
+        Self::with_backend(ImageBackend::Mock, model_name.unwrap_or("mock"), sizes)
+    }
+
+    fn with_backend(backend: ImageBackend, model_name: &str, sizes: &[(u32, u32)]) -> Self {
+        let sizes = if sizes.is_empty() {
+            MODEL_SIZES
+                .iter()
+                .find(|(model, _)| *model == model_name)
+                .map(|(_, sizes)| sizes.to_vec())
+                .unwrap_or_default()
+        } else {
+            sizes.to_vec()
+        };
         Self {
-            model,
+            backend,
             model_name: model_name.to_string(),
             default_width: 1024,
             default_height: 1024,
+            sizes,
+            defaults: ImageOptions::default(),
+            allow_local_files: false,
         }
     }
 
+    /// 允许参考图与返回的图像 URL 指向本地文件，仅用于测试与离线演示
+    pub fn with_local_files(mut self, allow: bool) -> Self {
+        self.allow_local_files = allow;
+        match &mut self.backend {
+            ImageBackend::OpenAi(client) => client.allow_local_files = allow,
+            ImageBackend::CogView(client) => client.set_allow_local_files(allow),
+            ImageBackend::SdWebUi(_) | ImageBackend::Mock => {}
+        }
+        self
+    }
+
+    /// 设置路由配置的默认可选参数
+    pub fn with_options(mut self, defaults: ImageOptions) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// 请求的尺寸不在可用列表中时，改用宽高比最接近、其次面积最接近的尺寸
+    fn fit_size(&self, requested: (u32, u32)) -> (u32, u32) {
+        if self.sizes.is_empty() || self.sizes.contains(&requested) {
+            return requested;
+        }
+
+        let ratio = |(width, height): (u32, u32)| (width as f64 / height.max(1) as f64).ln();
+        let area = |(width, height): (u32, u32)| width as f64 * height as f64;
+        let fitted = self
+            .sizes
+            .iter()
+            .copied()
+            .min_by(|a, b| {
+                let key = |size| {
+                    (
+                        (ratio(size) - ratio(requested)).abs(),
+                        (area(size) - area(requested)).abs(),
+                    )
+                };
+                key(*a)
+                    .partial_cmp(&key(*b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(requested);
+        debug!(
+            target: "image_generator",
+            model = %self.model_name,
+            requested = %format!("{}x{}", requested.0, requested.1),
+            used = %format!("{}x{}", fitted.0, fitted.1),
+            "模型不支持请求的尺寸，改用最接近的尺寸"
+        );
+        fitted
+    }
+
+    /// 按模型拆分为随请求发送的参数与被丢弃的参数
+    fn supported_params(&self, options: &ImageOptions) -> (Map<String, Value>, Map<String, Value>) {
+        let mut params = Map::new();
+        let mut ignored = Map::new();
+        let options = [
+            ("quality", &options.quality),
+            ("style", &options.style),
+            ("negative_prompt", &options.negative_prompt),
+        ];
+        for (name, value) in options {
+            let Some(value) = value else {
+                continue;
+            };
+            let accepted = match option_values(&self.model_name, name) {
+                _ if matches!(self.backend, ImageBackend::SdWebUi(_)) => name == "negative_prompt",
+                Some([]) => true,
+                Some(values) => values.contains(&value.to_lowercase().as_str()),
+                None => false,
+            };
+            if accepted {
+                params.insert(name.to_string(), json!(value));
+            } else {
+                debug!(
+                    target: "image_generator",
+                    model = %self.model_name,
+                    option = name,
+                    value = %value,
+                    "模型不支持该参数或取值，已忽略"
+                );
+                ignored.insert(name.to_string(), json!(value));
+            }
+        }
+        (params, ignored)
+    }
+
+    /// 生成一张图像；OpenAI 返回的改写提示词（`revised_prompt`）、实际尺寸（`reported_size`）
+    /// 与内容审核结果（`prompt_filter_results` / `content_filter_results`）一并写入元数据
     pub async fn generate(
         &self,
         prompt: &str,
         resolution: Option<(u32, u32)>,
     ) -> Result<BinaryArtifact> {
-        let (width, height) = resolution.unwrap_or((self.default_width, self.default_height));
-        let response = self
-            .model
-            .image_generation_request()
-            .prompt(prompt)
-            .width(width)
-            .height(height)
-            .send()
-            .await?;
+        let (prompt, inline) = split_inline_options(prompt);
+        let options = self.defaults.merged(inline);
+        let (params, ignored) = self.supported_params(&options);
+
+        let requested = resolution.unwrap_or((self.default_width, self.default_height));
+        let (width, height) = self.fit_size(requested);
+        let payload = match &self.backend {
+            ImageBackend::OpenAi(client) => {
+                client
+                    .generate(&self.model_name, prompt, width, height, &params)
+                    .await?
+            }
+            ImageBackend::CogView(client) => {
+                let image = client
+                    .generate(&self.model_name, prompt, (width, height), &params)
+                    .await?;
+                ImagePayload {
+                    bytes: image.bytes,
+                    response_format: image.response_format,
+                    parameters: Map::new(),
+                }
+            }
+            ImageBackend::SdWebUi(client) => {
+                let negative_prompt = params.get("negative_prompt").and_then(Value::as_str);
+                let image = client
+                    .txt2img(prompt, negative_prompt, (width, height))
+                    .await?;
+                ImagePayload {
+                    bytes: image.bytes,
+                    response_format: "b64_json",
+                    parameters: image.parameters,
+                }
+            }
+            ImageBackend::Mock => ImagePayload {
+                bytes: BASE64_STANDARD
+                    .decode(MOCK_PNG_BASE64)
+                    .map_err(|e| AgentError::other(format!("解码 mock 图像失败: {e}")))?,
+                response_format: "b64_json",
+                parameters: Map::new(),
+            },
+        };
 
         let mut metadata = Map::new();
         metadata.insert("prompt".to_string(), Value::String(prompt.to_string()));
         metadata.insert("model".to_string(), Value::String(self.model_name.clone()));
         metadata.insert("width".to_string(), json!(width));
         metadata.insert("height".to_string(), json!(height));
+        if (width, height) != requested {
+            metadata.insert(
+                "requested_size".to_string(),
+                json!(format!("{}x{}", requested.0, requested.1)),
+            );
+        }
+        metadata.insert(
+            "response_format".to_string(),
+            json!(payload.response_format),
+        );
+        metadata.extend(params);
+        metadata.extend(payload.parameters);
+        if !ignored.is_empty() {
+            metadata.insert("ignored_options".to_string(), Value::Object(ignored));
+        }
 
+        let (media_type, extension) = image_type(&payload.bytes);
         Ok(BinaryArtifact::with_metadata(
-            response.image,
-            "image/png",
-            "png",
+            payload.bytes,
+            media_type,
+            extension,
             format!("Model: {} | Size: {}x{}", self.model_name, width, height),
             metadata,
         ))
     }
+
+    /// 下载 URL 指向的参考图，失败时返回 `image_source_missing`；本地文件见 `with_local_files`。
+    /// 地址来自请求方，按 `fetch_source_bytes` 拒绝非公网地址并限制大小
+    pub async fn fetch_source(&self, url: &str) -> Result<ImageSource> {
+        let bytes = fetch_source_bytes(url, self.allow_local_files, MAX_SOURCE_BYTES)
+            .await
+            .map_err(|err| match err {
+                AgentError::Rejected { .. } => err,
+                other => AgentError::rejected(
+                    "image_source_missing",
+                    format!("无法获取参考图 {url}: {other}"),
+                ),
+            })?;
+        Ok(ImageSource {
+            bytes,
+            reference: ("source_url", url.to_string()),
+        })
+    }
+
+    /// 基于参考图生成：提示词为空时生成变体（仅 dall-e-2 支持，OpenAI 模型自动改用 dall-e-2），
+    /// 否则按提示词编辑（dall-e-3 与 CogView 不支持）
+    pub async fn edit(&self, source: ImageSource, prompt: &str) -> Result<BinaryArtifact> {
+        let (prompt, inline) = split_inline_options(prompt);
+        let operation = if prompt.is_empty() {
+            EditOperation::Variation
+        } else {
+            EditOperation::Edit
+        };
+        let model = match (operation, self.model_name.as_str()) {
+            (EditOperation::Edit, "dall-e-3") => {
+                return Err(AgentError::rejected(
+                    "image_edit_unsupported",
+                    "dall-e-3 不支持基于参考图修改，请将 providers.image.model 设为 gpt-image-1 或 dall-e-2",
+                ));
+            }
+            (EditOperation::Variation, "dall-e-3" | GPT_IMAGE_1) => "dall-e-2",
+            (_, model) => model,
+        };
+        let Some((source_type, extension)) = source_image_type(&source.bytes) else {
+            return Err(AgentError::rejected(
+                "image_source_unsupported_format",
+                "参考图格式不受支持，仅支持 PNG / JPEG / WebP",
+            ));
+        };
+        if model == "dall-e-2" && source_type != "image/png" {
+            return Err(AgentError::rejected(
+                "image_source_unsupported_format",
+                format!("dall-e-2 只接受 PNG 参考图，当前为 {source_type}"),
+            ));
+        }
+
+        let (params, ignored) = match operation {
+            EditOperation::Edit => self.supported_params(&self.defaults.merged(inline)),
+            EditOperation::Variation => Default::default(),
+        };
+        let requested = (self.default_width, self.default_height);
+        let (width, height) = if model == self.model_name {
+            self.fit_size(requested)
+        } else {
+            requested
+        };
+        let payload = match &self.backend {
+            ImageBackend::OpenAi(client) => {
+                let request = EditRequest {
+                    operation,
+                    model,
+                    prompt,
+                    source: &source.bytes,
+                    source_type,
+                    extension,
+                    size: (width, height),
+                    params: &params,
+                };
+                client.edit(request).await?
+            }
+            ImageBackend::CogView(_) => {
+                return Err(AgentError::rejected(
+                    "image_edit_unsupported",
+                    "智谱 CogView 不支持基于参考图修改或生成变体，请改用 OpenAI 图像模型",
+                ));
+            }
+            ImageBackend::SdWebUi(_) => {
+                return Err(AgentError::rejected(
+                    "image_edit_unsupported",
+                    "SD WebUI 提供方暂不支持基于参考图修改或生成变体，请改用 OpenAI 图像模型",
+                ));
+            }
+            ImageBackend::Mock => ImagePayload {
+                bytes: source.bytes.clone(),
+                response_format: "b64_json",
+                parameters: Map::new(),
+            },
+        };
+
+        let mut metadata = Map::new();
+        if !prompt.is_empty() {
+            metadata.insert("prompt".to_string(), Value::String(prompt.to_string()));
+        }
+        metadata.insert("operation".to_string(), json!(operation.as_str()));
+        let (source_key, source_value) = source.reference;
+        metadata.insert(source_key.to_string(), json!(source_value));
+        metadata.insert("model".to_string(), Value::String(model.to_string()));
+        metadata.insert("width".to_string(), json!(width));
+        metadata.insert("height".to_string(), json!(height));
+        metadata.insert(
+            "response_format".to_string(),
+            json!(payload.response_format),
+        );
+        metadata.extend(params);
+        metadata.extend(payload.parameters);
+        if !ignored.is_empty() {
+            metadata.insert("ignored_options".to_string(), Value::Object(ignored));
+        }
+
+        let (media_type, extension) = image_type(&payload.bytes);
+        Ok(BinaryArtifact::with_metadata(
+            payload.bytes,
+            media_type,
+            extension,
+            format!(
+                "Model: {model} | Size: {width}x{height} | Operation: {}",
+                operation.as_str()
+            ),
+            metadata,
+        ))
+    }
+}
+
+/// `/images/edits` 与 `/images/variations` 的请求参数
+struct EditRequest<'a> {
+    operation: EditOperation,
+    model: &'a str,
+    prompt: &'a str,
+    source: &'a [u8],
+    source_type: &'static str,
+    extension: &'static str,
+    size: (u32, u32),
+    params: &'a Map<String, Value>,
+}
+
+impl OpenAiImageClient {
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        width: u32,
+        height: u32,
+        params: &Map<String, Value>,
+    ) -> Result<ImagePayload> {
+        let mut body = json!({
+            "model": model,
+            "prompt": prompt,
+            "size": format!("{width}x{height}"),
+            "n": 1,
+        });
+        if let Value::Object(map) = &mut body {
+            map.extend(params.clone());
+        }
+        // gpt-image-1 只返回 Base64 且不接受 response_format，其它模型显式要求 Base64，
+        // 不遵守该参数的网关返回 URL 时再下载
+        if model != GPT_IMAGE_1 {
+            body["response_format"] = json!("b64_json");
+        }
+
+        let request = self
+            .http
+            .post(format!("{}/images/generations", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&body);
+        let response = send_with_retry("openai", request).await?;
+        self.read_payload(model, response).await
+    }
+
+    /// 以 multipart 上传参考图，调用编辑或变体接口
+    async fn edit(&self, request: EditRequest<'_>) -> Result<ImagePayload> {
+        let EditRequest {
+            operation,
+            model,
+            prompt,
+            source,
+            source_type,
+            extension,
+            size: (width, height),
+            params,
+        } = request;
+        let image = Part::bytes(source.to_vec())
+            .file_name(format!("source.{extension}"))
+            .mime_str(source_type)?;
+        let mut form = Form::new()
+            .part("image", image)
+            .text("model", model.to_string())
+            .text("size", format!("{width}x{height}"))
+            .text("n", "1");
+        if operation == EditOperation::Edit {
+            form = form.text("prompt", prompt.to_string());
+        }
+        if model != GPT_IMAGE_1 {
+            form = form.text("response_format", "b64_json");
+        }
+        for (name, value) in params {
+            let value = value
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| value.to_string());
+            form = form.text(name.clone(), value);
+        }
+
+        let request = self
+            .http
+            .post(format!("{}/{}", self.base_url, operation.endpoint()))
+            .bearer_auth(&self.api_key)
+            .multipart(form);
+        let response = send_with_retry("openai", request).await?;
+        self.read_payload(model, response).await
+    }
+
+    /// 解析图像接口的返回：优先 Base64，否则下载返回的 URL
+    async fn read_payload(&self, model: &str, response: reqwest::Response) -> Result<ImagePayload> {
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AgentError::other(format!(
+                "{model} 图像生成失败 ({status}): {body}"
+            )));
+        }
+
+        let response: ImageResponse = response.json().await?;
+        let data = response
+            .data
+            .into_iter()
+            .find(|data| data.b64_json.is_some() || data.url.is_some())
+            .ok_or_else(|| AgentError::other(format!("{model} 未返回图像数据")))?;
+
+        // 服务端改写后的提示词、实际尺寸与内容审核结果写入元数据，便于排查生成结果与请求不符
+        let mut parameters = Map::new();
+        if let Some(revised_prompt) = data.revised_prompt.filter(|text| !text.trim().is_empty()) {
+            debug!(target: "image_generator", model, %revised_prompt, "图像接口返回了改写后的提示词");
+            parameters.insert("revised_prompt".to_string(), json!(revised_prompt));
+        }
+        if let Some(size) = response.size {
+            parameters.insert("reported_size".to_string(), json!(size));
+        }
+        if let Some(results) = response.prompt_filter_results {
+            parameters.insert("prompt_filter_results".to_string(), results);
+        }
+        if let Some(results) = data.content_filter_results {
+            parameters.insert("content_filter_results".to_string(), results);
+        }
+
+        if let Some(b64_json) = data.b64_json {
+            let bytes = BASE64_STANDARD.decode(b64_json.trim()).map_err(|e| {
+                AgentError::other(format!("解码 {model} 返回的 Base64 图像失败: {e}"))
+            })?;
+            return Ok(ImagePayload {
+                bytes,
+                response_format: "b64_json",
+                parameters,
+            });
+        }
+
+        let url = data.url.unwrap_or_default();
+        let bytes = fetch_bytes("openai", &self.http, &url, self.allow_local_files)
+            .await
+            .map_err(|err| match err {
+                AgentError::Other(reason) => {
+                    AgentError::other(format!("下载 {model} 生成的图像失败: {reason}"))
+                }
+                other => other,
+            })?;
+        Ok(ImagePayload {
+            bytes,
+            response_format: "url",
+            parameters,
+        })
+    }
+}
+
+/// 模型接受的参数取值：`None` 表示不支持该参数，空列表表示原样透传（如 SD 兼容网关）
+fn option_values(model: &str, option: &str) -> Option<&'static [&'static str]> {
+    match (model, option) {
+        ("dall-e-3", "quality") => Some(&["standard", "hd"]),
+        ("dall-e-3", "style") => Some(&["vivid", "natural"]),
+        (GPT_IMAGE_1, "quality") => Some(&["low", "medium", "high", "auto"]),
+        ("dall-e-3" | "dall-e-2" | GPT_IMAGE_1, _) => None,
+        (model, "quality") if model.starts_with("cogview-4") => Some(&["standard", "hd"]),
+        (model, _) if model.starts_with("cogview") => None,
+        _ => Some(&[]),
+    }
+}
+
+/// 提示词末尾可附加的参数子句，如 `一只猫 --negative: blurry, lowres --quality: hd`
+const INLINE_OPTION_KEYS: &[(&str, &str)] = &[
+    ("negative", "negative_prompt"),
+    ("negative_prompt", "negative_prompt"),
+    ("neg", "negative_prompt"),
+    ("quality", "quality"),
+    ("style", "style"),
+];
+
+/// 拆出提示词末尾参数子句的原文，返回（提示词, 子句），改写提示词时子句原样保留
+pub fn split_option_clauses(prompt: &str) -> (&str, &str) {
+    let (body, _) = split_inline_options(prompt);
+    (body, &prompt[body.len()..])
+}
+
+/// 拆出提示词末尾的参数子句，返回去掉子句后的提示词；第一个可识别的子句之后的内容都视为子句
+fn split_inline_options(prompt: &str) -> (&str, ImageOptions) {
+    // (子句起点, 参数名, 取值起点)
+    let clauses: Vec<(usize, &str, usize)> = prompt
+        .match_indices("--")
+        .filter_map(|(start, _)| {
+            let rest = &prompt[start + 2..];
+            let key_len = rest
+                .find(|c: char| !(c.is_ascii_alphabetic() || c == '_'))
+                .unwrap_or(rest.len());
+            let key = rest[..key_len].to_lowercase();
+            let (_, option) = INLINE_OPTION_KEYS.iter().find(|(name, _)| *name == key)?;
+            let after_key = rest[key_len..].trim_start();
+            let value = after_key
+                .strip_prefix(':')
+                .or_else(|| after_key.strip_prefix('：'))?;
+            Some((start, *option, prompt.len() - value.len()))
+        })
+        .collect();
+
+    let Some((cut, _, _)) = clauses.first() else {
+        return (prompt, ImageOptions::default());
+    };
+
+    let mut options = ImageOptions::default();
+    for (index, (_, option, value_start)) in clauses.iter().enumerate() {
+        let value_end = clauses
+            .get(index + 1)
+            .map_or(prompt.len(), |(next, _, _)| *next);
+        let value = prompt[*value_start..value_end]
+            .trim()
+            .trim_end_matches([',', '，'])
+            .trim();
+        if value.is_empty() {
+            continue;
+        }
+        let slot = match *option {
+            "quality" => &mut options.quality,
+            "style" => &mut options.style,
+            _ => &mut options.negative_prompt,
+        };
+        *slot = Some(value.to_string());
+    }
+    (prompt[..*cut].trim_end(), options)
+}
+
+/// 参考图的格式，只接受 PNG / JPEG / WebP
+fn source_image_type(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    let is_png = bytes.starts_with(b"\x89PNG\r\n\x1a\n");
+    let detected = image_type(bytes);
+    (is_png || detected.0 != "image/png").then_some(detected)
+}
+
+/// 按文件头判断图像格式，无法识别时按 PNG 处理
+fn image_type(bytes: &[u8]) -> (&'static str, &'static str) {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        ("image/jpeg", "jpg")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        ("image/webp", "webp")
+    } else {
+        ("image/png", "png")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        Json, Router,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        response::{IntoResponse, Response},
+        routing::{get, post},
+    };
+
+    use super::*;
+    use crate::{
+        intent::Intent,
+        util::{ArtifactWriter, test_support::spawn_stub},
+    };
+
+    type Requests = Arc<Mutex<Vec<Value>>>;
+
+    /// 按请求的模型返回 Base64、URL、带改写提示词与审核结果的响应或错误，并记录收到的请求体
+    async fn serve() -> (OpenAiConfig, Requests) {
+        async fn generations(
+            State(requests): State<Requests>,
+            headers: HeaderMap,
+            Json(body): Json<Value>,
+        ) -> Response {
+            requests.lock().unwrap().push(body.clone());
+            match body["model"].as_str() {
+                Some("url-gateway") => {
+                    let host = headers["host"].to_str().unwrap();
+                    let url = format!("http://{host}/v1/files/cat.png");
+                    Json(json!({ "data": [{ "url": url }] })).into_response()
+                }
+                Some("broken") => (
+                    StatusCode::BAD_REQUEST,
+                    r#"{"error":{"message":"size is not supported"}}"#,
+                )
+                    .into_response(),
+                Some("revised") => Json(json!({
+                    "created": 1,
+                    "prompt_filter_results": [{
+                        "prompt_index": 0,
+                        "content_filter_results": { "hate": { "filtered": false } },
+                    }],
+                    "data": [{
+                        "b64_json": MOCK_PNG_BASE64,
+                        "revised_prompt": "一只坐在窗台上的橘猫，午后阳光，水彩风格",
+                        "content_filter_results": {
+                            "violence": { "filtered": false, "severity": "safe" },
+                        },
+                    }],
+                }))
+                .into_response(),
+                _ => Json(json!({ "data": [{ "b64_json": MOCK_PNG_BASE64 }] })).into_response(),
+            }
+        }
+
+        let requests = Requests::default();
+        let app = Router::new()
+            .route("/v1/images/generations", post(generations))
+            .route(
+                "/v1/files/cat.png",
+                get(|| async { BASE64_STANDARD.decode(MOCK_PNG_BASE64).unwrap() }),
+            )
+            .with_state(requests.clone());
+        let addr = spawn_stub(app).await;
+        let config = OpenAiConfig {
+            api_key: "test".to_string(),
+            base_url: Some(format!("http://{addr}/v1")),
+            chat_model: "gpt-4o-mini".to_string(),
+            agent_preamble: String::new(),
+            image_model: "dall-e-3".to_string(),
+            tts_voice: "alloy".to_string(),
+        };
+        (config, requests)
+    }
+
+    #[tokio::test]
+    async fn base64_response_with_fitted_size() {
+        let (config, requests) = serve().await;
+        let artifact = ImageGenerator::new(&config, "dall-e-3", &[])
+            .unwrap()
+            .generate("画一只猫", Some((1920, 1080)))
+            .await
+            .unwrap();
+        assert_eq!(
+            artifact.data,
+            BASE64_STANDARD.decode(MOCK_PNG_BASE64).unwrap()
+        );
+        assert_eq!(artifact.media_type, "image/png");
+        let metadata = &artifact.metadata;
+        assert_eq!(metadata["response_format"], "b64_json");
+        assert_eq!(
+            (&metadata["width"], &metadata["height"]),
+            (&json!(1792), &json!(1024))
+        );
+        assert_eq!(metadata["requested_size"], "1920x1080");
+
+        let body = requests.lock().unwrap()[0].clone();
+        assert_eq!(body["size"], "1792x1024");
+        assert_eq!(body["response_format"], "b64_json");
+
+        // gpt-image-1 不接受 response_format
+        ImageGenerator::new(&config, GPT_IMAGE_1, &[])
+            .unwrap()
+            .generate("画一只猫", None)
+            .await
+            .unwrap();
+        let body = requests.lock().unwrap()[1].clone();
+        assert!(body.get("response_format").is_none());
+        assert_eq!(body["size"], "1024x1024");
+    }
+
+    #[tokio::test]
+    async fn revised_prompt_and_filter_results_are_recorded() {
+        let (config, _) = serve().await;
+        let artifact = ImageGenerator::new(&config, "revised", &[])
+            .unwrap()
+            .generate("画一只猫", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            artifact.metadata["revised_prompt"],
+            "一只坐在窗台上的橘猫，午后阳光，水彩风格"
+        );
+
+        // 落盘后 .meta.json 同样带上这些字段
+        let root = std::env::temp_dir().join(format!("image-revised-{}", uuid::Uuid::new_v4()));
+        let writer = ArtifactWriter::new(root.clone()).await.unwrap();
+        let path = writer
+            .persist(Intent::ImageGeneration, &artifact)
+            .await
+            .unwrap();
+        let meta: Value =
+            serde_json::from_slice(&std::fs::read(path.with_extension("meta.json")).unwrap())
+                .unwrap();
+        let metadata = &meta["metadata"];
+        assert_eq!(metadata["prompt"], "画一只猫");
+        assert_eq!(
+            metadata["revised_prompt"],
+            "一只坐在窗台上的橘猫，午后阳光，水彩风格"
+        );
+        assert_eq!(
+            metadata["content_filter_results"]["violence"]["severity"],
+            "safe"
+        );
+        assert_eq!(metadata["prompt_filter_results"][0]["prompt_index"], 0);
+        assert!(metadata.get("reported_size").is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn url_response_is_downloaded() {
+        let (config, _) = serve().await;
+        let artifact = ImageGenerator::new(&config, "url-gateway", &[(512, 512)])
+            .unwrap()
+            .generate("画一只猫", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            artifact.data,
+            BASE64_STANDARD.decode(MOCK_PNG_BASE64).unwrap()
+        );
+        assert_eq!(artifact.metadata["response_format"], "url");
+        assert_eq!(artifact.metadata["width"], 512);
+        assert_eq!(artifact.metadata["requested_size"], "1024x1024");
+    }
+
+    #[tokio::test]
+    async fn error_includes_provider_body() {
+        let (config, _) = serve().await;
+        let err = ImageGenerator::new(&config, "broken", &[])
+            .unwrap()
+            .generate("画一只猫", None)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("400"), "{err}");
+        assert!(err.contains("size is not supported"), "{err}");
+    }
+
+    #[test]
+    fn inline_option_clauses() {
+        let options =
+            |negative: Option<&str>, quality: Option<&str>, style: Option<&str>| ImageOptions {
+                quality: quality.map(str::to_string),
+                style: style.map(str::to_string),
+                negative_prompt: negative.map(str::to_string),
+            };
+        let cases = [
+            (
+                "一只猫 --negative: blurry, lowres",
+                "一只猫",
+                options(Some("blurry, lowres"), None, None),
+            ),
+            (
+                "一只猫 --quality: hd --style：natural --NEG: 模糊，",
+                "一只猫",
+                options(Some("模糊"), Some("hd"), Some("natural")),
+            ),
+            // 空的取值忽略，但子句仍然去掉
+            (
+                "一只猫 --style: --quality: hd",
+                "一只猫",
+                options(None, Some("hd"), None),
+            ),
+            // 不认识的参数名或没有冒号时不算子句
+            (
+                "一只猫 --ar 16:9",
+                "一只猫 --ar 16:9",
+                ImageOptions::default(),
+            ),
+            ("a--b 日落", "a--b 日落", ImageOptions::default()),
+            (
+                "猫 --seed: 1 --negative: 狗",
+                "猫 --seed: 1",
+                options(Some("狗"), None, None),
+            ),
+        ];
+        for (prompt, body, expected) in cases {
+            assert_eq!(split_inline_options(prompt), (body, expected), "{prompt}");
+        }
+        assert_eq!(
+            split_option_clauses("一只猫 --negative: blurry"),
+            ("一只猫", " --negative: blurry")
+        );
+    }
+
+    #[test]
+    fn unsupported_options_are_dropped() {
+        let options = ImageOptions {
+            quality: Some("HD".to_string()),
+            style: Some("anime".to_string()),
+            negative_prompt: Some("blurry".to_string()),
+        };
+        let (params, ignored) =
+            ImageGenerator::mock(Some("dall-e-3"), &[]).supported_params(&options);
+        assert_eq!(Value::Object(params), json!({ "quality": "HD" }));
+        assert_eq!(
+            Value::Object(ignored),
+            json!({ "style": "anime", "negative_prompt": "blurry" })
+        );
+
+        // 未知模型（SD 兼容网关）原样透传
+        let (params, ignored) = ImageGenerator::mock(Some("sdxl"), &[]).supported_params(&options);
+        assert_eq!(params.len(), 3);
+        assert!(ignored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn options_are_recorded_in_metadata() {
+        let generator = ImageGenerator::mock(Some("dall-e-3"), &[]).with_options(ImageOptions {
+            quality: Some("standard".to_string()),
+            style: Some("vivid".to_string()),
+            negative_prompt: None,
+        });
+        let artifact = generator
+            .generate("一只猫 --quality: hd --negative: 狗", None)
+            .await
+            .unwrap();
+        let metadata = &artifact.metadata;
+        assert_eq!(metadata["prompt"], "一只猫");
+        assert_eq!(metadata["quality"], "hd");
+        assert_eq!(metadata["style"], "vivid");
+        assert_eq!(
+            metadata["ignored_options"],
+            json!({ "negative_prompt": "狗" })
+        );
+    }
 }
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use super::audio_duration;
+
+/// 按扩展名从文件头读出音视频时长：WAV / MP3 见 `audio_duration`，MP4 / M4A / MOV 读取 `moov/mvhd`；
+/// 其它格式、无法解析或时长为 0 时返回 `None`
+pub fn media_duration(data: &[u8], file_extension: &str) -> Option<Duration> {
+    let duration = match file_extension.to_ascii_lowercase().as_str() {
+        "wav" | "mp3" => audio_duration(data),
+        "mp4" | "m4a" | "mov" => mp4_duration(data),
+        _ => None,
+    };
+    duration.filter(|duration| !duration.is_zero())
+}
+
+fn mp4_duration(data: &[u8]) -> Option<Duration> {
+    let moov = find_box(data, b"moov")?;
+    let mvhd = find_box(moov, b"mvhd")?;
+    let read_u32 = |offset: usize| -> Option<u64> {
+        Some(u32::from_be_bytes(mvhd.get(offset..offset + 4)?.try_into().ok()?) as u64)
+    };
+    let (timescale, duration) = match mvhd.first()? {
+        0 => (read_u32(12)?, read_u32(16)?),
+        1 => (
+            read_u32(20)?,
+            u64::from_be_bytes(mvhd.get(24..32)?.try_into().ok()?),
+        ),
+        _ => return None,
+    };
+    // 时长来自后端返回的文件，超出 `Duration` 范围时视为无法解析
+    if timescale == 0 {
+        return None;
+    }
+    Duration::try_from_secs_f64(duration as f64 / timescale as f64).ok()
+}
+
+/// 在同一层级的 box 中查找指定类型，返回其内容（不含头部）
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().ok()?) as usize;
+        let (header, size) = match size {
+            // 64 位长度
+            1 => (
+                16,
+                u64::from_be_bytes(data.get(offset + 8..offset + 16)?.try_into().ok()?) as usize,
+            ),
+            // 延伸到文件末尾
+            0 => (8, data.len() - offset),
+            size => (8, size),
+        };
+        if size < header {
+            return None;
+        }
+        let end = offset.checked_add(size)?;
+        if &data[offset + 4..offset + 8] == kind {
+            return data.get(offset + header..end.min(data.len()));
+        }
+        offset = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn mvhd_v1(timescale: u32, duration: u64) -> Vec<u8> {
+        let mut mvhd = vec![1u8, 0, 0, 0];
+        mvhd.extend_from_slice(&[0; 16]);
+        mvhd.extend_from_slice(&timescale.to_be_bytes());
+        mvhd.extend_from_slice(&duration.to_be_bytes());
+        mvhd.resize(112, 0);
+        mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd))
+    }
+
+    #[test]
+    fn mp4_duration_from_mvhd() {
+        // 最小的 MP4：ftyp + moov/mvhd（版本 0，timescale 1000，duration 2500）+ 空的 mdat
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&2500u32.to_be_bytes());
+        mvhd.resize(100, 0);
+        let mut mp4 = mp4_box(b"ftyp", b"isom\0\0\x02\0isommp41");
+        mp4.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+        mp4.extend(mp4_box(b"mdat", &[]));
+        assert_eq!(
+            media_duration(&mp4, "mp4"),
+            Some(Duration::from_millis(2500))
+        );
+
+        // 版本 1 的 mvhd 使用 64 位时间字段，moov 放在 mdat 之后同样可以找到；扩展名不区分大小写
+        let mut mp4 = mp4_box(b"ftyp", b"isom\0\0\x02\0");
+        mp4.extend(mp4_box(b"mdat", &[0; 64]));
+        mp4.extend(mvhd_v1(90_000, 90_000 * 4));
+        assert_eq!(media_duration(&mp4, "MP4"), Some(Duration::from_secs(4)));
+
+        // 图片等其它格式不解析
+        assert_eq!(media_duration(&mp4, "png"), None);
+        assert_eq!(media_duration(b"not a video", "mp4"), None);
+    }
+
+    #[test]
+    fn out_of_range_mp4_duration_is_none() {
+        assert_eq!(media_duration(&mvhd_v1(1, u64::MAX), "mp4"), None);
+        assert_eq!(media_duration(&mvhd_v1(0, 1000), "mp4"), None);
+        assert_eq!(
+            media_duration(&mvhd_v1(1000, 1500), "mp4"),
+            Some(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn malformed_mp4_boxes_are_none() {
+        // 长度小于头部、64 位长度越界与截断的 mvhd
+        assert_eq!(mp4_duration(&[0, 0, 0, 4, b'm', b'o', b'o', b'v']), None);
+        let mut huge = vec![0, 0, 0, 1];
+        huge.extend_from_slice(b"moov");
+        huge.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(mp4_duration(&huge), None);
+        assert_eq!(
+            mp4_duration(&mp4_box(b"moov", &mp4_box(b"mvhd", &[0; 8]))),
+            None
+        );
+    }
+}
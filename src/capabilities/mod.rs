@@ -1,14 +1,24 @@
 mod conversation;
 mod image;
+mod media;
 mod music;
+mod speech;
+mod subtitles;
 mod video;
 
-pub use conversation::ConversationAgent;
-pub use image::ImageGenerator;
+pub use conversation::{ChatReply, ConversationAgent, ToolLookup, ToolRequest};
+pub use image::{ImageGenerator, ImageOptions, ImageSource, split_option_clauses};
+pub use media::media_duration;
 pub use music::MusicGenerator;
-pub use video::VideoGenerator;
+pub use speech::SpeechSynthesizer;
+#[cfg(feature = "live")]
+pub(crate) use speech::split_voice_clause;
+pub use subtitles::{audio_duration, estimate_speech_duration, format_srt, split_captions};
+pub use video::{VideoGenerator, VideoProgress, VideoRetry, concat_videos};
+pub(crate) use video::split_params_clause;
 
 use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone)]
 pub struct BinaryArtifact {
@@ -17,22 +27,47 @@ pub struct BinaryArtifact {
     pub file_extension: String,
     pub summary: String,
     pub metadata: Map<String, Value>,
+    /// `data` 的 SHA-256（小写十六进制），用于去重与 HTTP 缓存校验
+    pub sha256: String,
+    pub size_bytes: u64,
+    /// 从文件头读出的音视频时长，其它格式或无法解析时为 `None`
+    pub duration_seconds: Option<f64>,
 }
 
 impl BinaryArtifact {
+    /// 构造时计算 `sha256`、`size_bytes`，并按扩展名读出音视频时长
+    ///
+    /// ```
+    /// use vutber_agent::BinaryArtifact;
+    ///
+    /// let text = BinaryArtifact::new(b"abc".to_vec(), "text/plain", "txt", "示例");
+    /// assert_eq!(
+    ///     text.sha256,
+    ///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    /// );
+    /// assert_eq!((text.size_bytes, text.duration_seconds), (3, None));
+    ///
+    /// // 8kHz 8 位单声道 PCM，2 秒
+    /// let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+    /// wav.extend_from_slice(&16u32.to_le_bytes());
+    /// wav.extend_from_slice(&[1, 0, 1, 0]);
+    /// wav.extend_from_slice(&8_000u32.to_le_bytes());
+    /// wav.extend_from_slice(&8_000u32.to_le_bytes());
+    /// wav.extend_from_slice(&[1, 0, 8, 0]);
+    /// wav.extend_from_slice(b"data");
+    /// wav.extend_from_slice(&16_000u32.to_le_bytes());
+    /// wav.extend(std::iter::repeat_n(0x80u8, 16_000));
+    /// let audio = BinaryArtifact::new(wav, "audio/wav", "wav", "示例");
+    /// assert_eq!((audio.size_bytes, audio.duration_seconds), (16_044, Some(2.0)));
+    /// assert_eq!(audio.sha256.len(), 64);
+    /// ```
     pub fn new(
         data: Vec<u8>,
         media_type: impl Into<String>,
         file_extension: impl Into<String>,
         summary: impl Into<String>,
     ) -> Self {
-        Self {
-            data,
-            media_type: media_type.into(),
-            file_extension: file_extension.into(),
-            summary: summary.into(),
-            metadata: Map::new(),
-        }
+        Self::with_metadata(data, media_type, file_extension, summary, Map::new())
     }
 
     pub fn with_metadata(
@@ -42,12 +77,19 @@ impl BinaryArtifact {
         summary: impl Into<String>,
         metadata: Map<String, Value>,
     ) -> Self {
+        let file_extension = file_extension.into();
+        let sha256 = hex::encode(Sha256::digest(&data));
+        let duration_seconds =
+            media_duration(&data, &file_extension).map(|duration| duration.as_secs_f64());
         Self {
+            size_bytes: data.len() as u64,
             data,
             media_type: media_type.into(),
-            file_extension: file_extension.into(),
+            file_extension,
             summary: summary.into(),
             metadata,
+            sha256,
+            duration_seconds,
         }
     }
 }
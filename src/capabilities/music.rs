@@ -1,26 +1,429 @@
-use crate::{capabilities::BinaryArtifact, errors::Result};
+use crate::{
+    capabilities::BinaryArtifact,
+    config::MusicConfig,
+    errors::{AgentError, Result},
+    util::{fetch_bytes, send_with_retry},
+};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use reqwest::header::CONTENT_TYPE;
 use rig::providers::hyperbolic;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tracing::debug;
+
+/// mock 音频的采样率与时长：0.1 秒 8kHz 单声道静音
+const MOCK_SAMPLE_RATE: u32 = 8000;
+const MOCK_SAMPLES: u32 = 800;
+
+/// 音频扩展名与媒体类型的对应关系
+const AUDIO_TYPES: &[(&str, &str)] = &[
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+];
 
 pub struct MusicGenerator {
-    _client: hyperbolic::Client,
-    _model_name: String,
-    _voice: String,
+    backend: MusicBackend,
+}
+
+enum MusicBackend {
+    Hyperbolic {
+        _client: hyperbolic::Client,
+        _model_name: String,
+        _voice: String,
+    },
+    /// 自建音乐服务（`[music]`），接口约定与视频服务类似
+    Custom(CustomMusicService),
+    /// 离线调试用，返回一段很短的静音 WAV
+    Mock,
+}
+
+struct CustomMusicService {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: Option<String>,
+    format: String,
+    max_duration_seconds: Option<u32>,
+    /// `audio_url` 可以是 `file://` 或本地路径，见 `processing.allow_local_files`
+    allow_local_files: bool,
 }
 
 impl MusicGenerator {
     pub fn new(client: hyperbolic::Client, model_name: &str, voice: &str) -> Self {
         Self {
-            _client: client,
-            _model_name: model_name.to_string(),
-            _voice: voice.to_string(),
+            backend: MusicBackend::Hyperbolic {
+                _client: client,
+                _model_name: model_name.to_string(),
+                _voice: voice.to_string(),
+            },
+        }
+    }
+
+    /// 向自建服务 POST `{prompt, format, duration_seconds?}`，可选 Bearer 鉴权。
+    /// 响应可以直接是音频字节（按 `Content-Type` 判断格式，缺失时按文件头识别），
+    /// 也可以是带 `audio_base64` 或 `audio_url` 的 JSON；`format_override` 为路由上的 `model`
+    pub fn custom(config: &MusicConfig, format_override: Option<&str>) -> Self {
+        Self {
+            backend: MusicBackend::Custom(CustomMusicService {
+                client: reqwest::Client::new(),
+                endpoint: config.endpoint.clone(),
+                api_key: config.api_key.clone(),
+                format: format_override
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|| config.format.clone()),
+                max_duration_seconds: config.max_duration_seconds,
+                allow_local_files: false,
+            }),
+        }
+    }
+
+    pub fn mock() -> Self {
+        Self {
+            backend: MusicBackend::Mock,
+        }
+    }
+
+    /// 允许自建服务返回的 `audio_url` 指向本地文件，仅用于测试与离线演示
+    pub fn with_local_files(mut self, allow: bool) -> Self {
+        if let MusicBackend::Custom(service) = &mut self.backend {
+            service.allow_local_files = allow;
+        }
+        self
+    }
+
+    pub async fn compose(&self, prompt: &str) -> Result<BinaryArtifact> {
+        self.compose_song(prompt, None).await
+    }
+
+    /// 按歌词谱曲，`prompt` 只作为曲风提示；自建服务的请求体会多一个 `lyrics` 字段，
+    /// 歌词同时写入产物元数据
+    pub async fn compose_with_lyrics(&self, prompt: &str, lyrics: &str) -> Result<BinaryArtifact> {
+        let mut artifact = self.compose_song(prompt, Some(lyrics)).await?;
+        artifact
+            .metadata
+            .insert("lyrics".to_string(), Value::String(lyrics.to_string()));
+        Ok(artifact)
+    }
+
+    async fn compose_song(&self, prompt: &str, lyrics: Option<&str>) -> Result<BinaryArtifact> {
+        match &self.backend {
+            MusicBackend::Hyperbolic { .. } => {
+                // TODO: 修复 rig 0.22 的 AudioGeneration API
+                // 当前版本的 API 结构与之前不同，需要查阅最新文档
+                Err(crate::errors::AgentError::unsupported(
+                    "音乐生成功能暂时不可用，等待 rig-core 0.22 API 更新"
+                ))
+            }
+            MusicBackend::Custom(service) => {
+                let mut artifact = service.request_music(prompt, lyrics).await?;
+                artifact
+                    .metadata
+                    .insert("prompt".to_string(), Value::String(prompt.to_string()));
+                artifact
+                    .metadata
+                    .insert("format".to_string(), json!(artifact.file_extension));
+                if let Some(max_duration) = service.max_duration_seconds {
+                    artifact
+                        .metadata
+                        .insert("max_duration_seconds".to_string(), json!(max_duration));
+                }
+                Ok(artifact)
+            }
+            MusicBackend::Mock => Ok(BinaryArtifact::new(
+                silent_wav(),
+                "audio/wav",
+                "wav",
+                format!("Model: mock | Prompt: {prompt}"),
+            )),
+        }
+    }
+}
+
+impl CustomMusicService {
+    async fn request_music(&self, prompt: &str, lyrics: Option<&str>) -> Result<BinaryArtifact> {
+        let mut request_body = Map::new();
+        request_body.insert("prompt".to_string(), json!(prompt));
+        if let Some(lyrics) = lyrics {
+            request_body.insert("lyrics".to_string(), json!(lyrics));
+        }
+        request_body.insert("format".to_string(), json!(self.format));
+        if let Some(max_duration) = self.max_duration_seconds {
+            request_body.insert("duration_seconds".to_string(), json!(max_duration));
+        }
+
+        let mut builder = self.client.post(&self.endpoint).json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response = send_with_retry("music", builder).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AgentError::other(format!(
+                "音乐生成服务返回状态码 {status}: {body}"
+            )));
         }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase()
+            });
+
+        if content_type.as_deref() == Some("application/json") {
+            let body = response.json::<MusicGenerationResponse>().await?;
+            return self.decode_payload(body).await;
+        }
+
+        let data = response.bytes().await?.to_vec();
+        let (media_type, extension) = self.audio_type(content_type.as_deref(), None, &data);
+        Ok(BinaryArtifact::new(
+            data,
+            media_type,
+            extension,
+            "External music service",
+        ))
     }
 
-    pub async fn compose(&self, _prompt: &str) -> Result<BinaryArtifact> {
-        // TODO: 修复 rig 0.22 的 AudioGeneration API
-        // 当前版本的 API 结构与之前不同，需要查阅最新文档
-        Err(crate::errors::AgentError::unsupported(
-            "音乐生成功能暂时不可用，等待 rig-core 0.22 API 更新"
-        ).into())
+    async fn decode_payload(&self, payload: MusicGenerationResponse) -> Result<BinaryArtifact> {
+        let data = if let Some(b64) = &payload.audio_base64 {
+            BASE64_STANDARD
+                .decode(b64)
+                .map_err(|err| AgentError::other(format!("音频 Base64 解码失败: {err}")))?
+        } else if let Some(url) = &payload.audio_url {
+            debug!(target: "music_generator", "fetching audio from {url}");
+            fetch_bytes("music", &self.client, url, self.allow_local_files).await?
+        } else {
+            return Err(AgentError::unsupported(
+                "音乐服务返回结果缺少 audio_base64 或 audio_url 字段",
+            ));
+        };
+
+        let (media_type, extension) = self.audio_type(
+            payload.content_type.as_deref(),
+            payload.ext.as_deref(),
+            &data,
+        );
+        let mut artifact = BinaryArtifact::new(
+            data,
+            media_type,
+            extension,
+            payload
+                .summary
+                .unwrap_or_else(|| "External music service".to_string()),
+        );
+        if let Some(duration) = payload.duration_seconds {
+            artifact
+                .metadata
+                .insert("duration_seconds".to_string(), json!(duration));
+        }
+        Ok(artifact)
     }
-}
\ No newline at end of file
+
+    /// 依次按服务声明的类型、扩展名、文件头识别格式，都无法判断时按请求的 `format`
+    fn audio_type(
+        &self,
+        content_type: Option<&str>,
+        ext: Option<&str>,
+        data: &[u8],
+    ) -> (String, String) {
+        let declared = content_type
+            .filter(|media_type| media_type.starts_with("audio/"))
+            .map(|media_type| match media_type {
+                "audio/mp3" | "audio/mpeg3" => "audio/mpeg",
+                "audio/x-wav" | "audio/wave" => "audio/wav",
+                "audio/x-flac" => "audio/flac",
+                "audio/x-m4a" => "audio/mp4",
+                other => other,
+            });
+        let by_media_type = |media_type: &str| {
+            AUDIO_TYPES
+                .iter()
+                .find(|(_, known)| *known == media_type)
+                .map(|(extension, _)| *extension)
+        };
+        let by_extension = |extension: &str| {
+            AUDIO_TYPES
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+                .map(|(_, media_type)| *media_type)
+        };
+
+        if let Some(media_type) = declared {
+            let extension = ext
+                .map(str::to_string)
+                .or_else(|| by_media_type(media_type).map(str::to_string))
+                .unwrap_or_else(|| self.format.clone());
+            return (media_type.to_string(), extension);
+        }
+        if let Some(ext) = ext
+            && let Some(media_type) = by_extension(ext)
+        {
+            return (media_type.to_string(), ext.to_lowercase());
+        }
+        if let Some((extension, media_type)) = sniff_audio(data) {
+            return (media_type.to_string(), extension.to_string());
+        }
+        let media_type = by_extension(&self.format).unwrap_or("application/octet-stream");
+        (media_type.to_string(), self.format.clone())
+    }
+}
+
+/// 按文件头识别常见音频格式，返回扩展名与媒体类型
+pub(super) fn sniff_audio(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    let extension = match data {
+        [b'I', b'D', b'3', ..] => "mp3",
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 && second & 0x06 != 0 => "mp3",
+        [0xFF, second, ..] if second & 0xF6 == 0xF0 => "aac",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'A',
+            b'V',
+            b'E',
+            ..,
+        ] => "wav",
+        [b'O', b'g', b'g', b'S', ..] => "ogg",
+        [b'f', b'L', b'a', b'C', ..] => "flac",
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => "m4a",
+        _ => return None,
+    };
+    AUDIO_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .copied()
+}
+
+/// 音乐服务 JSON 响应中读取的字段：结果为 `audio_base64` 或 `audio_url`，
+/// 可选 `content_type` / `ext` / `summary` / `duration_seconds`
+#[derive(Deserialize, Debug)]
+struct MusicGenerationResponse {
+    #[serde(default)]
+    audio_base64: Option<String>,
+    #[serde(default)]
+    audio_url: Option<String>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    ext: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    duration_seconds: Option<f64>,
+}
+
+/// 8 位 PCM 的静音取值为 128
+pub(super) fn silent_wav() -> Vec<u8> {
+    let mut wav = Vec::with_capacity(44 + MOCK_SAMPLES as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + MOCK_SAMPLES).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // 单声道
+    wav.extend_from_slice(&MOCK_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&MOCK_SAMPLE_RATE.to_le_bytes()); // 字节率
+    wav.extend_from_slice(&1u16.to_le_bytes()); // 块对齐
+    wav.extend_from_slice(&8u16.to_le_bytes()); // 位深
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&MOCK_SAMPLES.to_le_bytes());
+    wav.resize(44 + MOCK_SAMPLES as usize, 128);
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{Json, Router, routing::post};
+
+    use super::*;
+    use crate::util::test_support::spawn_stub;
+
+    /// `/raw` 返回没有 Content-Type 的 WAV，`/mp3` 返回 MP3，`/json` 返回 Base64 并把请求体放进 summary
+    async fn serve() -> SocketAddr {
+        let app = Router::new()
+            .route("/raw", post(|| async { b"RIFF\0\0\0\0WAVEfmt ".to_vec() }))
+            .route(
+                "/mp3",
+                post(|| async { ([(CONTENT_TYPE, "audio/mpeg")], b"ID3".to_vec()) }),
+            )
+            .route(
+                "/json",
+                post(|Json(body): Json<Value>| async move {
+                    Json(json!({ "audio_base64": "T2dnUw==", "summary": body.to_string() }))
+                }),
+            );
+        spawn_stub(app).await
+    }
+
+    fn custom(addr: SocketAddr, path: &str) -> MusicGenerator {
+        let config = MusicConfig {
+            endpoint: format!("http://{addr}{path}"),
+            api_key: Some("secret".into()),
+            format: "mp3".into(),
+            max_duration_seconds: Some(30),
+        };
+        MusicGenerator::custom(&config, None)
+    }
+
+    #[tokio::test]
+    async fn custom_service_response_formats() {
+        let addr = serve().await;
+        let raw = custom(addr, "/raw").compose("夏夜的城市").await.unwrap();
+        assert_eq!(
+            (raw.media_type.as_str(), raw.file_extension.as_str()),
+            ("audio/wav", "wav")
+        );
+        assert_eq!(raw.metadata["prompt"], "夏夜的城市");
+
+        let mp3 = custom(addr, "/mp3").compose("夏夜的城市").await.unwrap();
+        assert_eq!(
+            (mp3.media_type.as_str(), mp3.file_extension.as_str()),
+            ("audio/mpeg", "mp3")
+        );
+
+        let json = custom(addr, "/json").compose("夏夜的城市").await.unwrap();
+        assert_eq!(json.data, b"OggS");
+        assert_eq!(
+            (json.media_type.as_str(), json.file_extension.as_str()),
+            ("audio/ogg", "ogg")
+        );
+        let sent: Value = serde_json::from_str(&json.summary).unwrap();
+        assert_eq!(
+            sent,
+            json!({ "prompt": "夏夜的城市", "format": "mp3", "duration_seconds": 30 })
+        );
+    }
+
+    #[tokio::test]
+    async fn lyrics_are_sent_and_recorded() {
+        let addr = serve().await;
+        let song = custom(addr, "/json")
+            .compose_with_lyrics("轻快的城市流行", "[副歌]\n晚风吹过天台")
+            .await
+            .unwrap();
+        let sent: Value = serde_json::from_str(&song.summary).unwrap();
+        assert_eq!(sent["lyrics"], "[副歌]\n晚风吹过天台");
+        assert_eq!(song.metadata["lyrics"], "[副歌]\n晚风吹过天台");
+    }
+}
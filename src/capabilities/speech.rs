@@ -0,0 +1,423 @@
+use std::time::Duration;
+
+use crate::{
+    capabilities::{
+        BinaryArtifact, audio_duration, estimate_speech_duration, format_srt,
+        music::{silent_wav, sniff_audio},
+        split_captions,
+    },
+    config::{DEFAULT_OPENAI_BASE_URL, IndexTtsConfig, OpenAiConfig},
+    errors::{AgentError, Result},
+    util::{send_with_retry, truncate_chars},
+};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{Map, Value, json};
+
+/// 错误响应体写入错误信息时保留的最大字符数
+const ERROR_BODY_MAX_CHARS: usize = 300;
+
+/// 文本转语音，目前用于直播弹幕回复的自动朗读
+pub struct SpeechSynthesizer {
+    backend: SpeechBackend,
+    model_name: String,
+    /// `providers.speech.voices`：允许按请求切换的音色
+    voices: Vec<String>,
+    /// `providers.speech.subtitles`：每行字幕的最大字数，未开启时为 `None`
+    subtitle_max_chars: Option<usize>,
+}
+
+enum SpeechBackend {
+    /// OpenAI 兼容的 `/audio/speech` 接口，返回 MP3
+    OpenAi {
+        http: reqwest::Client,
+        endpoint: String,
+        api_key: String,
+        voice: String,
+    },
+    /// 自建 IndexTTS 服务的 `/audio/speech` 接口，按响应的 `Content-Type` 保存为 WAV 或 MP3
+    IndexTts {
+        http: reqwest::Client,
+        endpoint: String,
+        voice: String,
+    },
+    /// 离线调试用，返回一段很短的静音 WAV
+    Mock,
+}
+
+/// 拆出文本末尾的 `--voice 音色`（也可写作 `--voice: 音色`）子句，返回（文本, 音色）；
+/// 音色须是子句中唯一的一个词，否则视为普通文本
+#[cfg(feature = "live")]
+pub(crate) fn split_voice_clause(text: &str) -> (&str, Option<&str>) {
+    let clause = text
+        .rmatch_indices("--voice")
+        .next()
+        .and_then(|(start, key)| {
+            let rest = text[start + key.len()..].trim_start();
+            let rest = rest
+                .strip_prefix([':', '：'])
+                .map_or(rest, str::trim_start)
+                .trim_end();
+            let body = text[..start].trim_end();
+            (!body.is_empty() && !rest.is_empty() && !rest.contains(char::is_whitespace))
+                .then_some((body, rest))
+        });
+    match clause {
+        Some((body, voice)) => (body, Some(voice)),
+        None => (text, None),
+    }
+}
+
+impl SpeechSynthesizer {
+    pub fn new(config: &OpenAiConfig, model_name: &str) -> Result<Self> {
+        let base_url = config
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_OPENAI_BASE_URL);
+        Ok(Self {
+            backend: SpeechBackend::OpenAi {
+                http: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(60))
+                    .build()?,
+                endpoint: format!("{}/audio/speech", base_url.trim_end_matches('/')),
+                api_key: config.api_key.clone(),
+                voice: config.tts_voice.clone(),
+            },
+            model_name: model_name.to_string(),
+            voices: Vec::new(),
+            subtitle_max_chars: None,
+        })
+    }
+
+    /// 自建 IndexTTS 服务：POST `{model, input, voice}` 到 `{url}/audio/speech`，
+    /// 响应为音频字节；服务返回错误时，错误信息中带上响应体（JSON 的 `detail` / `error` 优先）
+    pub fn indextts(config: &IndexTtsConfig, model_name: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            backend: SpeechBackend::IndexTts {
+                http: reqwest::Client::builder().timeout(config.timeout).build()?,
+                endpoint: format!("{}/audio/speech", config.url),
+                voice: config.voice.clone(),
+            },
+            model_name: model_name.unwrap_or(&config.model).to_string(),
+            voices: Vec::new(),
+            subtitle_max_chars: None,
+        })
+    }
+
+    pub fn mock() -> Self {
+        Self {
+            backend: SpeechBackend::Mock,
+            model_name: "mock".to_string(),
+            voices: Vec::new(),
+            subtitle_max_chars: None,
+        }
+    }
+
+    /// 允许请求通过 `--voice` 切换的音色
+    pub fn with_voices(mut self, voices: Vec<String>) -> Self {
+        self.voices = voices;
+        self
+    }
+
+    /// 开启后每次合成都附带按音频时长分配的 SRT 字幕
+    pub fn with_subtitles(mut self, max_chars: Option<usize>) -> Self {
+        self.subtitle_max_chars = max_chars;
+        self
+    }
+
+    pub fn voice_allowed(&self, voice: &str) -> bool {
+        self.voices.iter().any(|allowed| allowed == voice)
+    }
+
+    /// 合成语音；`voice` 为请求指定的音色，不在 `voices` 中时使用默认音色。
+    /// 开启字幕时元数据中带上 `srt`（由 `ArtifactWriter` 另存为同名 `.srt` 文件）、`subtitle_cues` 与 `duration_seconds`
+    pub async fn synthesize(&self, text: &str, voice: Option<&str>) -> Result<BinaryArtifact> {
+        let voice = voice.filter(|voice| self.voice_allowed(voice));
+        let mut metadata = Map::new();
+        metadata.insert("text".to_string(), Value::String(text.to_string()));
+        metadata.insert("model".to_string(), Value::String(self.model_name.clone()));
+
+        let mut artifact = match &self.backend {
+            SpeechBackend::OpenAi {
+                http,
+                endpoint,
+                api_key,
+                voice: default_voice,
+            } => {
+                let voice = voice.unwrap_or(default_voice);
+                let request = http.post(endpoint).bearer_auth(api_key).json(&json!({
+                    "model": self.model_name,
+                    "input": text,
+                    "voice": voice,
+                    "response_format": "mp3",
+                }));
+                let response = send_with_retry("openai", request).await?;
+
+                let status = response.status();
+                if !status.is_success() {
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(AgentError::other(format!(
+                        "{} 语音合成失败 ({status}): {body}",
+                        self.model_name
+                    )));
+                }
+
+                let audio = response.bytes().await?.to_vec();
+                metadata.insert("voice".to_string(), Value::String(voice.to_string()));
+                BinaryArtifact::with_metadata(
+                    audio,
+                    "audio/mpeg",
+                    "mp3",
+                    format!("Model: {} | Voice: {voice}", self.model_name),
+                    metadata,
+                )
+            }
+            SpeechBackend::IndexTts {
+                http,
+                endpoint,
+                voice: default_voice,
+            } => {
+                let voice = voice.unwrap_or(default_voice);
+                let request = http.post(endpoint).json(&json!({
+                    "model": self.model_name,
+                    "input": text,
+                    "voice": voice,
+                }));
+                let response = send_with_retry("indextts", request).await?;
+
+                let status = response.status();
+                if !status.is_success() {
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(AgentError::other(format!(
+                        "IndexTTS 语音合成失败 ({status}): {}",
+                        error_detail(&body)
+                    )));
+                }
+
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| {
+                        value
+                            .split(';')
+                            .next()
+                            .unwrap_or_default()
+                            .trim()
+                            .to_lowercase()
+                    });
+                let audio = response.bytes().await?.to_vec();
+                let (media_type, extension) = match content_type.as_deref() {
+                    Some("audio/wav" | "audio/x-wav" | "audio/wave") => ("audio/wav", "wav"),
+                    Some("audio/mpeg" | "audio/mp3") => ("audio/mpeg", "mp3"),
+                    declared => match sniff_audio(&audio) {
+                        Some((extension, media_type)) => (media_type, extension),
+                        None => {
+                            return Err(AgentError::other(format!(
+                                "IndexTTS 返回的内容无法识别为音频 (Content-Type: {})",
+                                declared.unwrap_or("未提供")
+                            )));
+                        }
+                    },
+                };
+                metadata.insert("voice".to_string(), Value::String(voice.to_string()));
+                BinaryArtifact::with_metadata(
+                    audio,
+                    media_type,
+                    extension,
+                    format!("Model: {} | Voice: {voice}", self.model_name),
+                    metadata,
+                )
+            }
+            SpeechBackend::Mock => {
+                if let Some(voice) = voice {
+                    metadata.insert("voice".to_string(), Value::String(voice.to_string()));
+                }
+                BinaryArtifact::with_metadata(
+                    silent_wav(),
+                    "audio/wav",
+                    "wav",
+                    "Model: mock",
+                    metadata,
+                )
+            }
+        };
+        self.attach_subtitles(&mut artifact, text);
+        Ok(artifact)
+    }
+
+    /// 按音频时长（文件头读不出时按字数估算）给字幕行分配时间
+    fn attach_subtitles(&self, artifact: &mut BinaryArtifact, text: &str) {
+        let Some(max_chars) = self.subtitle_max_chars else {
+            return;
+        };
+        let captions = split_captions(text, max_chars);
+        if captions.is_empty() {
+            return;
+        }
+        let (duration, estimated) =
+            match audio_duration(&artifact.data).filter(|duration| !duration.is_zero()) {
+                Some(duration) => (duration, false),
+                None => (estimate_speech_duration(text), true),
+            };
+        let metadata = &mut artifact.metadata;
+        metadata.insert(
+            "duration_seconds".to_string(),
+            json!((duration.as_secs_f64() * 1000.0).round() / 1000.0),
+        );
+        if estimated {
+            metadata.insert("duration_estimated".to_string(), json!(true));
+        }
+        metadata.insert("subtitle_cues".to_string(), json!(captions.len()));
+        metadata.insert("srt".to_string(), json!(format_srt(&captions, duration)));
+    }
+}
+
+/// 错误响应体中的说明：JSON 的 `detail` / `error` / `error.message` / `message` 优先，否则为截断后的原文
+fn error_detail(body: &str) -> String {
+    let detail = serde_json::from_str::<Value>(body).ok().and_then(|value| {
+        ["/detail", "/error/message", "/error", "/message"]
+            .iter()
+            .find_map(|pointer| match value.pointer(pointer)? {
+                Value::String(text) => Some(text.clone()),
+                Value::Null | Value::Object(_) => None,
+                other => Some(other.to_string()),
+            })
+    });
+    detail.unwrap_or_else(|| truncate_chars(body.trim(), ERROR_BODY_MAX_CHARS))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{Json, Router, http::StatusCode, routing::post};
+
+    use super::*;
+    use crate::util::test_support::spawn_stub;
+
+    type Requests = Arc<Mutex<Vec<Value>>>;
+
+    /// `/wav` 返回 WAV 并记录请求体，`/mp3` 返回 MP3，`/fail` 返回带 `detail` 的 400
+    async fn serve_indextts() -> (SocketAddr, Requests) {
+        let requests = Requests::default();
+        let recorded = requests.clone();
+        let app = Router::new()
+            .route(
+                "/wav/audio/speech",
+                post(move |Json(body): Json<Value>| async move {
+                    recorded.lock().unwrap().push(body);
+                    (
+                        [(CONTENT_TYPE, "audio/x-wav")],
+                        b"RIFF\0\0\0\0WAVEfmt ".to_vec(),
+                    )
+                }),
+            )
+            .route(
+                "/mp3/audio/speech",
+                post(|| async { ([(CONTENT_TYPE, "audio/mpeg")], b"ID3".to_vec()) }),
+            )
+            .route(
+                "/fail/audio/speech",
+                post(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "detail": "speaker narrator not found" })),
+                    )
+                }),
+            );
+        (spawn_stub(app).await, requests)
+    }
+
+    fn indextts(addr: SocketAddr, path: &str) -> SpeechSynthesizer {
+        let config = IndexTtsConfig {
+            url: format!("http://{addr}/{path}"),
+            model: "indextts-2".into(),
+            voice: "alice".into(),
+            timeout: Duration::from_secs(5),
+        };
+        SpeechSynthesizer::indextts(&config, None)
+            .unwrap()
+            .with_voices(vec!["narrator".into()])
+    }
+
+    #[tokio::test]
+    async fn indextts_voices_and_formats() {
+        let (addr, requests) = serve_indextts().await;
+        let wav = indextts(addr, "wav")
+            .synthesize("晚上好", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            (wav.media_type.as_str(), wav.file_extension.as_str()),
+            ("audio/wav", "wav")
+        );
+        assert_eq!(wav.metadata["text"], "晚上好");
+        assert_eq!(wav.metadata["voice"], "alice");
+
+        // 按请求切换到 voices 中的音色；不在其中的音色按默认音色合成
+        indextts(addr, "wav")
+            .synthesize("晚上好", Some("narrator"))
+            .await
+            .unwrap();
+        let fallback = indextts(addr, "wav")
+            .synthesize("晚上好", Some("bob"))
+            .await
+            .unwrap();
+        assert_eq!(fallback.metadata["voice"], "alice");
+        let sent = requests.lock().unwrap().clone();
+        assert_eq!(
+            sent[0],
+            json!({ "model": "indextts-2", "input": "晚上好", "voice": "alice" })
+        );
+        assert_eq!(sent[1]["voice"], "narrator");
+        assert_eq!(sent[2]["voice"], "alice");
+
+        let mp3 = indextts(addr, "mp3")
+            .synthesize("晚上好", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            (mp3.media_type.as_str(), mp3.file_extension.as_str()),
+            ("audio/mpeg", "mp3")
+        );
+    }
+
+    #[tokio::test]
+    async fn indextts_error_includes_detail() {
+        let (addr, _) = serve_indextts().await;
+        let err = indextts(addr, "fail")
+            .synthesize("晚上好", None)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("speaker narrator not found"),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "live")]
+    #[test]
+    fn voice_clause_is_split_off() {
+        assert_eq!(
+            split_voice_clause("讲个笑话 --voice 温柔女声"),
+            ("讲个笑话", Some("温柔女声"))
+        );
+        assert_eq!(
+            split_voice_clause("讲个笑话 --voice：narrator"),
+            ("讲个笑话", Some("narrator"))
+        );
+        // 没有正文或音色不止一个词时视为普通文本
+        assert_eq!(
+            split_voice_clause("--voice 参数怎么用"),
+            ("--voice 参数怎么用", None)
+        );
+        assert_eq!(
+            split_voice_clause("讲个笑话 --voice 温柔 女声"),
+            ("讲个笑话 --voice 温柔 女声", None)
+        );
+        assert_eq!(split_voice_clause("讲个笑话"), ("讲个笑话", None));
+    }
+}
@@ -0,0 +1,351 @@
+use std::{fmt::Write, time::Duration};
+
+/// 句末标点，字幕优先在这里断开
+const SENTENCE_ENDS: &[char] = &['。', '！', '？', '!', '?', '；', ';', '…', '\n'];
+/// 句中停顿，句子超过每行字数时在这里断开
+const CLAUSE_BREAKS: &[char] = &['，', '、', '：', ',', ':'];
+/// 行末去掉的标点，中文字幕习惯不保留逗号与句号
+const TRAILING_PUNCTUATION: &[char] = &['，', '。', '、', '；', ',', ';'];
+
+/// 无法从文件头读出时长时的估算语速：每个汉字（或约三个英文字母）0.25 秒
+const ESTIMATED_SECONDS_PER_UNIT: f64 = 0.25;
+const ASCII_UNIT: f64 = 0.3;
+
+/// 把朗读文本切成字幕行：先按句末标点分句，超过 `max_chars` 的句子再按逗号等停顿合并成不超过上限的片段，
+/// 仍然过长时硬切（尽量不切开英文单词）；行末的逗号、句号被去掉，空行被丢弃
+///
+/// ```
+/// use vutber_agent::capabilities::split_captions;
+///
+/// let text = "大家晚上好！今天我们来聊聊天气，明天会下雨，出门记得带伞。还有问题吗？";
+/// assert_eq!(
+///     split_captions(text, 10),
+///     ["大家晚上好！", "今天我们来聊聊天气", "明天会下雨", "出门记得带伞", "还有问题吗？"],
+/// );
+/// // 上限足够时整句保留，逗号留在句中
+/// assert_eq!(split_captions("明天会下雨，出门记得带伞。", 16), ["明天会下雨，出门记得带伞"]);
+/// // 短分句合并到同一行，放不下时换行
+/// assert_eq!(split_captions("好的，没问题，马上来，请稍等片刻。", 8), ["好的，没问题", "马上来", "请稍等片刻"]);
+/// // 没有标点的长句按字数硬切
+/// assert_eq!(split_captions("一二三四五六七八九十一二", 5), ["一二三四五", "六七八九十", "一二"]);
+/// // 行尾标点不占字数，不会单独成行
+/// assert_eq!(split_captions("（mock）收到：你好呀", 8), ["（mock）收到：", "你好呀"]);
+/// assert_eq!(split_captions("一二三四五六！", 6), ["一二三四五六！"]);
+/// // 英文在空格处断开，不切开单词
+/// assert_eq!(
+///     split_captions("Thanks for watching the stream tonight", 16),
+///     ["Thanks for", "watching the", "stream tonight"],
+/// );
+/// // 换行也视为断句，空白与空行被丢弃
+/// assert_eq!(split_captions("  第一行\n\n第二行。 ", 10), ["第一行", "第二行"]);
+/// assert!(split_captions(" 。 ", 10).is_empty());
+/// ```
+pub fn split_captions(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut captions = Vec::new();
+    for sentence in text.split_inclusive(SENTENCE_ENDS) {
+        let sentence = sentence.trim();
+        if visible_len(sentence) <= max_chars {
+            push_caption(&mut captions, sentence);
+            continue;
+        }
+        let mut line = String::new();
+        for clause in sentence.split_inclusive(CLAUSE_BREAKS) {
+            let candidate = line.chars().count() + visible_len(clause);
+            if !line.is_empty() && candidate > max_chars {
+                push_caption(&mut captions, &line);
+                line.clear();
+            }
+            if visible_len(clause) > max_chars {
+                for piece in hard_wrap(clause.trim(), max_chars) {
+                    push_caption(&mut captions, piece);
+                }
+            } else {
+                line.push_str(if line.is_empty() {
+                    clause.trim_start()
+                } else {
+                    clause
+                });
+            }
+        }
+        push_caption(&mut captions, &line);
+    }
+    captions
+}
+
+/// 按字幕行的字数（不含空白）分配总时长，生成 SRT 文本；没有字幕行时返回空字符串
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use vutber_agent::capabilities::format_srt;
+///
+/// let captions = ["大家晚上好", "今天我们来聊聊天气和出行"];
+/// let srt = format_srt(&captions, Duration::from_millis(8500));
+/// assert_eq!(
+///     srt,
+///     "1\n00:00:00,000 --> 00:00:02,500\n大家晚上好\n\n\
+///      2\n00:00:02,500 --> 00:00:08,500\n今天我们来聊聊天气和出行\n",
+/// );
+///
+/// // 超过一小时的时间戳
+/// let srt = format_srt(&["很长的一段"], Duration::from_secs(3725));
+/// assert_eq!(srt, "1\n00:00:00,000 --> 01:02:05,000\n很长的一段\n");
+/// assert_eq!(format_srt::<&str>(&[], Duration::from_secs(1)), "");
+/// ```
+pub fn format_srt<S: AsRef<str>>(captions: &[S], total: Duration) -> String {
+    let weights: Vec<usize> = captions
+        .iter()
+        .map(|caption| {
+            caption
+                .as_ref()
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .count()
+                .max(1)
+        })
+        .collect();
+    let total_weight: usize = weights.iter().sum();
+    let total_ms = total.as_millis() as u64;
+    let offset = |weight: usize| total_ms * weight as u64 / total_weight.max(1) as u64;
+
+    let mut srt = String::new();
+    let mut elapsed = 0;
+    for (index, (caption, weight)) in captions.iter().zip(&weights).enumerate() {
+        let start = offset(elapsed);
+        elapsed += weight;
+        let end = offset(elapsed);
+        if index > 0 {
+            srt.push('\n');
+        }
+        let _ = writeln!(
+            srt,
+            "{}\n{} --> {}\n{}",
+            index + 1,
+            srt_timestamp(start),
+            srt_timestamp(end),
+            caption.as_ref()
+        );
+    }
+    srt
+}
+
+/// 从 WAV（`fmt ` 与 `data` 块）或 MP3（逐帧累加，跳过开头的 ID3v2 标签）文件头读出音频时长，无法识别时返回 `None`
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use vutber_agent::capabilities::audio_duration;
+///
+/// // 16kHz 16 位单声道 PCM，1.5 秒
+/// let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+/// wav.extend_from_slice(&16u32.to_le_bytes());
+/// wav.extend_from_slice(&[1, 0, 1, 0]);
+/// wav.extend_from_slice(&16_000u32.to_le_bytes());
+/// wav.extend_from_slice(&32_000u32.to_le_bytes());
+/// wav.extend_from_slice(&[2, 0, 16, 0]);
+/// wav.extend_from_slice(b"data");
+/// wav.extend_from_slice(&48_000u32.to_le_bytes());
+/// wav.extend(std::iter::repeat_n(0u8, 48_000));
+/// assert_eq!(audio_duration(&wav), Some(Duration::from_millis(1500)));
+///
+/// // MPEG-1 Layer III，128kbps / 44.1kHz，每帧 417 字节、1152 个采样，前面带一个 ID3v2 标签
+/// let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+/// frame.resize(417, 0);
+/// let mut mp3 = b"ID3\x04\0\0\0\0\0\x0A".to_vec();
+/// mp3.extend_from_slice(&[0; 10]);
+/// mp3.extend(frame.repeat(100));
+/// let duration = audio_duration(&mp3).unwrap();
+/// assert_eq!(duration.as_millis(), 2612);
+///
+/// assert_eq!(audio_duration(b"not audio"), None);
+/// ```
+pub fn audio_duration(data: &[u8]) -> Option<Duration> {
+    if data.starts_with(b"RIFF") {
+        wav_duration(data)
+    } else {
+        mp3_duration(data)
+    }
+}
+
+/// 按字数估算朗读时长，用于无法从文件头读出时长的音频，至少 1 秒
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use vutber_agent::capabilities::estimate_speech_duration;
+///
+/// assert_eq!(estimate_speech_duration("大家晚上好，欢迎来到直播间"), Duration::from_millis(3000));
+/// assert_eq!(estimate_speech_duration("好"), Duration::from_secs(1));
+/// ```
+pub fn estimate_speech_duration(text: &str) -> Duration {
+    let units: f64 = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                ASCII_UNIT
+            } else if c.is_alphanumeric() {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    Duration::from_secs_f64((units * ESTIMATED_SECONDS_PER_UNIT).max(1.0))
+}
+
+/// 行尾的标点不计入字数，避免为了一个标点单独成行
+fn visible_len(text: &str) -> usize {
+    text.trim()
+        .trim_end_matches(|c: char| !c.is_alphanumeric())
+        .chars()
+        .count()
+}
+
+fn push_caption(captions: &mut Vec<String>, line: &str) {
+    let line = line
+        .trim()
+        .trim_end_matches(TRAILING_PUNCTUATION)
+        .trim_end();
+    if !line.is_empty() {
+        captions.push(line.to_string());
+    }
+}
+
+/// 按字数硬切；片段中有空格时在最后一个空格处断开，避免切开英文单词
+fn hard_wrap(text: &str, max_chars: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while visible_len(rest) > max_chars {
+        let limit = rest
+            .char_indices()
+            .nth(max_chars)
+            .map_or(rest.len(), |(index, _)| index);
+        let cut = match rest[..limit].rfind(' ') {
+            _ if rest[limit..].starts_with(' ') => limit,
+            Some(space) if space > 0 => space,
+            _ => limit,
+        };
+        pieces.push(rest[..cut].trim_end());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+fn srt_timestamp(millis: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn wav_duration(data: &[u8]) -> Option<Duration> {
+    if data.get(8..12) != Some(b"WAVE") {
+        return None;
+    }
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let mut offset = 12;
+    let mut byte_rate = None;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = read_u32(offset + 4)? as usize;
+        let body = offset + 8;
+        match id {
+            b"fmt " => byte_rate = read_u32(body + 8).filter(|rate| *rate > 0),
+            b"data" => {
+                // 流式写出的 WAV 可能没有回填块大小，按实际剩余字节计算
+                let available = data.len() - body;
+                let size = if size == 0 || size > available {
+                    available
+                } else {
+                    size
+                };
+                return Duration::try_from_secs_f64(size as f64 / byte_rate? as f64).ok();
+            }
+            _ => {}
+        }
+        offset = body.checked_add(size)?.checked_add(size & 1)?;
+    }
+    None
+}
+
+fn mp3_duration(data: &[u8]) -> Option<Duration> {
+    let mut offset = 0;
+    if data.starts_with(b"ID3") && data.len() >= 10 {
+        let size = data[6..10]
+            .iter()
+            .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7F) as usize);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        offset = 10 + size + footer;
+    }
+
+    let mut seconds = 0.0;
+    let mut frames = 0u32;
+    while let Some(header) = data.get(offset..offset + 4) {
+        match mp3_frame(header) {
+            Some((length, samples, sample_rate)) => {
+                seconds += samples as f64 / sample_rate as f64;
+                frames += 1;
+                offset += length;
+            }
+            // 第一帧之前允许有杂项数据，之后遇到非帧数据（如 ID3v1 标签）即结束
+            None if frames == 0 => offset += 1,
+            None => break,
+        }
+    }
+    if frames == 0 {
+        return None;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// 解析 MPEG Layer III 帧头，返回（帧长度, 每帧采样数, 采样率）
+fn mp3_frame(header: &[u8]) -> Option<(usize, u32, u32)> {
+    const MPEG1_BITRATES: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const MPEG2_BITRATES: [u32; 15] =
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const SAMPLE_RATES: [u32; 3] = [44_100, 48_000, 32_000];
+
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 || (header[1] >> 1) & 0x03 != 0x01 {
+        return None;
+    }
+    // 版本：3 为 MPEG-1，2 为 MPEG-2，0 为 MPEG-2.5，1 保留
+    let version = (header[1] >> 3) & 0x03;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+    if version == 1 || bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+    let (bitrate, sample_rate, samples) = match version {
+        3 => (
+            MPEG1_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index],
+            1152,
+        ),
+        2 => (
+            MPEG2_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index] / 2,
+            576,
+        ),
+        _ => (
+            MPEG2_BITRATES[bitrate_index],
+            SAMPLE_RATES[sample_rate_index] / 4,
+            576,
+        ),
+    };
+    let length = (samples / 8 * bitrate * 1000 / sample_rate) as usize + padding;
+    Some((length, samples, sample_rate))
+}
@@ -1,13 +1,22 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use crate::{
     capabilities::BinaryArtifact,
-    config::VideoConfig,
+    config::{VideoConfig, VideoRequestFields},
     errors::{AgentError, Result},
+    util::{MAX_RETRY_AFTER, fetch_bytes, retry_after, send_with_retry},
 };
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
-use reqwest::header::CONTENT_TYPE;
-use serde::{Deserialize, Serialize};
-use tracing::debug;
+use reqwest::{RequestBuilder, Response, StatusCode, header::CONTENT_TYPE};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::{fs, process::Command};
+use tracing::{debug, warn};
+use uuid::Uuid;
 
 pub struct VideoGenerator {
     client: reqwest::Client,
@@ -15,6 +24,137 @@ pub struct VideoGenerator {
     api_key: Option<String>,
     format: String,
     max_duration_seconds: Option<u32>,
+    status_endpoint: Option<String>,
+    poll_interval: Duration,
+    max_wait: Duration,
+    request_fields: VideoRequestFields,
+    /// `video.extra`，合并进每次生成请求
+    extra: Map<String, Value>,
+    retry_max_attempts: u32,
+    retry_base_delay: Duration,
+    /// `video_url` 可以是 `file://` 或本地路径，见 `processing.allow_local_files`
+    allow_local_files: bool,
+}
+
+/// 视频生成的进度：异步任务每次查询状态后回调一次，请求失败准备重试时也回调一次
+#[derive(Debug, Clone)]
+pub struct VideoProgress {
+    /// 提交任务阶段的重试还没有任务 ID
+    pub job_id: Option<String>,
+    /// 第几次查询状态；`retry` 不为空时为该请求即将进行的第几次尝试
+    pub attempt: u32,
+    /// 重试时为 `retrying`
+    pub status: Option<String>,
+    pub elapsed: Duration,
+    /// 任务已结束（成功或失败），这是该任务的最后一次进度
+    pub finished: bool,
+    pub retry: Option<VideoRetry>,
+}
+
+/// 视频服务请求失败后的一次重试
+#[derive(Debug, Clone)]
+pub struct VideoRetry {
+    /// 重试的请求：`submit`（提交任务）、`poll`（查询状态）或 `download`（下载 `video_url`）
+    pub stage: &'static str,
+    pub max_attempts: u32,
+    /// 重试前的等待时间
+    pub wait: Duration,
+    /// 触发重试的状态码或连接错误
+    pub reason: String,
+}
+
+/// 拆出视频提示词末尾的 `--params {json}` 子句（也可写作 `--params: {json}`），返回（提示词, 子句原文），
+/// 没有子句时子句为空；子句的 JSON 在生成时校验
+pub(crate) fn split_params_clause(prompt: &str) -> (&str, &str) {
+    let clause = prompt.match_indices("--params").find(|(start, key)| {
+        let rest = prompt[start + key.len()..].trim_start();
+        let rest = rest.strip_prefix([':', '：']).map_or(rest, str::trim_start);
+        rest.starts_with('{')
+    });
+    match clause {
+        Some((start, _)) => (prompt[..start].trim_end(), &prompt[start..]),
+        None => (prompt, ""),
+    }
+}
+
+/// 用 ffmpeg 的 concat 分离器按顺序无损拼接同一格式的视频片段（`-c copy`），
+/// 产物格式沿用第一个片段；临时文件在结束后删除
+pub async fn concat_videos(
+    ffmpeg: &str,
+    segments: &[PathBuf],
+    media_type: &str,
+    extension: &str,
+) -> Result<BinaryArtifact> {
+    let work_dir = std::env::temp_dir().join(format!("vutber-concat-{}", Uuid::new_v4()));
+    fs::create_dir_all(&work_dir).await?;
+    let result = run_concat(ffmpeg, segments, &work_dir, extension).await;
+    let _ = fs::remove_dir_all(&work_dir).await;
+
+    let data = result?;
+    let mut artifact = BinaryArtifact::new(
+        data,
+        media_type,
+        extension,
+        format!("ffmpeg concat of {} segments", segments.len()),
+    );
+    artifact
+        .metadata
+        .insert("segments".to_string(), json!(segments.len()));
+    Ok(artifact)
+}
+
+async fn run_concat(
+    ffmpeg: &str,
+    segments: &[PathBuf],
+    work_dir: &std::path::Path,
+    extension: &str,
+) -> Result<Vec<u8>> {
+    // concat 列表中的路径用单引号包裹，路径自身的单引号按 ffmpeg 的规则转义
+    let list = segments
+        .iter()
+        .map(|path| {
+            let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+            format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+        })
+        .collect::<String>();
+    let list_path = work_dir.join("segments.txt");
+    fs::write(&list_path, list).await?;
+    let output_path = work_dir.join(format!("combined.{extension}"));
+
+    let output = Command::new(ffmpeg)
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            "concat",
+            "-safe",
+            "0",
+            "-i",
+        ])
+        .arg(&list_path)
+        .args(["-c", "copy"])
+        .arg(&output_path)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => {
+                AgentError::unsupported(format!("未找到 ffmpeg（{ffmpeg}），无法拼接视频片段"))
+            }
+            _ => AgentError::other(format!("启动 ffmpeg 失败: {err}")),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(AgentError::other(format!(
+            "ffmpeg 拼接视频片段失败 ({}): {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    debug!(target: "video_generator", segments = segments.len(), "ffmpeg 拼接完成");
+    Ok(fs::read(&output_path).await?)
 }
 
 impl VideoGenerator {
@@ -27,15 +167,107 @@ impl VideoGenerator {
                 .map(|value| value.to_string())
                 .unwrap_or_else(|| config.format.clone()),
             max_duration_seconds: config.max_duration_seconds,
+            status_endpoint: config.status_endpoint.clone(),
+            poll_interval: config.poll_interval,
+            max_wait: config.max_wait,
+            request_fields: config.request_fields.clone(),
+            extra: config.extra.clone(),
+            retry_max_attempts: config.retry_max_attempts.max(1),
+            retry_base_delay: config.retry_base_delay,
+            allow_local_files: false,
         })
     }
 
-    pub async fn generate(&self, prompt: &str) -> Result<BinaryArtifact> {
-        let request_body = VideoGenerationRequest {
-            prompt,
-            format: &self.format,
-            max_duration_seconds: self.max_duration_seconds,
+    /// 允许 `video_url` 指向本地文件，仅用于测试与离线演示
+    pub fn with_local_files(mut self, allow: bool) -> Self {
+        self.allow_local_files = allow;
+        self
+    }
+
+    /// 提交生成请求并等待结果；`on_progress` 在轮询异步任务与请求失败重试时回调。
+    /// 连接失败、5xx 与 429 按 `video.retry_max_attempts` 重试，提交、查询状态与下载 `video_url` 各自计数
+    pub async fn generate<F>(&self, prompt: &str, mut on_progress: F) -> Result<BinaryArtifact>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        let (prompt, clause) = split_params_clause(prompt);
+        let params = self.request_params(clause)?;
+        let started = Instant::now();
+        let mut artifact = self
+            .request_video(prompt, &params, started, &mut on_progress)
+            .await?;
+        artifact
+            .metadata
+            .insert("prompt".to_string(), Value::String(prompt.to_string()));
+        if !params.is_empty() {
+            artifact
+                .metadata
+                .insert("params".to_string(), Value::Object(params));
+        }
+        artifact
+            .metadata
+            .insert("format".to_string(), json!(artifact.file_extension));
+        if let Some(max_duration) = self.max_duration_seconds {
+            artifact
+                .metadata
+                .insert("max_duration_seconds".to_string(), json!(max_duration));
+        }
+        Ok(artifact)
+    }
+
+    /// 本次请求的附加参数：`video.extra` 与提示词中的 `--params {json}` 合并，同名时以后者为准
+    fn request_params(&self, clause: &str) -> Result<Map<String, Value>> {
+        let mut params = self.extra.clone();
+        if clause.is_empty() {
+            return Ok(params);
+        }
+        let json = clause["--params".len()..]
+            .trim_start()
+            .trim_start_matches([':', '：'])
+            .trim();
+        let inline = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(inline)) => inline,
+            Ok(other) => {
+                return Err(AgentError::rejected(
+                    "invalid_video_params",
+                    format!("--params 需要是 JSON 对象，收到的是 {other}"),
+                ));
+            }
+            Err(err) => {
+                return Err(AgentError::rejected(
+                    "invalid_video_params",
+                    format!("--params 不是有效的 JSON: {err}"),
+                ));
+            }
         };
+        if inline.contains_key(&self.request_fields.prompt) {
+            return Err(AgentError::rejected(
+                "invalid_video_params",
+                format!("--params 不能覆盖提示词字段 {}", self.request_fields.prompt),
+            ));
+        }
+        params.extend(inline);
+        Ok(params)
+    }
+
+    async fn request_video<F>(
+        &self,
+        prompt: &str,
+        params: &Map<String, Value>,
+        started: Instant,
+        on_progress: &mut F,
+    ) -> Result<BinaryArtifact>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        let fields = &self.request_fields;
+        let mut request_body = Map::new();
+        request_body.insert(fields.prompt.clone(), json!(prompt));
+        request_body.insert(fields.format.clone(), json!(self.format));
+        if let Some(max_duration) = self.max_duration_seconds {
+            request_body.insert(fields.max_duration_seconds.clone(), json!(max_duration));
+        }
+        request_body.extend(params.clone());
 
         let mut builder = self.client.post(&self.endpoint).json(&request_body);
 
@@ -43,11 +275,14 @@ impl VideoGenerator {
             builder = builder.bearer_auth(api_key);
         }
 
-        let response = builder.send().await?;
-        if !response.status().is_success() {
+        let response = self
+            .send("submit", None, builder, started, on_progress)
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
             return Err(AgentError::other(format!(
                 "视频生成服务返回状态码 {}",
-                response.status()
+                status
             )));
         }
 
@@ -58,9 +293,23 @@ impl VideoGenerator {
             .unwrap_or("application/octet-stream")
             .to_string();
 
-        if content_type.contains("application/json") {
+        if content_type.contains("application/json") || status == StatusCode::ACCEPTED {
             let body = response.json::<VideoGenerationResponse>().await?;
-            self.from_json(body).await
+            if body.is_failed() {
+                return Err(AgentError::other(format!(
+                    "视频生成任务 {} 执行失败: {}",
+                    body.job_id.as_deref().unwrap_or("-"),
+                    body.failure_reason()
+                )));
+            }
+            if body.is_pending() || status == StatusCode::ACCEPTED {
+                let job_id = body.job_id.clone().ok_or_else(|| {
+                    AgentError::other("视频服务返回异步任务状态，但缺少 job_id 字段")
+                })?;
+                let completed = self.poll_job(&job_id, started, on_progress).await?;
+                return self.resolve_payload(completed, started, on_progress).await;
+            }
+            self.resolve_payload(body, started, on_progress).await
         } else {
             let bytes = response.bytes().await?.to_vec();
             Ok(BinaryArtifact::new(
@@ -72,7 +321,129 @@ impl VideoGenerator {
         }
     }
 
-    async fn from_json(&self, payload: VideoGenerationResponse) -> Result<BinaryArtifact> {
+    async fn poll_job<F>(
+        &self,
+        job_id: &str,
+        generation_started: Instant,
+        on_progress: &mut F,
+    ) -> Result<VideoGenerationResponse>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        let Some(template) = self.status_endpoint.as_deref() else {
+            return Err(AgentError::other(format!(
+                "视频服务返回异步任务 {job_id}，但未配置 video.status_endpoint，无法查询结果"
+            )));
+        };
+        let status_url = template.replace("{job_id}", job_id);
+        let started = Instant::now();
+        // 轮询间隔与每次查询（含重试）都不越过 max_wait
+        let deadline = tokio::time::Instant::now() + self.max_wait;
+        let timed_out = || {
+            AgentError::other(format!(
+                "视频生成任务 {job_id} 等待超时 (超过 {} 秒)",
+                self.max_wait.as_secs()
+            ))
+        };
+        let mut attempt = 0;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(timed_out());
+            }
+
+            tokio::time::sleep(self.poll_interval.min(remaining)).await;
+            attempt += 1;
+
+            let mut builder = self.client.get(&status_url);
+            if let Some(api_key) = &self.api_key {
+                builder = builder.bearer_auth(api_key);
+            }
+
+            let poll = async {
+                let response = self
+                    .send(
+                        "poll",
+                        Some(job_id),
+                        builder,
+                        generation_started,
+                        on_progress,
+                    )
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(AgentError::other(format!(
+                        "视频生成任务 {job_id} 状态查询失败，状态码 {}",
+                        response.status()
+                    )));
+                }
+                Ok(response.json::<VideoGenerationResponse>().await?)
+            };
+            let body = tokio::time::timeout_at(deadline, poll)
+                .await
+                .map_err(|_| timed_out())??;
+            debug!(
+                target: "video_generator",
+                job_id,
+                attempt,
+                status = ?body.status,
+                "polled video job"
+            );
+            on_progress(&VideoProgress {
+                job_id: Some(job_id.to_string()),
+                attempt,
+                status: body.status.clone(),
+                elapsed: started.elapsed(),
+                finished: body.is_failed() || !body.is_pending(),
+                retry: None,
+            });
+
+            if body.is_failed() {
+                return Err(AgentError::other(format!(
+                    "视频生成任务 {job_id} 执行失败: {}",
+                    body.failure_reason()
+                )));
+            }
+
+            if body.has_video() {
+                return Ok(body);
+            }
+        }
+    }
+
+    async fn resolve_payload<F>(
+        &self,
+        payload: VideoGenerationResponse,
+        started: Instant,
+        on_progress: &mut F,
+    ) -> Result<BinaryArtifact>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        let mut artifact = self.decode_payload(&payload, started, on_progress).await?;
+        if let Some(duration) = payload.duration_seconds {
+            artifact
+                .metadata
+                .insert("duration_seconds".to_string(), json!(duration));
+        }
+        if let (Some(width), Some(height)) = (payload.width, payload.height) {
+            artifact.metadata.insert("width".to_string(), json!(width));
+            artifact
+                .metadata
+                .insert("height".to_string(), json!(height));
+        }
+        Ok(artifact)
+    }
+
+    async fn decode_payload<F>(
+        &self,
+        payload: &VideoGenerationResponse,
+        started: Instant,
+        on_progress: &mut F,
+    ) -> Result<BinaryArtifact>
+    where
+        F: FnMut(&VideoProgress),
+    {
         if let Some(b64) = &payload.video_base64 {
             let data = BASE64_STANDARD
                 .decode(b64)
@@ -87,7 +458,7 @@ impl VideoGenerator {
                 payload
                     .ext
                     .as_deref()
-                    .unwrap_or_else(|| self.format.as_str())
+                    .unwrap_or(self.format.as_str())
                     .to_string(),
                 payload
                     .summary
@@ -98,7 +469,9 @@ impl VideoGenerator {
 
         if let Some(url) = &payload.video_url {
             debug!(target: "video_generator", "fetching video from {url}");
-            let bytes = self.client.get(url).send().await?.bytes().await?.to_vec();
+            let bytes = self
+                .download(url, payload.job_id.as_deref(), started, on_progress)
+                .await?;
             return Ok(BinaryArtifact::new(
                 bytes,
                 payload
@@ -109,7 +482,7 @@ impl VideoGenerator {
                 payload
                     .ext
                     .as_deref()
-                    .unwrap_or_else(|| self.format.as_str())
+                    .unwrap_or(self.format.as_str())
                     .to_string(),
                 payload
                     .summary
@@ -122,16 +495,117 @@ impl VideoGenerator {
             "视频服务返回结果缺少 video_base64 或 video_url 字段",
         ))
     }
-}
 
-#[derive(Serialize)]
-struct VideoGenerationRequest<'a> {
-    prompt: &'a str,
-    format: &'a str,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_duration_seconds: Option<u32>,
+    /// 下载 `video_url`，HTTP 地址按 `send` 的规则重试，本地路径交给 `fetch_bytes`
+    async fn download<F>(
+        &self,
+        url: &str,
+        job_id: Option<&str>,
+        started: Instant,
+        on_progress: &mut F,
+    ) -> Result<Vec<u8>>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return fetch_bytes("video", &self.client, url, self.allow_local_files).await;
+        }
+        let response = self
+            .send(
+                "download",
+                job_id,
+                self.client.get(url),
+                started,
+                on_progress,
+            )
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AgentError::other(format!(
+                "下载 {url} 失败 ({status}): {body}"
+            )));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// 发送请求；连接失败、5xx 与 429 时按 `retry_max_attempts` 退避重试（优先使用服务端的 `Retry-After`），
+    /// 每次重试前回调一次进度。其它错误与状态码、以及重试用尽后的响应原样交给调用方处理
+    async fn send<F>(
+        &self,
+        stage: &'static str,
+        job_id: Option<&str>,
+        builder: RequestBuilder,
+        started: Instant,
+        on_progress: &mut F,
+    ) -> Result<Response>
+    where
+        F: FnMut(&VideoProgress),
+    {
+        let mut builder = builder;
+        let mut attempt = 1;
+        loop {
+            let next = if attempt < self.retry_max_attempts {
+                builder.try_clone()
+            } else {
+                None
+            };
+            let result = send_with_retry("video", builder).await;
+            let (reason, hint) = match &result {
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    (
+                        format!("状态码 {}", response.status()),
+                        retry_after(response.headers()),
+                    )
+                }
+                Err(err) if err.is_connect() => (format!("连接失败: {err}"), None),
+                _ => return Ok(result?),
+            };
+            let Some(next) = next else {
+                return Ok(result?);
+            };
+
+            let wait = hint
+                .unwrap_or_else(|| {
+                    self.retry_base_delay
+                        .saturating_mul(2u32.saturating_pow(attempt - 1))
+                })
+                .min(MAX_RETRY_AFTER);
+            attempt += 1;
+            warn!(
+                target: "video_generator",
+                stage,
+                attempt,
+                max_attempts = self.retry_max_attempts,
+                wait_ms = wait.as_millis() as u64,
+                %reason,
+                "视频服务请求失败，等待后重试"
+            );
+            on_progress(&VideoProgress {
+                job_id: job_id.map(str::to_string),
+                attempt,
+                status: Some("retrying".to_string()),
+                elapsed: started.elapsed(),
+                finished: false,
+                retry: Some(VideoRetry {
+                    stage,
+                    max_attempts: self.retry_max_attempts,
+                    wait,
+                    reason,
+                }),
+            });
+            tokio::time::sleep(wait).await;
+            builder = next;
+        }
+    }
 }
 
+/// 视频服务 JSON 响应中读取的字段，字段名固定，不受 `video.request_fields` 影响：
+/// 结果为 `video_base64` 或 `video_url`，可选 `content_type` / `ext` / `summary` / `duration_seconds` / `width` / `height`；
+/// 异步任务使用 `job_id`、`status` 与失败时的 `error`
 #[derive(Deserialize, Debug)]
 struct VideoGenerationResponse {
     #[serde(default)]
@@ -144,4 +618,308 @@ struct VideoGenerationResponse {
     ext: Option<String>,
     #[serde(default)]
     summary: Option<String>,
+    #[serde(default)]
+    duration_seconds: Option<f64>,
+    #[serde(default)]
+    width: Option<u32>,
+    #[serde(default)]
+    height: Option<u32>,
+    #[serde(default)]
+    job_id: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl VideoGenerationResponse {
+    fn has_video(&self) -> bool {
+        self.video_base64.is_some() || self.video_url.is_some()
+    }
+
+    fn is_pending(&self) -> bool {
+        if self.has_video() {
+            return false;
+        }
+        let status_pending = self.status.as_deref().is_some_and(|status| {
+            matches!(
+                status.to_lowercase().as_str(),
+                "pending" | "queued" | "running" | "processing"
+            )
+        });
+        status_pending || self.job_id.is_some()
+    }
+
+    fn failure_reason(&self) -> &str {
+        self.error
+            .as_deref()
+            .or(self.status.as_deref())
+            .unwrap_or("unknown")
+    }
+
+    fn is_failed(&self) -> bool {
+        self.status.as_deref().is_some_and(|status| {
+            matches!(
+                status.to_lowercase().as_str(),
+                "failed" | "error" | "cancelled" | "canceled"
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            Arc,
+            atomic::{AtomicU32, Ordering},
+        },
+    };
+
+    use axum::{
+        Json, Router,
+        http::{
+            StatusCode,
+            header::{CONTENT_TYPE, RETRY_AFTER},
+        },
+        response::IntoResponse,
+        routing::{get, post},
+    };
+
+    use super::*;
+    use crate::util::test_support::spawn_stub;
+
+    const MP4: &[u8] = b"\0\0\0\x18ftypmp42";
+
+    /// `/submit` 返回 202 与 job_id；`/pending` 一直未完成，`/stuck` 迟迟不响应
+    async fn generator(status_path: Option<&str>, poll_interval: Duration) -> VideoGenerator {
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|| async { (StatusCode::ACCEPTED, Json(json!({ "job_id": "job-42" }))) }),
+            )
+            .route(
+                "/pending/{job_id}",
+                get(|| async { Json(json!({ "job_id": "job-42", "status": "running" })) }),
+            )
+            .route(
+                "/stuck/{job_id}",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    Json(json!({ "status": "succeeded" }))
+                }),
+            );
+        let addr = spawn_stub(app).await;
+        let config = VideoConfig {
+            endpoint: format!("http://{addr}/submit"),
+            api_key: None,
+            format: "mp4".into(),
+            max_duration_seconds: None,
+            status_endpoint: status_path.map(|path| format!("http://{addr}{path}/{{job_id}}")),
+            poll_interval,
+            max_wait: Duration::from_millis(300),
+            request_fields: VideoRequestFields::default(),
+            extra: Map::new(),
+            retry_max_attempts: 1,
+            retry_base_delay: Duration::from_millis(10),
+        };
+        VideoGenerator::new(&config, None).unwrap()
+    }
+
+    #[test]
+    fn params_clause_is_split_off() {
+        let (prompt, clause) =
+            split_params_clause(r#"海边日落 --params {"fps": 24, "style": "anime"}"#);
+        assert_eq!(prompt, "海边日落");
+        assert_eq!(clause, r#"--params {"fps": 24, "style": "anime"}"#);
+        assert_eq!(
+            split_params_clause(r#"海边日落 --params：{"fps": 24}"#),
+            ("海边日落", r#"--params：{"fps": 24}"#)
+        );
+        // 后面不是 JSON 对象时视为普通文本
+        assert_eq!(
+            split_params_clause("讲讲 --parameters 的用法"),
+            ("讲讲 --parameters 的用法", "")
+        );
+        assert_eq!(split_params_clause("海边日落"), ("海边日落", ""));
+    }
+
+    #[tokio::test]
+    async fn async_job_without_status_endpoint_names_the_job() {
+        let err = generator(None, Duration::from_millis(10))
+            .await
+            .generate("海边日落", |_| {})
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("job-42"), "{err}");
+        assert!(err.contains("video.status_endpoint"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn polling_stops_at_max_wait() {
+        // 轮询间隔远大于 max_wait 时只等到截止时间
+        let started = Instant::now();
+        let err = generator(Some("/pending"), Duration::from_secs(30))
+            .await
+            .generate("海边日落", |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("等待超时"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // 状态查询本身挂起时同样按截止时间返回
+        let started = Instant::now();
+        let mut polls = 0;
+        let err = generator(Some("/stuck"), Duration::from_millis(10))
+            .await
+            .generate("海边日落", |_| polls += 1)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("job-42"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(polls, 0);
+    }
+
+    /// 前 `failures` 次返回 503（第一次带 `Retry-After: 0`），之后返回视频
+    fn flaky(failures: u32) -> impl Fn() -> std::future::Ready<axum::response::Response> + Clone {
+        let calls = Arc::new(AtomicU32::new(0));
+        move || {
+            let response = match calls.fetch_add(1, Ordering::SeqCst) {
+                0 if failures > 0 => {
+                    (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, "0")]).into_response()
+                }
+                n if n < failures => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                _ => ([(CONTENT_TYPE, "video/mp4")], MP4).into_response(),
+            };
+            std::future::ready(response)
+        }
+    }
+
+    /// 同步返回视频的各种后端：偶发 503、持续 502、400、返回 `video_url` 与回显请求体
+    async fn serve_sync() -> SocketAddr {
+        let app = Router::new()
+            .route("/flaky", post(flaky(2)))
+            .route("/down", post(|| async { StatusCode::BAD_GATEWAY }))
+            .route("/bad", post(|| async { StatusCode::BAD_REQUEST }))
+            // 返回 video_url，下载时再遇到一次 503
+            .route(
+                "/url",
+                post(|headers: axum::http::HeaderMap| async move {
+                    let host = headers["host"].to_str().unwrap().to_string();
+                    Json(json!({ "video_url": format!("http://{host}/file") }))
+                }),
+            )
+            .route("/file", get(flaky(1)))
+            // 请求体放进 summary 带回
+            .route(
+                "/echo",
+                post(|Json(body): Json<Value>| async move {
+                    Json(json!({ "video_base64": "AAAAGGZ0eXA=", "summary": body.to_string() }))
+                }),
+            );
+        spawn_stub(app).await
+    }
+
+    fn sync_generator(addr: SocketAddr, path: &str) -> VideoGenerator {
+        let config = VideoConfig {
+            endpoint: format!("http://{addr}{path}"),
+            api_key: None,
+            format: "mp4".into(),
+            max_duration_seconds: None,
+            status_endpoint: None,
+            poll_interval: Duration::from_secs(1),
+            max_wait: Duration::from_secs(10),
+            request_fields: VideoRequestFields::default(),
+            extra: serde_json::from_value(json!({ "fps": 24, "style": "anime" })).unwrap(),
+            retry_max_attempts: 3,
+            retry_base_delay: Duration::from_millis(10),
+        };
+        VideoGenerator::new(&config, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let addr = serve_sync().await;
+
+        // 两次 503 之后成功：每次重试回调一次进度，Retry-After 优先于指数退避
+        let mut retries = Vec::new();
+        let video = sync_generator(addr, "/flaky")
+            .generate("海边日落", |progress| {
+                retries.push(progress.retry.clone().unwrap())
+            })
+            .await
+            .unwrap();
+        assert_eq!(video.data, MP4);
+        assert_eq!(retries.len(), 2);
+        assert!(
+            retries
+                .iter()
+                .all(|retry| retry.stage == "submit" && retry.max_attempts == 3)
+        );
+        assert_eq!(retries[0].wait, Duration::ZERO);
+        assert_eq!(retries[1].wait, Duration::from_millis(20));
+
+        // 重试用尽后按最后一次响应报错
+        let mut attempts = 0;
+        let err = sync_generator(addr, "/down")
+            .generate("海边日落", |_| attempts += 1)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("502"), "{err}");
+        assert_eq!(attempts, 2);
+
+        // 4xx 不重试
+        let mut attempts = 0;
+        assert!(
+            sync_generator(addr, "/bad")
+                .generate("海边日落", |_| attempts += 1)
+                .await
+                .is_err()
+        );
+        assert_eq!(attempts, 0);
+
+        // 下载 video_url 同样重试
+        let mut stages = Vec::new();
+        let video = sync_generator(addr, "/url")
+            .generate("海边日落", |progress| {
+                stages.push(progress.retry.as_ref().unwrap().stage)
+            })
+            .await
+            .unwrap();
+        assert_eq!(video.data, MP4);
+        assert_eq!(stages, ["download"]);
+    }
+
+    #[tokio::test]
+    async fn params_clause_is_merged_into_request() {
+        let addr = serve_sync().await;
+
+        // video.extra 与提示词末尾的 --params 合并进请求体，同名时以后者为准，并记入元数据
+        let video = sync_generator(addr, "/echo")
+            .generate(
+                r#"海边日落 --params {"style": "watercolor", "seed": 7}"#,
+                |_| {},
+            )
+            .await
+            .unwrap();
+        let sent: Value = serde_json::from_str(&video.summary).unwrap();
+        assert_eq!(
+            sent,
+            json!({ "prompt": "海边日落", "format": "mp4", "fps": 24, "style": "watercolor", "seed": 7 })
+        );
+        assert_eq!(
+            video.metadata["params"],
+            json!({ "fps": 24, "style": "watercolor", "seed": 7 })
+        );
+        assert_eq!(video.metadata["prompt"], "海边日落");
+
+        // --params 不是合法 JSON 时拒绝
+        let err = sync_generator(addr, "/echo")
+            .generate(r#"海边日落 --params {"fps": }"#, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some("invalid_video_params"));
+    }
 }
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use crate::{config::ClarificationConfig, intent::Intent, util::TtlLruMap};
+
+/// 生成类请求常见的指令前缀，判断提示词是否过短前先去掉
+const IMAGE_PREFIXES: &[&str] = &[
+    "画一张",
+    "画一幅",
+    "画一个",
+    "画个",
+    "画",
+    "绘制",
+    "生成图片",
+];
+const MUSIC_PREFIXES: &[&str] = &["来一首", "唱一首", "写一首", "作一首", "作曲"];
+const VIDEO_PREFIXES: &[&str] = &["生成视频", "做个视频", "来段视频", "做一段视频"];
+
+/// 触发追问的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClarifyReason {
+    /// 去掉指令前缀后内容过少
+    ShortPrompt,
+    /// 路由模型给出的置信度低于 `clarification.min_confidence`
+    LowConfidence(f64),
+}
+
+impl ClarifyReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClarifyReason::ShortPrompt => "short_prompt",
+            ClarifyReason::LowConfidence(_) => "low_confidence",
+        }
+    }
+}
+
+/// 按会话记录等待补充说明的生成请求。条目只保存在内存中，超过 TTL 或容量上限后淘汰。
+pub struct Clarifications {
+    min_prompt_chars: usize,
+    min_confidence: Option<f64>,
+    pending: TtlLruMap<String, PendingClarification>,
+}
+
+struct PendingClarification {
+    intent: Intent,
+    prompt: String,
+}
+
+impl Clarifications {
+    pub fn new(config: &ClarificationConfig) -> Self {
+        Self {
+            min_prompt_chars: config.min_prompt_chars,
+            min_confidence: config.min_confidence,
+            pending: TtlLruMap::new(config.max_pending, config.ttl),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.pending.ttl()
+    }
+
+    /// 若该会话有未过期的追问，把本次输入视为回答并合并为完整提示词
+    pub fn take_answer(&mut self, key: &str, answer: &str) -> Option<(Intent, String)> {
+        let pending = self.pending.take(&key.to_string())?;
+        let prompt = format!("{}，{}", pending.prompt.trim(), answer.trim());
+        Some((pending.intent, prompt))
+    }
+
+    /// 生成类请求的有效内容过短，或路由模型对意图的置信度过低时记录追问，
+    /// 返回要发给用户的问题与追问原因；`confidence` 为空（未经模型判定）时只检查长度
+    pub fn request(
+        &mut self,
+        key: &str,
+        intent: Intent,
+        input: &str,
+        confidence: Option<f64>,
+    ) -> Option<(&'static str, ClarifyReason)> {
+        let (prefixes, question, confirm) = match intent {
+            Intent::ImageGeneration => (
+                IMAGE_PREFIXES,
+                "你想画什么内容？想要什么风格？",
+                "是想让我画一张图吗？想画什么内容、什么风格？",
+            ),
+            Intent::MusicGeneration => (
+                MUSIC_PREFIXES,
+                "想要什么主题或风格的曲子？",
+                "是想让我写一首曲子吗？想要什么主题或风格？",
+            ),
+            Intent::VideoGeneration => (
+                VIDEO_PREFIXES,
+                "视频想表现什么内容？大概多长、什么风格？",
+                "是想让我生成一段视频吗？想表现什么内容？",
+            ),
+            _ => return None,
+        };
+
+        let too_short = self.min_prompt_chars > 0
+            && Self::content_chars(input, prefixes) < self.min_prompt_chars;
+        let (question, reason) = match (confidence, self.min_confidence) {
+            _ if too_short => (question, ClarifyReason::ShortPrompt),
+            (Some(confidence), Some(min)) if confidence < min => {
+                (confirm, ClarifyReason::LowConfidence(confidence))
+            }
+            _ => return None,
+        };
+
+        self.pending.insert(
+            key.to_string(),
+            PendingClarification {
+                intent,
+                prompt: input.to_string(),
+            },
+        );
+        Some((question, reason))
+    }
+
+    fn content_chars(input: &str, prefixes: &[&str]) -> usize {
+        let trimmed = input.trim();
+        let stripped = prefixes
+            .iter()
+            .find_map(|prefix| trimmed.strip_prefix(prefix))
+            .unwrap_or(trimmed);
+        stripped
+            .chars()
+            .filter(|c| !c.is_whitespace() && !c.is_ascii_punctuation())
+            .filter(|c| !"，。！？、…~～".contains(*c))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_min_chars(min_prompt_chars: usize) -> Clarifications {
+        with_thresholds(min_prompt_chars, None)
+    }
+
+    fn with_thresholds(min_prompt_chars: usize, min_confidence: Option<f64>) -> Clarifications {
+        Clarifications::new(&ClarificationConfig {
+            min_prompt_chars,
+            min_confidence,
+            ttl: Duration::from_secs(120),
+            max_pending: 16,
+        })
+    }
+
+    #[test]
+    fn answer_is_merged_into_pending_prompt() {
+        let mut clarifications = with_min_chars(1);
+        assert_eq!(
+            clarifications.request("u1", Intent::ImageGeneration, "画", None),
+            Some(("你想画什么内容？想要什么风格？", ClarifyReason::ShortPrompt))
+        );
+        assert!(clarifications.take_answer("u2", "一只猫").is_none());
+        assert_eq!(
+            clarifications.take_answer("u1", " 一只猫，水彩 "),
+            Some((Intent::ImageGeneration, "画，一只猫，水彩".to_string()))
+        );
+        // 追问只回答一次
+        assert!(clarifications.take_answer("u1", "再来一张").is_none());
+    }
+
+    #[test]
+    fn only_short_generation_requests_ask() {
+        let mut clarifications = with_min_chars(2);
+        assert!(
+            clarifications
+                .request("u1", Intent::ImageGeneration, "画一只猫", None)
+                .is_none()
+        );
+        assert!(
+            clarifications
+                .request("u1", Intent::Conversation, "画", None)
+                .is_none()
+        );
+        // 去掉前缀与标点后只剩一个字
+        assert!(
+            clarifications
+                .request("u1", Intent::MusicGeneration, "来一首 歌！", None)
+                .is_some()
+        );
+        assert!(
+            clarifications
+                .request("u2", Intent::VideoGeneration, "生成视频", None)
+                .is_some()
+        );
+
+        let mut disabled = with_min_chars(0);
+        assert!(
+            disabled
+                .request("u1", Intent::ImageGeneration, "画", None)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn low_confidence_generation_requests_ask() {
+        let mut clarifications = with_thresholds(1, Some(0.6));
+        let confirm = "是想让我画一张图吗？想画什么内容、什么风格？";
+        assert_eq!(
+            clarifications.request("u1", Intent::ImageGeneration, "画一只猫", Some(0.4)),
+            Some((confirm, ClarifyReason::LowConfidence(0.4)))
+        );
+        assert_eq!(
+            clarifications.take_answer("u1", "对，要水彩风格"),
+            Some((
+                Intent::ImageGeneration,
+                "画一只猫，对，要水彩风格".to_string()
+            ))
+        );
+        // 达到阈值、未给出置信度或不是生成类请求时不追问
+        for (intent, confidence) in [
+            (Intent::ImageGeneration, Some(0.6)),
+            (Intent::ImageGeneration, None),
+            (Intent::Conversation, Some(0.1)),
+        ] {
+            assert!(
+                clarifications
+                    .request("u1", intent, "画一只猫", confidence)
+                    .is_none()
+            );
+        }
+        // 内容过短优先按长度追问
+        assert_eq!(
+            clarifications
+                .request("u1", Intent::ImageGeneration, "画", Some(0.1))
+                .map(|(_, reason)| reason),
+            Some(ClarifyReason::ShortPrompt)
+        );
+
+        // 未配置 min_confidence 时只看长度
+        let mut length_only = with_min_chars(1);
+        assert!(
+            length_only
+                .request("u1", Intent::ImageGeneration, "画一只猫", Some(0.01))
+                .is_none()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_clarification_expires() {
+        let mut clarifications = with_min_chars(1);
+        clarifications.request("u1", Intent::ImageGeneration, "画", None);
+        clarifications.request("u2", Intent::MusicGeneration, "来一首", None);
+        tokio::time::advance(Duration::from_secs(119)).await;
+        assert!(clarifications.take_answer("u1", "一只猫").is_some());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(clarifications.take_answer("u2", "摇滚").is_none());
+    }
+}
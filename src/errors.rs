@@ -19,8 +19,9 @@ pub enum AgentError {
     #[error("JSON 解析失败: {0}")]
     SerdeJson(#[from] serde_json::Error),
 
+    /// `PromptError` 内联了一整条 `Message`，装箱以免撑大所有 `Result`
     #[error("LLM 请求失败: {0}")]
-    Prompt(#[from] rig::completion::PromptError),
+    Prompt(Box<rig::completion::PromptError>),
 
     #[error("文本生成失败: {0}")]
     Completion(#[from] rig::completion::CompletionError),
@@ -31,6 +32,10 @@ pub enum AgentError {
     #[error("图像生成失败: {0}")]
     ImageGeneration(#[from] rig::image_generation::ImageGenerationError),
 
+    /// 请求本身不合法（如缺少参考图），`code` 随 `agent.error` 返回给客户端
+    #[error("{message}")]
+    Rejected { code: &'static str, message: String },
+
     #[error("内部错误: {0}")]
     Other(String),
 }
@@ -43,6 +48,27 @@ impl AgentError {
     pub fn other(msg: impl Into<String>) -> Self {
         Self::Other(msg.into())
     }
+
+    pub fn rejected(code: &'static str, message: impl Into<String>) -> Self {
+        Self::Rejected {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// 供客户端区分的错误码，目前只有 `Rejected` 携带
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Self::Rejected { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl From<rig::completion::PromptError> for AgentError {
+    fn from(value: rig::completion::PromptError) -> Self {
+        Self::Prompt(Box::new(value))
+    }
 }
 
 impl From<anyhow::Error> for AgentError {
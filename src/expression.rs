@@ -0,0 +1,248 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::{
+    ExpressionClassifierKind, ExpressionConfig, ExpressionLabel, NEUTRAL_EXPRESSION,
+};
+
+/// 启发式判断中命中这么多次关键词或 emoji 时强度为 1
+const SATURATION_HITS: f64 = 3.0;
+/// 模型只回复了表情名称、没有给出强度时使用
+const DEFAULT_LLM_INTENSITY: f64 = 0.5;
+
+/// 一条回复对应的表情提示，随 `agent.conversation` 与 `agent.audio` 广播
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    pub label: String,
+    /// 0~1，保留两位小数；`neutral` 时为 0
+    pub intensity: f64,
+}
+
+impl Expression {
+    pub fn neutral() -> Self {
+        Self {
+            label: NEUTRAL_EXPRESSION.to_string(),
+            intensity: 0.0,
+        }
+    }
+
+    /// 在事件负载中写入 `expression` 与 `intensity`
+    pub fn insert_into(&self, payload: &mut Value) {
+        if let Value::Object(map) = payload {
+            map.insert("expression".to_string(), json!(self.label));
+            map.insert("intensity".to_string(), json!(self.intensity));
+        }
+    }
+}
+
+/// 把对话回复归为配置中的某个表情，供 Live2D 等前端播放对应动作；无法判断时为 `neutral`
+#[derive(Debug)]
+pub struct ExpressionClassifier {
+    kind: ExpressionClassifierKind,
+    /// 关键词已转为小写
+    labels: Vec<ExpressionLabel>,
+    min_intensity: f64,
+}
+
+#[derive(Deserialize)]
+struct LlmExpression {
+    expression: String,
+    #[serde(default)]
+    intensity: Option<f64>,
+}
+
+impl ExpressionClassifier {
+    pub fn new(config: &ExpressionConfig) -> Self {
+        let labels = config
+            .labels
+            .iter()
+            .map(|label| ExpressionLabel {
+                keywords: label
+                    .keywords
+                    .iter()
+                    .map(|keyword| keyword.to_lowercase())
+                    .collect(),
+                ..label.clone()
+            })
+            .collect();
+        Self {
+            kind: config.classifier,
+            labels,
+            min_intensity: config.min_intensity,
+        }
+    }
+
+    pub fn kind(&self) -> ExpressionClassifierKind {
+        self.kind
+    }
+
+    /// 按各表情的关键词与 emoji 在回复中的命中次数判断，命中最多的表情胜出；
+    /// 没有命中、最高票并列或强度低于 `min_intensity` 时为 `neutral`
+    pub fn heuristic(&self, text: &str) -> Expression {
+        let lowered = text.to_lowercase();
+        let mut best: Option<(&str, usize)> = None;
+        let mut tied = false;
+        for label in &self.labels {
+            let hits = label
+                .keywords
+                .iter()
+                .map(|keyword| lowered.matches(keyword.as_str()).count())
+                .chain(
+                    label
+                        .emoji
+                        .iter()
+                        .map(|emoji| text.matches(emoji.as_str()).count()),
+                )
+                .sum::<usize>();
+            match best {
+                Some((_, top)) if hits == top => tied = true,
+                Some((_, top)) if hits < top => {}
+                _ => {
+                    best = Some((&label.name, hits));
+                    tied = false;
+                }
+            }
+        }
+
+        match best {
+            Some((label, hits)) if hits > 0 && !tied => {
+                self.finish(label, hits as f64 / SATURATION_HITS)
+            }
+            _ => Expression::neutral(),
+        }
+    }
+
+    /// `llm` 模式的系统提示词：只允许从配置的表情与 `neutral` 中选择，并以 JSON 给出强度
+    pub fn system_prompt(&self) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .chain([NEUTRAL_EXPRESSION])
+            .collect::<Vec<_>>()
+            .join("、");
+        format!(
+            "你是虚拟主播的表情控制器。用户消息是主播刚说的一段话，请从以下表情中选出最贴切的一个：{labels}；\
+             都不合适或拿不准时选 {NEUTRAL_EXPRESSION}。只输出 JSON，例如 {{\"expression\": \"{NEUTRAL_EXPRESSION}\", \"intensity\": 0.5}}，\
+             intensity 为 0~1 的强度，不要输出其它内容。"
+        )
+    }
+
+    /// 解析 `llm` 模式的回复：JSON 中的表情不在配置中、无法解析或强度过低时为 `neutral`
+    pub fn parse_reply(&self, reply: &str) -> Expression {
+        let parsed = match (reply.find('{'), reply.rfind('}')) {
+            (Some(start), Some(end)) if start < end => {
+                serde_json::from_str::<LlmExpression>(&reply[start..=end]).ok()
+            }
+            _ => Some(LlmExpression {
+                expression: reply.trim().to_string(),
+                intensity: None,
+            }),
+        };
+        match parsed {
+            Some(parsed) => self.finish(
+                &parsed.expression,
+                parsed.intensity.unwrap_or(DEFAULT_LLM_INTENSITY),
+            ),
+            None => Expression::neutral(),
+        }
+    }
+
+    /// 表情不在配置中或强度低于下限时改为 `neutral`
+    fn finish(&self, label: &str, intensity: f64) -> Expression {
+        let label = label.trim().to_lowercase();
+        let intensity = intensity.clamp(0.0, 1.0);
+        if intensity < self.min_intensity || !self.labels.iter().any(|known| known.name == label) {
+            return Expression::neutral();
+        }
+        Expression {
+            label,
+            intensity: (intensity * 100.0).round() / 100.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str, keywords: &[&str], emoji: &[&str]) -> ExpressionLabel {
+        ExpressionLabel {
+            name: name.into(),
+            keywords: keywords.iter().map(|s| s.to_string()).collect(),
+            emoji: emoji.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn classifier(
+        kind: ExpressionClassifierKind,
+        labels: Vec<ExpressionLabel>,
+    ) -> ExpressionClassifier {
+        ExpressionClassifier::new(&ExpressionConfig {
+            classifier: kind,
+            labels,
+            min_intensity: 0.3,
+        })
+    }
+
+    #[test]
+    fn heuristic_picks_the_most_hit_label() {
+        let classifier = classifier(
+            ExpressionClassifierKind::Heuristic,
+            vec![
+                label("happy", &["哈哈", "开心", "LOL"], &["😄"]),
+                label("sad", &["难过", "呜呜"], &["😢"]),
+            ],
+        );
+
+        let happy = classifier.heuristic("哈哈，今天好开心😄");
+        assert_eq!((happy.label.as_str(), happy.intensity), ("happy", 1.0));
+        // 一次命中为 0.33，英文关键词忽略大小写
+        let mild = classifier.heuristic("lol 有点意思");
+        assert_eq!((mild.label.as_str(), mild.intensity), ("happy", 0.33));
+        assert_eq!(classifier.heuristic("呜呜，有点难过😢😢").label, "sad");
+    }
+
+    #[test]
+    fn heuristic_without_hits_or_with_ties_is_neutral() {
+        let classifier = classifier(
+            ExpressionClassifierKind::Heuristic,
+            vec![label("happy", &["哈哈"], &[]), label("sad", &["难过"], &[])],
+        );
+
+        assert_eq!(classifier.heuristic("明天见").label, NEUTRAL_EXPRESSION);
+        assert_eq!(
+            classifier.heuristic("哈哈，又有点难过").label,
+            NEUTRAL_EXPRESSION
+        );
+    }
+
+    #[test]
+    fn llm_reply_is_parsed_against_configured_labels() {
+        let classifier = classifier(ExpressionClassifierKind::Llm, vec![label("wink", &[], &[])]);
+        assert!(classifier.system_prompt().contains("wink、neutral"));
+
+        let wink =
+            classifier.parse_reply("```json\n{\"expression\": \"Wink\", \"intensity\": 0.8}\n```");
+        assert_eq!((wink.label.as_str(), wink.intensity), ("wink", 0.8));
+        // 只回复名称时使用默认强度
+        assert_eq!(classifier.parse_reply("wink").intensity, 0.5);
+    }
+
+    #[test]
+    fn unknown_weak_or_unparsable_llm_replies_are_neutral() {
+        let classifier = classifier(ExpressionClassifierKind::Llm, vec![label("wink", &[], &[])]);
+
+        for reply in [
+            "{\"expression\": \"angry\"}",
+            "{\"expression\": \"wink\", \"intensity\": 0.1}",
+            "不知道",
+        ] {
+            assert_eq!(
+                classifier.parse_reply(reply).label,
+                NEUTRAL_EXPRESSION,
+                "{reply}"
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use futures::future::join_all;
+use serde_json::json;
+use tracing::{info, warn};
+
+#[cfg(feature = "live")]
+use crate::live::LiveManager;
+use crate::{
+    config::{
+        CapabilityProviders, CapabilityRoute, DEFAULT_OPENAI_BASE_URL, DeepSeekConfig,
+        HyperbolicConfig, MusicConfig, OpenAiConfig, SdWebUiConfig, VideoConfig, ZhipuConfig,
+    },
+    errors::{AgentError, Result},
+    intent::Intent,
+};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const HYPERBOLIC_BASE_URL: &str = "https://api.hyperbolic.xyz/v1";
+
+/// 启动自检的结果，只用于标注能力状态，不影响启动
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Ok,
+    Degraded(String),
+    Unchecked,
+}
+
+impl HealthStatus {
+    pub fn label(&self) -> String {
+        match self {
+            HealthStatus::Ok => "ok".to_string(),
+            HealthStatus::Degraded(reason) => format!("degraded: {reason}"),
+            HealthStatus::Unchecked => "unchecked".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HealthReport {
+    capabilities: HashMap<Intent, HealthStatus>,
+    intent_router: Option<HealthStatus>,
+    live: Option<HealthStatus>,
+}
+
+impl HealthReport {
+    /// 未执行自检或能力未配置时返回 `Unchecked`
+    pub fn capability(&self, intent: Intent) -> &HealthStatus {
+        self.capabilities
+            .get(&intent)
+            .unwrap_or(&HealthStatus::Unchecked)
+    }
+
+    pub fn intent_router(&self) -> &HealthStatus {
+        self.intent_router
+            .as_ref()
+            .unwrap_or(&HealthStatus::Unchecked)
+    }
+
+    pub fn live(&self) -> &HealthStatus {
+        self.live.as_ref().unwrap_or(&HealthStatus::Unchecked)
+    }
+}
+
+/// 需要自检的提供方配置
+pub struct HealthTargets<'a> {
+    pub providers: &'a CapabilityProviders,
+    pub openai: Option<&'a OpenAiConfig>,
+    pub openai_profiles: &'a HashMap<String, OpenAiConfig>,
+    pub zhipu: Option<&'a ZhipuConfig>,
+    pub deepseek: Option<&'a DeepSeekConfig>,
+    pub hyperbolic: Option<&'a HyperbolicConfig>,
+    pub video: Option<&'a VideoConfig>,
+    pub music: Option<&'a MusicConfig>,
+    pub sd_webui: Option<&'a SdWebUiConfig>,
+    #[cfg(feature = "live")]
+    pub live: Option<&'a LiveManager>,
+}
+
+/// 并发对每个已配置的能力发起一次低成本请求，单项超时或失败只会标记为 degraded；
+/// 只有无法构建 HTTP 客户端时返回错误
+pub async fn run_startup_checks(targets: HealthTargets<'_>) -> Result<HealthReport> {
+    let http = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
+
+    // `None` 表示意图路由，其余为各能力
+    let routes = [
+        (None, targets.providers.intent.as_ref()),
+        (
+            Some(Intent::Conversation),
+            targets.providers.conversation.as_ref(),
+        ),
+        (
+            Some(Intent::ImageGeneration),
+            targets.providers.image.as_ref(),
+        ),
+        (
+            Some(Intent::MusicGeneration),
+            targets.providers.music.as_ref(),
+        ),
+        (
+            Some(Intent::VideoGeneration),
+            targets.providers.video.as_ref(),
+        ),
+    ];
+
+    let checks = routes
+        .into_iter()
+        .filter_map(|(capability, route)| route.map(|route| (capability, route)))
+        .map(|(capability, route)| {
+            let http = &http;
+            let targets = &targets;
+            async move {
+                let status = with_timeout(check_route(http, targets, capability, route)).await;
+                (capability, status)
+            }
+        });
+
+    #[cfg(feature = "live")]
+    let live_check = async {
+        match targets.live {
+            Some(live) => {
+                Some(with_timeout(async { live.ping().await.map(|()| HealthStatus::Ok) }).await)
+            }
+            None => None,
+        }
+    };
+    #[cfg(not(feature = "live"))]
+    let live_check = async { None };
+
+    let (results, live) = tokio::join!(join_all(checks), live_check);
+
+    let mut report = HealthReport {
+        live,
+        ..HealthReport::default()
+    };
+    for (capability, status) in results {
+        let name = capability.map_or("intent_router".to_string(), |intent| intent.to_string());
+        match &status {
+            HealthStatus::Degraded(reason) => {
+                warn!(target: "health", capability = %name, %reason, "启动自检未通过")
+            }
+            _ => {
+                info!(target: "health", capability = %name, status = %status.label(), "启动自检完成")
+            }
+        }
+        match capability {
+            Some(intent) => {
+                report.capabilities.insert(intent, status);
+            }
+            None => report.intent_router = Some(status),
+        }
+    }
+    if let Some(HealthStatus::Degraded(reason)) = &report.live {
+        warn!(target: "health", %reason, "B 站直播接口自检未通过");
+    }
+
+    Ok(report)
+}
+
+async fn with_timeout<F>(check: F) -> HealthStatus
+where
+    F: Future<Output = Result<HealthStatus>>,
+{
+    match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(status)) => status,
+        Ok(Err(err)) => HealthStatus::Degraded(err.to_string()),
+        Err(_) => HealthStatus::Degraded(format!("超过 {} 秒未响应", CHECK_TIMEOUT.as_secs())),
+    }
+}
+
+async fn check_route(
+    http: &reqwest::Client,
+    targets: &HealthTargets<'_>,
+    capability: Option<Intent>,
+    route: &CapabilityRoute,
+) -> Result<HealthStatus> {
+    match (route.provider.as_str(), capability) {
+        ("openai", _) => {
+            let cfg = route
+                .openai_config(targets.openai, targets.openai_profiles)
+                .ok_or(AgentError::MissingConfig("openai.api_key"))?;
+            let base_url = cfg.base_url.as_deref().unwrap_or(DEFAULT_OPENAI_BASE_URL);
+            let url = format!("{}/models", base_url.trim_end_matches('/'));
+            expect_success(http.get(url).bearer_auth(&cfg.api_key).send().await?)
+        }
+        ("zhipu", _) => {
+            let cfg = targets
+                .zhipu
+                .ok_or(AgentError::MissingConfig("zhipu.api_key"))?;
+            // 图像路由只校验密钥，用对话模型发一次最小请求，避免真的生成图片
+            let model = match capability {
+                Some(Intent::ImageGeneration) => &cfg.chat_model,
+                _ => route.model.as_deref().unwrap_or(&cfg.chat_model),
+            };
+            let body = json!({
+                "model": model,
+                "messages": [{ "role": "user", "content": "ping" }],
+                "max_tokens": 1,
+            });
+            expect_success(
+                http.post(&cfg.api_url)
+                    .bearer_auth(&cfg.api_key)
+                    .json(&body)
+                    .send()
+                    .await?,
+            )
+        }
+        ("deepseek", _) => {
+            let cfg = targets
+                .deepseek
+                .ok_or(AgentError::MissingConfig("deepseek.api_key"))?;
+            let base_url = cfg
+                .api_url
+                .trim_end_matches('/')
+                .trim_end_matches("/chat/completions");
+            let url = format!("{base_url}/models");
+            expect_success(http.get(url).bearer_auth(&cfg.api_key).send().await?)
+        }
+        ("hyperbolic", _) => {
+            let cfg = targets
+                .hyperbolic
+                .ok_or(AgentError::MissingConfig("hyperbolic.api_key"))?;
+            let url = format!("{HYPERBOLIC_BASE_URL}/models");
+            expect_success(http.get(url).bearer_auth(&cfg.api_key).send().await?)
+        }
+        ("custom", Some(Intent::VideoGeneration)) => {
+            let cfg = targets
+                .video
+                .ok_or(AgentError::MissingConfig("video.endpoint"))?;
+            probe_endpoint(http, "视频", &cfg.endpoint, cfg.api_key.as_deref()).await
+        }
+        ("custom", Some(Intent::MusicGeneration)) => {
+            let cfg = targets
+                .music
+                .ok_or(AgentError::MissingConfig("music.endpoint"))?;
+            probe_endpoint(http, "音乐", &cfg.endpoint, cfg.api_key.as_deref()).await
+        }
+        ("sd_webui", Some(Intent::ImageGeneration)) => {
+            let cfg = targets
+                .sd_webui
+                .ok_or(AgentError::MissingConfig("sd_webui.endpoint"))?;
+            let mut builder = http.get(format!("{}/sdapi/v1/sd-models", cfg.endpoint));
+            if let Some(auth) = &cfg.auth {
+                let (user, password) = auth.split_once(':').unwrap_or((auth, ""));
+                builder = builder.basic_auth(user, Some(password));
+            }
+            expect_success(builder.send().await?)
+        }
+        // mock、static 与 fixture 提供方不依赖任何外部服务
+        ("mock" | "static" | "fixture", _) => Ok(HealthStatus::Ok),
+        // 已禁用或暂不支持自检的提供方
+        _ => Ok(HealthStatus::Unchecked),
+    }
+}
+
+/// 多数生成接口不支持 HEAD，只要服务可达且未拒绝鉴权即视为可用
+async fn probe_endpoint(
+    http: &reqwest::Client,
+    label: &str,
+    endpoint: &str,
+    api_key: Option<&str>,
+) -> Result<HealthStatus> {
+    let mut builder = http.head(endpoint);
+    if let Some(api_key) = api_key {
+        builder = builder.bearer_auth(api_key);
+    }
+    let status = builder.send().await?.status();
+    if status.is_server_error()
+        || status == reqwest::StatusCode::UNAUTHORIZED
+        || status == reqwest::StatusCode::FORBIDDEN
+    {
+        return Err(AgentError::other(format!("{label}服务返回状态码 {status}")));
+    }
+    Ok(HealthStatus::Ok)
+}
+
+fn expect_success(response: reqwest::Response) -> Result<HealthStatus> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(HealthStatus::Ok)
+    } else {
+        Err(AgentError::other(format!("接口返回状态码 {status}")))
+    }
+}
@@ -1,52 +1,193 @@
-use std::fmt;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
-    config::{CapabilityRoute, OpenAiConfig, ZhipuConfig},
+    config::{
+        CapabilityRoute, DeepSeekConfig, FallbackKeywords, IntentConfig, IntentExample,
+        IntentFallback, IntentRule, OpenAiConfig, ROUTER_INTENT_LABELS, SamplingParams,
+        ZhipuConfig,
+    },
     errors::{AgentError, Result},
-    providers::zhipu::ZhipuCompletionModel,
+    providers::{
+        apply_sampling, deepseek::DeepSeekCompletionModel, mock::MockCompletionModel,
+        zhipu::ZhipuCompletionModel,
+    },
+    usage::{PromptCacheUsage, TokenUsage},
+    util::{TtlLruMap, truncate_chars},
 };
+use futures_util::StreamExt;
 use rig::{
-    agent::Agent,
+    agent::{Agent, AgentBuilder},
     client::CompletionClient,
-    completion::{Prompt, request::CompletionModel},
+    completion::{
+        Completion,
+        message::AssistantContent,
+        request::{CompletionError, CompletionModel, CompletionRequest, GetTokenUsage},
+    },
     providers::openai,
+    streaming::StreamedAssistantContent,
 };
 use serde::Deserialize;
-use serde_json::Value;
-use tracing::warn;
+use serde_json::{Value, json};
+use tracing::{debug, info, warn};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Intent {
     Conversation,
     ImageGeneration,
     MusicGeneration,
     VideoGeneration,
+    /// 控制直播监听（开始/停止/查询状态），具体动作从输入中解析
+    LiveControl,
     Help,
     Unknown,
 }
 
 impl Intent {
+    /// 按声明顺序排列，统计时以判别值作为下标
+    pub(crate) const ALL: [Intent; 7] = [
+        Intent::Conversation,
+        Intent::ImageGeneration,
+        Intent::MusicGeneration,
+        Intent::VideoGeneration,
+        Intent::LiveControl,
+        Intent::Help,
+        Intent::Unknown,
+    ];
+
     pub fn as_prefix(&self) -> &'static str {
         match self {
             Intent::Conversation => "chat",
             Intent::ImageGeneration => "image",
             Intent::MusicGeneration => "music",
             Intent::VideoGeneration => "video",
+            Intent::LiveControl => "live",
             Intent::Help => "help",
             Intent::Unknown => "unknown",
         }
     }
 
+    /// 路由器输出的标签（`ROUTER_INTENT_LABELS` 之一），无法判定的意图按对话处理
+    fn router_label(&self) -> &'static str {
+        match self {
+            Intent::Conversation | Intent::Unknown => "conversation",
+            Intent::ImageGeneration => "image_generation",
+            Intent::MusicGeneration => "music_generation",
+            Intent::VideoGeneration => "video_generation",
+            Intent::LiveControl => "live_control",
+            Intent::Help => "help",
+        }
+    }
+
     fn from_str(value: &str) -> Self {
         match value.trim().to_lowercase().as_str() {
             "conversation" | "chat" | "dialogue" | "text" => Intent::Conversation,
             "image_generation" | "image" | "drawing" | "paint" | "art" => Intent::ImageGeneration,
             "music_generation" | "music" | "song" | "audio" => Intent::MusicGeneration,
             "video_generation" | "video" | "animation" | "film" => Intent::VideoGeneration,
+            "live_control" | "live" => Intent::LiveControl,
             "help" | "support" => Intent::Help,
-            _ => Intent::Unknown,
+            other => Self::from_fuzzy(other).unwrap_or(Intent::Unknown),
         }
     }
+
+    /// 在模型的自由文本中查找意图标签；找不到或同时命中多个意图时视为无法判定
+    ///
+    /// 英文标签按整词匹配（`start` 不会命中 `art`），中文标签按子串匹配。
+    fn from_fuzzy(value: &str) -> Option<Self> {
+        let normalized = value.to_lowercase();
+        let mut matched: Option<Intent> = None;
+
+        for (intent, labels) in INTENT_LABELS {
+            if labels
+                .iter()
+                .any(|label| contains_label(&normalized, label))
+            {
+                match matched {
+                    Some(existing) if existing != *intent => return None,
+                    _ => matched = Some(*intent),
+                }
+            }
+        }
+
+        matched
+    }
+}
+
+/// 模糊匹配使用的中英文标签，包含路由器标签与 `Intent::from_str` 的别名，顺序不影响结果
+const INTENT_LABELS: &[(Intent, &[&str])] = &[
+    (
+        Intent::Conversation,
+        &[
+            "conversation",
+            "chat",
+            "dialogue",
+            "text",
+            "对话",
+            "聊天",
+            "闲聊",
+        ],
+    ),
+    (
+        Intent::ImageGeneration,
+        &[
+            "image_generation",
+            "image",
+            "drawing",
+            "paint",
+            "art",
+            "绘画",
+            "画图",
+            "图片",
+            "图像",
+        ],
+    ),
+    (
+        Intent::MusicGeneration,
+        &[
+            "music_generation",
+            "music",
+            "song",
+            "audio",
+            "音乐",
+            "歌曲",
+            "作曲",
+        ],
+    ),
+    (
+        Intent::VideoGeneration,
+        &[
+            "video_generation",
+            "video",
+            "animation",
+            "film",
+            "视频",
+            "动画",
+        ],
+    ),
+    (Intent::LiveControl, &["live_control", "live", "直播监听"]),
+    (Intent::Help, &["help", "support", "帮助"]),
+];
+
+/// 英文标签要求前后不是字母、数字或下划线，其余标签按子串匹配
+fn contains_label(text: &str, label: &str) -> bool {
+    if !label.is_ascii() {
+        return text.contains(label);
+    }
+
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(label).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + label.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
 }
 
 impl fmt::Display for Intent {
@@ -58,12 +199,181 @@ impl fmt::Display for Intent {
 #[derive(Deserialize)]
 struct IntentResponse {
     intent: String,
+    /// 结构化输出时由 schema 要求返回，宽松解析时通常没有
+    #[serde(default)]
+    confidence: Option<f64>,
+    #[serde(default)]
+    segments: Vec<SegmentResponse>,
+}
+
+/// 解析出的意图、置信度与未经校验的拆分片段
+type ParsedResponse = (Intent, Option<f64>, Vec<SegmentResponse>);
+
+#[derive(Deserialize)]
+struct SegmentResponse {
+    intent: String,
+    segment: String,
+}
+
+/// 复合请求拆分出的一段，按原输入中的先后顺序执行
+#[derive(Debug, Clone)]
+pub struct IntentSegment {
+    pub intent: Intent,
+    /// 该段请求对应的原文
+    pub segment: String,
 }
 
 type OpenAiCompletionModel = <openai::Client as CompletionClient>::CompletionModel;
 
 pub struct IntentClassifier {
     provider: Option<IntentProvider>,
+    fallback: IntentFallback,
+    /// 只在配置了路由模型时启用；`classify_detailed` 是 `&self`，因此用互斥锁保护
+    cache: Option<IntentCache>,
+    rules: Vec<IntentRule>,
+    fallback_keywords: FallbackKeywords,
+    /// 路由模型请求（含重试）的总时限，超时后立即按关键字规则判定
+    timeout: Option<Duration>,
+    stats: ClassifierStats,
+    /// 复合请求最多拆分的段数，小于 2 时不拆分
+    max_segments: usize,
+}
+
+/// 判定过程的累计计数，`classify_detailed` 是 `&self`，因此全部使用原子变量
+#[derive(Default)]
+struct ClassifierStats {
+    total: AtomicU64,
+    /// 兜底策略为 `error` 时返回错误的判定
+    errors: AtomicU64,
+    /// 路由模型请求失败（网络错误、接口报错等）的次数，含重试
+    provider_errors: AtomicU64,
+    /// 路由模型有返回但无法解析出意图的次数，含重试
+    parse_failures: AtomicU64,
+    /// 路由模型失败后启用兜底策略的次数
+    fallbacks: AtomicU64,
+    timeouts: AtomicU64,
+    by_source: [AtomicU64; ClassificationSource::ALL.len()],
+    by_intent: [AtomicU64; Intent::ALL.len()],
+}
+
+impl ClassifierStats {
+    fn record(&self, result: &Result<Classification>) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(classification) => {
+                self.by_source[classification.source as usize].fetch_add(1, Ordering::Relaxed);
+                self.by_intent[classification.intent as usize].fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn stats(&self) -> Value {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let total = load(&self.total);
+        let fallbacks = load(&self.fallbacks);
+        let timeouts = load(&self.timeouts);
+        let fallback_rate = if total == 0 {
+            0.0
+        } else {
+            (fallbacks + timeouts) as f64 / total as f64
+        };
+        let by_source = ClassificationSource::ALL
+            .iter()
+            .zip(&self.by_source)
+            .map(|(source, counter)| (source.as_str().to_string(), json!(load(counter))))
+            .collect::<serde_json::Map<_, _>>();
+        let by_intent = Intent::ALL
+            .iter()
+            .zip(&self.by_intent)
+            .map(|(intent, counter)| (intent.as_prefix().to_string(), json!(load(counter))))
+            .collect::<serde_json::Map<_, _>>();
+
+        json!({
+            "total": total,
+            "errors": load(&self.errors),
+            "provider_errors": load(&self.provider_errors),
+            "parse_failures": load(&self.parse_failures),
+            "fallbacks": fallbacks,
+            "timeouts": timeouts,
+            "fallback_rate": fallback_rate,
+            "cache_hits": load(&self.by_source[ClassificationSource::Cache as usize]),
+            "by_source": by_source,
+            "by_intent": by_intent,
+        })
+    }
+}
+
+struct IntentCache {
+    /// 意图与路由模型给出的置信度
+    entries: Mutex<TtlLruMap<String, (Intent, Option<f64>)>>,
+    fallback_ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl IntentCache {
+    /// 按去除首尾空白并转小写后的输入缓存
+    fn key(input: &str) -> String {
+        input.trim().to_lowercase()
+    }
+
+    fn get(&self, input: &str) -> Option<(Intent, Option<f64>)> {
+        let cached = self
+            .entries
+            .lock()
+            .ok()
+            .and_then(|mut entries| entries.get(&Self::key(input)).copied());
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    fn insert(&self, input: &str, classification: &Classification) {
+        // 拆分结果依赖完整输入，且缓存只保存单个意图
+        if !classification.segments.is_empty() {
+            return;
+        }
+        let ttl = match classification.source {
+            ClassificationSource::Provider => None,
+            ClassificationSource::Fallback => match self.fallback_ttl {
+                Some(ttl) => Some(ttl),
+                None => return,
+            },
+            ClassificationSource::Empty
+            | ClassificationSource::Rule
+            | ClassificationSource::Cache
+            | ClassificationSource::Timeout => return,
+        };
+
+        if let Ok(mut entries) = self.entries.lock() {
+            let ttl = ttl.unwrap_or_else(|| entries.ttl());
+            entries.insert_with_ttl(
+                Self::key(input),
+                (classification.intent, classification.confidence),
+                ttl,
+            );
+        }
+    }
+
+    fn stats(&self) -> Value {
+        let entries = self
+            .entries
+            .lock()
+            .map(|entries| entries.len())
+            .unwrap_or_default();
+        json!({
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "entries": entries,
+        })
+    }
 }
 
 enum IntentProvider {
@@ -73,10 +383,109 @@ enum IntentProvider {
     Zhipu {
         model: ZhipuCompletionModel,
         system_prompt: String,
+        /// 请求时附带 `response_format = json_object`
+        json_mode: bool,
+        /// 以流式请求，解析出完整的意图 JSON 后丢弃剩余输出
+        streaming: bool,
+        sampling: SamplingParams,
+    },
+    DeepSeek {
+        agent: Agent<DeepSeekCompletionModel>,
+    },
+    /// 离线调试用，按关键字规则给出确定的判定结果
+    Mock {
+        agent: Agent<MockCompletionModel>,
+    },
+    /// 只按 `[[intent.rules]]` 与兜底关键字判定，不发起任何请求
+    Static {
+        rules: Vec<IntentRule>,
+        keywords: FallbackKeywords,
     },
+    /// 从 JSON 文件读取输入到意图标签的映射，用于可复现的测试场景
+    Fixture {
+        mappings: HashMap<String, String>,
+    },
+}
+
+impl IntentProvider {
+    fn label(&self) -> &'static str {
+        match self {
+            IntentProvider::OpenAi { .. } => "openai",
+            IntentProvider::Zhipu { .. } => "zhipu",
+            IntentProvider::DeepSeek { .. } => "deepseek",
+            IntentProvider::Mock { .. } => "mock",
+            IntentProvider::Static { .. } => "static",
+            IntentProvider::Fixture { .. } => "fixture",
+        }
+    }
+}
+
+/// 一次意图判定的完整结果
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub intent: Intent,
+    pub source: ClassificationSource,
+    /// 路由模型的原始输出（未调用模型或请求失败时为空）
+    pub raw_response: Option<String>,
+    /// 本次判定消耗的 token（含重试），未调用模型时为 0
+    pub usage: TokenUsage,
+    /// 路由模型返回的置信度（0~1），模型未给出或未经模型判定时为空；命中缓存时沿用缓存时的值
+    pub confidence: Option<f64>,
+    /// 路由模型把输入拆分为多个先后执行的请求时按顺序列出；单一意图或未经模型判定时为空
+    pub segments: Vec<IntentSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassificationSource {
+    /// 输入为空，直接返回帮助
+    Empty,
+    /// 由路由模型判定
+    Provider,
+    /// 由关键字规则判定
+    Fallback,
+    /// 命中 `[[intent.rules]]` 中的确定性规则
+    Rule,
+    /// 命中意图缓存，未调用模型
+    Cache,
+    /// 路由模型超时，改用关键字规则判定
+    Timeout,
 }
 
-const INTENT_ROUTER_SYSTEM_PROMPT: &str = "你是一名严格的路由器，只回答 JSON，格式为 {\"intent\": \"...\"}。intent 必须是 conversation、image_generation、music_generation、video_generation 或 help 之一。";
+impl ClassificationSource {
+    /// 按声明顺序排列，统计时以判别值作为下标
+    const ALL: [ClassificationSource; 6] = [
+        ClassificationSource::Empty,
+        ClassificationSource::Provider,
+        ClassificationSource::Fallback,
+        ClassificationSource::Rule,
+        ClassificationSource::Cache,
+        ClassificationSource::Timeout,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClassificationSource::Empty => "empty",
+            ClassificationSource::Provider => "provider",
+            ClassificationSource::Fallback => "fallback",
+            ClassificationSource::Rule => "rule",
+            ClassificationSource::Cache => "cache",
+            ClassificationSource::Timeout => "timeout",
+        }
+    }
+}
+
+/// 注入路由提示词的示例数量上限，过多会稀释规则并增加每次请求的 token
+const MAX_INTENT_EXAMPLES: usize = 20;
+const DEFAULT_CLASSIFY_TIMEOUT: Duration = Duration::from_millis(2500);
+/// 路由请求未配置采样参数时的默认值：温度 0 让判定稳定，只需输出一个很短的 JSON
+const INTENT_DEFAULT_TEMPERATURE: f64 = 0.0;
+const INTENT_DEFAULT_MAX_TOKENS: u64 = 32;
+/// 开启拆分时每段额外预留的 token，片段中会复述用户输入
+const INTENT_SEGMENT_MAX_TOKENS: u64 = 128;
+
+const INTENT_RETRY_PROMPT: &str = "只能输出以下标签之一：conversation、image_generation、music_generation、video_generation、live_control、help。不要输出任何其他文字。";
+
+const INTENT_ROUTER_SYSTEM_PROMPT: &str = "你是一名严格的路由器，只回答 JSON，格式为 {\"intent\": \"...\", \"confidence\": 0.9}。confidence 为 0 到 1 之间的数字，表示你对判断的把握。intent 必须是 conversation、image_generation、music_generation、video_generation、live_control 或 help 之一。要求开始、停止直播监听或查询直播监听状态时输出 live_control；只是聊到直播话题时仍为 conversation。";
 
 impl IntentClassifier {
     pub fn new(
@@ -84,7 +493,17 @@ impl IntentClassifier {
         openai_client: Option<openai::Client>,
         openai_config: Option<&OpenAiConfig>,
         zhipu_config: Option<&ZhipuConfig>,
+        deepseek_config: Option<&DeepSeekConfig>,
+        config: &IntentConfig,
     ) -> Result<Self> {
+        let system_prompt = route
+            .map(|route| Self::router_prompt(route, config.max_segments))
+            .unwrap_or_else(|| INTENT_ROUTER_SYSTEM_PROMPT.to_string());
+
+        let sampling = route
+            .map(|route| Self::router_sampling(route, config.max_segments))
+            .unwrap_or_default();
+
         let provider = match route {
             None => None,
             Some(route) => match route.provider.as_str() {
@@ -94,12 +513,15 @@ impl IntentClassifier {
                     let cfg = openai_config
                         .ok_or_else(|| AgentError::MissingConfig("openai.chat_model (意图路由)"))?;
                     let model = route.model.as_deref().unwrap_or(&cfg.chat_model);
+                    let builder = client
+                        .agent(model)
+                        .name("intent-router")
+                        .preamble(&system_prompt);
+                    let structured_output = config
+                        .structured_output
+                        .then(|| Self::openai_structured_output(config.max_segments > 1));
                     Some(IntentProvider::OpenAi {
-                        agent: client
-                            .agent(model)
-                            .name("intent-router")
-                            .preamble(INTENT_ROUTER_SYSTEM_PROMPT)
-                            .build(),
+                        agent: apply_sampling(builder, &sampling, structured_output).build(),
                     })
                 }
                 "zhipu" => {
@@ -109,9 +531,36 @@ impl IntentClassifier {
                         .map_err(|e| AgentError::other(format!("初始化智谱模型失败: {:?}", e)))?;
                     Some(IntentProvider::Zhipu {
                         model,
-                        system_prompt: INTENT_ROUTER_SYSTEM_PROMPT.to_string(),
+                        system_prompt: system_prompt.clone(),
+                        json_mode: config.structured_output,
+                        streaming: config.streaming,
+                        sampling,
                     })
                 }
+                "deepseek" => {
+                    let cfg = deepseek_config
+                        .ok_or_else(|| AgentError::MissingConfig("deepseek.api_key (意图路由)"))?;
+                    let model = DeepSeekCompletionModel::from_config(cfg, route.model.as_deref())?;
+                    let builder = AgentBuilder::new(model)
+                        .name("intent-router")
+                        .preamble(&system_prompt);
+                    Some(IntentProvider::DeepSeek {
+                        agent: apply_sampling(builder, &sampling, None).build(),
+                    })
+                }
+                "mock" => Some(IntentProvider::Mock {
+                    agent: AgentBuilder::new(MockCompletionModel::new(Self::mock_reply))
+                        .name("intent-router")
+                        .preamble(&system_prompt)
+                        .build(),
+                }),
+                "static" => Some(IntentProvider::Static {
+                    rules: config.rules.clone(),
+                    keywords: config.fallback_keywords.clone(),
+                }),
+                "fixture" => Some(IntentProvider::Fixture {
+                    mappings: Self::load_fixture(route)?,
+                }),
                 provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
                     None
                 }
@@ -123,87 +572,690 @@ impl IntentClassifier {
             },
         };
 
-        Ok(Self { provider })
+        if provider.is_some() {
+            debug!(
+                target: "intent_classifier",
+                preamble = %truncate_chars(&system_prompt, 80),
+                "意图路由提示词"
+            );
+        }
+
+        let cache = config
+            .cache
+            .as_ref()
+            .filter(|_| provider.is_some())
+            .map(|cache| IntentCache {
+                entries: Mutex::new(TtlLruMap::new(cache.max_entries, cache.ttl)),
+                fallback_ttl: cache.fallback_ttl,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            });
+
+        Ok(Self {
+            provider,
+            fallback: config.fallback,
+            cache,
+            rules: config.rules.clone(),
+            fallback_keywords: config.fallback_keywords.clone(),
+            timeout: route
+                .map(|route| route.timeout.unwrap_or(DEFAULT_CLASSIFY_TIMEOUT))
+                .filter(|timeout| !timeout.is_zero()),
+            stats: ClassifierStats::default(),
+            max_segments: config.max_segments,
+        })
+    }
+
+    /// 意图缓存的命中/未命中次数与当前条目数，未启用缓存时为 `None`
+    pub fn cache_stats(&self) -> Option<Value> {
+        self.cache.as_ref().map(IntentCache::stats)
+    }
+
+    /// 路由模型超时后改用关键字规则的累计次数
+    pub fn classifier_timeouts(&self) -> u64 {
+        self.stats.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// 判定总数、路由模型失败/解析失败/兜底次数与按来源、按意图的分布
+    pub fn stats(&self) -> Value {
+        self.stats.stats()
+    }
+
+    /// 已配置的路由模型提供方，未配置时为 `None`
+    pub fn provider_label(&self) -> Option<&'static str> {
+        self.provider.as_ref().map(IntentProvider::label)
     }
 
-    pub async fn classify(&self, input: &str) -> Result<Intent> {
+    /// 路由配置中的 preamble 与少样本示例追加在内置规则之后，只能补充判定规则，不能替换输出格式要求
+    fn router_prompt(route: &CapabilityRoute, max_segments: usize) -> String {
+        let mut prompt = INTENT_ROUTER_SYSTEM_PROMPT.to_string();
+        if max_segments > 1 {
+            prompt.push_str(&format!(
+                "若输入包含多个需要先后执行的不同请求（例如先聊天再画图），intent 给出第一个请求的意图，并额外输出 segments 数组，按顺序列出每个请求的 {{\"intent\": \"...\", \"segment\": \"该请求对应的原文\"}}，最多 {max_segments} 段；只有一个请求时 segments 为空数组。"
+            ));
+        }
+        if let Some(extra) = route.preamble.as_deref() {
+            prompt.push('\n');
+            prompt.push_str(extra.trim());
+        }
+
+        if route.examples.len() > MAX_INTENT_EXAMPLES {
+            warn!(
+                target: "intent_classifier",
+                configured = route.examples.len(),
+                max = MAX_INTENT_EXAMPLES,
+                "意图示例过多，只使用前面的部分"
+            );
+        }
+        let examples = &route.examples[..route.examples.len().min(MAX_INTENT_EXAMPLES)];
+        if !examples.is_empty() {
+            prompt.push_str("\n示例：");
+            for example in examples {
+                prompt.push('\n');
+                prompt.push_str(&Self::render_example(example));
+            }
+        }
+
+        prompt
+    }
+
+    /// 示例使用与实际请求相同的输入包裹方式和 JSON 输出格式
+    fn render_example(example: &IntentExample) -> String {
+        format!(
+            "用户输入: ```{}``` → {}",
+            example.input.trim(),
+            serde_json::json!({ "intent": example.intent.trim() })
+        )
+    }
+
+    /// 返回判定的意图、来源、模型原始输出与 token 用量；
+    /// 依次尝试确定性规则、意图缓存（配置了路由模型时）与路由模型
+    pub async fn classify_detailed(&self, input: &str) -> Result<Classification> {
+        let result = self.classify_routed(input).await;
+        self.stats.record(&result);
+        result
+    }
+
+    async fn classify_routed(&self, input: &str) -> Result<Classification> {
+        if let Some(classification) = self.classify_by_rules(input) {
+            return Ok(classification);
+        }
+
+        let Some(cache) = self.cache.as_ref().filter(|_| !input.trim().is_empty()) else {
+            return self.classify_uncached(input).await;
+        };
+
+        if let Some((intent, confidence)) = cache.get(input) {
+            debug!(target: "intent_classifier", %intent, "命中意图缓存");
+            return Ok(Classification {
+                intent,
+                source: ClassificationSource::Cache,
+                raw_response: None,
+                usage: TokenUsage::new(),
+                confidence,
+                segments: Vec::new(),
+            });
+        }
+
+        let classification = self.classify_uncached(input).await?;
+        cache.insert(input, &classification);
+        Ok(classification)
+    }
+
+    async fn classify_uncached(&self, input: &str) -> Result<Classification> {
         if input.trim().is_empty() {
-            return Ok(Intent::Help);
+            return Ok(Classification {
+                intent: Intent::Help,
+                source: ClassificationSource::Empty,
+                raw_response: None,
+                usage: TokenUsage::new(),
+                confidence: None,
+                segments: Vec::new(),
+            });
         }
 
         if let Some(provider) = &self.provider {
-            let prompt = format!(
-                "请读取用户输入并判断其意图。只输出 JSON，形如 {{\"intent\": \"...\"}}，不输出额外内容。\n用户输入: ```{}```",
-                input.trim()
+            let Some(timeout) = self.timeout else {
+                return self.classify_with_provider(provider, input).await;
+            };
+            // 超时后丢弃请求 future 即取消进行中的 HTTP 请求
+            return match tokio::time::timeout(timeout, self.classify_with_provider(provider, input))
+                .await
+            {
+                Ok(result) => {
+                    debug!(
+                        target: "intent_classifier",
+                        timeout_ms = timeout.as_millis() as u64,
+                        fallback = false,
+                        "路由模型在时限内返回"
+                    );
+                    result
+                }
+                Err(_) => {
+                    self.stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        target: "intent_classifier",
+                        provider = provider.label(),
+                        timeout_ms = timeout.as_millis() as u64,
+                        fallback = true,
+                        "意图路由超时，改用关键字规则"
+                    );
+                    Ok(Classification {
+                        intent: Self::fallback_intent(&self.fallback_keywords, input),
+                        source: ClassificationSource::Timeout,
+                        raw_response: None,
+                        usage: TokenUsage::new(),
+                        confidence: None,
+                        segments: Vec::new(),
+                    })
+                }
+            };
+        }
+
+        // 未配置路由模型时，关键字规则就是唯一的判定方式
+        Ok(self.classify_fallback(input))
+    }
+
+    /// 请求路由模型，无法解析时以更严格的提示重试一次，仍失败则按兜底策略处理
+    async fn classify_with_provider(
+        &self,
+        provider: &IntentProvider,
+        input: &str,
+    ) -> Result<Classification> {
+        let prompt = format!(
+            "请读取用户输入并判断其意图。只输出 JSON，形如 {{\"intent\": \"...\", \"confidence\": 0.9}}，不输出额外内容。\n用户输入: ```{}```",
+            input.trim()
+        );
+
+        let mut last_response = None;
+        let mut usage = TokenUsage::new();
+        if let Some((response, first_usage)) = self.request_counted(provider, &prompt).await {
+            usage += first_usage;
+            if let Some((intent, confidence, segments)) = self.parse_counted(&response) {
+                return Ok(Classification {
+                    intent,
+                    source: ClassificationSource::Provider,
+                    raw_response: Some(response),
+                    usage,
+                    confidence,
+                    segments,
+                });
+            }
+
+            warn!(
+                target: "intent_classifier",
+                provider = provider.label(),
+                response = %response,
+                "模型返回中没有可识别的意图标签，使用更严格的提示重试一次"
             );
 
-            match provider {
-                IntentProvider::OpenAi { agent } => match agent.prompt(&prompt).await {
-                    Ok(response) => {
-                        if let Some(intent) = Self::parse_intent(&response) {
-                            return Ok(intent);
-                        }
+            let retry_prompt = format!("{INTENT_RETRY_PROMPT}\n用户输入: ```{}```", input.trim());
+            last_response = Some(response);
+            if let Some((retry, retry_usage)) = self.request_counted(provider, &retry_prompt).await
+            {
+                usage += retry_usage;
+                if let Some((intent, confidence, segments)) = self.parse_counted(&retry) {
+                    return Ok(Classification {
+                        intent,
+                        source: ClassificationSource::Provider,
+                        raw_response: Some(retry),
+                        usage,
+                        confidence,
+                        segments,
+                    });
+                }
+                last_response = Some(retry);
+            }
 
-                        warn!(
-                            target: "intent_classifier",
-                            response = %response,
-                            "无法从模型返回中解析意图，改用关键字 fallback"
-                        );
+            warn!(
+                target: "intent_classifier",
+                provider = provider.label(),
+                response = last_response.as_deref().unwrap_or_default(),
+                "无法从模型返回中解析意图"
+            );
+        }
+
+        self.provider_fallback(input, last_response, usage)
+    }
+
+    async fn request_counted(
+        &self,
+        provider: &IntentProvider,
+        prompt: &str,
+    ) -> Option<(String, TokenUsage)> {
+        let response = Self::request_intent(provider, prompt).await;
+        if response.is_none() {
+            self.stats.provider_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        response
+    }
+
+    fn parse_counted(&self, response: &str) -> Option<(Intent, Option<f64>, Vec<IntentSegment>)> {
+        let parsed = self.parse_intent(response);
+        if parsed.is_none() {
+            self.stats.parse_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        parsed
+    }
+
+    /// 路由模型失败后按配置的兜底策略处理
+    fn provider_fallback(
+        &self,
+        input: &str,
+        raw_response: Option<String>,
+        usage: TokenUsage,
+    ) -> Result<Classification> {
+        info!(
+            target: "intent_classifier",
+            fallback = self.fallback.as_str(),
+            "意图路由失败，使用兜底策略"
+        );
+        self.stats.fallbacks.fetch_add(1, Ordering::Relaxed);
+
+        let intent = match self.fallback {
+            IntentFallback::Keyword => Self::fallback_intent(&self.fallback_keywords, input),
+            IntentFallback::Conversation => Intent::Conversation,
+            IntentFallback::Error => {
+                return Err(AgentError::other(
+                    "意图识别失败：路由模型不可用或返回无法解析",
+                ));
+            }
+        };
+
+        Ok(Classification {
+            intent,
+            source: ClassificationSource::Fallback,
+            raw_response,
+            usage,
+            confidence: None,
+            segments: Vec::new(),
+        })
+    }
+
+    /// 按配置顺序匹配确定性规则，第一条命中的规则决定意图
+    fn classify_by_rules(&self, input: &str) -> Option<Classification> {
+        if input.trim().is_empty() {
+            return None;
+        }
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.matcher.is_match(input))?;
+        let intent = Intent::from_str(&rule.intent);
+        info!(
+            target: "intent_classifier",
+            rule = %rule.name,
+            %intent,
+            "命中意图规则，跳过路由模型"
+        );
+
+        Some(Classification {
+            intent,
+            source: ClassificationSource::Rule,
+            raw_response: None,
+            usage: TokenUsage::new(),
+            confidence: None,
+            segments: Vec::new(),
+        })
+    }
+
+    /// 仅使用确定性规则与关键字规则判定意图，不发起任何模型请求
+    pub fn classify_fallback(&self, input: &str) -> Classification {
+        if let Some(classification) = self.classify_by_rules(input) {
+            return classification;
+        }
+
+        Classification {
+            intent: Self::fallback_intent(&self.fallback_keywords, input),
+            source: ClassificationSource::Fallback,
+            raw_response: None,
+            usage: TokenUsage::new(),
+            confidence: None,
+            segments: Vec::new(),
+        }
+    }
+
+    async fn request_intent(
+        provider: &IntentProvider,
+        prompt: &str,
+    ) -> Option<(String, TokenUsage)> {
+        match provider {
+            IntentProvider::OpenAi { agent } => match Self::prompt_agent(agent, prompt).await {
+                Ok(response) => Some(response),
+                Err(err) => {
+                    warn!(
+                        target: "intent_classifier",
+                        error = ?err,
+                        "向模型请求意图失败"
+                    );
+                    None
+                }
+            },
+            IntentProvider::DeepSeek { agent } => match Self::prompt_agent(agent, prompt).await {
+                Ok(response) => Some(response),
+                Err(err) => {
+                    warn!(
+                        target: "intent_classifier",
+                        error = ?err,
+                        "向 DeepSeek 请求意图失败"
+                    );
+                    None
+                }
+            },
+            IntentProvider::Mock { agent } => Self::prompt_agent(agent, prompt).await.ok(),
+            IntentProvider::Static { rules, keywords } => {
+                let input = Self::prompt_input(prompt);
+                let label = rules
+                    .iter()
+                    .find(|rule| rule.matcher.is_match(input))
+                    .map(|rule| rule.intent.as_str())
+                    .unwrap_or_else(|| Self::fallback_intent(keywords, input).router_label());
+                Some((json!({ "intent": label }).to_string(), TokenUsage::new()))
+            }
+            IntentProvider::Fixture { mappings } => {
+                let input = Self::prompt_input(prompt);
+                match mappings.get(&IntentCache::key(input)) {
+                    Some(label) => {
+                        Some((json!({ "intent": label }).to_string(), TokenUsage::new()))
                     }
-                    Err(err) => {
+                    None => {
                         warn!(
                             target: "intent_classifier",
-                            error = ?err,
-                            "向模型请求意图失败，改用关键字 fallback"
+                            input,
+                            "意图 fixture 中没有该输入"
                         );
+                        None
                     }
-                },
-                IntentProvider::Zhipu {
-                    model,
-                    system_prompt,
-                } => {
-                    // 使用 rig 的 CompletionModel API
-                    let request = model.completion_request(&prompt)
-                        .preamble(system_prompt.clone())
-                        .build();
-
-                    match model.completion(request).await {
-                        Ok(response) => {
-                            // 从 response.choice 中提取文本
-                            let text = response.choice.iter()
-                                .filter_map(|content| match content {
-                                    rig::completion::message::AssistantContent::Text(t) => Some(t.text.as_str()),
-                                    _ => None,
-                                })
-                                .collect::<Vec<_>>()
-                                .join("\n");
-
-                            if let Some(intent) = Self::parse_intent(&text) {
-                                return Ok(intent);
-                            }
+                }
+            }
+            IntentProvider::Zhipu {
+                model,
+                system_prompt,
+                json_mode,
+                streaming,
+                sampling,
+            } => {
+                // 使用 rig 的 CompletionModel API
+                let mut request = model
+                    .completion_request(prompt)
+                    .preamble(system_prompt.clone())
+                    .temperature_opt(sampling.temperature)
+                    .max_tokens_opt(sampling.max_tokens);
+                if let Some(top_p) = sampling.top_p {
+                    request = request.additional_params(json!({ "top_p": top_p }));
+                }
+                if *json_mode {
+                    request = request
+                        .additional_params(json!({ "response_format": { "type": "json_object" } }));
+                }
+                let request = request.build();
 
-                            warn!(
-                                target: "intent_classifier",
-                                response = %text,
-                                "无法从智谱返回中解析意图，改用关键字 fallback"
-                            );
-                        }
+                if *streaming {
+                    return match Self::stream_zhipu(model, request).await {
+                        Ok(response) => Some(response),
                         Err(err) => {
                             warn!(
                                 target: "intent_classifier",
                                 error = ?err,
-                                "向智谱请求意图失败，改用关键字 fallback"
+                                "向智谱流式请求意图失败"
                             );
+                            None
                         }
+                    };
+                }
+
+                match model.completion(request).await {
+                    Ok(response) => {
+                        // 从 response.choice 中提取文本
+                        let text = response
+                            .choice
+                            .iter()
+                            .filter_map(|content| match content {
+                                rig::completion::message::AssistantContent::Text(t) => {
+                                    Some(t.text.as_str())
+                                }
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        Some((text, response.usage.into()))
+                    }
+                    Err(err) => {
+                        warn!(
+                            target: "intent_classifier",
+                            error = ?err,
+                            "向智谱请求意图失败"
+                        );
+                        None
                     }
                 }
             }
         }
+    }
+
+    /// 流式请求智谱路由模型，累积到第一个完整的 JSON 对象且能严格解析时立即丢弃剩余输出，
+    /// 否则在流结束后返回全部文本；提前结束时拿不到最后一块中的用量，token 记为 0
+    async fn stream_zhipu(
+        model: &ZhipuCompletionModel,
+        request: CompletionRequest,
+    ) -> std::result::Result<(String, TokenUsage), CompletionError> {
+        let started = Instant::now();
+        let mut stream = model.stream(request).await?;
+        let mut text = String::new();
+        let mut usage = TokenUsage::new();
+        let mut first_object_ms = None;
+
+        while let Some(item) = stream.next().await {
+            match item? {
+                StreamedAssistantContent::Text(delta) => {
+                    text.push_str(&delta.text);
+                    if first_object_ms.is_some() || !delta.text.contains('}') {
+                        continue;
+                    }
+                    if let Some(object) = Self::first_json_object(&text)
+                        && Self::parse_strict(object).is_some()
+                    {
+                        let elapsed_ms = started.elapsed().as_millis() as u64;
+                        debug!(
+                            target: "intent_classifier",
+                            elapsed_ms,
+                            chars = text.chars().count(),
+                            early_stop = true,
+                            "流式意图解析完成，丢弃剩余输出"
+                        );
+                        return Ok((object.to_string(), usage));
+                    }
+                    // 第一个对象已经完整但无法解析，之后不再尝试提前结束
+                    if Self::first_json_object(&text).is_some() {
+                        first_object_ms = Some(started.elapsed().as_millis() as u64);
+                    }
+                }
+                StreamedAssistantContent::Final(response) => {
+                    usage = response.token_usage().map_or(usage, TokenUsage::from);
+                }
+                _ => {}
+            }
+        }
+
+        debug!(
+            target: "intent_classifier",
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            first_object_ms = ?first_object_ms,
+            chars = text.chars().count(),
+            early_stop = false,
+            "流式意图请求读取到结束"
+        );
+        Ok((text, usage))
+    }
+
+    /// 返回文本中第一个括号配对完整的 JSON 对象（忽略字符串内的括号），尚不完整时为 `None`
+    fn first_json_object(text: &str) -> Option<&str> {
+        let start = text.find('{')?;
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        for (offset, c) in text[start..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&text[start..=start + offset]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// 不使用 `Prompt`，以便拿到响应中的 token 用量
+    async fn prompt_agent<M>(
+        agent: &Agent<M>,
+        prompt: &str,
+    ) -> std::result::Result<(String, TokenUsage), CompletionError>
+    where
+        M: PromptCacheUsage + 'static,
+    {
+        let response = agent.completion(prompt, Vec::new()).await?.send().await?;
+        let text = response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(t) => Some(t.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok((text, TokenUsage::of::<M>(&response)))
+    }
+
+    /// OpenAI Responses API 的结构化输出参数，保证返回可直接解析的 `{"intent", "confidence"}`；
+    /// 开启拆分时额外要求 `segments` 数组（单一请求时为空）
+    /// 路由配置的采样参数，未配置的温度与 token 上限使用路由专用的默认值
+    fn router_sampling(route: &CapabilityRoute, max_segments: usize) -> SamplingParams {
+        let mut sampling = route.sampling;
+        sampling
+            .temperature
+            .get_or_insert(INTENT_DEFAULT_TEMPERATURE);
+        sampling.max_tokens.get_or_insert(if max_segments > 1 {
+            INTENT_DEFAULT_MAX_TOKENS + INTENT_SEGMENT_MAX_TOKENS * max_segments as u64
+        } else {
+            INTENT_DEFAULT_MAX_TOKENS
+        });
+        sampling
+    }
+
+    fn openai_structured_output(split: bool) -> Value {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "intent": { "type": "string", "enum": ROUTER_INTENT_LABELS },
+                "confidence": { "type": "number" },
+            },
+            "required": ["intent", "confidence"],
+            "additionalProperties": false,
+        });
+        if split {
+            schema["properties"]["segments"] = json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "intent": { "type": "string", "enum": ROUTER_INTENT_LABELS },
+                        "segment": { "type": "string" },
+                    },
+                    "required": ["intent", "segment"],
+                    "additionalProperties": false,
+                },
+            });
+            schema["required"] = json!(["intent", "confidence", "segments"]);
+        }
+
+        json!({
+            "text": {
+                "format": {
+                    "type": "json_schema",
+                    "name": "intent_classification",
+                    "strict": true,
+                    "schema": schema,
+                },
+            },
+        })
+    }
+
+    /// 先按结构化输出严格解析，失败时（提供方不支持 JSON 模式或返回了额外内容）再宽松解析；
+    /// 只有 JSON 形式的响应可能带有置信度与拆分片段
+    fn parse_intent(&self, response: &str) -> Option<(Intent, Option<f64>, Vec<IntentSegment>)> {
+        let (intent, confidence, segments) =
+            Self::parse_strict(response).or_else(|| Self::parse_lenient(response))?;
+        Some((intent, confidence, self.collect_segments(segments)))
+    }
+
+    /// 整段响应必须是 JSON 对象且意图为路由器标签之一
+    fn parse_strict(response: &str) -> Option<ParsedResponse> {
+        let resp = serde_json::from_str::<IntentResponse>(response.trim()).ok()?;
+        let label = resp.intent.trim();
+        if !ROUTER_INTENT_LABELS.contains(&label) {
+            return None;
+        }
+        debug!(
+            target: "intent_classifier",
+            intent = label,
+            confidence = ?resp.confidence,
+            segments = resp.segments.len(),
+            "结构化解析意图"
+        );
+        Some((
+            Intent::from_str(label),
+            Self::valid_confidence(resp.confidence),
+            resp.segments,
+        ))
+    }
 
-        Ok(Self::fallback_intent(input))
+    /// 只接受 0~1 之间的置信度，模型偶尔返回的百分数等其他值视为未给出
+    fn valid_confidence(confidence: Option<f64>) -> Option<f64> {
+        confidence.filter(|value| (0.0..=1.0).contains(value))
     }
 
-    fn parse_intent(response: &str) -> Option<Intent> {
+    /// 有效片段少于两段时视为单一意图；超过上限时只保留前面的片段
+    fn collect_segments(&self, raw: Vec<SegmentResponse>) -> Vec<IntentSegment> {
+        if self.max_segments < 2 {
+            return Vec::new();
+        }
+
+        let mut segments: Vec<IntentSegment> = raw
+            .into_iter()
+            .filter_map(|raw| {
+                let label = raw.intent.trim();
+                let segment = raw.segment.trim();
+                (ROUTER_INTENT_LABELS.contains(&label) && !segment.is_empty()).then(|| {
+                    IntentSegment {
+                        intent: Intent::from_str(label),
+                        segment: segment.to_string(),
+                    }
+                })
+            })
+            .collect();
+        if segments.len() < 2 {
+            return Vec::new();
+        }
+
+        if segments.len() > self.max_segments {
+            warn!(
+                target: "intent_classifier",
+                count = segments.len(),
+                max = self.max_segments,
+                "拆分出的请求段数超过上限，只执行前面的部分"
+            );
+            segments.truncate(self.max_segments);
+        }
+        segments
+    }
+
+    fn parse_lenient(response: &str) -> Option<ParsedResponse> {
         let trimmed = response.trim();
         let sanitized = if trimmed.starts_with("```json") {
             trimmed
@@ -222,41 +1274,744 @@ impl IntentClassifier {
         };
 
         if let Ok(resp) = serde_json::from_str::<IntentResponse>(sanitized) {
-            return Some(Intent::from_str(resp.intent.as_str()));
+            return Some((
+                Intent::from_str(resp.intent.as_str()),
+                Self::valid_confidence(resp.confidence),
+                resp.segments,
+            ));
         }
 
-        if let Ok(value) = serde_json::from_str::<Value>(sanitized) {
-            if let Some(intent) = value.get("intent").and_then(Value::as_str) {
-                return Some(Intent::from_str(intent));
-            }
+        if let Ok(value) = serde_json::from_str::<Value>(sanitized)
+            && let Some(intent) = value.get("intent").and_then(Value::as_str)
+        {
+            let confidence = value.get("confidence").and_then(Value::as_f64);
+            return Some((
+                Intent::from_str(intent),
+                Self::valid_confidence(confidence),
+                Vec::new(),
+            ));
         }
 
-        None
+        // 模型没有按 JSON 输出时，尝试从自由文本中找出唯一的意图标签
+        Intent::from_fuzzy(sanitized).map(|intent| (intent, None, Vec::new()))
     }
 
-    fn fallback_intent(input: &str) -> Intent {
+    /// 取出请求提示词中以 ``` 包裹的用户输入
+    fn prompt_input(prompt: &str) -> &str {
+        prompt.split("```").nth(1).unwrap_or(prompt)
+    }
+
+    /// mock 路由器的回复：按内置关键字输出 JSON
+    fn mock_reply(prompt: &str) -> String {
+        let input = Self::prompt_input(prompt);
+        let intent = Self::fallback_intent(&FallbackKeywords::default(), input);
+        json!({ "intent": intent.router_label() }).to_string()
+    }
+
+    /// fixture 文件为 `{"输入": "意图标签"}` 形式的 JSON 对象，输入按去除首尾空白并转小写后匹配
+    fn load_fixture(route: &CapabilityRoute) -> Result<HashMap<String, String>> {
+        let path = route
+            .fixture
+            .as_ref()
+            .ok_or(AgentError::MissingConfig("providers.intent.fixture"))?;
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            AgentError::other(format!("读取意图 fixture {} 失败: {e}", path.display()))
+        })?;
+        let mappings: HashMap<String, String> = serde_json::from_str(&text).map_err(|e| {
+            AgentError::other(format!("解析意图 fixture {} 失败: {e}", path.display()))
+        })?;
+
+        mappings
+            .into_iter()
+            .map(|(input, intent)| {
+                let intent = intent.trim().to_string();
+                if !ROUTER_INTENT_LABELS.contains(&intent.as_str()) {
+                    return Err(AgentError::other(format!(
+                        "意图 fixture 中 {input:?} 的意图 {intent:?} 无效，可选值: {}",
+                        ROUTER_INTENT_LABELS.join(", ")
+                    )));
+                }
+                Ok((IntentCache::key(&input), intent))
+            })
+            .collect()
+    }
+
+    fn fallback_intent(keywords: &FallbackKeywords, input: &str) -> Intent {
         let normalized = input.to_lowercase();
+        let matches = |list: &[String]| list.iter().any(|k| normalized.contains(k.as_str()));
 
-        let chat_keywords = ["聊", "chat", "问", "explain", "说", "help"];
-        if chat_keywords.iter().any(|k| normalized.contains(k)) {
+        if matches(&keywords.live_control) {
+            return Intent::LiveControl;
+        }
+        if matches(&keywords.conversation) {
             return Intent::Conversation;
         }
-
-        let image_keywords = ["画", "image", "绘", "图", "picture", "logo", "design"];
-        if image_keywords.iter().any(|k| normalized.contains(k)) {
+        if matches(&keywords.image_generation) {
             return Intent::ImageGeneration;
         }
-
-        let music_keywords = ["music", "旋律", "歌曲", "歌", "伴奏", "和弦", "曲"];
-        if music_keywords.iter().any(|k| normalized.contains(k)) {
+        if matches(&keywords.music_generation) {
             return Intent::MusicGeneration;
         }
-
-        let video_keywords = ["视频", "video", "动画", "片段", "mv", "剪辑"];
-        if video_keywords.iter().any(|k| normalized.contains(k)) {
+        if matches(&keywords.video_generation) {
             return Intent::VideoGeneration;
         }
 
         Intent::Conversation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_support::spawn_stub;
+
+    /// 路由模型换成按提示词给出固定回复的 mock
+    fn with_reply(reply: fn(&str) -> String, intent: &str) -> IntentClassifier {
+        let config = IntentConfig::from_toml(intent).unwrap();
+        let mut classifier = IntentClassifier::new(None, None, None, None, None, &config).unwrap();
+        classifier.provider = Some(IntentProvider::Mock {
+            agent: AgentBuilder::new(MockCompletionModel::new(reply)).build(),
+        });
+        classifier
+    }
+
+    /// 与 `with_reply` 相同，但按 `[providers.intent]` 配置了路由，意图缓存与超时随之启用
+    fn routed_with_reply(reply: fn(&str) -> String, intent: &str) -> IntentClassifier {
+        let route = CapabilityRoute::from_toml("intent", "provider = \"mock\"").unwrap();
+        let config = IntentConfig::from_toml(intent).unwrap();
+        let mut classifier =
+            IntentClassifier::new(Some(&route), None, None, None, None, &config).unwrap();
+        classifier.provider = Some(IntentProvider::Mock {
+            agent: AgentBuilder::new(MockCompletionModel::new(reply)).build(),
+        });
+        classifier
+    }
+
+    #[test]
+    fn fuzzy_labels_in_messy_outputs() {
+        let cases = [
+            ("这应该是绘画请求吧", Some(Intent::ImageGeneration)),
+            (
+                "I think this is image_generation.",
+                Some(Intent::ImageGeneration),
+            ),
+            ("Intent: MUSIC", Some(Intent::MusicGeneration)),
+            ("用户想要一段视频", Some(Intent::VideoGeneration)),
+            ("帮助", Some(Intent::Help)),
+            // 英文标签按整词匹配，start 中的 art 不算
+            ("let's start", None),
+            ("图片还是音乐？", None),
+            ("嗯……", None),
+        ];
+        for (response, expected) in cases {
+            assert_eq!(Intent::from_fuzzy(response), expected, "{response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_once_with_stricter_prompt() {
+        let classifier = with_reply(
+            |prompt: &str| {
+                if prompt.starts_with(INTENT_RETRY_PROMPT) {
+                    "image_generation".to_string()
+                } else {
+                    "嗯……让我想想".to_string()
+                }
+            },
+            "",
+        );
+        let result = classifier.classify_detailed("来张赛博朋克").await.unwrap();
+        assert_eq!(result.intent, Intent::ImageGeneration);
+        assert_eq!(result.source, ClassificationSource::Provider);
+        assert_eq!(result.raw_response.as_deref(), Some("image_generation"));
+        assert_eq!(classifier.stats()["parse_failures"], 1);
+
+        let classifier = with_reply(|_: &str| "让我想想".to_string(), "");
+        let result = classifier.classify_detailed("来张赛博朋克").await.unwrap();
+        assert_eq!(result.intent, Intent::Conversation);
+        assert_eq!(result.source, ClassificationSource::Fallback);
+        assert_eq!(result.raw_response.as_deref(), Some("让我想想"));
+        assert_eq!(classifier.stats()["parse_failures"], 2);
+    }
+
+    #[test]
+    fn route_preamble_extends_router_rules() {
+        let route = CapabilityRoute::from_toml(
+            "intent",
+            "provider = \"mock\"\npreamble = \"  出图按 image_generation 处理  \"",
+        )
+        .unwrap();
+        let prompt = IntentClassifier::router_prompt(&route, 1);
+        assert!(prompt.starts_with(INTENT_ROUTER_SYSTEM_PROMPT), "{prompt}");
+        assert!(
+            prompt.ends_with("\n出图按 image_generation 处理"),
+            "{prompt}"
+        );
+
+        let route = CapabilityRoute::from_toml("intent", "provider = \"mock\"").unwrap();
+        assert_eq!(
+            IntentClassifier::router_prompt(&route, 1),
+            INTENT_ROUTER_SYSTEM_PROMPT
+        );
+    }
+
+    #[test]
+    fn examples_are_rendered_in_order_and_capped() {
+        let route = CapabilityRoute::from_toml(
+            "intent",
+            r#"
+            provider = "mock"
+            preamble = "出图属于画图请求"
+            examples = [
+                { input = " 整个活 ", intent = "conversation" },
+                { input = "出图", intent = "image_generation" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let prompt = IntentClassifier::router_prompt(&route, 1);
+        assert!(
+            prompt.ends_with(concat!(
+                "\n出图属于画图请求\n示例：",
+                "\n用户输入: ```整个活``` → {\"intent\":\"conversation\"}",
+                "\n用户输入: ```出图``` → {\"intent\":\"image_generation\"}",
+            )),
+            "{prompt}"
+        );
+
+        let examples = (0..MAX_INTENT_EXAMPLES + 5)
+            .map(|index| format!("{{ input = \"样例{index}\", intent = \"help\" }}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let route = CapabilityRoute::from_toml(
+            "intent",
+            &format!("provider = \"mock\"\nexamples = [{examples}]"),
+        )
+        .unwrap();
+        let prompt = IntentClassifier::router_prompt(&route, 1);
+        assert_eq!(prompt.matches("用户输入: ").count(), MAX_INTENT_EXAMPLES);
+        assert!(prompt.contains(&format!("样例{}```", MAX_INTENT_EXAMPLES - 1)));
+        assert!(!prompt.contains(&format!("样例{}```", MAX_INTENT_EXAMPLES)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cache_evicts_oldest_and_expires() {
+        let classifier = routed_with_reply(
+            IntentClassifier::mock_reply,
+            "cache_max_entries = 2\ncache_ttl_seconds = 60",
+        );
+        let source = |input: &'static str| {
+            let classifier = &classifier;
+            async move { classifier.classify_detailed(input).await.unwrap().source }
+        };
+
+        assert_eq!(source("晚上好").await, ClassificationSource::Provider);
+        assert_eq!(source("  晚上好 ").await, ClassificationSource::Cache);
+        assert_eq!(source("画只猫").await, ClassificationSource::Provider);
+        assert_eq!(source("来首歌").await, ClassificationSource::Provider);
+        // 容量为 2，最久未访问的“晚上好”已被淘汰
+        assert_eq!(source("晚上好").await, ClassificationSource::Provider);
+        assert_eq!(source("来首歌").await, ClassificationSource::Cache);
+        assert_eq!(
+            classifier.cache_stats().unwrap(),
+            json!({ "hits": 2, "misses": 4, "entries": 2 })
+        );
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(source("来首歌").await, ClassificationSource::Provider);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fallback_results_use_their_own_ttl() {
+        let unparseable = |_: &str| "不知道".to_string();
+        let classifier = routed_with_reply(unparseable, "cache_fallback_ttl_seconds = 5");
+        let first = classifier.classify_detailed("整个活").await.unwrap();
+        assert_eq!(first.source, ClassificationSource::Fallback);
+        let second = classifier.classify_detailed("整个活").await.unwrap();
+        assert_eq!(second.source, ClassificationSource::Cache);
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let third = classifier.classify_detailed("整个活").await.unwrap();
+        assert_eq!(third.source, ClassificationSource::Fallback);
+
+        // 未配置 cache_fallback_ttl_seconds 时不缓存兜底结果
+        let classifier = routed_with_reply(unparseable, "");
+        for _ in 0..2 {
+            let result = classifier.classify_detailed("整个活").await.unwrap();
+            assert_eq!(result.source, ClassificationSource::Fallback);
+        }
+        assert_eq!(classifier.cache_stats().unwrap()["entries"], 0);
+    }
+
+    const ORDERED_RULES: &str = r#"
+        [[rules]]
+        name = "live"
+        keywords = ["直播"]
+        intent = "live_control"
+
+        [[rules]]
+        name = "draw"
+        pattern = "^画"
+        intent = "image_generation"
+    "#;
+
+    #[tokio::test]
+    async fn rules_run_in_order_before_the_router() {
+        let classifier = with_reply(|_: &str| "music_generation".to_string(), ORDERED_RULES);
+        let cases = [
+            (
+                "画一只猫",
+                Intent::ImageGeneration,
+                ClassificationSource::Rule,
+            ),
+            // 两条规则都命中时取配置在前的一条
+            (
+                "画直播封面",
+                Intent::LiveControl,
+                ClassificationSource::Rule,
+            ),
+            // 正则匹配去除首尾空白后的输入
+            (
+                "  画山水",
+                Intent::ImageGeneration,
+                ClassificationSource::Rule,
+            ),
+            (
+                "来首歌",
+                Intent::MusicGeneration,
+                ClassificationSource::Provider,
+            ),
+        ];
+        for (input, intent, source) in cases {
+            let result = classifier.classify_detailed(input).await.unwrap();
+            assert_eq!((result.intent, result.source), (intent, source), "{input}");
+        }
+        assert_eq!(classifier.stats()["by_source"]["rule"], 3);
+        assert_eq!(classifier.stats()["by_source"]["provider"], 1);
+        assert_eq!(
+            classifier.classify_fallback("画直播封面").source,
+            ClassificationSource::Rule
+        );
+    }
+
+    #[test]
+    fn fallback_keywords_are_overridable() {
+        let config =
+            IntentConfig::from_toml("[fallback_keywords]\nimage_generation = [\" 出图 \"]")
+                .unwrap();
+        let classifier = IntentClassifier::new(None, None, None, None, None, &config).unwrap();
+        assert_eq!(
+            classifier.classify_fallback("出图！").intent,
+            Intent::ImageGeneration
+        );
+        // 覆盖后不再使用内置的图像关键字，其他类别沿用内置关键字
+        assert_eq!(
+            classifier.classify_fallback("画一只猫").intent,
+            Intent::Conversation
+        );
+        assert_eq!(
+            classifier.classify_fallback("写首歌").intent,
+            Intent::MusicGeneration
+        );
+    }
+
+    #[test]
+    fn strict_parsing_requires_a_bare_router_json() {
+        let strict = |response: &str| {
+            IntentClassifier::parse_strict(response).map(|(intent, _, segments)| {
+                assert!(segments.is_empty());
+                intent
+            })
+        };
+        assert_eq!(
+            strict(r#" {"intent": "image_generation", "confidence": 0.92} "#),
+            Some(Intent::ImageGeneration)
+        );
+        assert_eq!(strict(r#"{"intent": "help"}"#), Some(Intent::Help));
+        for response in [
+            r#"{"intent": "drawing"}"#,
+            "```json\n{\"intent\": \"help\"}\n```",
+            r#"好的：{"intent": "help"}"#,
+            r#"{"confidence": 0.5}"#,
+            "help",
+        ] {
+            assert_eq!(strict(response), None, "{response}");
+        }
+    }
+
+    #[test]
+    fn lenient_parsing_accepts_what_strict_rejects() {
+        let config = IntentConfig::from_toml("").unwrap();
+        let classifier = IntentClassifier::new(None, None, None, None, None, &config).unwrap();
+        let cases = [
+            (r#"{"intent": "drawing"}"#, Some(Intent::ImageGeneration)),
+            ("```json\n{\"intent\": \"help\"}\n```", Some(Intent::Help)),
+            (
+                "```\n{\"intent\": \"video\"}\n```",
+                Some(Intent::VideoGeneration),
+            ),
+            (
+                r#"{"intent": "music", "extra": [1, 2]}"#,
+                Some(Intent::MusicGeneration),
+            ),
+            (r#"{"intent": 3}"#, None),
+        ];
+        for (response, expected) in cases {
+            let parsed = classifier
+                .parse_intent(response)
+                .map(|(intent, _, _)| intent);
+            assert_eq!(parsed, expected, "{response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn router_confidence_is_exposed_and_cached() {
+        let classifier = routed_with_reply(
+            |prompt| {
+                if prompt.contains("来点好玩的") {
+                    r#"{"intent": "image_generation", "confidence": 0.35}"#.to_string()
+                } else {
+                    r#"```json
+{"intent": "music", "confidence": 87}
+```"#
+                        .to_string()
+                }
+            },
+            "",
+        );
+
+        let first = classifier.classify_detailed("来点好玩的").await.unwrap();
+        assert_eq!(first.source, ClassificationSource::Provider);
+        assert_eq!(first.confidence, Some(0.35));
+        let cached = classifier.classify_detailed("来点好玩的").await.unwrap();
+        assert_eq!(cached.source, ClassificationSource::Cache);
+        assert_eq!(cached.confidence, Some(0.35));
+
+        // 宽松解析同样读取置信度，超出 0~1 的值视为未给出
+        let lenient = classifier.classify_detailed("整首曲子").await.unwrap();
+        assert_eq!(lenient.intent, Intent::MusicGeneration);
+        assert_eq!(lenient.confidence, None);
+        assert_eq!(classifier.classify_fallback("画一只猫").confidence, None);
+    }
+
+    #[test]
+    fn structured_output_schema_lists_router_labels() {
+        let single = IntentClassifier::openai_structured_output(false);
+        let schema = &single["text"]["format"]["schema"];
+        assert_eq!(single["text"]["format"]["strict"], true);
+        assert_eq!(
+            schema["properties"]["intent"]["enum"],
+            json!(ROUTER_INTENT_LABELS)
+        );
+        assert_eq!(schema["required"], json!(["intent", "confidence"]));
+        assert!(schema["properties"].get("segments").is_none());
+
+        let split = IntentClassifier::openai_structured_output(true);
+        assert_eq!(
+            split["text"]["format"]["schema"]["required"],
+            json!(["intent", "confidence", "segments"])
+        );
+    }
+
+    #[test]
+    fn first_complete_json_object_in_a_stream() {
+        let first = IntentClassifier::first_json_object;
+        assert_eq!(first(r#"{"intent": "he"#), None);
+        assert_eq!(
+            first(r#"好的 {"intent": "help", "note": "{}"} 其余"#),
+            Some(r#"{"intent": "help", "note": "{}"}"#)
+        );
+        assert_eq!(
+            first(r#"{"intent": "a\"}", "segments": [{}]}"#),
+            Some(r#"{"intent": "a\"}", "segments": [{}]}"#)
+        );
+    }
+
+    /// 迟迟不返回的路由模型
+    async fn sleeping_router() -> DeepSeekConfig {
+        let app = axum::Router::new().route(
+            "/chat/completions",
+            axum::routing::post(|| async {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                "{}"
+            }),
+        );
+        let addr = spawn_stub(app).await;
+        DeepSeekConfig {
+            api_key: "test".to_string(),
+            chat_model: "deepseek-chat".to_string(),
+            agent_preamble: String::new(),
+            api_url: format!("http://{addr}/chat/completions"),
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_router_times_out_to_keywords() {
+        let deepseek = sleeping_router().await;
+        let route =
+            CapabilityRoute::from_toml("intent", "provider = \"deepseek\"\ntimeout_ms = 100")
+                .unwrap();
+        // 超时直接按关键字规则判定，不走 error 兜底策略
+        let config = IntentConfig::from_toml("fallback = \"error\"").unwrap();
+        let classifier =
+            IntentClassifier::new(Some(&route), None, None, None, Some(&deepseek), &config)
+                .unwrap();
+
+        let started = Instant::now();
+        let result = classifier.classify_detailed("画一只猫").await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(result.intent, Intent::ImageGeneration);
+        assert_eq!(result.source, ClassificationSource::Timeout);
+        assert_eq!(classifier.classifier_timeouts(), 1);
+        assert_eq!(classifier.stats()["timeouts"], 1);
+        assert_eq!(classifier.stats()["fallbacks"], 0);
+
+        let route = CapabilityRoute::from_toml("intent", "provider = \"deepseek\"\ntimeout_ms = 0")
+            .unwrap();
+        let classifier =
+            IntentClassifier::new(Some(&route), None, None, None, Some(&deepseek), &config)
+                .unwrap();
+        assert_eq!(classifier.timeout, None);
+
+        let route = CapabilityRoute::from_toml("intent", "provider = \"deepseek\"").unwrap();
+        let classifier =
+            IntentClassifier::new(Some(&route), None, None, None, Some(&deepseek), &config)
+                .unwrap();
+        assert_eq!(classifier.timeout, Some(DEFAULT_CLASSIFY_TIMEOUT));
+    }
+
+    #[tokio::test]
+    async fn deepseek_prompt_cache_tokens_are_kept() {
+        let app = axum::Router::new().route(
+            "/chat/completions",
+            axum::routing::post(|| async {
+                axum::Json(json!({
+                    "choices": [{ "message": { "content": "{\"intent\": \"image_generation\"}" } }],
+                    "usage": {
+                        "prompt_tokens": 120,
+                        "completion_tokens": 8,
+                        "total_tokens": 128,
+                        "prompt_cache_hit_tokens": 96,
+                        "prompt_cache_miss_tokens": 24,
+                    },
+                }))
+            }),
+        );
+        let addr = spawn_stub(app).await;
+        let deepseek = DeepSeekConfig {
+            api_key: "test".to_string(),
+            chat_model: "deepseek-chat".to_string(),
+            agent_preamble: String::new(),
+            api_url: format!("http://{addr}/chat/completions"),
+        };
+        let route = CapabilityRoute::from_toml("intent", "provider = \"deepseek\"").unwrap();
+        let config = IntentConfig::from_toml("").unwrap();
+        let classifier =
+            IntentClassifier::new(Some(&route), None, None, None, Some(&deepseek), &config)
+                .unwrap();
+
+        let result = classifier.classify_detailed("画一只猫").await.unwrap();
+        assert_eq!(result.intent, Intent::ImageGeneration);
+        assert_eq!(
+            result.usage,
+            TokenUsage {
+                input_tokens: 120,
+                output_tokens: 8,
+                total_tokens: 128,
+                prompt_cache_hit_tokens: 96,
+                prompt_cache_miss_tokens: 24,
+            }
+        );
+    }
+
+    fn segments_of(max_segments: usize, response: &str) -> Option<(Intent, Vec<(Intent, String)>)> {
+        let config = IntentConfig::from_toml(&format!("max_segments = {max_segments}")).unwrap();
+        let classifier = IntentClassifier::new(None, None, None, None, None, &config).unwrap();
+        let (intent, _, segments) = classifier.parse_intent(response)?;
+        let segments = segments
+            .into_iter()
+            .map(|segment| (segment.intent, segment.segment))
+            .collect();
+        Some((intent, segments))
+    }
+
+    #[test]
+    fn compound_requests_are_split_in_order() {
+        let response = json!({
+            "intent": "conversation",
+            "confidence": 0.8,
+            "segments": [
+                { "intent": "conversation", "segment": " 先聊两句今天的直播计划 " },
+                { "intent": "image_generation", "segment": "然后画一张封面图" },
+            ],
+        })
+        .to_string();
+        assert_eq!(
+            segments_of(3, &response),
+            Some((
+                Intent::Conversation,
+                vec![
+                    (Intent::Conversation, "先聊两句今天的直播计划".to_string()),
+                    (Intent::ImageGeneration, "然后画一张封面图".to_string()),
+                ]
+            ))
+        );
+        // 未开启拆分时只取首个意图
+        assert_eq!(
+            segments_of(1, &response),
+            Some((Intent::Conversation, vec![]))
+        );
+
+        // 单一意图的旧格式保持不变
+        assert_eq!(
+            segments_of(3, r#"{"intent": "help", "confidence": 1, "segments": []}"#),
+            Some((Intent::Help, vec![]))
+        );
+        assert_eq!(
+            segments_of(3, r#"{"intent": "help"}"#),
+            Some((Intent::Help, vec![]))
+        );
+    }
+
+    #[test]
+    fn segments_beyond_the_cap_are_dropped() {
+        let segment = |intent: &str, text: &str| json!({ "intent": intent, "segment": text });
+        let response = json!({
+            "intent": "conversation",
+            "segments": [
+                segment("conversation", "一"),
+                segment("drawing", "无效标签"),
+                segment("image_generation", "  "),
+                segment("image_generation", "二"),
+                segment("music_generation", "三"),
+                segment("video_generation", "四"),
+            ],
+        })
+        .to_string();
+        let (_, segments) = segments_of(3, &response).unwrap();
+        let texts: Vec<_> = segments.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, ["一", "二", "三"]);
+
+        // 过滤后只剩一段时视为单一意图
+        let response = json!({
+            "intent": "image_generation",
+            "segments": [segment("image_generation", "画猫"), segment("drawing", "无效")],
+        })
+        .to_string();
+        assert_eq!(
+            segments_of(3, &response),
+            Some((Intent::ImageGeneration, vec![]))
+        );
+    }
+
+    #[test]
+    fn split_prompt_and_token_budget_follow_max_segments() {
+        let route = CapabilityRoute::from_toml("intent", "provider = \"mock\"").unwrap();
+        assert!(IntentClassifier::router_prompt(&route, 3).contains("最多 3 段"));
+        assert!(!IntentClassifier::router_prompt(&route, 1).contains("segments"));
+        assert_eq!(
+            IntentClassifier::router_sampling(&route, 3).max_tokens,
+            Some(INTENT_DEFAULT_MAX_TOKENS + 3 * INTENT_SEGMENT_MAX_TOKENS)
+        );
+        assert_eq!(
+            IntentClassifier::router_sampling(&route, 1).max_tokens,
+            Some(INTENT_DEFAULT_MAX_TOKENS)
+        );
+    }
+
+    fn fixture_classifier(name: &str, intent: &str) -> IntentClassifier {
+        let path = std::env::temp_dir().join(format!(
+            "vutber-intent-fixture-{name}-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            json!({ " 晚上好 ": "conversation", "画猫": "image_generation" }).to_string(),
+        )
+        .unwrap();
+        let route = CapabilityRoute::from_toml(
+            "intent",
+            &format!(
+                "provider = \"fixture\"\nfixture = {:?}",
+                path.display().to_string()
+            ),
+        )
+        .unwrap();
+        let config = IntentConfig::from_toml(intent).unwrap();
+        let classifier =
+            IntentClassifier::new(Some(&route), None, None, None, None, &config).unwrap();
+        std::fs::remove_file(path).unwrap();
+        classifier
+    }
+
+    #[tokio::test]
+    async fn stats_after_a_scripted_sequence() {
+        let classifier = fixture_classifier(
+            "stats",
+            "fallback = \"keyword\"\n[[rules]]\nkeywords = [\"直播状态\"]\nintent = \"live_control\"",
+        );
+        for input in ["", "晚上好", "画猫", "直播状态", "未知输入", "晚上好"] {
+            classifier.classify_detailed(input).await.unwrap();
+        }
+        assert_eq!(
+            classifier.stats(),
+            json!({
+                "total": 6,
+                "errors": 0,
+                // fixture 中没有的输入按请求失败计，不会重试
+                "provider_errors": 1,
+                "parse_failures": 0,
+                "fallbacks": 1,
+                "timeouts": 0,
+                "fallback_rate": 1.0 / 6.0,
+                "cache_hits": 1,
+                "by_source": {
+                    "empty": 1, "provider": 2, "fallback": 1,
+                    "rule": 1, "cache": 1, "timeout": 0,
+                },
+                "by_intent": {
+                    "chat": 3, "image": 1, "music": 0, "video": 0,
+                    "live": 1, "help": 1, "unknown": 0,
+                },
+            })
+        );
+
+        let classifier = fixture_classifier("errors", "fallback = \"error\"");
+        assert!(classifier.classify_detailed("未知输入").await.is_err());
+        assert_eq!(classifier.stats()["errors"], 1);
+        assert_eq!(classifier.stats()["fallbacks"], 1);
+        assert_eq!(classifier.stats()["by_source"]["fallback"], 0);
+    }
+
+    #[test]
+    fn bare_label_responses() {
+        let config = IntentConfig::from_toml("").unwrap();
+        let classifier = IntentClassifier::new(None, None, None, None, None, &config).unwrap();
+        let cases = [
+            ("image_generation", Some(Intent::ImageGeneration)),
+            ("  music_generation\n", Some(Intent::MusicGeneration)),
+            ("意图：绘画请求", Some(Intent::ImageGeneration)),
+            ("Intent: video_generation.", Some(Intent::VideoGeneration)),
+            ("**conversation**", Some(Intent::Conversation)),
+            ("这是一个闲聊。", Some(Intent::Conversation)),
+            ("用户想听歌曲，应为 music", Some(Intent::MusicGeneration)),
+            (
+                "live_control（查询直播监听状态）",
+                Some(Intent::LiveControl),
+            ),
+            ("Label: help", Some(Intent::Help)),
+            ("```\nimage\n```", Some(Intent::ImageGeneration)),
+            // 同时出现多个意图时无法判定
+            ("image_generation 或 music_generation", None),
+            ("可能是画图，也可能是视频", None),
+            // 整词匹配：artist、starting 中的 art 不算
+            ("The artist is starting", None),
+            ("我不确定", None),
+        ];
+        for (response, expected) in cases {
+            let parsed = classifier
+                .parse_intent(response)
+                .map(|(intent, _, _)| intent);
+            assert_eq!(parsed, expected, "{response}");
+        }
+    }
+}
@@ -1,3 +1,3 @@
 mod classifier;
 
-pub use classifier::{Intent, IntentClassifier};
+pub use classifier::{Classification, Intent, IntentClassifier, IntentSegment};
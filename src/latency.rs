@@ -0,0 +1,106 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde_json::{Map, Value, json};
+
+/// 指数移动平均的平滑系数，越大越偏向最近的调用
+const EMA_ALPHA: f64 = 0.2;
+
+/// 按能力与提供方记录模型/生成调用的耗时（指数移动平均），用于判断哪个提供方偏慢
+///
+/// 能力路由在启动时登记，之后只更新原子计数，`&self` 即可记录
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    entries: Vec<LatencyEntry>,
+}
+
+#[derive(Debug)]
+struct LatencyEntry {
+    capability: &'static str,
+    provider: String,
+    /// 以 `f64::to_bits` 存储的平均耗时（毫秒）
+    ema_ms: AtomicU64,
+    /// 最近一次与最长一次的耗时（微秒）
+    last_us: AtomicU64,
+    max_us: AtomicU64,
+    samples: AtomicU64,
+}
+
+impl LatencyStats {
+    /// `routes` 为已配置的（能力, 提供方）
+    pub fn new(routes: impl IntoIterator<Item = (&'static str, String)>) -> Self {
+        let entries = routes
+            .into_iter()
+            .map(|(capability, provider)| LatencyEntry {
+                capability,
+                provider,
+                ema_ms: AtomicU64::new(0f64.to_bits()),
+                last_us: AtomicU64::new(0),
+                max_us: AtomicU64::new(0),
+                samples: AtomicU64::new(0),
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// 记录一次成功调用的耗时；未登记的能力直接忽略
+    pub fn record(&self, capability: &str, elapsed: Duration) {
+        let Some(entry) = self
+            .entries
+            .iter()
+            .find(|entry| entry.capability == capability)
+        else {
+            return;
+        };
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        let first = entry.samples.fetch_add(1, Ordering::Relaxed) == 0;
+        let _ = entry
+            .ema_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let next = if first {
+                    millis
+                } else {
+                    let previous = f64::from_bits(bits);
+                    previous + EMA_ALPHA * (millis - previous)
+                };
+                Some(next.to_bits())
+            });
+        let micros = elapsed.as_micros() as u64;
+        entry.last_us.store(micros, Ordering::Relaxed);
+        entry.max_us.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// 按提供方分组：`{ provider: { capability: { ema_ms, last_ms, max_ms, samples } } }`，未调用过的能力不输出
+    pub fn snapshot(&self) -> Value {
+        let mut providers = Map::new();
+        for entry in &self.entries {
+            let samples = entry.samples.load(Ordering::Relaxed);
+            if samples == 0 {
+                continue;
+            }
+            let ema_ms = f64::from_bits(entry.ema_ms.load(Ordering::Relaxed));
+            let capabilities = providers
+                .entry(entry.provider.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(capabilities) = capabilities {
+                capabilities.insert(
+                    entry.capability.to_string(),
+                    json!({
+                        "ema_ms": round_ms(ema_ms),
+                        "last_ms": round_ms(entry.last_us.load(Ordering::Relaxed) as f64 / 1000.0),
+                        "max_ms": round_ms(entry.max_us.load(Ordering::Relaxed) as f64 / 1000.0),
+                        "samples": samples,
+                    }),
+                );
+            }
+        }
+        Value::Object(providers)
+    }
+}
+
+/// 保留一位小数
+fn round_ms(millis: f64) -> f64 {
+    (millis * 10.0).round() / 10.0
+}
@@ -0,0 +1,32 @@
+//! VutberAgent 的库入口：意图识别、各项能力与编排控制器，可脱离 SSE 服务单独嵌入使用
+
+pub mod cache;
+pub mod capabilities;
+pub mod clarification;
+pub mod config;
+pub mod errors;
+pub mod expression;
+pub mod health;
+pub mod intent;
+pub mod latency;
+pub mod limiter;
+#[cfg(feature = "live")]
+pub mod live;
+pub mod moderation;
+pub mod orchestrator;
+pub mod outcomes;
+pub mod postprocess;
+pub mod providers;
+pub mod scheduler;
+pub mod sse;
+pub mod usage;
+pub mod util;
+// mod ws;  // 已被 SSE 替代，保留文件作为参考
+
+pub use capabilities::BinaryArtifact;
+pub use config::AppConfig;
+pub use errors::{AgentError, Result};
+pub use intent::{Intent, IntentClassifier};
+#[cfg(feature = "live")]
+pub use live::LiveEvent;
+pub use orchestrator::{AgentController, ExecutionOutcome, RequestSource};
@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde_json::{Value, json};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::info;
+
+use crate::{
+    config::GenerationOverflow,
+    errors::{AgentError, Result},
+};
+
+/// 限制同时进行的图像/音乐/视频生成数，避免组合请求、分镜与工具调用同时打满上游而被限流
+///
+/// 未配置上限时只统计进行中的生成数；`&self` 即可获取名额
+#[derive(Debug)]
+pub struct GenerationLimiter {
+    /// 未配置上限时为 `None`
+    semaphore: Option<Semaphore>,
+    limit: Option<usize>,
+    overflow: GenerationOverflow,
+    in_flight: AtomicUsize,
+    /// 正在排队等待名额的生成数
+    queued: AtomicUsize,
+    /// 因达到上限被拒绝的次数
+    rejected: AtomicU64,
+}
+
+/// 一个生成名额，drop 时归还
+#[derive(Debug)]
+pub struct GenerationPermit<'a> {
+    in_flight: &'a AtomicUsize,
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for GenerationPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 排队期间计入 `queued`，等待被取消时同样扣回
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl GenerationLimiter {
+    pub fn new(limit: Option<usize>, overflow: GenerationOverflow) -> Self {
+        Self {
+            semaphore: limit.map(Semaphore::new),
+            limit,
+            overflow,
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// 获取一个生成名额：未达上限时立即返回；达到上限时按配置排队等待，或以错误码 `busy` 拒绝
+    pub async fn acquire(&self, capability: &str) -> Result<GenerationPermit<'_>> {
+        let permit = match self.semaphore.as_ref().zip(self.limit) {
+            None => None,
+            Some((semaphore, limit)) => Some(match semaphore.try_acquire() {
+                Ok(permit) => permit,
+                Err(_) if self.overflow == GenerationOverflow::Reject => {
+                    self.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(AgentError::rejected(
+                        "busy",
+                        format!("同时进行的生成已达上限 {limit}，请稍后再试"),
+                    ));
+                }
+                Err(_) => {
+                    self.queued.fetch_add(1, Ordering::Relaxed);
+                    let _queued = Queued(&self.queued);
+                    info!(target: "agent_controller", capability, "生成名额已满，排队等待");
+                    semaphore
+                        .acquire()
+                        .await
+                        .map_err(|err| AgentError::other(err.to_string()))?
+                }
+            }),
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(GenerationPermit {
+            in_flight: &self.in_flight,
+            _permit: permit,
+        })
+    }
+
+    /// 正在进行的生成数
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// `{ in_flight, queued, rejected, limit, overflow }`，未配置上限时 `limit` 为 `null`
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "in_flight": self.in_flight(),
+            "queued": self.queued.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+            "limit": self.limit,
+            "overflow": self.overflow.as_str(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reject_mode_refuses_when_full() {
+        let limiter = GenerationLimiter::new(Some(1), GenerationOverflow::Reject);
+        let permit = limiter.acquire("image").await.unwrap();
+        let err = limiter.acquire("video").await.unwrap_err();
+        assert_eq!(err.code(), Some("busy"));
+        assert_eq!(limiter.in_flight(), 1);
+        drop(permit);
+        assert!(limiter.acquire("video").await.is_ok());
+        assert_eq!(limiter.snapshot()["rejected"], 1);
+    }
+
+    #[tokio::test]
+    async fn queue_mode_waits_for_a_permit() {
+        let limiter = GenerationLimiter::new(Some(1), GenerationOverflow::Queue);
+        let permit = limiter.acquire("image").await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("music"));
+        assert!(waiting.await.is_err());
+        // 等待被取消后不再计入排队
+        assert_eq!(limiter.snapshot()["queued"], 0);
+        drop(permit);
+        assert!(limiter.acquire("music").await.is_ok());
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn unlimited_never_waits() {
+        let limiter = GenerationLimiter::new(None, GenerationOverflow::Reject);
+        let permits = [
+            limiter.acquire("image").await.unwrap(),
+            limiter.acquire("video").await.unwrap(),
+        ];
+        assert_eq!(limiter.in_flight(), 2);
+        assert!(limiter.snapshot()["limit"].is_null());
+        drop(permits);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+}
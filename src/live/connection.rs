@@ -0,0 +1,193 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// 长链的连接状态，由读取任务在连接、断线与重试时更新
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveConnectionState {
+    /// 首次连接中，尚未收到鉴权回包
+    Connecting,
+    Connected,
+    /// 长链已断开，正在按退避间隔重连，期间的弹幕会丢失
+    Reconnecting,
+    /// 长链仍在，但项目心跳失败，B 站可能随时结束本场推送
+    Degraded,
+    /// 重连次数用完或推送已结束，不再接收事件，需要 `live_stop` 后重新开启
+    Disconnected,
+}
+
+impl LiveConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiveConnectionState::Connecting => "connecting",
+            LiveConnectionState::Connected => "connected",
+            LiveConnectionState::Reconnecting => "reconnecting",
+            LiveConnectionState::Degraded => "degraded",
+            LiveConnectionState::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// `live.status` 中的连接状态
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveConnectionSnapshot {
+    pub state: LiveConnectionState,
+    /// 当前这轮重连已尝试的次数，连接成功后归零
+    pub reconnect_attempt: u32,
+    /// 本场累计重连成功的次数
+    pub reconnects: u32,
+    /// 最近一次断线或心跳失败的原因，恢复后仍保留以便排查
+    pub last_error: Option<String>,
+}
+
+/// 单次直播会话的连接状态
+///
+/// 只由长链读取任务写入，查询方读取快照。
+#[derive(Debug)]
+pub struct LiveConnection {
+    snapshot: Mutex<LiveConnectionSnapshot>,
+}
+
+impl Default for LiveConnection {
+    fn default() -> Self {
+        Self {
+            snapshot: Mutex::new(LiveConnectionSnapshot {
+                state: LiveConnectionState::Connecting,
+                reconnect_attempt: 0,
+                reconnects: 0,
+                last_error: None,
+            }),
+        }
+    }
+}
+
+impl LiveConnection {
+    /// 收到鉴权回包；从重连中恢复时累计一次重连
+    pub fn connected(&self) {
+        self.update(|snapshot| {
+            if snapshot.state == LiveConnectionState::Reconnecting {
+                snapshot.reconnects += 1;
+            }
+            snapshot.state = LiveConnectionState::Connected;
+            snapshot.reconnect_attempt = 0;
+        });
+    }
+
+    /// 开始一次重连，返回这轮重连的次数
+    pub fn reconnecting(&self, error: &str) -> u32 {
+        let mut attempt = 0;
+        self.update(|snapshot| {
+            snapshot.state = LiveConnectionState::Reconnecting;
+            snapshot.reconnect_attempt += 1;
+            snapshot.last_error = Some(error.to_string());
+            attempt = snapshot.reconnect_attempt;
+        });
+        attempt
+    }
+
+    /// 项目心跳失败，已连接时降级为 `degraded`
+    pub fn heartbeat_failed(&self, error: &str) {
+        self.update(|snapshot| {
+            if snapshot.state == LiveConnectionState::Connected {
+                snapshot.state = LiveConnectionState::Degraded;
+            }
+            snapshot.last_error = Some(error.to_string());
+        });
+    }
+
+    /// 项目心跳恢复，`degraded` 回到 `connected`
+    pub fn heartbeat_succeeded(&self) {
+        self.update(|snapshot| {
+            if snapshot.state == LiveConnectionState::Degraded {
+                snapshot.state = LiveConnectionState::Connected;
+            }
+        });
+    }
+
+    /// 不再重连；`error` 为空时保留上一次的错误
+    pub fn disconnected(&self, error: Option<&str>) {
+        self.update(|snapshot| {
+            snapshot.state = LiveConnectionState::Disconnected;
+            if let Some(error) = error {
+                snapshot.last_error = Some(error.to_string());
+            }
+        });
+    }
+
+    pub fn snapshot(&self) -> LiveConnectionSnapshot {
+        self.snapshot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut LiveConnectionSnapshot)) {
+        let mut snapshot = self
+            .snapshot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        apply(&mut snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_failure_degrades_until_recovered() {
+        let connection = LiveConnection::default();
+        assert_eq!(connection.snapshot().state, LiveConnectionState::Connecting);
+        connection.connected();
+
+        // 项目心跳失败只标记为 degraded，下一次成功后恢复
+        connection.heartbeat_failed("项目心跳超时");
+        assert_eq!(connection.snapshot().state, LiveConnectionState::Degraded);
+        connection.heartbeat_succeeded();
+        assert_eq!(connection.snapshot().state, LiveConnectionState::Connected);
+    }
+
+    #[test]
+    fn reconnect_attempts_are_counted() {
+        let connection = LiveConnection::default();
+        connection.connected();
+
+        // 断线后每次重试递增次数，重新连上后归零并累计重连次数
+        assert_eq!(connection.reconnecting("服务器主动关闭连接"), 1);
+        assert_eq!(connection.reconnecting("连接超时"), 2);
+        let snapshot = connection.snapshot();
+        assert_eq!(snapshot.state.as_str(), "reconnecting");
+        assert_eq!(
+            (snapshot.reconnect_attempt, snapshot.last_error.as_deref()),
+            (2, Some("连接超时"))
+        );
+        connection.connected();
+        let snapshot = connection.snapshot();
+        assert_eq!(
+            (
+                snapshot.state,
+                snapshot.reconnect_attempt,
+                snapshot.reconnects
+            ),
+            (LiveConnectionState::Connected, 0, 1)
+        );
+    }
+
+    #[test]
+    fn heartbeat_failure_while_reconnecting_keeps_state() {
+        let connection = LiveConnection::default();
+        connection.reconnecting("读取失败");
+        connection.heartbeat_failed("项目心跳超时");
+        assert_eq!(
+            connection.snapshot().state,
+            LiveConnectionState::Reconnecting
+        );
+
+        // 不带错误的断开保留上一次的错误
+        connection.disconnected(None);
+        let snapshot = connection.snapshot();
+        assert_eq!(snapshot.state, LiveConnectionState::Disconnected);
+        assert_eq!(snapshot.last_error.as_deref(), Some("项目心跳超时"));
+    }
+}
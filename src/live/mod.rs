@@ -1,14 +1,17 @@
+mod connection;
 mod packet;
+mod stats;
 
 use std::{sync::Arc, time::Duration};
 
 use crate::{
-    config::BilibiliLiveConfig,
+    config::{BilibiliLiveConfig, LiveRenderConfig},
     errors::{AgentError, Result},
     sse::broadcast_json,
-    util::now_in_beijing,
+    util::{format_beijing, now_in_beijing},
 };
 use chrono::{DateTime, FixedOffset, TimeZone};
+pub use connection::{LiveConnection, LiveConnectionSnapshot, LiveConnectionState};
 use futures::{SinkExt, StreamExt};
 use hmac::{Hmac, Mac};
 use md5::{Digest, Md5};
@@ -16,15 +19,18 @@ use packet::{
     BiliPacket, OP_AUTH, OP_AUTH_REPLY, OP_HEARTBEAT, OP_HEARTBEAT_REPLY, OP_SEND_EVENT,
     decode_packets, encode_packet,
 };
+pub use packet::{PacketSequence, StaleReason};
 use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Value, json};
 use sha2::Sha256;
+pub use stats::LiveStatsSnapshot;
+use stats::{LiveStats, Viewers};
 use tokio::{
     select,
     sync::{broadcast, mpsc},
     task::JoinHandle,
-    time::{self, MissedTickBehavior},
+    time::{self, Interval, MissedTickBehavior},
 };
 use tokio_stream::wrappers::BroadcastStream;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
@@ -32,7 +38,13 @@ use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 const DEFAULT_BASE_URL: &str = "https://live-open.biliapi.com";
-const DEFAULT_HEARTBEAT_INTERVAL: u64 = 20;
+/// B 站结束某一场次推送时发送的事件
+const INTERACTION_END_CMD: &str = "LIVE_OPEN_PLATFORM_INTERACTION_END";
+/// 开放平台签名、时间戳与应用相关的错误码
+const AUTH_ERROR_CODES: [i32; 6] = [4001, 4002, 4003, 4004, 4005, 4006];
+/// 长链断开后首次重连的等待时间，之后每次翻倍，最长 `RECONNECT_MAX_BACKOFF`
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub struct LiveManager {
@@ -70,16 +82,14 @@ impl LiveManager {
             .ok_or_else(|| AgentError::MissingConfig("live.bilibili.id_code"))?;
 
         let start = self.client.start(&code).await?;
-        let ws_url = start
-            .websocket_info
-            .wss_link
-            .first()
-            .cloned()
-            .ok_or_else(|| AgentError::other("B站返回的 wss_link 为空"))?;
+        let ws_urls = start.websocket_info.wss_link.clone();
+        if ws_urls.is_empty() {
+            return Err(AgentError::other("B站返回的 wss_link 为空"));
+        }
 
         let session = LiveSession::spawn(
             self.client.clone(),
-            ws_url,
+            ws_urls,
             start.websocket_info.auth_body.clone(),
             start.game_info.game_id.clone(),
             start.anchor_info.clone(),
@@ -106,6 +116,25 @@ impl LiveManager {
     pub fn info(&self) -> Option<LiveSessionInfo> {
         self.session.as_ref().map(LiveSession::info)
     }
+
+    /// 当前会话的累计统计，未在监听时为 `None`
+    pub fn stats(&self) -> Option<LiveStatsSnapshot> {
+        self.session
+            .as_ref()
+            .map(|session| session.stats.snapshot())
+    }
+
+    /// 当前会话的长链连接状态，未在监听时为 `None`
+    pub fn connection(&self) -> Option<LiveConnectionSnapshot> {
+        self.session
+            .as_ref()
+            .map(|session| session.connection.snapshot())
+    }
+
+    /// 以签名请求探测开放平台接口，用于启动自检；不会开启或影响长链会话
+    pub async fn ping(&self) -> Result<()> {
+        self.client.ping().await
+    }
 }
 
 impl Drop for LiveManager {
@@ -125,9 +154,32 @@ pub struct LiveSessionInfo {
     pub started_at: DateTime<FixedOffset>,
 }
 
+impl LiveSessionInfo {
+    /// `live.started` / `live.status` 等事件的负载
+    pub fn to_payload(&self) -> Value {
+        let started_at = format_beijing(&self.started_at, "%Y-%m-%d %H:%M:%S%:z");
+        let uptime = now_in_beijing()
+            .signed_duration_since(self.started_at)
+            .num_seconds()
+            .max(0);
+
+        json!({
+            "active": true,
+            "game_id": self.game_id,
+            "room_id": self.room_id,
+            "anchor_name": self.anchor_name,
+            "anchor_open_id": self.anchor_open_id,
+            "started_at": started_at,
+            "uptime_seconds": uptime,
+        })
+    }
+}
+
 #[derive(Debug)]
 struct LiveSession {
     info: LiveSessionInfo,
+    stats: Arc<LiveStats>,
+    connection: Arc<LiveConnection>,
     shutdown_tx: broadcast::Sender<()>,
     task: JoinHandle<Result<()>>,
 }
@@ -159,7 +211,7 @@ impl LiveSession {
 
     async fn spawn(
         client: Arc<BilibiliLiveClient>,
-        ws_url: String,
+        ws_urls: Vec<String>,
         auth_body: String,
         game_id: String,
         anchor: AnchorInfo,
@@ -178,84 +230,225 @@ impl LiveSession {
             started_at: now_in_beijing(),
         };
 
+        let stats = Arc::new(LiveStats::default());
+        let connection = Arc::new(LiveConnection::default());
         let shutdown_rx = shutdown_tx.subscribe();
         let task = tokio::spawn(run_live_loop(
             client,
-            ws_url,
+            ws_urls,
             auth_body,
             game_id,
             shutdown_rx,
             event_tx,
             broadcaster.clone(),
+            stats.clone(),
+            connection.clone(),
         ));
 
         Ok(Self {
             info,
+            stats,
+            connection,
             shutdown_tx,
             task,
         })
     }
 }
 
+/// 一次长链连接结束的原因
+enum ConnectionEnd {
+    /// 收到关闭信号
+    Shutdown,
+    /// B 站已结束本场推送
+    InteractionEnd,
+    /// 连接断开或失效，可以重连
+    Dropped(String),
+}
+
+/// 维持本场的长链：断开后按退避间隔轮换 `wss_link` 重连，期间照常发送项目心跳，
+/// 并把连接状态写入 `connection`
+#[allow(clippy::too_many_arguments)]
 async fn run_live_loop(
     client: Arc<BilibiliLiveClient>,
-    mut ws_url: String,
+    ws_urls: Vec<String>,
     auth_body: String,
     game_id: String,
     shutdown_rx: broadcast::Receiver<()>,
     event_tx: Option<mpsc::Sender<LiveEvent>>,
     broadcaster: Option<broadcast::Sender<String>>,
+    stats: Arc<LiveStats>,
+    connection: Arc<LiveConnection>,
 ) -> Result<()> {
-    if !ws_url.ends_with("/sub") {
-        if ws_url.ends_with('/') {
-            ws_url.push_str("sub");
+    let mut api_heartbeat = time::interval(Duration::from_secs(
+        client.config.heartbeat_interval_seconds,
+    ));
+    api_heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    api_heartbeat.tick().await;
+
+    let mut shutdown_stream = BroadcastStream::new(shutdown_rx);
+    // 去重观众只在读取任务内维护，对外只暴露数量，重连后继续累计
+    let mut viewers = stats::viewers();
+    let max_attempts = client.config.ws_reconnect_attempts;
+    let mut ws_urls = ws_urls.iter().cycle();
+
+    loop {
+        let ws_url = subscribe_url(ws_urls.next().map(String::as_str).unwrap_or_default());
+        let end = run_connection(
+            &client,
+            ws_url,
+            &auth_body,
+            &game_id,
+            &mut api_heartbeat,
+            &mut shutdown_stream,
+            event_tx.as_ref(),
+            broadcaster.as_ref(),
+            &stats,
+            &connection,
+            &mut viewers,
+        )
+        .await;
+        let error = match end {
+            Ok(ConnectionEnd::Shutdown) => return Ok(()),
+            Ok(ConnectionEnd::InteractionEnd) => {
+                connection.disconnected(None);
+                return Ok(());
+            }
+            Ok(ConnectionEnd::Dropped(reason)) => reason,
+            Err(err) => err.to_string(),
+        };
+
+        if connection.snapshot().reconnect_attempt >= max_attempts {
+            warn!(
+                target: "bilibili::live",
+                max_attempts,
+                %error,
+                "直播长链已断开且不再重连，需要 live_stop 后重新开启"
+            );
+            connection.disconnected(Some(&error));
+            return Ok(());
+        }
+        let attempt = connection.reconnecting(&error);
+        let delay = RECONNECT_BACKOFF
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(RECONNECT_MAX_BACKOFF);
+        warn!(
+            target: "bilibili::live",
+            attempt,
+            max_attempts,
+            delay_ms = delay.as_millis() as u64,
+            %error,
+            "直播长链已断开，稍后重连"
+        );
+
+        let sleep = time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            select! {
+                _ = &mut sleep => break,
+                _ = api_heartbeat.tick() => {
+                    send_api_heartbeat(&client, &game_id, &connection).await;
+                }
+                maybe_shutdown = shutdown_stream.next() => {
+                    if maybe_shutdown.is_some() {
+                        info!(target: "bilibili::live", "收到关闭信号，放弃重连");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 长链地址需要以 `/sub` 结尾
+fn subscribe_url(url: &str) -> String {
+    let mut url = url.to_string();
+    if !url.ends_with("/sub") {
+        if url.ends_with('/') {
+            url.push_str("sub");
         } else {
-            ws_url.push_str("/sub");
+            url.push_str("/sub");
+        }
+    }
+    url
+}
+
+/// 调用项目心跳，结果反映到连接状态
+async fn send_api_heartbeat(
+    client: &BilibiliLiveClient,
+    game_id: &str,
+    connection: &LiveConnection,
+) {
+    match client.heartbeat(game_id).await {
+        Ok(()) => connection.heartbeat_succeeded(),
+        Err(err) => {
+            warn!(target: "bilibili::live", error = ?err, "调用项目心跳失败");
+            connection.heartbeat_failed(&format!("项目心跳失败: {err}"));
         }
     }
+}
 
+/// 建立一次长链连接并读取到断开为止
+#[allow(clippy::too_many_arguments)]
+async fn run_connection(
+    client: &BilibiliLiveClient,
+    ws_url: String,
+    auth_body: &str,
+    game_id: &str,
+    api_heartbeat: &mut Interval,
+    shutdown_stream: &mut BroadcastStream<()>,
+    event_tx: Option<&mpsc::Sender<LiveEvent>>,
+    broadcaster: Option<&broadcast::Sender<String>>,
+    stats: &LiveStats,
+    connection: &LiveConnection,
+    viewers: &mut Viewers,
+) -> Result<ConnectionEnd> {
     info!(target: "bilibili::live", url = %ws_url, "开始连接 B 站直播长链");
     let (ws_stream, _) = connect_async(ws_url)
         .await
         .map_err(|err| AgentError::other(format!("连接 B 站直播长链失败: {err}")))?;
     let (mut writer, mut reader) = ws_stream.split();
 
-    let auth_packet = encode_packet(OP_AUTH, auth_body.as_bytes());
+    // 发送序号按连接计数，每次重连后从 1 开始
+    let mut sequence = PacketSequence::new(time::Instant::now());
+    let auth_packet = encode_packet(OP_AUTH, sequence.next_outgoing(), auth_body.as_bytes());
     writer
         .send(Message::Binary(auth_packet.into()))
         .await
         .map_err(|err| AgentError::other(format!("发送鉴权包失败: {err}")))?;
 
-    let mut ws_heartbeat = time::interval(Duration::from_secs(DEFAULT_HEARTBEAT_INTERVAL));
+    let mut ws_heartbeat = time::interval(client.config.ws_heartbeat_interval);
     ws_heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
     ws_heartbeat.tick().await; // align interval
 
-    let mut api_heartbeat = time::interval(Duration::from_secs(
-        client.config.heartbeat_interval_seconds,
-    ));
-    api_heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
-    api_heartbeat.tick().await;
-
-    let mut shutdown_stream = BroadcastStream::new(shutdown_rx);
-
     loop {
         select! {
             _ = ws_heartbeat.tick() => {
-                if let Err(err) = writer.send(Message::Binary(encode_packet(OP_HEARTBEAT, &[]).into())).await {
+                if let Some(timeout) = client.config.ws_stale_timeout
+                    && let Some(reason) = sequence.stale(time::Instant::now(), timeout)
+                {
+                    warn!(
+                        target: "bilibili::live",
+                        reason = reason.as_str(),
+                        last_sequence = ?sequence.last_received(),
+                        timeout_seconds = timeout.as_secs(),
+                        "直播长链已失效，断开连接"
+                    );
+                    return Ok(ConnectionEnd::Dropped(format!("直播长链已失效: {}", reason.as_str())));
+                }
+                let heartbeat = encode_packet(OP_HEARTBEAT, sequence.next_outgoing(), &[]);
+                if let Err(err) = writer.send(Message::Binary(heartbeat.into())).await {
                     warn!(target: "bilibili::live", error = ?err, "发送 WS 心跳失败");
-                    break;
+                    return Ok(ConnectionEnd::Dropped(format!("发送 WS 心跳失败: {err}")));
                 }
             }
             _ = api_heartbeat.tick() => {
-                if let Err(err) = client.heartbeat(&game_id).await {
-                    warn!(target: "bilibili::live", error = ?err, "调用项目心跳失败");
-                }
+                send_api_heartbeat(client, game_id, connection).await;
             }
             maybe_shutdown = shutdown_stream.next() => {
                 match maybe_shutdown {
                     Some(Ok(_)) | Some(Err(_)) => {
                         info!(target: "bilibili::live", "收到关闭信号，准备退出直播长链");
-                        break;
+                        return Ok(ConnectionEnd::Shutdown);
                     }
                     None => {}
                 }
@@ -263,7 +456,23 @@ async fn run_live_loop(
             message = reader.next() => {
                 match message {
                     Some(Ok(Message::Binary(payload))) => {
-                        handle_packets(&payload, event_tx.as_ref(), broadcaster.as_ref()).await?;
+                        let ended = handle_packets(
+                            &payload,
+                            game_id,
+                            event_tx,
+                            broadcaster,
+                            client.config.broadcast_raw,
+                            &client.config.render,
+                            stats,
+                            connection,
+                            viewers,
+                            &mut sequence,
+                        )
+                        .await?;
+                        if ended {
+                            info!(target: "bilibili::live", %game_id, "B 站已结束本场推送，停止心跳并退出直播长链");
+                            return Ok(ConnectionEnd::InteractionEnd);
+                        }
                     }
                     Some(Ok(Message::Text(text))) => {
                         debug!(target: "bilibili::live", %text, "收到文本消息");
@@ -275,37 +484,59 @@ async fn run_live_loop(
                     }
                     Some(Ok(Message::Close(frame))) => {
                         info!(target: "bilibili::live", frame = ?frame, "服务器主动关闭连接");
-                        break;
+                        return Ok(ConnectionEnd::Dropped("服务器主动关闭连接".to_string()));
                     }
                     Some(Ok(other)) => {
                         debug!(target: "bilibili::live", message = ?other, "收到未处理的 WebSocket 消息");
                     }
                     Some(Err(err)) => {
                         warn!(target: "bilibili::live", error = ?err, "读取直播长链消息失败");
-                        break;
+                        return Ok(ConnectionEnd::Dropped(format!("读取直播长链消息失败: {err}")));
                     }
                     None => {
                         info!(target: "bilibili::live", "直播长链已断开");
-                        break;
+                        return Ok(ConnectionEnd::Dropped("直播长链已断开".to_string()));
                     }
                 }
             }
         }
     }
-
-    Ok(())
 }
 
+/// 处理一个 WebSocket 帧，返回其中是否有本场（`game_id` 一致）的推送结束事件
+#[allow(clippy::too_many_arguments)]
 async fn handle_packets(
     payload: &[u8],
+    game_id: &str,
     event_tx: Option<&mpsc::Sender<LiveEvent>>,
     broadcaster: Option<&broadcast::Sender<String>>,
-) -> Result<()> {
+    broadcast_raw: bool,
+    render: &LiveRenderConfig,
+    stats: &LiveStats,
+    connection: &LiveConnection,
+    viewers: &mut Viewers,
+    sequence: &mut PacketSequence,
+) -> Result<bool> {
+    let mut ended = false;
+    // 同一个 WebSocket 帧里的事件共用读取时刻，作为端到端延迟的起点
+    let received_at = time::Instant::now();
     let packets = decode_packets(payload)?;
 
     for packet in packets {
+        if let Some(missing) = sequence.observe(packet.operation, packet.sequence, received_at) {
+            warn!(
+                target: "bilibili::live",
+                operation = packet.operation,
+                sequence = packet.sequence,
+                missing,
+                "收到的包序号不连续，可能有丢帧"
+            );
+            stats.record_sequence_gap(missing);
+        }
+        stats.record_sequence(packet.sequence);
         match packet.operation {
             OP_AUTH_REPLY => {
+                connection.connected();
                 info!(
                     target: "bilibili::live",
                     packet_len = packet.packet_len,
@@ -334,20 +565,25 @@ async fn handle_packets(
                     sequence = packet.sequence,
                     "解析直播事件包"
                 );
-                for event in parse_events(&packet)? {
+                for event in parse_events(&packet, received_at)? {
+                    ended |= event.is_interaction_end(game_id);
+                    stats.record(&event, viewers);
                     if let Some(broadcaster) = broadcaster {
                         let payload = serde_json::json!({
                             "cmd": event.cmd,
                             "data": event.data,
                         });
-                        broadcast_json(broadcaster, "live.event", payload);
-                    }
-                    if let Some(sender) = event_tx {
-                        if let Err(err) = sender.send(event.clone()).await {
-                            warn!(target: "bilibili::live", error = ?err, "直播事件投递失败");
+                        if broadcast_raw {
+                            broadcast_json(broadcaster, "live.event.raw", payload.clone());
                         }
+                        broadcast_json(broadcaster, "live.event", event.to_payload());
+                    }
+                    if let Some(sender) = event_tx
+                        && let Err(err) = sender.send(event.clone()).await
+                    {
+                        warn!(target: "bilibili::live", error = ?err, "直播事件投递失败");
                     }
-                    render_event(&event);
+                    render_event(&event, render);
                 }
             }
             other => {
@@ -365,13 +601,13 @@ async fn handle_packets(
         }
     }
 
-    Ok(())
+    Ok(ended)
 }
 
-fn parse_events(packet: &BiliPacket) -> Result<Vec<LiveEvent>> {
+fn parse_events(packet: &BiliPacket, received_at: time::Instant) -> Result<Vec<LiveEvent>> {
     let mut events = Vec::new();
-    let mut slices = packet.body.split(|b| *b == 0);
-    while let Some(chunk) = slices.next() {
+    let slices = packet.body.split(|b| *b == 0);
+    for chunk in slices {
         if chunk.is_empty() {
             continue;
         }
@@ -379,6 +615,7 @@ fn parse_events(packet: &BiliPacket) -> Result<Vec<LiveEvent>> {
             Ok(message) => events.push(LiveEvent {
                 cmd: message.cmd,
                 data: message.data,
+                received_at,
             }),
             Err(err) => {
                 warn!(target: "bilibili::live", error = ?err, "解析直播 JSON 失败: {}", String::from_utf8_lossy(chunk));
@@ -388,10 +625,14 @@ fn parse_events(packet: &BiliPacket) -> Result<Vec<LiveEvent>> {
     Ok(events)
 }
 
-fn render_event(event: &LiveEvent) {
+fn render_event(event: &LiveEvent, render: &LiveRenderConfig) {
+    if !render_enabled(&event.cmd, render) {
+        return;
+    }
+
     match event.cmd.as_str() {
         "LIVE_OPEN_PLATFORM_DM" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let mut name = event
                 .field_str(&["uname"])
                 .filter(|s| !s.is_empty())
@@ -404,8 +645,18 @@ fn render_event(event: &LiveEvent) {
                 .unwrap_or_else(|| "<空>".to_string());
 
             let mut details = Vec::new();
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
             if let Some(level) = event.field_i64(&["guard_level"]).filter(|level| *level > 0) {
                 details.push(format!("大航海: {}", guard_level_label(level)));
             }
@@ -417,6 +668,7 @@ fn render_event(event: &LiveEvent) {
             }
             push_detail(
                 &mut details,
+                render,
                 "佩戴粉丝勋章",
                 event.field_bool(&["fans_medal_wearing_status"]).map(yes_no),
             );
@@ -436,7 +688,7 @@ fn render_event(event: &LiveEvent) {
                     details.push("表情包弹幕".to_string());
                 }
             }
-            push_detail(&mut details, "msg_id", event.field_str(&["msg_id"]));
+            push_detail(&mut details, render, "msg_id", event.field_str(&["msg_id"]));
 
             println!("💬 [{}] {}：{}", timestamp, name, message);
             if !details.is_empty() {
@@ -444,7 +696,7 @@ fn render_event(event: &LiveEvent) {
             }
         }
         "LIVE_OPEN_PLATFORM_SEND_GIFT" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let uname = event
                 .field_str(&["uname"])
                 .filter(|s| !s.is_empty())
@@ -484,11 +736,22 @@ fn render_event(event: &LiveEvent) {
             if let Some(level) = event.field_i64(&["guard_level"]).filter(|level| *level > 0) {
                 details.push(format!("大航海: {}", guard_level_label(level)));
             }
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "msg_id", event.field_str(&["msg_id"]));
             push_detail(
                 &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(&mut details, render, "msg_id", event.field_str(&["msg_id"]));
+            push_detail(
+                &mut details,
+                render,
                 "礼物图标",
                 event
                     .field_str(&["gift_icon"])
@@ -501,7 +764,7 @@ fn render_event(event: &LiveEvent) {
             }
         }
         "LIVE_OPEN_PLATFORM_SUPER_CHAT" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let uname = event
                 .field_str(&["uname"])
                 .filter(|s| !s.is_empty())
@@ -512,10 +775,25 @@ fn render_event(event: &LiveEvent) {
                 .unwrap_or_else(|| "<空>".to_string());
 
             let mut details = Vec::new();
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
-            push_detail(&mut details, "message_id", event.field_i64(&["message_id"]));
-            push_detail(&mut details, "msg_id", event.field_str(&["msg_id"]));
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "message_id",
+                event.field_i64(&["message_id"]),
+            );
+            push_detail(&mut details, render, "msg_id", event.field_str(&["msg_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
             if let Some(medal) = format_medal(
                 event.field_str(&["fans_medal_name"]),
                 event.field_i64(&["fans_medal_level"]),
@@ -545,7 +823,7 @@ fn render_event(event: &LiveEvent) {
             }
         }
         "LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let ids = event
                 .data
                 .get("message_ids")
@@ -568,14 +846,19 @@ fn render_event(event: &LiveEvent) {
 
             println!("🚫 [{}] Super Chat 撤回: {}", timestamp, id_text);
             let mut details = Vec::new();
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "msg_id", event.field_str(&["msg_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(&mut details, render, "msg_id", event.field_str(&["msg_id"]));
             if !details.is_empty() {
                 println!("    {}", details.join(" · "));
             }
         }
         "LIVE_OPEN_PLATFORM_GUARD" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let uname = event
                 .field_str(&["user_info", "uname"])
                 .filter(|s| !s.is_empty())
@@ -598,9 +881,15 @@ fn render_event(event: &LiveEvent) {
             if price > 0 {
                 details.push(format!("价值 {}", format_currency(price)));
             }
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
             push_detail(
                 &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
                 "open_id",
                 event.field_str(&["user_info", "open_id"]),
             );
@@ -612,6 +901,7 @@ fn render_event(event: &LiveEvent) {
             }
             push_detail(
                 &mut details,
+                render,
                 "佩戴粉丝勋章",
                 event.field_bool(&["fans_medal_wearing_status"]).map(yes_no),
             );
@@ -620,7 +910,7 @@ fn render_event(event: &LiveEvent) {
             }
         }
         "LIVE_OPEN_PLATFORM_LIKE" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let uname = event
                 .field_str(&["uname"])
                 .filter(|s| !s.is_empty())
@@ -636,14 +926,24 @@ fn render_event(event: &LiveEvent) {
             {
                 details.push(format!("文案: {}", text));
             }
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
             if !details.is_empty() {
                 println!("    {}", details.join(" · "));
             }
         }
         "LIVE_OPEN_PLATFORM_LIVE_ROOM_ENTER" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let uname = event
                 .field_str(&["uname"])
                 .filter(|s| !s.is_empty())
@@ -652,14 +952,24 @@ fn render_event(event: &LiveEvent) {
             println!("🚪 [{}] {} 进入直播间", timestamp, uname);
 
             let mut details = Vec::new();
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
             if !details.is_empty() {
                 println!("    {}", details.join(" · "));
             }
         }
         "LIVE_OPEN_PLATFORM_LIVE_START" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let title = event
                 .field_str(&["title"])
                 .filter(|s| !s.is_empty())
@@ -668,15 +978,30 @@ fn render_event(event: &LiveEvent) {
             println!("🚀 [{}] 直播开始：{}", timestamp, title);
 
             let mut details = Vec::new();
-            push_detail(&mut details, "分区", event.field_str(&["area_name"]));
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "分区",
+                event.field_str(&["area_name"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
             if !details.is_empty() {
                 println!("    {}", details.join(" · "));
             }
         }
         "LIVE_OPEN_PLATFORM_LIVE_END" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let title = event
                 .field_str(&["title"])
                 .filter(|s| !s.is_empty())
@@ -685,15 +1010,30 @@ fn render_event(event: &LiveEvent) {
             println!("🏁 [{}] 直播结束：{}", timestamp, title);
 
             let mut details = Vec::new();
-            push_detail(&mut details, "分区", event.field_str(&["area_name"]));
-            push_detail(&mut details, "room_id", event.field_i64(&["room_id"]));
-            push_detail(&mut details, "open_id", event.field_str(&["open_id"]));
+            push_detail(
+                &mut details,
+                render,
+                "分区",
+                event.field_str(&["area_name"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "room_id",
+                event.field_i64(&["room_id"]),
+            );
+            push_detail(
+                &mut details,
+                render,
+                "open_id",
+                event.field_str(&["open_id"]),
+            );
             if !details.is_empty() {
                 println!("    {}", details.join(" · "));
             }
         }
         "LIVE_OPEN_PLATFORM_INTERACTION_END" => {
-            let timestamp = format_timestamp(event.field_i64(&["timestamp"]));
+            let timestamp = format_time(event.occurred_at());
             let game_id = event
                 .field_str(&["game_id"])
                 .unwrap_or_else(|| "-".to_string());
@@ -705,10 +1045,34 @@ fn render_event(event: &LiveEvent) {
     }
 }
 
-fn push_detail<T>(details: &mut Vec<String>, label: &str, value: Option<T>)
-where
+/// 按 `[live.render]` 判断该类事件是否输出到控制台
+fn render_enabled(cmd: &str, render: &LiveRenderConfig) -> bool {
+    match cmd {
+        "LIVE_OPEN_PLATFORM_DM" => render.show_danmaku,
+        "LIVE_OPEN_PLATFORM_SEND_GIFT"
+        | "LIVE_OPEN_PLATFORM_SUPER_CHAT"
+        | "LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL"
+        | "LIVE_OPEN_PLATFORM_GUARD" => render.show_gifts,
+        "LIVE_OPEN_PLATFORM_LIVE_ROOM_ENTER" => render.show_enter,
+        "LIVE_OPEN_PLATFORM_LIKE" => render.show_likes,
+        _ => true,
+    }
+}
+
+/// 标识类字段，`show_ids = false` 时不输出
+const ID_DETAIL_LABELS: &[&str] = &["open_id", "room_id", "msg_id", "message_id"];
+
+fn push_detail<T>(
+    details: &mut Vec<String>,
+    render: &LiveRenderConfig,
+    label: &str,
+    value: Option<T>,
+) where
     T: std::fmt::Display,
 {
+    if !render.show_ids && ID_DETAIL_LABELS.contains(&label) {
+        return;
+    }
     if let Some(val) = value {
         let text = val.to_string();
         if !text.trim().is_empty() {
@@ -748,21 +1112,35 @@ fn format_currency(amount: i64) -> String {
 }
 
 fn format_timestamp(timestamp: Option<i64>) -> String {
-    timestamp
-        .and_then(timestamp_to_beijing)
-        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+    format_time(timestamp.and_then(timestamp_to_beijing))
+}
+
+fn format_time(time: Option<DateTime<FixedOffset>>) -> String {
+    time.map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
         .unwrap_or_else(|| "--:--:--".to_string())
 }
 
+/// 超过该值的时间戳按毫秒处理，否则按秒处理
+///
+/// 秒级时间戳要到 2286 年才会超过该值，毫秒级时间戳早在 1970 年 4 月就已超过。
+const MILLIS_TIMESTAMP_THRESHOLD: i64 = 10_000_000_000;
+
+/// 将 B 站的秒级或毫秒级时间戳转换为北京时间
 fn timestamp_to_beijing(timestamp: i64) -> Option<DateTime<FixedOffset>> {
     let offset = FixedOffset::east_opt(8 * 3600)?;
-    offset.timestamp_opt(timestamp, 0).single()
+    if timestamp.abs() > MILLIS_TIMESTAMP_THRESHOLD {
+        offset.timestamp_millis_opt(timestamp).single()
+    } else {
+        offset.timestamp_opt(timestamp, 0).single()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct LiveEvent {
     pub cmd: String,
     pub data: Value,
+    /// 从长链读到该事件的时刻，用于计算弹幕到结果广播的 `latency_ms`
+    pub received_at: time::Instant,
 }
 
 impl LiveEvent {
@@ -789,6 +1167,83 @@ impl LiveEvent {
         }
         current.as_bool()
     }
+
+    /// 事件发生时间（北京时间），读取 `timestamp` 字段，自动区分秒级与毫秒级时间戳
+    pub fn occurred_at(&self) -> Option<DateTime<FixedOffset>> {
+        self.field_i64(&["timestamp"])
+            .and_then(timestamp_to_beijing)
+    }
+
+    /// 是否为指定 `game_id` 的推送结束事件（`LIVE_OPEN_PLATFORM_INTERACTION_END`），
+    /// 收到后该场次不会再有事件，长链与项目心跳都应停止
+    pub fn is_interaction_end(&self, game_id: &str) -> bool {
+        self.cmd == INTERACTION_END_CMD && self.field_str(&["game_id"]).as_deref() == Some(game_id)
+    }
+
+    /// 发送者的大航海、粉丝勋章与房管身份；事件不带这些字段时为默认值（非舰长、无勋章、非房管）。
+    /// 上舰事件的 `guard_level` 为本次开通的等级
+    pub fn badges(&self) -> SenderBadges {
+        let guard_level = self
+            .field_i64(&["guard_level"])
+            .and_then(|level| u8::try_from(level).ok())
+            .unwrap_or(0);
+        let guard_label = if guard_level > 0 {
+            guard_level_label(guard_level.into())
+        } else {
+            String::new()
+        };
+        let medal = self
+            .field_str(&["fans_medal_name"])
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .map(|name| FansMedal {
+                name,
+                level: self
+                    .field_i64(&["fans_medal_level"])
+                    .and_then(|level| u32::try_from(level).ok())
+                    .unwrap_or(0),
+                wearing: self
+                    .field_bool(&["fans_medal_wearing_status"])
+                    .unwrap_or(false),
+            });
+        SenderBadges {
+            guard_level,
+            guard_label,
+            medal,
+            is_admin: self.field_bool(&["is_admin"]).unwrap_or(false),
+        }
+    }
+
+    /// `live.event` 的负载：原始的 `cmd` / `data` 加上整理后的身份字段（见 `badges`）
+    pub fn to_payload(&self) -> Value {
+        let mut payload = json!(self.badges());
+        if let Value::Object(map) = &mut payload {
+            map.insert("cmd".to_string(), json!(self.cmd));
+            map.insert("data".to_string(), self.data.clone());
+        }
+        payload
+    }
+}
+
+/// 弹幕、礼物等事件发送者的身份标识，便于前端按身份区分样式
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SenderBadges {
+    /// 大航海等级：1 总督、2 提督、3 舰长，0 表示不是舰长
+    pub guard_level: u8,
+    /// 大航海等级的中文名称，不是舰长时为空字符串
+    pub guard_label: String,
+    /// 发送者在该直播间的粉丝勋章，没有勋章时为 `None`
+    pub medal: Option<FansMedal>,
+    /// 是否为房管，仅弹幕事件携带
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FansMedal {
+    pub name: String,
+    pub level: u32,
+    /// 是否正佩戴着这枚勋章（`fans_medal_wearing_status`）
+    pub wearing: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -874,7 +1329,7 @@ impl BilibiliLiveClient {
             .clone()
             .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
         let http = reqwest::Client::builder()
-            .timeout(Duration::from_secs(10))
+            .timeout(config.http_timeout)
             .build()
             .expect("构建 reqwest 客户端失败");
         Self {
@@ -924,6 +1379,21 @@ impl BilibiliLiveClient {
         Ok(())
     }
 
+    /// 对空 game_id 发送心跳：签名或应用配置有误时平台会返回鉴权类错误码
+    async fn ping(&self) -> Result<()> {
+        let body = serde_json::to_string(&HeartbeatRequest { game_id: "" })?;
+        let response: EmptyApiResponse = self
+            .post("/v2/app/heartbeat", body, Some("heartbeat"))
+            .await?;
+        if AUTH_ERROR_CODES.contains(&response.code) {
+            return Err(AgentError::other(format!(
+                "B 站接口鉴权失败: {} {}",
+                response.code, response.message
+            )));
+        }
+        Ok(())
+    }
+
     async fn post<T>(&self, path: &str, body: String, label: Option<&str>) -> Result<T>
     where
         T: for<'de> Deserialize<'de>,
@@ -1007,3 +1477,113 @@ impl BilibiliLiveClient {
         Ok(headers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_and_millis_resolve_to_the_same_time() {
+        let seconds = timestamp_to_beijing(1_760_000_000).unwrap();
+        let millis = timestamp_to_beijing(1_760_000_000_123).unwrap();
+        assert_eq!(seconds.timestamp(), millis.timestamp());
+        assert_eq!(format_timestamp(Some(1_760_000_000)), "2025-10-09 16:53:20");
+        assert_eq!(
+            format_timestamp(Some(1_760_000_000_123)),
+            "2025-10-09 16:53:20"
+        );
+        // 阈值附近：秒级上限与 1970 年 4 月的毫秒时间戳
+        assert_eq!(
+            timestamp_to_beijing(MILLIS_TIMESTAMP_THRESHOLD)
+                .unwrap()
+                .format("%Y")
+                .to_string(),
+            "2286"
+        );
+        assert_eq!(
+            format_timestamp(Some(MILLIS_TIMESTAMP_THRESHOLD + 1)),
+            "1970-04-27 01:46:40"
+        );
+        assert_eq!(format_timestamp(None), "--:--:--");
+    }
+
+    #[test]
+    fn occurred_at_reads_either_unit() {
+        let event = |timestamp: i64| LiveEvent {
+            cmd: "LIVE_OPEN_PLATFORM_DM".to_string(),
+            data: serde_json::json!({ "timestamp": timestamp }),
+            received_at: time::Instant::now(),
+        };
+        assert_eq!(
+            event(1_760_000_000).occurred_at(),
+            event(1_760_000_000_000).occurred_at()
+        );
+        assert_eq!(
+            event(1_760_000_000).occurred_at().unwrap().to_rfc3339(),
+            "2025-10-09T16:53:20+08:00"
+        );
+    }
+
+    #[test]
+    fn interaction_end_matches_its_game_only() {
+        let event = LiveEvent {
+            cmd: INTERACTION_END_CMD.to_string(),
+            data: serde_json::json!({ "game_id": "g-1", "timestamp": 1_700_000_000 }),
+            received_at: time::Instant::now(),
+        };
+        assert!(event.is_interaction_end("g-1"));
+        // 其他场次的结束事件与普通事件都不算
+        assert!(!event.is_interaction_end("g-2"));
+        let danmaku = LiveEvent {
+            cmd: "LIVE_OPEN_PLATFORM_DM".to_string(),
+            ..event
+        };
+        assert!(!danmaku.is_interaction_end("g-1"));
+    }
+
+    #[test]
+    fn badges_are_read_from_the_event() {
+        let event = LiveEvent {
+            cmd: "LIVE_OPEN_PLATFORM_DM".to_string(),
+            data: serde_json::json!({
+                "uname": "观众",
+                "guard_level": 3,
+                "fans_medal_name": " 小电视 ",
+                "fans_medal_level": 21,
+                "fans_medal_wearing_status": true,
+                "is_admin": true,
+            }),
+            received_at: time::Instant::now(),
+        };
+        let badges = event.badges();
+        assert_eq!(
+            (badges.guard_level, badges.guard_label.as_str()),
+            (3, "舰长")
+        );
+        assert_eq!(
+            badges.medal,
+            Some(FansMedal {
+                name: "小电视".to_string(),
+                level: 21,
+                wearing: true,
+            })
+        );
+        assert!(badges.is_admin);
+
+        // 原始数据原样保留
+        let payload = event.to_payload();
+        assert_eq!(payload["cmd"], "LIVE_OPEN_PLATFORM_DM");
+        assert_eq!(payload["data"]["fans_medal_name"], " 小电视 ");
+        assert_eq!(payload["medal"]["level"], 21);
+    }
+
+    #[test]
+    fn missing_or_blank_badge_fields_are_default() {
+        let event = LiveEvent {
+            cmd: "LIVE_OPEN_PLATFORM_DM".to_string(),
+            data: serde_json::json!({ "fans_medal_name": "" }),
+            received_at: time::Instant::now(),
+        };
+        assert_eq!(event.badges(), SenderBadges::default());
+    }
+}
@@ -1,7 +1,8 @@
-use std::io::Read;
+use std::{collections::HashMap, io::Read, time::Duration};
 
 use crate::errors::Result;
 use flate2::read::ZlibDecoder;
+use tokio::time::Instant;
 
 pub const HEADER_LEN: u16 = 16;
 
@@ -21,7 +22,92 @@ pub struct BiliPacket {
     pub body: Vec<u8>,
 }
 
-pub fn encode_packet(operation: u32, body: &[u8]) -> Vec<u8> {
+/// 单条长链连接内的包序号：发送序号从 1 开始递增，接收序号按操作类型记录，用于发现丢帧与停滞的连接
+///
+/// 每次连接都新建一个，重连后发送序号重新从 1 开始。序号为 0 的包视为服务端未编号，不参与判断。
+#[derive(Debug)]
+pub struct PacketSequence {
+    next_outgoing: u32,
+    /// 各操作类型最近一次收到的非 0 序号
+    received: HashMap<u32, u32>,
+    last_received: Option<u32>,
+    last_packet_at: Instant,
+    /// 心跳回包序号最近一次变化的时刻；从未变化（服务端不编号或原样回 1）时不做停滞判断
+    heartbeat_advanced_at: Option<Instant>,
+}
+
+/// 长链被判定为失效的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleReason {
+    /// 超时内没有收到任何数据包
+    Silent,
+    /// 仍有心跳回包，但序号不再变化
+    SequenceFrozen,
+}
+
+impl StaleReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StaleReason::Silent => "silent",
+            StaleReason::SequenceFrozen => "sequence_frozen",
+        }
+    }
+}
+
+impl PacketSequence {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            next_outgoing: 1,
+            received: HashMap::new(),
+            last_received: None,
+            last_packet_at: now,
+            heartbeat_advanced_at: None,
+        }
+    }
+
+    /// 下一个发送包的序号，溢出后跳过 0 重新从 1 开始
+    pub fn next_outgoing(&mut self) -> u32 {
+        let sequence = self.next_outgoing;
+        self.next_outgoing = sequence.checked_add(1).unwrap_or(1);
+        sequence
+    }
+
+    /// 记录收到的包，同一操作类型的序号跳号时返回中间缺失的包数
+    pub fn observe(&mut self, operation: u32, sequence: u32, now: Instant) -> Option<u32> {
+        self.last_packet_at = now;
+        if sequence == 0 {
+            return None;
+        }
+        self.last_received = Some(sequence);
+
+        let previous = self.received.insert(operation, sequence)?;
+        if operation == OP_HEARTBEAT_REPLY && previous != sequence {
+            self.heartbeat_advanced_at = Some(now);
+        }
+        // 序号回退视为服务端重新编号，以新序号为准
+        sequence
+            .checked_sub(previous)
+            .filter(|step| *step > 1)
+            .map(|step| step - 1)
+    }
+
+    /// 最近一次收到的非 0 序号
+    pub fn last_received(&self) -> Option<u32> {
+        self.last_received
+    }
+
+    /// 超过 `timeout` 没有收到数据包，或心跳回包序号停滞时返回失效原因
+    pub fn stale(&self, now: Instant, timeout: Duration) -> Option<StaleReason> {
+        if now.duration_since(self.last_packet_at) > timeout {
+            return Some(StaleReason::Silent);
+        }
+        self.heartbeat_advanced_at
+            .filter(|advanced_at| now.duration_since(*advanced_at) > timeout)
+            .map(|_| StaleReason::SequenceFrozen)
+    }
+}
+
+pub fn encode_packet(operation: u32, sequence: u32, body: &[u8]) -> Vec<u8> {
     let header_len = HEADER_LEN;
     let packet_len = header_len as usize + body.len();
     let mut buffer = Vec::with_capacity(packet_len);
@@ -30,7 +116,7 @@ pub fn encode_packet(operation: u32, body: &[u8]) -> Vec<u8> {
     buffer.extend_from_slice(&header_len.to_be_bytes());
     buffer.extend_from_slice(&1u16.to_be_bytes()); // version = 1 (plain JSON)
     buffer.extend_from_slice(&operation.to_be_bytes());
-    buffer.extend_from_slice(&1u32.to_be_bytes()); // sequence id
+    buffer.extend_from_slice(&sequence.to_be_bytes());
     buffer.extend_from_slice(body);
 
     buffer
@@ -72,6 +158,11 @@ pub fn decode_packets(data: &[u8]) -> Result<Vec<BiliPacket>> {
                 .expect("slice length checked"),
         );
 
+        // 头部长度异常的帧无法确定正文边界，视为流结束而不是越界 panic
+        if header_len < HEADER_LEN || header_len as usize > packet_len {
+            break;
+        }
+
         let body_start = offset + header_len as usize;
         let body_end = offset + packet_len;
         let body = data[body_start..body_end].to_vec();
@@ -99,3 +190,169 @@ pub fn decode_packets(data: &[u8]) -> Result<Vec<BiliPacket>> {
 
     Ok(packets)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::ZlibEncoder};
+
+    use super::*;
+
+    #[test]
+    fn auth_packet_round_trip() {
+        let body = br#"{"key":"x"}"#;
+        let encoded = encode_packet(OP_AUTH, 1, body);
+        assert_eq!(encoded.len(), HEADER_LEN as usize + body.len());
+
+        let packets = decode_packets(&encoded).unwrap();
+        assert_eq!(packets.len(), 1);
+        let packet = &packets[0];
+        assert_eq!(packet.packet_len as usize, encoded.len());
+        assert_eq!(packet.header_len, HEADER_LEN);
+        assert_eq!(packet.version, 1);
+        assert_eq!(packet.operation, OP_AUTH);
+        assert_eq!(packet.sequence, 1);
+        assert_eq!(packet.body, body);
+    }
+
+    #[test]
+    fn multiple_packets_keep_order() {
+        let mut frame = encode_packet(OP_AUTH_REPLY, 1, br#"{"code":0}"#);
+        frame.extend(encode_packet(OP_HEARTBEAT_REPLY, 2, &[0, 0, 0, 7]));
+        frame.extend(encode_packet(OP_SEND_EVENT, 3, br#"{"cmd":"A"}"#));
+
+        let packets = decode_packets(&frame).unwrap();
+        let summary = packets
+            .iter()
+            .map(|packet| (packet.operation, packet.sequence))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (OP_AUTH_REPLY, 1),
+                (OP_HEARTBEAT_REPLY, 2),
+                (OP_SEND_EVENT, 3)
+            ]
+        );
+        assert_eq!(packets[2].body, br#"{"cmd":"A"}"#);
+    }
+
+    #[test]
+    fn zlib_v2_frame_is_unpacked() {
+        let mut inner = encode_packet(OP_SEND_EVENT, 5, br#"{"cmd":"A"}"#);
+        inner.extend(encode_packet(OP_SEND_EVENT, 6, br#"{"cmd":"B"}"#));
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&inner).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut frame = ((HEADER_LEN as usize + compressed.len()) as u32)
+            .to_be_bytes()
+            .to_vec();
+        frame.extend_from_slice(&HEADER_LEN.to_be_bytes());
+        frame.extend_from_slice(&2u16.to_be_bytes());
+        frame.extend_from_slice(&OP_SEND_EVENT.to_be_bytes());
+        frame.extend_from_slice(&0u32.to_be_bytes());
+        frame.extend_from_slice(&compressed);
+
+        let packets = decode_packets(&frame).unwrap();
+        let bodies = packets
+            .iter()
+            .map(|packet| (packet.sequence, packet.body.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [(5, &br#"{"cmd":"A"}"#[..]), (6, &br#"{"cmd":"B"}"#[..])]
+        );
+    }
+
+    #[test]
+    fn truncated_and_empty_frames() {
+        assert!(decode_packets(&[]).unwrap().is_empty());
+        // 不足一个头部
+        assert!(decode_packets(&[0, 0, 0, 20, 0, 16]).unwrap().is_empty());
+
+        // 声明的长度超过实际数据时丢弃这个包，之前完整的包保留
+        let complete = encode_packet(OP_HEARTBEAT_REPLY, 1, &[0, 0, 0, 1]);
+        let mut frame = complete.clone();
+        frame.extend(&encode_packet(OP_SEND_EVENT, 2, br#"{"cmd":"A"}"#)[..20]);
+        let packets = decode_packets(&frame).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].operation, OP_HEARTBEAT_REPLY);
+
+        // 长度为 0 的包无法前进，停止解析
+        let mut zero = vec![0u8; HEADER_LEN as usize];
+        zero.extend(complete);
+        assert!(decode_packets(&zero).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_header_length_stops_decoding() {
+        let mut frame = encode_packet(OP_SEND_EVENT, 1, &[0; 4]);
+        // 头部长度超过包长时正文的起点在终点之后，直接切片会越界 panic
+        frame[4..6].copy_from_slice(&32u16.to_be_bytes());
+        assert!(decode_packets(&frame).unwrap().is_empty());
+
+        // 短于固定头部时正文会混入头部字段
+        frame[4..6].copy_from_slice(&4u16.to_be_bytes());
+        assert!(decode_packets(&frame).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sequence_gaps_are_counted_per_operation() {
+        let start = Instant::now();
+        let mut sequence = PacketSequence::new(start);
+        assert_eq!((sequence.next_outgoing(), sequence.next_outgoing()), (1, 2));
+
+        // 同一操作类型内跳号时返回缺失的包数，不同类型各自计数
+        assert_eq!(sequence.observe(OP_SEND_EVENT, 10, start), None);
+        assert_eq!(sequence.observe(OP_SEND_EVENT, 11, start), None);
+        assert_eq!(sequence.observe(OP_SEND_EVENT, 14, start), Some(2));
+        assert_eq!(sequence.observe(OP_HEARTBEAT_REPLY, 1, start), None);
+        assert_eq!(sequence.observe(OP_SEND_EVENT, 0, start), None);
+        assert_eq!(sequence.last_received(), Some(1));
+    }
+
+    #[test]
+    fn outgoing_sequence_skips_zero_on_overflow() {
+        let mut sequence = PacketSequence::new(Instant::now());
+        sequence.next_outgoing = u32::MAX;
+        assert_eq!(
+            (sequence.next_outgoing(), sequence.next_outgoing()),
+            (u32::MAX, 1)
+        );
+    }
+
+    #[test]
+    fn frozen_heartbeat_sequence_is_stale() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut sequence = PacketSequence::new(start);
+        sequence.observe(OP_HEARTBEAT_REPLY, 1, start);
+
+        // 心跳回包的序号递增过之后又长时间不变，视为连接停滞
+        let later = start + Duration::from_secs(20);
+        assert_eq!(sequence.observe(OP_HEARTBEAT_REPLY, 2, later), None);
+        assert_eq!(
+            sequence.stale(later + Duration::from_secs(30), timeout),
+            None
+        );
+        sequence.observe(OP_HEARTBEAT_REPLY, 2, later + Duration::from_secs(40));
+        assert_eq!(
+            sequence.stale(later + Duration::from_secs(61), timeout),
+            Some(StaleReason::SequenceFrozen)
+        );
+    }
+
+    #[test]
+    fn silent_connection_is_stale() {
+        let start = Instant::now();
+        let silent = PacketSequence::new(start);
+        let timeout = Duration::from_secs(60);
+        assert_eq!(silent.stale(start + Duration::from_secs(59), timeout), None);
+        assert_eq!(
+            silent.stale(start + Duration::from_secs(61), timeout),
+            Some(StaleReason::Silent)
+        );
+    }
+}
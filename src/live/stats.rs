@@ -0,0 +1,197 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use super::LiveEvent;
+use crate::util::TtlLruMap;
+
+/// 去重时最多记住的观众数，超出后淘汰最久未互动的观众
+pub(super) const MAX_TRACKED_VIEWERS: usize = 50_000;
+/// 观众超过这段时间未互动即不再记住，再次互动时会重新计数
+pub(super) const VIEWER_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// 读取任务持有的去重观众集合
+pub(super) type Viewers = TtlLruMap<String, ()>;
+
+pub(super) fn viewers() -> Viewers {
+    TtlLruMap::new(MAX_TRACKED_VIEWERS, VIEWER_TTL)
+}
+
+/// 单次直播监听会话的累计统计
+///
+/// 只由长链读取任务写入，查询方通过原子读取获得快照，不会阻塞读取循环。
+#[derive(Debug, Default)]
+pub(super) struct LiveStats {
+    events: AtomicU64,
+    danmaku: AtomicU64,
+    gifts: AtomicU64,
+    gift_items: AtomicU64,
+    /// 与礼物事件的 `price` 单位一致，1000 为 1 元
+    gift_value: AtomicU64,
+    super_chats: AtomicU64,
+    super_chat_yuan: AtomicU64,
+    guards: AtomicU64,
+    likes: AtomicU64,
+    enters: AtomicU64,
+    unique_viewers: AtomicU64,
+    unique_viewers_approximate: AtomicBool,
+    last_sequence: AtomicU64,
+    missing_packets: AtomicU64,
+}
+
+/// `live.status` / `live.stats` 中的统计快照
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LiveStatsSnapshot {
+    pub events: u64,
+    pub danmaku: u64,
+    /// 送礼事件数
+    pub gifts: u64,
+    /// 送出的礼物总个数
+    pub gift_items: u64,
+    pub gift_value_yuan: f64,
+    pub super_chats: u64,
+    pub super_chat_yuan: u64,
+    /// 开通大航海的总数量（按 `guard_num` 累加）
+    pub guards: u64,
+    pub likes: u64,
+    pub enters: u64,
+    /// 按 open_id 去重的互动观众数（弹幕、礼物、醒目留言、大航海、点赞、进房）
+    pub unique_viewers: u64,
+    /// 去重集合淘汰过观众时为 `true`，此时被淘汰的观众再次互动会重复计数，`unique_viewers` 偏大
+    pub unique_viewers_approximate: bool,
+    /// 长链最近一次收到的非 0 包序号
+    pub last_sequence: u64,
+    /// 按包序号跳号估算的丢帧数
+    pub missing_packets: u64,
+}
+
+impl LiveStats {
+    /// 记录一条直播事件；`viewers` 由读取任务独占持有，只把去重后的数量写回原子计数
+    pub(super) fn record(&self, event: &LiveEvent, viewers: &mut Viewers) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+
+        let count = |path: &[&str]| event.field_i64(path).unwrap_or(0).max(0) as u64;
+        let open_id = match event.cmd.as_str() {
+            "LIVE_OPEN_PLATFORM_DM" => {
+                self.danmaku.fetch_add(1, Ordering::Relaxed);
+                event.field_str(&["open_id"])
+            }
+            "LIVE_OPEN_PLATFORM_SEND_GIFT" => {
+                let items = count(&["gift_num"]).max(1);
+                let value = match count(&["r_price"]) {
+                    0 => count(&["price"]) * items,
+                    total => total,
+                };
+                self.gifts.fetch_add(1, Ordering::Relaxed);
+                self.gift_items.fetch_add(items, Ordering::Relaxed);
+                self.gift_value.fetch_add(value, Ordering::Relaxed);
+                event.field_str(&["open_id"])
+            }
+            "LIVE_OPEN_PLATFORM_SUPER_CHAT" => {
+                self.super_chats.fetch_add(1, Ordering::Relaxed);
+                self.super_chat_yuan
+                    .fetch_add(count(&["rmb"]), Ordering::Relaxed);
+                event.field_str(&["open_id"])
+            }
+            "LIVE_OPEN_PLATFORM_GUARD" => {
+                self.guards
+                    .fetch_add(count(&["guard_num"]).max(1), Ordering::Relaxed);
+                event.field_str(&["user_info", "open_id"])
+            }
+            "LIVE_OPEN_PLATFORM_LIKE" => {
+                self.likes
+                    .fetch_add(count(&["like_count"]), Ordering::Relaxed);
+                event.field_str(&["open_id"])
+            }
+            "LIVE_OPEN_PLATFORM_LIVE_ROOM_ENTER" => {
+                self.enters.fetch_add(1, Ordering::Relaxed);
+                event.field_str(&["open_id"])
+            }
+            _ => None,
+        };
+
+        if let Some(open_id) = open_id.filter(|id| !id.is_empty())
+            && viewers.get(&open_id).is_none()
+        {
+            if viewers.len() >= viewers.max_entries() {
+                self.unique_viewers_approximate
+                    .store(true, Ordering::Relaxed);
+            }
+            viewers.insert(open_id, ());
+            self.unique_viewers.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn record_sequence(&self, sequence: u32) {
+        if sequence != 0 {
+            self.last_sequence.store(sequence as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn record_sequence_gap(&self, missing: u32) {
+        self.missing_packets
+            .fetch_add(missing as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot(&self) -> LiveStatsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        LiveStatsSnapshot {
+            events: load(&self.events),
+            danmaku: load(&self.danmaku),
+            gifts: load(&self.gifts),
+            gift_items: load(&self.gift_items),
+            gift_value_yuan: load(&self.gift_value) as f64 / 1000.0,
+            super_chats: load(&self.super_chats),
+            super_chat_yuan: load(&self.super_chat_yuan),
+            guards: load(&self.guards),
+            likes: load(&self.likes),
+            enters: load(&self.enters),
+            unique_viewers: load(&self.unique_viewers),
+            unique_viewers_approximate: self.unique_viewers_approximate.load(Ordering::Relaxed),
+            last_sequence: load(&self.last_sequence),
+            missing_packets: load(&self.missing_packets),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn danmaku(open_id: &str) -> LiveEvent {
+        LiveEvent {
+            cmd: "LIVE_OPEN_PLATFORM_DM".to_string(),
+            data: serde_json::json!({ "open_id": open_id }),
+            received_at: tokio::time::Instant::now(),
+        }
+    }
+
+    #[test]
+    fn viewers_are_counted_once() {
+        let stats = LiveStats::default();
+        let mut viewers = viewers();
+        for open_id in ["a", "b", "a", "", "b"] {
+            stats.record(&danmaku(open_id), &mut viewers);
+        }
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.danmaku, snapshot.unique_viewers), (5, 2));
+        assert!(!snapshot.unique_viewers_approximate);
+    }
+
+    #[test]
+    fn evicted_viewers_make_the_count_approximate() {
+        let stats = LiveStats::default();
+        let mut viewers = TtlLruMap::new(2, VIEWER_TTL);
+        for open_id in ["a", "b", "c", "a"] {
+            stats.record(&danmaku(open_id), &mut viewers);
+        }
+        // a 被淘汰后再次互动会重复计数
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.unique_viewers, 4);
+        assert!(snapshot.unique_viewers_approximate);
+        assert_eq!(viewers.len(), 2);
+    }
+}
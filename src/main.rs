@@ -1,27 +1,15 @@
-mod capabilities;
-mod config;
-mod errors;
-mod intent;
-mod live;
-mod orchestrator;
-mod providers;
-mod sse;
-mod util;
-// mod ws;  // 已被 SSE 替代，保留文件作为参考
-
 use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
 
-use crate::{
-    errors::Result,
-    live::LiveSessionInfo,
-    orchestrator::AgentController,
-    sse::{AgentCommand, BroadcastSender, SignatureAuth},
+use vutber_agent::{
+    errors::{AgentError, Result},
+    orchestrator::{AgentController, ModelOverride, RequestSource},
+    sse::{AgentCommand, BroadcastSender, QueuedCommand, SignatureAuth},
     util::{format_beijing, now_in_beijing},
 };
 use serde_json::json;
-use std::sync::Arc;
-use tokio::sync::mpsc;
-use tracing::{error, info};
+use std::{future::Future, sync::Arc};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -29,16 +17,18 @@ async fn main() -> Result<()> {
 
     init_tracing();
 
-    let config = config::AppConfig::load()?;
+    let config = vutber_agent::AppConfig::load()?;
     let sse_config = config.sse.clone();
-    let bind_addr = sse_config.bind_addr;
+    let artifacts_dir = config.artifacts_dir.clone();
+    let max_batch_size = config.processing.max_batch_size;
+    let shutdown_timeout = config.processing.shutdown_timeout;
     let auth = Arc::new(SignatureAuth::new(
         sse_config.access_key.clone(),
         sse_config.secret_key.clone(),
         sse_config.signature_ttl,
     ));
 
-    let (broadcaster, _bus_rx) = crate::sse::message_bus();
+    let (broadcaster, _bus_rx) = vutber_agent::sse::message_bus();
     let (command_tx, mut command_rx) = mpsc::channel(64);
 
     let mut controller = AgentController::new(config, Some(broadcaster.clone())).await?;
@@ -48,48 +38,220 @@ async fn main() -> Result<()> {
         let auth = auth.clone();
         let command_tx = command_tx.clone();
         tokio::spawn(async move {
-            if let Err(err) = crate::sse::run_server(bind_addr, auth, broadcaster, command_tx).await
+            if let Err(err) = vutber_agent::sse::run_server(
+                sse_config,
+                auth,
+                broadcaster,
+                command_tx,
+                artifacts_dir,
+            )
+            .await
             {
                 error!(target: "sse", error = ?err, "SSE 服务器异常退出");
             }
         })
     };
 
+    // 上游限流发生在控制器等待提供方响应期间，由独立任务转发才能实时通知前端
+    let throttle_task = {
+        let broadcaster = broadcaster.clone();
+        let mut throttle_rx = vutber_agent::util::subscribe_throttle_events();
+        tokio::spawn(async move {
+            loop {
+                match throttle_rx.recv().await {
+                    Ok(event) => vutber_agent::sse::broadcast_json(
+                        &broadcaster,
+                        "provider.throttled",
+                        json!({
+                            "provider": event.provider,
+                            "attempt": event.attempt,
+                            "wait_ms": event.wait.as_millis() as u64,
+                            "server_hint": event.server_hint,
+                        }),
+                    ),
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    };
+
     broadcast_system_ready(&broadcaster, &controller);
+    #[cfg(feature = "live")]
+    if controller.live_auto_start_enabled() {
+        auto_start_live(&mut controller, &broadcaster).await;
+    }
 
     loop {
-        tokio::select! {
-            _ = tokio::signal::ctrl_c() => {
+        let deadlines = Deadlines {
+            schedule: controller.next_schedule_deadline(),
+            batch: controller.danmaku_batch_deadline(),
+            live_retry: controller.live_auto_start_deadline(),
+        };
+        let has_live_listener = controller.has_live_listener();
+        let live_event = async {
+            if has_live_listener {
+                controller.recv_live_event().await
+            } else {
+                std::future::pending().await
+            }
+        };
+
+        match next_turn(
+            tokio::signal::ctrl_c(),
+            &mut command_rx,
+            deadlines,
+            live_event,
+        )
+        .await
+        {
+            Turn::Shutdown => {
                 info!(target: "agent", "收到退出信号，准备关闭");
                 break;
             }
-            Some(event) = controller.recv_live_event(), if controller.has_live_listener() => {
+            Turn::Command(Some(command)) => {
+                handle_agent_command(&mut controller, &broadcaster, command, max_batch_size)
+                    .await?;
+            }
+            Turn::Command(None) => {
+                error!(target: "agent", "命令通道已关闭，SSE 服务器可能已退出");
+                break;
+            }
+            Turn::Schedule => run_due_schedules(&mut controller, &broadcaster).await,
+            Turn::FlushBatch => controller.flush_danmaku_batch().await,
+            Turn::LiveRetry => {
+                #[cfg(feature = "live")]
+                auto_start_live(&mut controller, &broadcaster).await;
+            }
+            #[cfg(feature = "live")]
+            Turn::LiveEvent(event) => {
                 if let Err(err) = controller.handle_live_event(event).await {
                     error!(target: "agent", error = ?err, "处理直播事件失败");
                     broadcast_error(&broadcaster, "live", &err.to_string());
                 }
             }
-            command = command_rx.recv() => {
-                match command {
-                    Some(command) => {
-                        handle_agent_command(&mut controller, &broadcaster, command).await?;
-                    }
-                    None => {
-                        error!(target: "agent", "命令通道已关闭，SSE 服务器可能已退出");
-                        break;
-                    }
-                }
-            }
+            // 未启用 live 功能时不会收到直播事件
+            #[cfg(not(feature = "live"))]
+            Turn::LiveEvent(event) => match event {},
         }
     }
 
-    controller.shutdown().await?;
+    // 先停止接收新命令，再依次处理排队命令、合并弹幕与直播会话，最后关闭 SSE 服务
+    let deadline = tokio::time::Instant::now() + shutdown_timeout;
+    command_rx.close();
+    let (commands_drained, commands_abandoned) = drain_commands(
+        &mut controller,
+        &broadcaster,
+        &mut command_rx,
+        max_batch_size,
+        deadline,
+    )
+    .await;
+    let mut summary = controller.shutdown(deadline).await?;
+    summary.commands_drained = commands_drained;
+    summary.commands_abandoned = commands_abandoned;
+    summary.timed_out |= commands_abandoned > 0;
+
+    info!(
+        target: "agent",
+        commands_drained = summary.commands_drained,
+        commands_abandoned = summary.commands_abandoned,
+        danmaku_drained = summary.danmaku_drained,
+        danmaku_abandoned = summary.danmaku_abandoned,
+        live_events_abandoned = summary.live_events_abandoned,
+        live_session_stopped = summary.live_session.is_some(),
+        timed_out = summary.timed_out,
+        "退出收尾完成"
+    );
+    vutber_agent::sse::broadcast_json(&broadcaster, "system.shutdown", summary.to_payload());
 
     sse_task.abort();
+    throttle_task.abort();
 
     Ok(())
 }
 
+/// 在截止时间前处理通道中已排队的命令，返回已处理与被放弃的数量；超时时正在执行的命令也算作放弃
+async fn drain_commands(
+    controller: &mut AgentController,
+    broadcaster: &BroadcastSender,
+    command_rx: &mut mpsc::Receiver<QueuedCommand>,
+    max_batch_size: usize,
+    deadline: tokio::time::Instant,
+) -> (usize, usize) {
+    let mut drained = 0;
+    let drain = async {
+        while let Some(command) = command_rx.recv().await {
+            if let Err(err) =
+                handle_agent_command(controller, broadcaster, command, max_batch_size).await
+            {
+                error!(target: "agent", error = ?err, "退出前处理排队命令失败");
+            }
+            drained += 1;
+        }
+    };
+    if tokio::time::timeout_at(deadline, drain).await.is_ok() {
+        return (drained, 0);
+    }
+
+    let mut abandoned = 1;
+    while command_rx.try_recv().is_ok() {
+        abandoned += 1;
+    }
+    warn!(target: "agent", drained, abandoned, "退出超时，放弃剩余的排队命令");
+    (drained, abandoned)
+}
+
+/// 主循环各定时分支的截止时间，`None` 表示没有待触发的任务
+#[derive(Default)]
+struct Deadlines {
+    schedule: Option<Instant>,
+    batch: Option<Instant>,
+    live_retry: Option<Instant>,
+}
+
+/// 主循环一轮要处理的工作
+enum Turn<C, E> {
+    Shutdown,
+    /// 命令通道关闭时为 `None`
+    Command(Option<C>),
+    Schedule,
+    FlushBatch,
+    LiveRetry,
+    LiveEvent(E),
+}
+
+/// 按声明顺序等待下一项工作：退出信号、命令、定时任务、合并弹幕、直播重试，最后才是直播事件。
+/// 每轮只取一项，持续到达的直播事件不会让已排队的命令多等
+async fn next_turn<C, E>(
+    shutdown: impl Future,
+    command_rx: &mut mpsc::Receiver<C>,
+    deadlines: Deadlines,
+    live_event: impl Future<Output = Option<E>>,
+) -> Turn<C, E> {
+    tokio::select! {
+        biased;
+        _ = shutdown => Turn::Shutdown,
+        command = command_rx.recv() => Turn::Command(command),
+        _ = sleep_until_deadline(deadlines.schedule), if deadlines.schedule.is_some() => {
+            Turn::Schedule
+        }
+        _ = sleep_until_deadline(deadlines.batch), if deadlines.batch.is_some() => {
+            Turn::FlushBatch
+        }
+        _ = sleep_until_deadline(deadlines.live_retry), if deadlines.live_retry.is_some() => {
+            Turn::LiveRetry
+        }
+        Some(event) = live_event => Turn::LiveEvent(event),
+    }
+}
+
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline).await;
+    }
+}
+
 fn init_tracing() {
     use tracing_subscriber::EnvFilter;
 
@@ -106,30 +268,88 @@ fn init_tracing() {
 }
 
 fn broadcast_system_ready(broadcaster: &BroadcastSender, controller: &AgentController) {
+    let mut payload = capabilities_payload(controller);
+    payload["message"] = json!("Vutber Agent 已准备就绪");
+    payload["build"] = vutber_agent::util::build_info();
+    payload["help"] = json!(controller.help_message());
+    vutber_agent::sse::broadcast_json(broadcaster, "system.ready", payload);
+}
+
+/// 能力概览：各能力是否可用、提供方/模型与自检结果，`system.ready` 与 `agent.capabilities` 共用
+fn capabilities_payload(controller: &AgentController) -> serde_json::Value {
     let capabilities: Vec<_> = controller
         .capabilities_overview()
         .into_iter()
-        .map(|(intent, enabled)| {
+        .map(|capability| {
             json!({
-                "intent": intent.to_string(),
-                "enabled": enabled,
+                "intent": capability.intent.to_string(),
+                "enabled": capability.enabled,
+                "provider": capability.provider,
+                "model": capability.model,
+                "health": capability.health.label(),
             })
         })
         .collect();
+    let health = controller.health();
 
-    crate::sse::broadcast_json(
-        broadcaster,
-        "system.ready",
-        json!({
-            "message": "Vutber Agent 已准备就绪",
-            "capabilities": capabilities,
-            "help": controller.help_message(),
-        }),
-    );
+    json!({
+        "capabilities": capabilities,
+        "health": {
+            "intent_router": health.intent_router().label(),
+            "live": health.live().label(),
+        },
+    })
+}
+
+/// 命令执行失败：`code` 为 `AgentError::code` 给出的错误码，没有时为 `null`；`context` 与成功结果相同，带有请求 ID
+fn broadcast_agent_error(
+    broadcaster: &BroadcastSender,
+    origin: &str,
+    err: &AgentError,
+    context: Option<serde_json::Value>,
+) {
+    let mut payload = json!({
+        "code": err.code(),
+        "message": err.to_string(),
+    });
+    attach_context(&mut payload, origin, context);
+    vutber_agent::sse::broadcast_json(broadcaster, "agent.error", payload);
+}
+
+/// `live.bilibili.auto_start`：连接成功广播 `live.started`，失败广播带重试间隔的 `live.error`
+#[cfg(feature = "live")]
+async fn auto_start_live(controller: &mut AgentController, broadcaster: &BroadcastSender) {
+    match controller.auto_start_live().await {
+        Some(Ok(info)) => {
+            info!(target: "agent", room_id = info.room_id, "已自动连接直播长链");
+            vutber_agent::sse::broadcast_json(broadcaster, "live.started", info.to_payload());
+        }
+        Some(Err(failure)) => {
+            let retry_in_ms = failure.retry_in.map(|delay| delay.as_millis() as u64);
+            warn!(
+                target: "agent",
+                attempt = failure.attempt,
+                retry_in_ms,
+                error = %failure.error,
+                "自动连接直播长链失败"
+            );
+            vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "live.error",
+                json!({
+                    "message": failure.error.to_string(),
+                    "auto_start": true,
+                    "attempt": failure.attempt,
+                    "retry_in_ms": retry_in_ms,
+                }),
+            );
+        }
+        None => {}
+    }
 }
 
 fn broadcast_error(broadcaster: &BroadcastSender, origin: &str, message: &str) {
-    crate::sse::broadcast_json(
+    vutber_agent::sse::broadcast_json(
         broadcaster,
         "agent.error",
         json!({
@@ -142,48 +362,308 @@ fn broadcast_error(broadcaster: &BroadcastSender, origin: &str, message: &str) {
 async fn handle_agent_command(
     controller: &mut AgentController,
     broadcaster: &BroadcastSender,
-    command: AgentCommand,
+    command: QueuedCommand,
+    max_batch_size: usize,
 ) -> Result<()> {
+    let QueuedCommand {
+        request_id,
+        command,
+    } = command;
     match command {
-        AgentCommand::Command { input } => {
-            let outcome = controller.handle(&input).await?;
-            let (event, mut payload) = outcome.as_event_payload();
-            attach_context(&mut payload, "command", Some(json!({ "input": input })));
-            crate::sse::broadcast_json(broadcaster, &event, payload);
+        AgentCommand::Command {
+            input,
+            origin,
+            user_id,
+            provider,
+            model,
+        } => {
+            let source = RequestSource {
+                sender: user_id.clone(),
+                request_id: request_id.clone(),
+                model_override: ModelOverride::new(provider, model),
+                ..RequestSource::new(origin)
+            };
+            let mut context = json!({ "input": input, "request_id": request_id });
+            if let Some(user_id) = user_id {
+                context["user_id"] = json!(user_id);
+            }
+            if let Some(requested) = &source.model_override {
+                context["model_override"] = requested.to_value();
+            }
+            match controller.handle(&input, &source).await {
+                Ok(outcome) => {
+                    let (event, mut payload) = outcome.as_event_payload();
+                    attach_context(&mut payload, origin, Some(context));
+                    vutber_agent::sse::broadcast_json(broadcaster, event, payload);
+                }
+                Err(err) => {
+                    error!(target: "agent", error = ?err, "处理命令失败");
+                    broadcast_agent_error(broadcaster, origin, &err, Some(context));
+                }
+            }
+        }
+        AgentCommand::DryRun {
+            input,
+            fallback_only,
+        } => match controller.dry_run(&input, fallback_only).await {
+            Ok(mut payload) => {
+                attach_context(
+                    &mut payload,
+                    "command",
+                    Some(json!({ "request_id": request_id })),
+                );
+                vutber_agent::sse::broadcast_json(broadcaster, "agent.dry_run", payload);
+            }
+            Err(err) => broadcast_agent_error(
+                broadcaster,
+                "command",
+                &err,
+                Some(json!({ "request_id": request_id })),
+            ),
+        },
+        AgentCommand::Batch { inputs } => {
+            if inputs.len() > max_batch_size {
+                broadcast_error(
+                    broadcaster,
+                    "batch",
+                    &format!(
+                        "批量命令条目数 {} 超过上限 {}",
+                        inputs.len(),
+                        max_batch_size
+                    ),
+                );
+                return Ok(());
+            }
+            run_batch(controller, broadcaster, request_id, inputs).await;
+        }
+        AgentCommand::Schedule {
+            input,
+            at,
+            every_seconds,
+        } => match controller.schedule_command(input, at.as_deref(), every_seconds) {
+            Ok(entry) => vutber_agent::sse::broadcast_json(broadcaster, "schedule.created", entry),
+            Err(err) => broadcast_error(broadcaster, "schedule", &err.to_string()),
+        },
+        AgentCommand::ScheduleList => {
+            vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "schedule.list",
+                json!({ "entries": controller.list_schedules() }),
+            );
         }
+        AgentCommand::ScheduleCancel { id } => {
+            let cancelled = controller.cancel_schedule(&id);
+            vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "schedule.cancelled",
+                json!({ "id": id, "cancelled": cancelled }),
+            );
+        }
+        #[cfg(feature = "live")]
         AgentCommand::LiveStart => match controller.start_live().await {
             Ok(info) => {
-                crate::sse::broadcast_json(broadcaster, "live.started", live_session_payload(&info));
+                vutber_agent::sse::broadcast_json(broadcaster, "live.started", info.to_payload());
             }
             Err(err) => {
                 broadcast_error(broadcaster, "live", &err.to_string());
             }
         },
+        #[cfg(feature = "live")]
         AgentCommand::LiveStop => match controller.stop_live().await {
-            Ok(Some(info)) => {
-                crate::sse::broadcast_json(broadcaster, "live.stopped", live_session_payload(&info));
+            Ok(Some((info, usage))) => {
+                let mut payload = info.to_payload();
+                if let serde_json::Value::Object(map) = &mut payload {
+                    map.insert(
+                        "usage".to_string(),
+                        vutber_agent::usage::usage_value(&usage),
+                    );
+                }
+                vutber_agent::sse::broadcast_json(broadcaster, "live.stopped", payload);
             }
             Ok(None) => {
-                crate::sse::broadcast_json(broadcaster, "live.stopped", json!({ "active": false }));
+                vutber_agent::sse::broadcast_json(
+                    broadcaster,
+                    "live.stopped",
+                    json!({ "active": false }),
+                );
             }
             Err(err) => {
                 broadcast_error(broadcaster, "live", &err.to_string());
             }
         },
         AgentCommand::LiveStatus => match controller.live_status() {
-            Ok(Some(info)) => {
-                crate::sse::broadcast_json(broadcaster, "live.status", live_session_payload(&info))
-            }
-            Ok(None) => {
-                crate::sse::broadcast_json(broadcaster, "live.status", json!({ "active": false }))
-            }
+            Ok(payload) => vutber_agent::sse::broadcast_json(broadcaster, "live.status", payload),
+            Err(err) => broadcast_error(broadcaster, "live", &err.to_string()),
+        },
+        #[cfg(feature = "live")]
+        AgentCommand::LiveStats => match controller.live_stats() {
+            Ok(stats) => vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "live.stats",
+                json!({ "active": stats.is_some(), "stats": stats }),
+            ),
             Err(err) => broadcast_error(broadcaster, "live", &err.to_string()),
         },
+        #[cfg(not(feature = "live"))]
+        AgentCommand::LiveStart | AgentCommand::LiveStop | AgentCommand::LiveStats => {
+            let err = vutber_agent::orchestrator::live_unsupported();
+            broadcast_error(broadcaster, "live", &err.to_string());
+        }
+        AgentCommand::Capabilities => {
+            vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "agent.capabilities",
+                capabilities_payload(controller),
+            );
+        }
+        AgentCommand::Stats => {
+            vutber_agent::sse::broadcast_json(
+                broadcaster,
+                "agent.stats",
+                json!({
+                    "usage": controller.usage_snapshot(),
+                    "intent_cache": controller.intent_cache_stats(),
+                    "classifier_timeouts": controller.classifier_timeouts(),
+                    "classifier": controller.classifier_stats(),
+                    "languages": controller.language_stats(),
+                    "latency": controller.latency_stats(),
+                    "outcomes": controller.outcome_stats(),
+                    "generations": controller.generation_stats(),
+                }),
+            );
+        }
+        AgentCommand::SetPreamble { text } => {
+            let source = RequestSource {
+                request_id: request_id.clone(),
+                ..RequestSource::new("command")
+            };
+            let context = json!({ "request_id": request_id });
+            match controller.set_preamble(&text, &source) {
+                Ok(mut payload) => {
+                    attach_context(&mut payload, "command", Some(context));
+                    vutber_agent::sse::broadcast_json(
+                        broadcaster,
+                        "agent.preamble_changed",
+                        payload,
+                    );
+                }
+                Err(err) => broadcast_agent_error(broadcaster, "command", &err, Some(context)),
+            }
+        }
+        AgentCommand::SetMood { name } => {
+            let source = RequestSource {
+                request_id: request_id.clone(),
+                ..RequestSource::new("command")
+            };
+            let context = json!({ "request_id": request_id });
+            match controller.set_mood(&name, &source) {
+                Ok(mut payload) => {
+                    attach_context(&mut payload, "command", Some(context));
+                    vutber_agent::sse::broadcast_json(broadcaster, "agent.mood.changed", payload);
+                }
+                Err(err) => broadcast_agent_error(broadcaster, "command", &err, Some(context)),
+            }
+        }
     }
 
     Ok(())
 }
 
+async fn run_due_schedules(controller: &mut AgentController, broadcaster: &BroadcastSender) {
+    for due in controller.take_due_schedules() {
+        info!(target: "agent", id = %due.id, input = %due.input, "执行定时命令");
+        let context = json!({
+            "schedule_id": due.id,
+            "input": due.input,
+            "recurring": due.recurring,
+        });
+
+        match controller
+            .handle(&due.input, &RequestSource::new("schedule"))
+            .await
+        {
+            Ok(outcome) => {
+                let (event, mut payload) = outcome.as_event_payload();
+                attach_context(&mut payload, "schedule", Some(context));
+                vutber_agent::sse::broadcast_json(broadcaster, event, payload);
+            }
+            Err(err) => {
+                error!(target: "agent", error = ?err, id = %due.id, "定时命令执行失败");
+                vutber_agent::sse::broadcast_json(
+                    broadcaster,
+                    "agent.error",
+                    json!({
+                        "origin": "schedule",
+                        "code": err.code(),
+                        "message": err.to_string(),
+                        "context": context,
+                    }),
+                );
+            }
+        }
+    }
+}
+
+async fn run_batch(
+    controller: &mut AgentController,
+    broadcaster: &BroadcastSender,
+    batch_id: String,
+    inputs: Vec<String>,
+) {
+    let started = std::time::Instant::now();
+    let total = inputs.len();
+    let mut succeeded = 0;
+    let mut failed = 0;
+
+    info!(target: "agent", %batch_id, total, "开始执行批量命令");
+
+    for (index, input) in inputs.into_iter().enumerate() {
+        let context = json!({
+            "batch_id": batch_id,
+            "index": index,
+            "input": input,
+        });
+
+        match controller
+            .handle(&input, &RequestSource::new("batch"))
+            .await
+        {
+            Ok(outcome) => {
+                succeeded += 1;
+                let (event, mut payload) = outcome.as_event_payload();
+                attach_context(&mut payload, "batch", Some(context));
+                vutber_agent::sse::broadcast_json(broadcaster, event, payload);
+            }
+            Err(err) => {
+                failed += 1;
+                error!(target: "agent", error = ?err, %batch_id, index, "批量命令条目执行失败");
+                vutber_agent::sse::broadcast_json(
+                    broadcaster,
+                    "agent.error",
+                    json!({
+                        "origin": "batch",
+                        "code": err.code(),
+                        "message": err.to_string(),
+                        "context": context,
+                    }),
+                );
+            }
+        }
+    }
+
+    vutber_agent::sse::broadcast_json(
+        broadcaster,
+        "agent.batch_complete",
+        json!({
+            "batch_id": batch_id,
+            "total": total,
+            "succeeded": succeeded,
+            "failed": failed,
+            "duration_ms": started.elapsed().as_millis() as u64,
+        }),
+    );
+}
+
 fn attach_context(
     payload: &mut serde_json::Value,
     origin: &str,
@@ -197,24 +677,6 @@ fn attach_context(
     }
 }
 
-fn live_session_payload(info: &LiveSessionInfo) -> serde_json::Value {
-    let started_at = format_beijing(&info.started_at, "%Y-%m-%d %H:%M:%S%:z");
-    let uptime = now_in_beijing()
-        .signed_duration_since(info.started_at)
-        .num_seconds()
-        .max(0);
-
-    json!({
-        "active": true,
-        "game_id": info.game_id,
-        "room_id": info.room_id,
-        "anchor_name": info.anchor_name,
-        "anchor_open_id": info.anchor_open_id,
-        "started_at": started_at,
-        "uptime_seconds": uptime,
-    })
-}
-
 struct LocalTimer;
 
 impl FormatTime for LocalTimer {
@@ -223,3 +685,67 @@ impl FormatTime for LocalTimer {
         write!(w, "{}", format_beijing(&now, "%Y-%m-%d %H:%M:%S%:z"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{future::pending, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn commands_are_served_under_sustained_live_events() {
+        let (command_tx, mut command_rx) = mpsc::channel(8);
+        let (live_tx, mut live_rx) = mpsc::channel(1024);
+        // 直播事件通道始终有事件可取
+        tokio::spawn(async move {
+            let mut event = 0u64;
+            while live_tx.send(event).await.is_ok() {
+                event += 1;
+            }
+        });
+
+        let (mut commands, mut live_events) = (0, 0);
+        for turn in 0..10_000 {
+            if turn % 100 == 0 {
+                command_tx.send(turn).await.unwrap();
+            }
+            let deadlines = Deadlines::default();
+            match next_turn(pending::<()>(), &mut command_rx, deadlines, live_rx.recv()).await {
+                // 命令在发送后的下一轮立即得到处理
+                Turn::Command(Some(sent)) => {
+                    assert_eq!(sent, turn);
+                    commands += 1;
+                }
+                Turn::LiveEvent(_) => live_events += 1,
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!((commands, live_events), (100, 9_900));
+    }
+
+    #[tokio::test]
+    async fn due_tasks_run_before_live_events() {
+        let (_command_tx, mut command_rx) = mpsc::channel::<()>(1);
+        let now = Instant::now();
+        let due = now - Duration::from_secs(1);
+        let ready = || async { Some(()) };
+
+        let deadlines = Deadlines {
+            batch: Some(due),
+            live_retry: Some(due),
+            ..Deadlines::default()
+        };
+        let turn = next_turn(pending::<()>(), &mut command_rx, deadlines, ready()).await;
+        assert!(matches!(turn, Turn::FlushBatch));
+
+        let deadlines = Deadlines {
+            schedule: Some(now + Duration::from_secs(60)),
+            ..Deadlines::default()
+        };
+        let turn = next_turn(pending::<()>(), &mut command_rx, deadlines, ready()).await;
+        assert!(matches!(turn, Turn::LiveEvent(())));
+
+        let turn = next_turn(async {}, &mut command_rx, Deadlines::default(), ready()).await;
+        assert!(matches!(turn, Turn::Shutdown));
+    }
+}
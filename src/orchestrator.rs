@@ -1,76 +1,1001 @@
-use std::path::PathBuf;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use crate::{
-    capabilities::{ConversationAgent, ImageGenerator, MusicGenerator, VideoGenerator},
-    config::AppConfig,
+    cache::{CachedArtifact, ResultCache},
+    capabilities::{
+        BinaryArtifact, ConversationAgent, ImageGenerator, ImageOptions, ImageSource,
+        MusicGenerator, ToolLookup, ToolRequest, VideoGenerator, concat_videos,
+        split_option_clauses, split_params_clause,
+    },
+    clarification::{Clarifications, ClarifyReason},
+    config::{
+        AppConfig, CapabilityProviders, CapabilityRoute, ConversationTool,
+        DEFAULT_IMAGE_PROMPT_PREAMBLE, DEFAULT_PREAMBLE, DeepSeekConfig, ExpressionClassifierKind,
+        ModerationAction, OpenAiConfig, Persona, SdWebUiConfig, StoryboardConfig, ZhipuConfig,
+    },
     errors::{AgentError, Result},
-    intent::{Intent, IntentClassifier},
-    live::{LiveEvent, LiveManager, LiveSessionInfo},
-    util::ArtifactWriter,
+    expression::{Expression, ExpressionClassifier},
+    health::{self, HealthReport, HealthStatus, HealthTargets},
+    intent::{Classification, Intent, IntentClassifier, IntentSegment},
+    latency::LatencyStats,
+    limiter::GenerationLimiter,
+    moderation::{InjectionGuard, Moderation},
+    outcomes::OutcomeStats,
+    postprocess::ResponsePostprocessor,
+    providers::apply_sampling,
+    scheduler::{DueSchedule, Scheduler},
+    sse::ArtifactTokens,
+    usage::UsageStats,
+    util::{
+        ArtifactWriter, Language, TtlLruMap, detect_language, format_beijing, now_in_beijing,
+        reply_language, truncate_chars,
+    },
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use chrono::Datelike;
+use futures::{
+    future::join_all,
+    stream::{self, StreamExt},
 };
 use rig::{
     client::CompletionClient,
     providers::{hyperbolic, openai},
 };
-use serde_json::{Value, json};
-use tokio::sync::{broadcast, mpsc};
-use tracing::{info, warn};
+use serde_json::{Map, Value, json};
+use tokio::{sync::broadcast, time::Instant};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+#[cfg(feature = "live")]
+use crate::{
+    capabilities::{SpeechSynthesizer, split_voice_clause},
+    config::{DEFAULT_TTS_MODEL, DanmakuBatchConfig, InteractionEndAction},
+    live::{LiveEvent, LiveManager, LiveSessionInfo, LiveStatsSnapshot},
+    usage::{TokenUsage, usage_value},
+    util::beijing_rfc3339,
+};
+#[cfg(feature = "live")]
+use tokio::sync::mpsc;
+
+/// 回复命中屏蔽词后重新生成时追加到人设后的要求，不向模型透露具体词语
+const MODERATION_RETRY_INSTRUCTION: &str =
+    "上一次回复包含不适合在直播中出现的内容。请换一种说法重新回答，避免粗俗、冒犯或敏感的措辞。";
+
+/// 对话模型可调用的生成工具：对应意图、工具名与描述
+const CONVERSATION_TOOLS: [(Intent, &str, &str); 3] = [
+    (
+        Intent::ImageGeneration,
+        "generate_image",
+        "根据描述生成一张图片。仅在用户明确要求画图或生成图片时调用。",
+    ),
+    (
+        Intent::MusicGeneration,
+        "compose_music",
+        "根据描述创作一段音乐。仅在用户明确要求作曲、唱歌或生成音乐时调用。",
+    ),
+    (
+        Intent::VideoGeneration,
+        "generate_video",
+        "根据描述生成一段短视频。仅在用户明确要求生成视频或动画时调用。",
+    ),
+];
+
+/// 对话模型可以查询的实时信息（`providers.conversation.tools`），查询结果回填后模型再给出最终回复
+const LOOKUP_TOOLS: [(ConversationTool, &str, &str); 3] = [
+    (
+        ConversationTool::Time,
+        "get_current_time",
+        "查询当前的北京时间、日期与星期。用户询问现在几点、今天几号或星期几时调用。",
+    ),
+    (
+        ConversationTool::LiveStatus,
+        "get_live_status",
+        "查询直播状态：是否在播、开播时间、已播时长（秒）以及本场的弹幕、礼物、点赞等统计。用户询问直播了多久或直播数据时调用。",
+    ),
+    (
+        ConversationTool::Artifacts,
+        "get_recent_artifacts",
+        "查询最近生成的图片、音乐与视频：文件名、提示词与生成时间。用户询问刚才生成了什么时调用。",
+    ),
+];
+
+/// 星期的中文名称，按 `num_days_from_monday` 排列
+const WEEKDAYS: [&str; 7] = [
+    "星期一",
+    "星期二",
+    "星期三",
+    "星期四",
+    "星期五",
+    "星期六",
+    "星期日",
+];
 
 pub enum ExecutionOutcome {
     Conversation {
         response: String,
+        /// 按输入检测到的语言，无法判断时为 `None`
+        language: Option<Language>,
+        /// 开启工具调用时模型在本轮调用的生成工具及其结果；产物事件已在执行时单独广播
+        tools: Vec<(ToolRequest, std::result::Result<ExecutionOutcome, String>)>,
+        /// 模型在本轮查询的信息工具及回填的结果
+        lookups: Vec<ToolLookup>,
+        /// 命令覆盖了提供方/模型时实际使用的 `{provider, model}`
+        served_by: Option<Value>,
+        /// 开启 `[expressions]` 时为回复选择的表情
+        expression: Option<Expression>,
     },
     Artifact {
         intent: Intent,
-        path: PathBuf,
+        /// 落盘失败时为 `None`，此时产物通过 `inline_base64` 直接返回
+        path: Option<PathBuf>,
+        inline_base64: Option<String>,
+        warning: Option<String>,
         description: String,
+        media_type: String,
+        file_extension: String,
+        /// 从生成器元数据中挑选的播放相关字段（尺寸、时长、采样率等）
+        details: Map<String, Value>,
+        /// 触发请求的来源，与 `.meta.json` 中的 `source` 一致
+        source: Option<Value>,
+        /// 复用了相同请求此前生成的文件，未调用提供方
+        cached: bool,
+        /// 带短期令牌的 `/artifacts/{name}` 下载链接，内联返回时为 `None`
+        url: Option<String>,
+        /// 命令覆盖了提供方/模型时实际使用的 `{provider, model}`
+        served_by: Option<Value>,
     },
     Help {
         message: String,
     },
+    /// 同一输入交给多个生成能力的结果，按请求顺序排列；失败的能力只携带错误信息
+    Composite {
+        results: Vec<(Intent, std::result::Result<ExecutionOutcome, String>)>,
+    },
+    /// 复合请求按段拆分后依次执行的结果；各段结果已在执行时单独广播，这里只作汇总
+    Segments {
+        results: Vec<(IntentSegment, std::result::Result<ExecutionOutcome, String>)>,
+    },
+    /// 分镜模式的视频结果，镜头按分镜顺序排列，失败的镜头只携带错误信息；
+    /// `combined` 为 ffmpeg 拼接的结果，未开启拼接时为 `None`
+    Storyboard {
+        storyboard: String,
+        scenes: Vec<(String, std::result::Result<ExecutionOutcome, String>)>,
+        combined: Option<std::result::Result<Box<ExecutionOutcome>, String>>,
+    },
+    /// 直播监听控制的结果，事件与 `live_start` / `live_stop` / `live_status` 命令一致
+    LiveControl {
+        action: LiveAction,
+        event: &'static str,
+        payload: Value,
+    },
+    /// 生成请求内容过少或意图置信度过低，先向用户追问，下一条消息会与本次输入合并
+    Clarify {
+        intent: Intent,
+        reason: ClarifyReason,
+        question: String,
+        expires_in_seconds: u64,
+    },
+}
+
+/// 会被带入 `agent.artifact` 事件的元数据字段，便于前端无需探测文件即可播放
+const ARTIFACT_DESCRIPTOR_KEYS: &[&str] = &[
+    "width",
+    "height",
+    "resolution",
+    "duration_seconds",
+    "sample_rate",
+    "channels",
+    "format",
+    "model",
+    "original_input",
+    "enhanced_prompt",
+    "revised_prompt",
+    "lyrics",
+    "scene_index",
+    "segments",
+    "params",
+    "seed",
+];
+
+const SOURCE_METADATA_KEY: &str = "source";
+
+/// 触发请求的来源，随产物一起写入元数据，便于追溯是谁发起的生成
+#[derive(Debug, Clone)]
+pub struct RequestSource {
+    /// 来源类型：command / batch / schedule / live，兼容旧客户端的弹幕消息为 danmaku
+    pub kind: &'static str,
+    pub sender: Option<String>,
+    pub open_id: Option<String>,
+    pub room_id: Option<i64>,
+    pub request_id: String,
+    /// 命令中指定的提供方与模型，只作用于本次请求
+    pub model_override: Option<ModelOverride>,
+    /// 弹幕末尾 `--voice` 指定的朗读音色，只作用于本次回复的语音合成
+    pub voice: Option<String>,
+}
+
+/// 单次请求的提供方/模型覆盖；`provider` 可以是 `openai`、`zhipu`、`deepseek`、`sd_webui`、`mock`
+/// 或 `providers_profiles` 中的配置档名称，省略时沿用路由的提供方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelOverride {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+}
+
+impl ModelOverride {
+    /// 去掉首尾空白，两项都为空时返回 `None`
+    ///
+    /// ```
+    /// use vutber_agent::orchestrator::ModelOverride;
+    ///
+    /// let requested = ModelOverride::new(None, Some(" gpt-4o ".to_string())).unwrap();
+    /// assert_eq!(requested.model.as_deref(), Some("gpt-4o"));
+    /// assert!(ModelOverride::new(Some(" ".to_string()), None).is_none());
+    /// ```
+    pub fn new(provider: Option<String>, model: Option<String>) -> Option<Self> {
+        let clean = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let requested = Self {
+            provider: clean(provider),
+            model: clean(model),
+        };
+        (requested.provider.is_some() || requested.model.is_some()).then_some(requested)
+    }
+
+    pub fn to_value(&self) -> Value {
+        json!({
+            "provider": self.provider,
+            "model": self.model,
+        })
+    }
+}
+
+impl RequestSource {
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            sender: None,
+            open_id: None,
+            room_id: None,
+            request_id: Uuid::new_v4().to_string(),
+            model_override: None,
+            voice: None,
+        }
+    }
+
+    /// 追问等会话状态的归属键：优先使用弹幕发送者的 open_id，其次是昵称，最后按来源类型共享
+    fn conversation_key(&self) -> String {
+        match (&self.open_id, &self.sender) {
+            (Some(open_id), _) => format!("{}:{open_id}", self.kind),
+            (None, Some(sender)) => format!("{}:{sender}", self.kind),
+            (None, None) => self.kind.to_string(),
+        }
+    }
+
+    /// 批量与定时命令没有人能回答追问，不参与追问
+    fn is_interactive(&self) -> bool {
+        !matches!(self.kind, "batch" | "schedule")
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "kind": self.kind,
+            "sender": self.sender,
+            "open_id": self.open_id,
+            "room_id": self.room_id,
+            "request_id": self.request_id,
+        })
+    }
+}
+
+/// 组合与分段请求会用到多个能力，不支持单次覆盖提供方/模型
+fn reject_override(source: &RequestSource, kind: &str) -> Result<()> {
+    match source.model_override {
+        Some(_) => Err(AgentError::rejected(
+            "model_override_unsupported",
+            format!("{kind}请求不支持指定提供方/模型"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// 图像路由配置的默认生成参数
+fn image_options(route: &CapabilityRoute) -> ImageOptions {
+    ImageOptions {
+        quality: route.quality.clone(),
+        style: route.style.clone(),
+        negative_prompt: route.negative_prompt_default.clone(),
+    }
+}
+
+fn artifact_details(artifact: &BinaryArtifact) -> Map<String, Value> {
+    let mut details: Map<String, Value> = ARTIFACT_DESCRIPTOR_KEYS
+        .iter()
+        .filter_map(|key| {
+            artifact
+                .metadata
+                .get(*key)
+                .map(|value| (key.to_string(), value.clone()))
+        })
+        .collect();
+    details.insert("sha256".to_string(), json!(artifact.sha256));
+    details.insert("size_bytes".to_string(), json!(artifact.size_bytes));
+    // 服务商未给出时长时使用从文件头读出的值
+    if let Some(duration) = artifact.duration_seconds
+        && !details.contains_key("duration_seconds")
+    {
+        details.insert("duration_seconds".to_string(), json!(duration));
+    }
+    details
 }
 
 impl ExecutionOutcome {
+    fn artifact(
+        intent: Intent,
+        path: PathBuf,
+        artifact: &BinaryArtifact,
+        url: Option<String>,
+    ) -> Self {
+        let details = artifact_details(artifact);
+
+        ExecutionOutcome::Artifact {
+            intent,
+            path: Some(path),
+            inline_base64: None,
+            warning: None,
+            description: artifact.summary.clone(),
+            media_type: artifact.media_type.clone(),
+            file_extension: artifact.file_extension.clone(),
+            details,
+            source: artifact.metadata.get(SOURCE_METADATA_KEY).cloned(),
+            cached: false,
+            url,
+            served_by: None,
+        }
+    }
+
+    fn cached_artifact(
+        intent: Intent,
+        cached: CachedArtifact,
+        source: &RequestSource,
+        url: Option<String>,
+    ) -> Self {
+        ExecutionOutcome::Artifact {
+            intent,
+            path: Some(cached.path),
+            inline_base64: None,
+            warning: None,
+            description: cached.description,
+            media_type: cached.media_type,
+            file_extension: cached.file_extension,
+            details: cached.details,
+            source: Some(source.to_value()),
+            cached: true,
+            url,
+            served_by: None,
+        }
+    }
+
+    fn inline_artifact(intent: Intent, artifact: &BinaryArtifact, warning: String) -> Self {
+        ExecutionOutcome::Artifact {
+            intent,
+            path: None,
+            inline_base64: Some(BASE64_STANDARD.encode(&artifact.data)),
+            warning: Some(warning),
+            description: artifact.summary.clone(),
+            media_type: artifact.media_type.clone(),
+            file_extension: artifact.file_extension.clone(),
+            details: artifact_details(artifact),
+            source: artifact.metadata.get(SOURCE_METADATA_KEY).cloned(),
+            cached: false,
+            url: None,
+            served_by: None,
+        }
+    }
+
+    /// 记录覆盖提供方/模型后实际使用的模型，只对对话与产物结果生效
+    fn set_served_by(&mut self, value: Value) {
+        if let ExecutionOutcome::Conversation { served_by, .. }
+        | ExecutionOutcome::Artifact { served_by, .. } = self
+        {
+            *served_by = Some(value);
+        }
+    }
+
     pub fn as_event_payload(&self) -> (&'static str, serde_json::Value) {
         match self {
-            ExecutionOutcome::Conversation { response } => (
-                "agent.conversation",
-                json!({
+            ExecutionOutcome::Conversation {
+                response,
+                language,
+                tools,
+                lookups,
+                served_by,
+                expression,
+            } => {
+                let mut payload = json!({
                     "response": response,
-                }),
-            ),
+                    "language": language.map(|language| language.code()),
+                });
+                if let Some(served_by) = served_by {
+                    payload["served_by"] = served_by.clone();
+                }
+                if let Some(expression) = expression {
+                    expression.insert_into(&mut payload);
+                }
+                if !tools.is_empty() {
+                    payload["tools"] = tools
+                        .iter()
+                        .map(|(call, result)| {
+                            let mut summary = json!({
+                                "name": call.name,
+                                "prompt": call.prompt,
+                                "ok": result.is_ok(),
+                            });
+                            match result {
+                                Ok(outcome) => {
+                                    summary["event"] = json!(outcome.as_event_payload().0)
+                                }
+                                Err(error) => summary["error"] = json!(error),
+                            }
+                            summary
+                        })
+                        .collect();
+                }
+                if !lookups.is_empty() {
+                    payload["lookups"] = lookups
+                        .iter()
+                        .map(|lookup| json!({ "name": lookup.name, "result": lookup.result }))
+                        .collect();
+                }
+                ("agent.conversation", payload)
+            }
             ExecutionOutcome::Artifact {
                 intent,
                 path,
+                inline_base64,
+                warning,
                 description,
-            } => (
-                "agent.artifact",
-                json!({
+                media_type,
+                file_extension,
+                details,
+                source,
+                cached,
+                url,
+                served_by,
+            } => {
+                let mut payload = json!({
                     "intent": intent.to_string(),
-                    "path": path.to_string_lossy(),
+                    "path": path.as_ref().map(|path| path.to_string_lossy()),
                     "description": description,
-                }),
-            ),
+                    "media_type": media_type,
+                    "file_extension": file_extension,
+                    "content": details,
+                    "cached": cached,
+                });
+                if let Value::Object(map) = &mut payload {
+                    if let Some(data) = inline_base64 {
+                        map.insert("data_base64".to_string(), json!(data));
+                    }
+                    if let Some(warning) = warning {
+                        map.insert("warning".to_string(), json!(warning));
+                    }
+                    if let Some(source) = source {
+                        map.insert("source".to_string(), source.clone());
+                    }
+                    if let Some(url) = url {
+                        map.insert("url".to_string(), json!(url));
+                    }
+                    if let Some(served_by) = served_by {
+                        map.insert("served_by".to_string(), served_by.clone());
+                    }
+                }
+                ("agent.artifact", payload)
+            }
             ExecutionOutcome::Help { message } => (
                 "agent.help",
                 json!({
                     "message": message,
                 }),
             ),
+            ExecutionOutcome::Composite { results } => {
+                let results = results
+                    .iter()
+                    .map(|(intent, result)| match result {
+                        Ok(outcome) => {
+                            let (_, mut payload) = outcome.as_event_payload();
+                            if let Value::Object(map) = &mut payload {
+                                map.insert("intent".to_string(), json!(intent.to_string()));
+                                map.insert("ok".to_string(), json!(true));
+                            }
+                            payload
+                        }
+                        Err(error) => json!({
+                            "intent": intent.to_string(),
+                            "ok": false,
+                            "error": error,
+                        }),
+                    })
+                    .collect::<Vec<_>>();
+                ("agent.composite", json!({ "results": results }))
+            }
+            ExecutionOutcome::Segments { results } => {
+                let results = results
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (segment, result))| {
+                        let mut summary = json!({
+                            "index": index,
+                            "intent": segment.intent.to_string(),
+                            "segment": segment.segment,
+                            "ok": result.is_ok(),
+                        });
+                        match result {
+                            Ok(outcome) => summary["event"] = json!(outcome.as_event_payload().0),
+                            Err(error) => summary["error"] = json!(error),
+                        }
+                        summary
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    "agent.segments",
+                    json!({ "count": results.len(), "results": results }),
+                )
+            }
+            ExecutionOutcome::Storyboard {
+                storyboard,
+                scenes,
+                combined,
+            } => {
+                let segments = scenes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (scene, result))| {
+                        let mut payload = match result {
+                            Ok(outcome) => {
+                                let (_, mut payload) = outcome.as_event_payload();
+                                payload["ok"] = json!(true);
+                                payload
+                            }
+                            Err(error) => json!({ "ok": false, "error": error }),
+                        };
+                        payload["index"] = json!(index);
+                        payload["scene"] = json!(scene);
+                        payload
+                    })
+                    .collect::<Vec<_>>();
+                let combined = combined.as_ref().map(|result| match result {
+                    Ok(outcome) => outcome.as_event_payload().1,
+                    Err(error) => json!({ "error": error }),
+                });
+                (
+                    "agent.storyboard",
+                    json!({
+                        "intent": Intent::VideoGeneration.to_string(),
+                        "storyboard": storyboard,
+                        "count": scenes.len(),
+                        "succeeded": scenes.iter().filter(|(_, result)| result.is_ok()).count(),
+                        "segments": segments,
+                        "combined": combined,
+                    }),
+                )
+            }
+            ExecutionOutcome::LiveControl { event, payload, .. } => (event, payload.clone()),
+            ExecutionOutcome::Clarify {
+                intent,
+                reason,
+                question,
+                expires_in_seconds,
+            } => {
+                let mut payload = json!({
+                    "intent": intent.to_string(),
+                    "reason": reason.as_str(),
+                    "question": question,
+                    "expires_in_seconds": expires_in_seconds,
+                });
+                if let ClarifyReason::LowConfidence(confidence) = reason {
+                    payload["confidence"] = json!(confidence);
+                }
+                ("agent.clarify", payload)
+            }
         }
     }
 }
 
+/// 退出时各阶段已处理与被放弃的工作，供 `main.rs` 记录日志
+#[derive(Debug, Default, Clone)]
+pub struct ShutdownSummary {
+    /// 停止接收后仍在截止时间前处理完的排队命令
+    pub commands_drained: usize,
+    /// 截止时间到达时尚未处理或被中断的命令
+    pub commands_abandoned: usize,
+    pub danmaku_drained: usize,
+    pub danmaku_abandoned: usize,
+    /// 直播事件通道中尚未处理的事件
+    pub live_events_abandoned: usize,
+    /// 结束的直播会话（`live.status` 格式的负载），未开启监听时为 `None`
+    pub live_session: Option<Value>,
+    /// 是否因超过截止时间而放弃了部分工作
+    pub timed_out: bool,
+}
+
+impl ShutdownSummary {
+    pub fn to_payload(&self) -> Value {
+        json!({
+            "commands": {
+                "drained": self.commands_drained,
+                "abandoned": self.commands_abandoned,
+            },
+            "danmaku": {
+                "drained": self.danmaku_drained,
+                "abandoned": self.danmaku_abandoned,
+            },
+            "live_events_abandoned": self.live_events_abandoned,
+            "live_session": self.live_session,
+            "timed_out": self.timed_out,
+        })
+    }
+}
+
 pub struct AgentController {
     classifier: IntentClassifier,
     conversation: Option<ConversationAgent>,
     image: Option<ImageGenerator>,
     music: Option<MusicGenerator>,
     video: Option<VideoGenerator>,
+    /// 仅在开启 `live.bilibili.auto_tts` 时创建
+    #[cfg(feature = "live")]
+    speech: Option<SpeechSynthesizer>,
+    #[cfg(feature = "live")]
+    auto_tts_max_chars: Option<usize>,
     writer: ArtifactWriter,
+    #[cfg(feature = "live")]
     live: Option<LiveManager>,
+    #[cfg(feature = "live")]
     live_event_rx: Option<mpsc::Receiver<LiveEvent>>,
+    /// 仅在开启 `live.bilibili.auto_start` 时创建
+    #[cfg(feature = "live")]
+    live_auto_start: Option<LiveAutoStart>,
+    #[cfg(feature = "live")]
+    live_interaction_end: InteractionEndAction,
+    #[cfg(feature = "live")]
+    danmaku_batch: Option<DanmakuBatch>,
+    scheduler: Scheduler,
+    clarifications: Clarifications,
+    result_cache: Option<ResultCache>,
+    usage: UsageStats,
+    /// 各能力调用耗时的移动平均，随 `agent.stats` 输出
+    latency: LatencyStats,
+    /// 按意图统计的执行成败，随 `agent.stats` 输出
+    outcomes: OutcomeStats,
+    /// `processing.max_concurrent_generations`：图像/音乐/视频生成的并发名额，对话不占用
+    generations: GenerationLimiter,
+    room_preambles: HashMap<i64, String>,
+    /// 按配置生成的对话人设提示词，`set_preamble` 传入空文本时恢复为它
+    configured_preamble: Option<String>,
+    /// `[[personas]]` 中的命名人设，`set_mood` 按名称切换
+    personas: Vec<Persona>,
+    /// 当前生效的人设名称；使用配置中的提示词或 `set_preamble` 自定义的提示词时为 `None`
+    mood: Option<String>,
+    /// 按会话键记录最近生成的图片，供“上一张图”引用
+    last_images: TtlLruMap<String, PathBuf>,
+    /// 对话模型已开启的信息查询工具
+    lookup_tools: Vec<ConversationTool>,
+    /// 按意图记录最近一次生成的产物，供 `get_recent_artifacts` 查询
+    recent_artifacts: BTreeMap<&'static str, Value>,
+    /// 屏蔽词过滤：拦下命中的弹幕，并处理命中的对话回复
+    moderation: Option<Moderation>,
+    /// 提示词注入防护：拦下命中注入短语的弹幕，并为对话输入加围栏、为人设追加加固说明
+    injection_guard: Option<InjectionGuard>,
+    /// `providers.conversation.force_language`，配置后忽略输入语言
+    force_language: Option<Language>,
+    /// `providers.conversation.postprocess`，在屏蔽词处理之后、广播与朗读之前执行
+    postprocessor: ResponsePostprocessor,
+    /// `[expressions]`，未启用时为 `None`
+    expressions: Option<ExpressionClassifier>,
+    /// 对话输入按检测语言的累计次数，无法判断的记为 `unknown`
+    languages: BTreeMap<&'static str, u64>,
+    #[cfg(feature = "live")]
+    live_danmaku_control: bool,
+    routes: CapabilityProviders,
+    health: HealthReport,
+    inline_artifact_max_bytes: usize,
+    /// `processing.progress_coalesce_ms`，未配置时每次进度都广播
+    progress_coalesce_window: Option<Duration>,
     broadcaster: Option<broadcast::Sender<String>>,
+    artifact_tokens: ArtifactTokens,
+    override_providers: OverrideProviders,
+}
+
+/// `[openai]` 与各 `[providers_profiles.*]` 的配置及客户端，每个配置档只创建一个客户端
+struct OpenAiEndpoints<'a> {
+    /// 键为配置档名称，`[openai]` 为 `None`
+    endpoints: HashMap<Option<String>, (&'a OpenAiConfig, openai::Client)>,
+}
+
+impl<'a> OpenAiEndpoints<'a> {
+    fn new(default: Option<&'a OpenAiConfig>, profiles: &'a HashMap<String, OpenAiConfig>) -> Self {
+        let endpoints = default
+            .map(|cfg| (None, cfg))
+            .into_iter()
+            .chain(profiles.iter().map(|(name, cfg)| (Some(name.clone()), cfg)))
+            .map(|(name, cfg)| {
+                let mut builder = openai::Client::builder(&cfg.api_key);
+                if let Some(base_url) = cfg.base_url.as_deref() {
+                    builder = builder.base_url(base_url);
+                }
+                (name, (cfg, builder.build()))
+            })
+            .collect();
+        Self { endpoints }
+    }
+
+    /// 路由使用的配置与客户端；引用了不存在的配置档时报错，未配置 `[openai]` 时返回 `None`
+    fn resolve(
+        &self,
+        route: &CapabilityRoute,
+    ) -> Result<Option<(&'a OpenAiConfig, openai::Client)>> {
+        match self.endpoints.get(&route.profile) {
+            Some((cfg, client)) => Ok(Some((*cfg, client.clone()))),
+            None => match &route.profile {
+                Some(profile) => Err(AgentError::other(format!(
+                    "未找到 OpenAI 配置档 providers_profiles.{profile}"
+                ))),
+                None => Ok(None),
+            },
+        }
+    }
+}
+
+/// 按路由创建对话代理；提供方为空或 `none`/`disabled` 时返回 `None`
+fn build_conversation(
+    route: &CapabilityRoute,
+    openai_endpoints: &OpenAiEndpoints,
+    zhipu: Option<&ZhipuConfig>,
+    deepseek: Option<&DeepSeekConfig>,
+) -> Result<Option<ConversationAgent>> {
+    Ok(match route.provider.as_str() {
+        "openai" => {
+            let (cfg, client) = openai_endpoints
+                .resolve(route)?
+                .ok_or_else(|| AgentError::MissingConfig("openai.api_key (聊天)"))?;
+            let model = route.model.as_deref().unwrap_or(&cfg.chat_model);
+            let builder = client
+                .agent(model)
+                .name("vutber-conversation")
+                .preamble(route.preamble_or(&cfg.agent_preamble));
+            let agent = apply_sampling(builder, &route.sampling, None).build();
+            Some(ConversationAgent::with_openai(agent))
+        }
+        "zhipu" => {
+            let cfg = zhipu.ok_or_else(|| AgentError::MissingConfig("zhipu.api_key (聊天)"))?;
+            Some(ConversationAgent::with_zhipu(
+                cfg,
+                route.model.as_deref(),
+                route.preamble_or(&cfg.agent_preamble),
+                &route.sampling,
+            )?)
+        }
+        "deepseek" => {
+            let cfg =
+                deepseek.ok_or_else(|| AgentError::MissingConfig("deepseek.api_key (聊天)"))?;
+            Some(ConversationAgent::with_deepseek(
+                cfg,
+                route.model.as_deref(),
+                route.preamble_or(&cfg.agent_preamble),
+                &route.sampling,
+            )?)
+        }
+        "mock" => Some(ConversationAgent::with_mock(
+            route.preamble_or(DEFAULT_PREAMBLE),
+        )),
+        provider if provider.is_empty() || provider == "none" || provider == "disabled" => None,
+        other => {
+            return Err(AgentError::unsupported(format!(
+                "未支持的对话提供方: {other}"
+            )));
+        }
+    })
+}
+
+/// 按路由创建图像生成器；提供方为空或 `none`/`disabled` 时返回 `None`
+fn build_image(
+    route: &CapabilityRoute,
+    openai_endpoints: &OpenAiEndpoints,
+    zhipu: Option<&ZhipuConfig>,
+    sd_webui: Option<&SdWebUiConfig>,
+) -> Result<Option<ImageGenerator>> {
+    Ok(match route.provider.as_str() {
+        "openai" => {
+            let (cfg, _) = openai_endpoints
+                .resolve(route)?
+                .ok_or_else(|| AgentError::MissingConfig("openai.api_key (绘画生成)"))?;
+            let model = route.model.as_deref().unwrap_or(&cfg.image_model);
+            Some(ImageGenerator::new(cfg, model, &route.sizes)?.with_options(image_options(route)))
+        }
+        "zhipu" => {
+            let cfg = zhipu.ok_or_else(|| AgentError::MissingConfig("zhipu.api_key (绘画生成)"))?;
+            Some(
+                ImageGenerator::zhipu(cfg, route.model.as_deref(), &route.sizes)?
+                    .with_options(image_options(route)),
+            )
+        }
+        "sd_webui" => {
+            let cfg = sd_webui
+                .ok_or_else(|| AgentError::MissingConfig("sd_webui.endpoint (绘画生成)"))?;
+            Some(
+                ImageGenerator::sd_webui(cfg, route.model.as_deref(), &route.sizes)?
+                    .with_options(image_options(route)),
+            )
+        }
+        "mock" => Some(
+            ImageGenerator::mock(route.model.as_deref(), &route.sizes)
+                .with_options(image_options(route)),
+        ),
+        provider if provider.is_empty() || provider == "none" || provider == "disabled" => None,
+        other => {
+            return Err(AgentError::unsupported(format!(
+                "未支持的图像生成提供方: {other}"
+            )));
+        }
+    })
+}
+
+/// 命令覆盖提供方/模型时临时创建模型所需的配置
+struct OverrideProviders {
+    openai: Option<OpenAiConfig>,
+    openai_profiles: HashMap<String, OpenAiConfig>,
+    zhipu: Option<ZhipuConfig>,
+    deepseek: Option<DeepSeekConfig>,
+    sd_webui: Option<SdWebUiConfig>,
+    allow_local_files: bool,
+}
+
+impl OverrideProviders {
+    /// `provider` 为 `openai` 时 `profile` 为要使用的配置档；`deepseek` 只能对话，`sd_webui` 只能绘画
+    fn available(&self, provider: &str, profile: Option<&str>, image: bool) -> bool {
+        match provider {
+            "openai" => match profile {
+                Some(profile) => self.openai_profiles.contains_key(profile),
+                None => self.openai.is_some(),
+            },
+            "zhipu" => self.zhipu.is_some(),
+            "deepseek" => !image && self.deepseek.is_some(),
+            "sd_webui" => image && self.sd_webui.is_some(),
+            "mock" => true,
+            _ => false,
+        }
+    }
+
+    /// 路由未指定模型时提供方实际使用的模型，与各生成器的默认值一致
+    fn default_model(&self, route: &CapabilityRoute, image: bool) -> String {
+        let openai = match &route.profile {
+            Some(profile) => self.openai_profiles.get(profile),
+            None => self.openai.as_ref(),
+        };
+        match route.provider.as_str() {
+            "openai" => openai.map(|cfg| {
+                if image {
+                    cfg.image_model.clone()
+                } else {
+                    cfg.chat_model.clone()
+                }
+            }),
+            "zhipu" => self.zhipu.as_ref().map(|cfg| {
+                if image {
+                    cfg.image_model.clone()
+                } else {
+                    cfg.chat_model.clone()
+                }
+            }),
+            "deepseek" => self.deepseek.as_ref().map(|cfg| cfg.chat_model.clone()),
+            "sd_webui" => self
+                .sd_webui
+                .as_ref()
+                .and_then(|cfg| cfg.checkpoint.clone()),
+            _ => None,
+        }
+        .unwrap_or_else(|| route.provider.clone())
+    }
+}
+
+#[cfg(feature = "live")]
+struct PendingDanmaku {
+    sender: String,
+    message: String,
+    received_at: Instant,
+}
+
+#[cfg(feature = "live")]
+struct DanmakuBatch {
+    window: Duration,
+    max_size: usize,
+    pending: Vec<PendingDanmaku>,
+    deadline: Option<Instant>,
+}
+
+#[cfg(feature = "live")]
+impl DanmakuBatch {
+    fn new(config: &DanmakuBatchConfig) -> Self {
+        Self {
+            window: config.window,
+            max_size: config.max_size,
+            pending: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    fn push(&mut self, item: PendingDanmaku) {
+        if self.pending.is_empty() {
+            self.deadline = Some(Instant::now() + self.window);
+        }
+        self.pending.push(item);
+    }
+
+    fn is_full(&self) -> bool {
+        self.pending.len() >= self.max_size
+    }
+
+    fn take(&mut self) -> Vec<PendingDanmaku> {
+        self.deadline = None;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// 自动连接的首次重试间隔，之后每次失败翻倍，最长 `LIVE_AUTO_START_MAX_BACKOFF`
+#[cfg(feature = "live")]
+const LIVE_AUTO_START_BACKOFF: Duration = Duration::from_secs(5);
+#[cfg(feature = "live")]
+const LIVE_AUTO_START_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// `live.bilibili.auto_start` 的重试状态
+#[cfg(feature = "live")]
+#[derive(Default)]
+struct LiveAutoStart {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[cfg(feature = "live")]
+impl LiveAutoStart {
+    fn backoff(&self) -> Duration {
+        LIVE_AUTO_START_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.failures.saturating_sub(1)))
+            .min(LIVE_AUTO_START_MAX_BACKOFF)
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
+        self.retry_at = None;
+    }
+}
+
+/// 能力概览中的一项
+#[derive(Debug, Clone)]
+pub struct CapabilityInfo {
+    pub intent: Intent,
+    pub enabled: bool,
+    /// 路由的提供方，引用配置档时为 `openai/<配置档>`；未配置路由时为 `None`
+    pub provider: Option<String>,
+    /// 路由指定的模型，`None` 表示使用提供方的默认模型
+    pub model: Option<String>,
+    /// 当前的自检结果，未开启 `startup_checks` 或能力未配置时为 `Unchecked`
+    pub health: HealthStatus,
+}
+
+/// 自动连接直播长链失败；`retry_in` 为 `None` 时不再重试（配置缺失等重试也无法恢复的错误）
+#[cfg(feature = "live")]
+pub struct LiveAutoStartError {
+    pub error: AgentError,
+    pub attempt: u32,
+    pub retry_in: Option<Duration>,
 }
 
 impl AgentController {
@@ -80,96 +1005,126 @@ impl AgentController {
     ) -> Result<Self> {
         let AppConfig {
             openai,
+            openai_profiles,
             hyperbolic,
-            video,
+            video: video_config,
+            music: music_config,
+            sd_webui,
+            #[cfg(feature = "live")]
+            indextts,
+            #[cfg(not(feature = "live"))]
+                indextts: _,
             zhipu,
+            deepseek,
+            #[cfg(feature = "live")]
             bilibili_live,
+            room_preambles,
+            personas,
+            #[cfg(feature = "live")]
+            live_danmaku_control,
+            #[cfg(not(feature = "live"))]
+                live_danmaku_control: _,
             providers,
             artifacts_dir,
-            sse: _,
+            sse,
+            processing,
+            intent,
+            clarification,
+            moderation,
+            injection_guard,
+            cache,
+            expressions,
+            usage_log,
+            startup_checks,
         } = config;
 
+        let usage = UsageStats::new(usage_log.then(|| artifacts_dir.join("usage")));
+        let latency = LatencyStats::new(providers.routes().map(|(capability, route)| {
+            let provider = match route.profile.as_deref() {
+                Some(profile) => format!("{}/{profile}", route.provider),
+                None => route.provider.clone(),
+            };
+            (capability, provider)
+        }));
         let writer = ArtifactWriter::new(artifacts_dir).await?;
+        let moderation = moderation.as_ref().map(Moderation::new).transpose()?;
+        let injection_guard = injection_guard
+            .as_ref()
+            .map(InjectionGuard::new)
+            .transpose()?;
 
-        let openai_client = if let Some(cfg) = openai.as_ref() {
-            let mut builder = openai::Client::builder(&cfg.api_key);
-            if let Some(base_url) = cfg.base_url.as_deref() {
-                builder = builder.base_url(base_url);
-            }
-            Some(builder.build())
-        } else {
-            None
-        };
+        let openai_endpoints = OpenAiEndpoints::new(openai.as_ref(), &openai_profiles);
 
         let hyperbolic_client = hyperbolic
             .as_ref()
             .map(|cfg| hyperbolic::Client::new(&cfg.api_key));
 
+        let intent_openai = match providers.intent.as_ref() {
+            Some(route) if route.provider == "openai" => openai_endpoints.resolve(route)?,
+            _ => None,
+        };
         let classifier = IntentClassifier::new(
             providers.intent.as_ref(),
-            openai_client.clone(),
-            openai.as_ref(),
+            intent_openai.as_ref().map(|(_, client)| client.clone()),
+            intent_openai.map(|(cfg, _)| cfg),
             zhipu.as_ref(),
+            deepseek.as_ref(),
+            &intent,
         )?;
 
-        let conversation = match providers.conversation.as_ref() {
-            Some(route) => match route.provider.as_str() {
-                "openai" => {
-                    let client = openai_client
-                        .clone()
-                        .ok_or_else(|| AgentError::MissingConfig("openai.api_key (聊天)"))?;
-                    let cfg = openai
-                        .as_ref()
-                        .ok_or_else(|| AgentError::MissingConfig("openai.chat_model (聊天)"))?;
-                    let model = route.model.as_deref().unwrap_or(&cfg.chat_model);
-                    let agent = client
-                        .agent(model)
-                        .name("vutber-conversation")
-                        .preamble(&cfg.agent_preamble)
-                        .build();
-                    Some(ConversationAgent::with_openai(agent))
-                }
-                "zhipu" => {
-                    let cfg = zhipu
-                        .as_ref()
-                        .ok_or_else(|| AgentError::MissingConfig("zhipu.api_key (聊天)"))?;
-                    Some(ConversationAgent::with_zhipu(cfg, route.model.as_deref())?)
-                }
-                provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
-                    None
-                }
-                other => {
-                    return Err(AgentError::unsupported(format!(
-                        "未支持的对话提供方: {other}"
-                    )));
-                }
-            },
-            None => None,
-        };
+        let mut conversation = providers
+            .conversation
+            .as_ref()
+            .map(|route| {
+                build_conversation(route, &openai_endpoints, zhipu.as_ref(), deepseek.as_ref())
+            })
+            .transpose()?
+            .flatten();
 
-        let image = match providers.image.as_ref() {
-            Some(route) => match route.provider.as_str() {
-                "openai" => {
-                    let client = openai_client
-                        .clone()
-                        .ok_or_else(|| AgentError::MissingConfig("openai.api_key (绘画生成)"))?;
-                    let cfg = openai.as_ref().ok_or_else(|| {
-                        AgentError::MissingConfig("openai.image_model (绘画生成)")
-                    })?;
-                    let model = route.model.as_deref().unwrap_or(&cfg.image_model);
-                    Some(ImageGenerator::new(client, model))
-                }
-                provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
-                    None
-                }
-                other => {
-                    return Err(AgentError::unsupported(format!(
-                        "未支持的图像生成提供方: {other}"
-                    )));
-                }
-            },
-            None => None,
-        };
+        if let (Some(agent), Some(route)) = (conversation.as_mut(), providers.conversation.as_ref())
+        {
+            agent.set_history_budget(route.history_token_budget);
+        }
+
+        let force_language = providers
+            .conversation
+            .as_ref()
+            .and_then(|route| route.force_language.as_deref())
+            .map(|code| {
+                Language::from_code(code).ok_or_else(|| {
+                    AgentError::unsupported(format!(
+                        "providers.conversation.force_language 不支持 {code}，可选 zh、ja、ko、en"
+                    ))
+                })
+            })
+            .transpose()?;
+        let postprocessor = ResponsePostprocessor::new(
+            providers
+                .conversation
+                .as_ref()
+                .map(|route| route.postprocess.clone())
+                .unwrap_or_default(),
+        );
+
+        let configured_preamble = conversation
+            .as_ref()
+            .and_then(ConversationAgent::preamble)
+            .map(str::to_string);
+        if let Some(preamble) = configured_preamble.as_deref() {
+            debug!(
+                target: "agent_controller",
+                preamble = %truncate_chars(preamble, 80),
+                "对话人设提示词"
+            );
+        }
+
+        let image = providers
+            .image
+            .as_ref()
+            .map(|route| build_image(route, &openai_endpoints, zhipu.as_ref(), sd_webui.as_ref()))
+            .transpose()?
+            .flatten()
+            .map(|generator| generator.with_local_files(processing.allow_local_files));
 
         let music = match providers.music.as_ref() {
             Some(route) => match route.provider.as_str() {
@@ -183,6 +1138,16 @@ impl AgentController {
                     let model = route.model.as_deref().unwrap_or(&cfg.language);
                     Some(MusicGenerator::new(client, model, &cfg.voice))
                 }
+                "custom" => {
+                    let cfg = music_config
+                        .as_ref()
+                        .ok_or_else(|| AgentError::MissingConfig("music.endpoint (音乐生成)"))?;
+                    Some(
+                        MusicGenerator::custom(cfg, route.model.as_deref())
+                            .with_local_files(processing.allow_local_files),
+                    )
+                }
+                "mock" => Some(MusicGenerator::mock()),
                 provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
                     None
                 }
@@ -198,10 +1163,13 @@ impl AgentController {
         let video = match providers.video.as_ref() {
             Some(route) => match route.provider.as_str() {
                 "custom" => {
-                    let cfg = video
+                    let cfg = video_config
                         .as_ref()
                         .ok_or_else(|| AgentError::MissingConfig("video.endpoint (视频生成)"))?;
-                    Some(VideoGenerator::new(cfg, route.model.as_deref())?)
+                    Some(
+                        VideoGenerator::new(cfg, route.model.as_deref())?
+                            .with_local_files(processing.allow_local_files),
+                    )
                 }
                 provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
                     None
@@ -215,213 +1183,2440 @@ impl AgentController {
             None => None,
         };
 
-        let (live, live_event_rx) = match bilibili_live {
-            Some(cfg) => {
-                let (tx, rx) = mpsc::channel(64);
-                (
-                    Some(LiveManager::new(cfg, Some(tx), broadcaster.clone())),
-                    Some(rx),
-                )
-            }
-            None => (None, None),
+        #[cfg(feature = "live")]
+        let auto_tts_max_chars = bilibili_live
+            .as_ref()
+            .and_then(|cfg| cfg.auto_tts_max_chars);
+        #[cfg(feature = "live")]
+        let speech = match providers
+            .speech
+            .as_ref()
+            .filter(|_| auto_tts_max_chars.is_some())
+        {
+            Some(route) => match route.provider.as_str() {
+                "openai" => {
+                    let (cfg, _) = openai_endpoints
+                        .resolve(route)?
+                        .ok_or_else(|| AgentError::MissingConfig("openai.api_key (语音合成)"))?;
+                    let model = route.model.as_deref().unwrap_or(DEFAULT_TTS_MODEL);
+                    Some(
+                        SpeechSynthesizer::new(cfg, model)?
+                            .with_voices(route.voices.clone())
+                            .with_subtitles(route.subtitle_max_chars),
+                    )
+                }
+                "indextts" => {
+                    let cfg = indextts
+                        .as_ref()
+                        .ok_or_else(|| AgentError::MissingConfig("indextts.url (语音合成)"))?;
+                    Some(
+                        SpeechSynthesizer::indextts(cfg, route.model.as_deref())?
+                            .with_voices(route.voices.clone())
+                            .with_subtitles(route.subtitle_max_chars),
+                    )
+                }
+                "mock" => Some(
+                    SpeechSynthesizer::mock()
+                        .with_voices(route.voices.clone())
+                        .with_subtitles(route.subtitle_max_chars),
+                ),
+                provider if provider.is_empty() || provider == "none" || provider == "disabled" => {
+                    None
+                }
+                other => {
+                    return Err(AgentError::unsupported(format!(
+                        "未支持的语音合成提供方: {other}"
+                    )));
+                }
+            },
+            None => None,
         };
+        if conversation.is_none()
+            && providers
+                .image
+                .as_ref()
+                .is_some_and(|route| route.enhance_prompts)
+        {
+            warn!(
+                target: "agent_controller",
+                "已开启 providers.image.enhance_prompts，但未配置对话能力，绘画提示词不会改写"
+            );
+        }
+        if conversation.is_none()
+            && providers
+                .music
+                .as_ref()
+                .is_some_and(|route| route.lyrics.is_some())
+        {
+            warn!(
+                target: "agent_controller",
+                "已配置 providers.music.lyrics，但未配置对话能力，点歌不会先写歌词"
+            );
+        }
+        if conversation.is_none()
+            && providers
+                .video
+                .as_ref()
+                .is_some_and(|route| route.storyboard.is_some())
+        {
+            warn!(
+                target: "agent_controller",
+                "已配置 providers.video.storyboard，但未配置对话能力，视频请求不会拆分分镜"
+            );
+        }
+        #[cfg(feature = "live")]
+        if auto_tts_max_chars.is_some() && speech.is_none() {
+            warn!(
+                target: "agent_controller",
+                "已开启 live.bilibili.auto_tts，但未配置可用的 providers.speech，弹幕回复不会朗读"
+            );
+        }
 
-        Ok(Self {
-            classifier,
-            conversation,
+        if let Some(agent) = conversation.as_mut()
+            && providers
+                .conversation
+                .as_ref()
+                .is_some_and(|route| route.tools)
+        {
+            // 只暴露已配置的生成能力
+            let available = |intent: Intent| match intent {
+                Intent::ImageGeneration => image.is_some(),
+                Intent::MusicGeneration => music.is_some(),
+                Intent::VideoGeneration => video.is_some(),
+                _ => false,
+            };
+            let tools = CONVERSATION_TOOLS
+                .iter()
+                .filter(|(intent, _, _)| available(*intent))
+                .map(|(_, name, description)| (*name, *description))
+                .collect::<Vec<_>>();
+            if agent.enable_tools(&tools) {
+                let names = tools.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                info!(target: "agent_controller", tools = ?names, "对话已开启工具调用");
+            } else {
+                warn!(
+                    target: "agent_controller",
+                    provider = agent.provider(),
+                    "当前对话提供方不支持工具调用，providers.conversation.tools 已忽略"
+                );
+            }
+        }
+
+        let mut lookup_tools = providers
+            .conversation
+            .as_ref()
+            .map(|route| route.lookup_tools.clone())
+            .unwrap_or_default();
+        if let Some(agent) = conversation.as_mut()
+            && !lookup_tools.is_empty()
+        {
+            let tools = LOOKUP_TOOLS
+                .iter()
+                .filter(|(tool, _, _)| lookup_tools.contains(tool))
+                .map(|(_, name, description)| (*name, *description))
+                .collect::<Vec<_>>();
+            if agent.enable_lookup_tools(&tools) {
+                let names = tools.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                info!(target: "agent_controller", tools = ?names, "对话已开启信息查询工具");
+            } else {
+                debug!(
+                    target: "agent_controller",
+                    provider = agent.provider(),
+                    "当前对话提供方不支持工具调用，不开启信息查询工具"
+                );
+                lookup_tools.clear();
+            }
+        }
+
+        #[cfg(feature = "live")]
+        let danmaku_batch = bilibili_live
+            .as_ref()
+            .and_then(|cfg| cfg.danmaku_batch.as_ref())
+            .map(DanmakuBatch::new);
+
+        #[cfg(feature = "live")]
+        let live_auto_start = bilibili_live
+            .as_ref()
+            .filter(|cfg| cfg.auto_start)
+            .map(|_| LiveAutoStart::default());
+        #[cfg(feature = "live")]
+        let live_interaction_end = bilibili_live
+            .as_ref()
+            .map(|cfg| cfg.on_interaction_end)
+            .unwrap_or_default();
+
+        #[cfg(feature = "live")]
+        let (live, live_event_rx) = match bilibili_live {
+            Some(cfg) => {
+                let (tx, rx) = mpsc::channel(64);
+                (
+                    Some(LiveManager::new(cfg, Some(tx), broadcaster.clone())),
+                    Some(rx),
+                )
+            }
+            None => (None, None),
+        };
+
+        let override_providers = OverrideProviders {
+            openai: openai.clone(),
+            openai_profiles: openai_profiles.clone(),
+            zhipu: zhipu.clone(),
+            deepseek: deepseek.clone(),
+            sd_webui: sd_webui.clone(),
+            allow_local_files: processing.allow_local_files,
+        };
+
+        let health = if startup_checks {
+            health::run_startup_checks(HealthTargets {
+                providers: &providers,
+                openai: openai.as_ref(),
+                openai_profiles: &openai_profiles,
+                zhipu: zhipu.as_ref(),
+                deepseek: deepseek.as_ref(),
+                hyperbolic: hyperbolic.as_ref(),
+                video: video_config.as_ref(),
+                music: music_config.as_ref(),
+                sd_webui: sd_webui.as_ref(),
+                #[cfg(feature = "live")]
+                live: live.as_ref(),
+            })
+            .await?
+        } else {
+            HealthReport::default()
+        };
+
+        Ok(Self {
+            classifier,
+            conversation,
             image,
             music,
             video,
+            #[cfg(feature = "live")]
+            speech,
+            #[cfg(feature = "live")]
+            auto_tts_max_chars,
             writer,
+            #[cfg(feature = "live")]
             live,
+            #[cfg(feature = "live")]
             live_event_rx,
+            #[cfg(feature = "live")]
+            live_auto_start,
+            #[cfg(feature = "live")]
+            live_interaction_end,
+            #[cfg(feature = "live")]
+            danmaku_batch,
+            scheduler: Scheduler::default(),
+            clarifications: Clarifications::new(&clarification),
+            result_cache: cache.as_ref().map(ResultCache::new),
+            usage,
+            latency,
+            outcomes: OutcomeStats::default(),
+            room_preambles,
+            configured_preamble,
+            personas,
+            mood: None,
+            last_images: TtlLruMap::new(LAST_IMAGES_MAX, LAST_IMAGES_TTL),
+            lookup_tools,
+            recent_artifacts: BTreeMap::new(),
+            moderation,
+            injection_guard,
+            force_language,
+            postprocessor,
+            expressions: expressions.as_ref().map(ExpressionClassifier::new),
+            languages: BTreeMap::new(),
+            #[cfg(feature = "live")]
+            live_danmaku_control,
+            routes: providers,
+            health,
+            inline_artifact_max_bytes: processing.inline_artifact_max_bytes,
+            progress_coalesce_window: processing.progress_coalesce_window,
+            generations: GenerationLimiter::new(
+                processing.max_concurrent_generations,
+                processing.generation_overflow,
+            ),
             broadcaster,
+            artifact_tokens: ArtifactTokens::new(sse.secret_key, sse.artifact_token_ttl),
+            override_providers,
+        })
+    }
+
+    pub fn health(&self) -> &HealthReport {
+        &self.health
+    }
+
+    /// 各生成与对话能力是否可用及其路由与自检结果，`system.ready` 与 `capabilities` 命令共用
+    pub fn capabilities_overview(&self) -> Vec<CapabilityInfo> {
+        let health = self.health();
+        [
+            Intent::Conversation,
+            Intent::ImageGeneration,
+            Intent::MusicGeneration,
+            Intent::VideoGeneration,
+        ]
+        .into_iter()
+        .map(|intent| {
+            let route = self.capability_route(intent);
+            CapabilityInfo {
+                intent,
+                enabled: self.capability_enabled(intent),
+                provider: route.map(|route| match &route.profile {
+                    Some(profile) => format!("{}/{profile}", route.provider),
+                    None => route.provider.clone(),
+                }),
+                model: route.and_then(|route| route.model.clone()),
+                health: health.capability(intent).clone(),
+            }
+        })
+        .collect()
+    }
+
+    /// 意图实际交由哪个能力处理（与 `execute` 的分派保持一致），帮助意图不对应任何能力
+    fn capability_for(intent: Intent) -> Option<Intent> {
+        match intent {
+            Intent::Conversation | Intent::Unknown => Some(Intent::Conversation),
+            Intent::Help | Intent::LiveControl => None,
+            other => Some(other),
+        }
+    }
+
+    fn capability_enabled(&self, capability: Intent) -> bool {
+        match capability {
+            Intent::Conversation => self.conversation.is_some(),
+            Intent::ImageGeneration => self.image.is_some(),
+            Intent::MusicGeneration => self.music.is_some(),
+            Intent::VideoGeneration => self.video.is_some(),
+            Intent::LiveControl => self.live_configured(),
+            Intent::Help | Intent::Unknown => false,
+        }
+    }
+
+    fn capability_route(&self, capability: Intent) -> Option<&CapabilityRoute> {
+        match capability {
+            Intent::Conversation => self.routes.conversation.as_ref(),
+            Intent::ImageGeneration => self.routes.image.as_ref(),
+            Intent::MusicGeneration => self.routes.music.as_ref(),
+            Intent::VideoGeneration => self.routes.video.as_ref(),
+            Intent::LiveControl | Intent::Help | Intent::Unknown => None,
+        }
+    }
+
+    /// 是否配置了 `live.bilibili`
+    #[cfg(feature = "live")]
+    fn live_configured(&self) -> bool {
+        self.live.is_some()
+    }
+
+    #[cfg(feature = "live")]
+    pub fn has_live_listener(&self) -> bool {
+        self.live_event_rx.is_some()
+    }
+
+    #[cfg(feature = "live")]
+    pub async fn recv_live_event(&mut self) -> Option<LiveEvent> {
+        self.live_event_rx.as_ref()?;
+
+        let result = {
+            let receiver = self.live_event_rx.as_mut().expect("checked is_some");
+            receiver.recv().await
+        };
+
+        if result.is_none() {
+            self.live_event_rx = None;
+        }
+
+        result
+    }
+
+    #[cfg(feature = "live")]
+    pub async fn start_live(&mut self) -> Result<LiveSessionInfo> {
+        let manager = self
+            .live
+            .as_mut()
+            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))?;
+        let info = manager.start().await?;
+        self.usage.begin_live_session();
+        if let Some(auto_start) = self.live_auto_start.as_mut() {
+            auto_start.reset();
+        }
+        Ok(info)
+    }
+
+    /// 是否需要在启动后自动连接直播长链
+    #[cfg(feature = "live")]
+    pub fn live_auto_start_enabled(&self) -> bool {
+        self.live_auto_start.is_some()
+    }
+
+    /// 自动连接失败后下一次重试的时间，没有待重试的连接时返回 `None`
+    #[cfg(feature = "live")]
+    pub fn live_auto_start_deadline(&self) -> Option<Instant> {
+        self.live_auto_start
+            .as_ref()
+            .and_then(|auto_start| auto_start.retry_at)
+    }
+
+    /// 执行一次自动连接，失败时按退避间隔安排下一次重试。
+    /// 未开启自动连接或长链已由 `live_start` 连上时返回 `None`
+    #[cfg(feature = "live")]
+    pub async fn auto_start_live(
+        &mut self,
+    ) -> Option<std::result::Result<LiveSessionInfo, LiveAutoStartError>> {
+        let auto_start = self.live_auto_start.as_mut()?;
+        auto_start.retry_at = None;
+        if self.live.as_ref().is_some_and(|live| live.info().is_some()) {
+            auto_start.reset();
+            return None;
+        }
+
+        let result = self.start_live().await;
+        let auto_start = self.live_auto_start.as_mut()?;
+        Some(result.map_err(|error| {
+            auto_start.failures += 1;
+            let retry_in = match error {
+                AgentError::MissingConfig(_) => None,
+                _ => Some(auto_start.backoff()),
+            };
+            auto_start.retry_at = retry_in.map(|delay| Instant::now() + delay);
+            LiveAutoStartError {
+                error,
+                attempt: auto_start.failures,
+                retry_in,
+            }
+        }))
+    }
+
+    /// 停止直播监听，同时返回本场直播期间累计的 token 用量
+    #[cfg(feature = "live")]
+    pub async fn stop_live(&mut self) -> Result<Option<(LiveSessionInfo, TokenUsage)>> {
+        let manager = self
+            .live
+            .as_mut()
+            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))?;
+        let info = manager.stop().await?;
+        // 手动停止后不再自动重连，直到下次启动或 `live_start`
+        if let Some(auto_start) = self.live_auto_start.as_mut() {
+            auto_start.reset();
+        }
+        let usage = self.usage.end_live_session().unwrap_or_default();
+        Ok(info.map(|info| (info, usage)))
+    }
+
+    /// 累计的 token 用量（按提供方与来源）
+    pub fn usage_snapshot(&self) -> Value {
+        self.usage.snapshot()
+    }
+
+    /// 意图缓存的命中统计，未启用缓存时为 `None`
+    pub fn intent_cache_stats(&self) -> Option<Value> {
+        self.classifier.cache_stats()
+    }
+
+    /// 路由模型超时后改用关键字规则的累计次数
+    pub fn classifier_timeouts(&self) -> u64 {
+        self.classifier.classifier_timeouts()
+    }
+
+    /// 对话输入按检测语言的累计次数
+    pub fn language_stats(&self) -> Value {
+        json!(self.languages)
+    }
+
+    /// 意图判定的累计统计，见 `IntentClassifier::stats`
+    pub fn classifier_stats(&self) -> Value {
+        self.classifier.stats()
+    }
+
+    /// 各提供方、各能力调用耗时的移动平均，见 `LatencyStats::snapshot`
+    pub fn latency_stats(&self) -> Value {
+        self.latency.snapshot()
+    }
+
+    /// 按意图统计的执行成败，见 `OutcomeStats::snapshot`
+    pub fn outcome_stats(&self) -> Value {
+        self.outcomes.snapshot()
+    }
+
+    /// 进行中、排队中与被拒绝的生成数，见 `GenerationLimiter::snapshot`
+    pub fn generation_stats(&self) -> Value {
+        self.generations.snapshot()
+    }
+
+    /// `live.status` 事件的负载：会话信息加上长链连接状态与累计统计，未在监听时只有 `active: false`
+    #[cfg(feature = "live")]
+    pub fn live_status(&self) -> Result<Value> {
+        let manager = self.live_manager()?;
+        let Some(info) = manager.info() else {
+            return Ok(json!({ "active": false }));
+        };
+        let mut payload = info.to_payload();
+        if let Value::Object(map) = &mut payload {
+            map.insert("connection".to_string(), json!(manager.connection()));
+            map.insert("stats".to_string(), json!(manager.stats()));
+        }
+        Ok(payload)
+    }
+
+    /// 只读取当前会话的计数器，供频繁轮询使用
+    #[cfg(feature = "live")]
+    pub fn live_stats(&self) -> Result<Option<LiveStatsSnapshot>> {
+        Ok(self.live_manager()?.stats())
+    }
+
+    #[cfg(feature = "live")]
+    fn live_manager(&self) -> Result<&LiveManager> {
+        self.live
+            .as_ref()
+            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))
+    }
+
+    /// B 站结束本场推送后关闭会话并广播 `live.interaction_end`，按 `on_interaction_end` 重新开启项目。
+    /// 结束场次的 end 接口可能报错，此时会话同样视为已关闭
+    #[cfg(feature = "live")]
+    async fn end_live_interaction(&mut self, info: LiveSessionInfo) {
+        let action = self.live_interaction_end;
+        info!(
+            target: "bilibili::live",
+            game_id = %info.game_id,
+            action = action.as_str(),
+            "B 站已结束本场推送，关闭直播会话"
+        );
+        if let Some(manager) = self.live.as_mut()
+            && let Err(err) = manager.stop().await
+        {
+            warn!(target: "bilibili::live", error = ?err, "推送结束后关闭项目失败");
+        }
+        if let Some(auto_start) = self.live_auto_start.as_mut() {
+            auto_start.reset();
+        }
+        let usage = self.usage.end_live_session().unwrap_or_default();
+
+        let mut payload = info.to_payload();
+        if let Value::Object(map) = &mut payload {
+            map.insert("active".to_string(), json!(false));
+            map.insert("action".to_string(), json!(action.as_str()));
+            map.insert("usage".to_string(), usage_value(&usage));
+        }
+        self.broadcast("live.interaction_end", payload);
+
+        if action != InteractionEndAction::Restart {
+            return;
+        }
+        match self.start_live().await {
+            Ok(info) => {
+                info!(target: "bilibili::live", game_id = %info.game_id, "推送结束后已重新开启直播长链");
+                self.broadcast("live.started", info.to_payload());
+            }
+            Err(err) => {
+                warn!(target: "bilibili::live", error = ?err, "推送结束后重新开启直播长链失败");
+                self.broadcast(
+                    "live.error",
+                    json!({
+                        "message": err.to_string(),
+                        "interaction_end": true,
+                    }),
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "live")]
+    pub async fn handle_live_event(&mut self, event: LiveEvent) -> Result<()> {
+        if let Some(info) = self.live.as_ref().and_then(LiveManager::info)
+            && event.is_interaction_end(&info.game_id)
+        {
+            self.end_live_interaction(info).await;
+            return Ok(());
+        }
+
+        if event.cmd.as_str() == "LIVE_OPEN_PLATFORM_DM" {
+            let raw_message = match event.field_str(&["msg"]) {
+                Some(msg) => msg,
+                None => return Ok(()),
+            };
+
+            let trimmed = raw_message.trim();
+            if trimmed.is_empty() {
+                return Ok(());
+            }
+
+            let sender = event
+                .field_str(&["uname"])
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "匿名用户".to_string());
+            let mut source = RequestSource {
+                sender: Some(sender.clone()),
+                open_id: event.field_str(&["open_id"]).filter(|id| !id.is_empty()),
+                room_id: event
+                    .field_i64(&["room_id"])
+                    .or_else(|| self.live.as_ref()?.info().map(|info| info.room_id)),
+                ..RequestSource::new("live")
+            };
+            let occurred_at = event.occurred_at().map(|time| beijing_rfc3339(&time));
+            let received_at = event.received_at;
+
+            if let Some(moderation) = &self.moderation
+                && let Some(matched) = moderation.find(trimmed)
+            {
+                info!(
+                    target: "moderation",
+                    %sender,
+                    matched,
+                    "弹幕命中屏蔽词，已拦截"
+                );
+                self.broadcast(
+                    "live.blocked",
+                    json!({
+                        "origin": "live",
+                        "sender": sender,
+                        "open_id": source.open_id,
+                        "message": moderation.redact(trimmed),
+                        "matched": matched,
+                        "occurred_at": occurred_at,
+                    }),
+                );
+                return Ok(());
+            }
+
+            if let Some(matched) = self
+                .injection_guard
+                .as_ref()
+                .and_then(|guard| guard.find(trimmed))
+            {
+                info!(
+                    target: "moderation",
+                    %sender,
+                    matched,
+                    "弹幕疑似提示词注入，已拦截"
+                );
+                self.broadcast(
+                    "live.injection_blocked",
+                    json!({
+                        "origin": "live",
+                        "sender": sender,
+                        "open_id": source.open_id,
+                        "message": trimmed,
+                        "matched": matched,
+                        "occurred_at": occurred_at,
+                    }),
+                );
+                return Ok(());
+            }
+
+            // 开启自动朗读时拆出末尾的 `--voice`，只影响本条回复的音色，不交给模型
+            let trimmed = match (self.speech.as_ref(), split_voice_clause(trimmed)) {
+                (Some(speech), (message, Some(voice))) => {
+                    if speech.voice_allowed(voice) {
+                        source.voice = Some(voice.to_string());
+                    } else {
+                        info!(
+                            target: "bilibili::live",
+                            %sender,
+                            voice,
+                            "弹幕指定的音色不在 providers.speech.voices 中，使用默认音色"
+                        );
+                    }
+                    message
+                }
+                _ => trimmed,
+            };
+
+            info!(
+                target: "bilibili::live",
+                %sender,
+                message = trimmed,
+                "收到直播弹幕，准备执行意图判断"
+            );
+
+            let (intent, prompt) = match self.take_clarification_answer(trimmed, &source) {
+                Some(answer) => answer,
+                None => {
+                    // 直播弹幕只按主意图处理，不执行拆分
+                    let classification = match self.classify(trimmed, &source).await {
+                        Ok(classification) => classification,
+                        Err(err) => {
+                            self.report_live_failure(&sender, trimmed, &err);
+                            return Ok(());
+                        }
+                    };
+                    let intent = classification.intent;
+                    if let Some(outcome) =
+                        self.clarify(intent, trimmed, classification.confidence, &source)
+                    {
+                        let metadata = json!({
+                            "sender": sender,
+                            "message": trimmed,
+                            "occurred_at": occurred_at,
+                            "latency_ms": received_at.elapsed().as_millis() as u64,
+                        });
+                        self.broadcast_outcome("live", Some(metadata), &outcome);
+                        return Ok(());
+                    }
+                    (intent, trimmed.to_string())
+                }
+            };
+
+            if matches!(intent, Intent::Conversation | Intent::Unknown)
+                && let Some(batch) = self.danmaku_batch.as_mut()
+            {
+                batch.push(PendingDanmaku {
+                    sender,
+                    message: prompt,
+                    received_at,
+                });
+                if batch.is_full() {
+                    self.flush_danmaku_batch().await;
+                }
+                return Ok(());
+            }
+
+            match self.execute(intent, &prompt, &source).await {
+                Ok(outcome) => {
+                    let latency_ms = received_at.elapsed().as_millis() as u64;
+                    info!(
+                        target: "bilibili::live",
+                        %sender,
+                        message = trimmed,
+                        latency_ms,
+                        "直播消息触发自动执行"
+                    );
+                    let metadata = json!({
+                        "sender": sender,
+                        "message": trimmed,
+                        "occurred_at": occurred_at,
+                        "latency_ms": latency_ms,
+                    });
+                    self.broadcast_outcome("live", Some(metadata.clone()), &outcome);
+                    self.speak_live_reply(&outcome, metadata, &source).await;
+                }
+                Err(err) => self.report_live_failure(&sender, trimmed, &err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 当前弹幕合并窗口的截止时间，没有待处理弹幕时返回 `None`
+    #[cfg(feature = "live")]
+    pub fn danmaku_batch_deadline(&self) -> Option<Instant> {
+        self.danmaku_batch.as_ref().and_then(|batch| batch.deadline)
+    }
+
+    /// 将窗口内收集的对话类弹幕合并为一次对话请求，并把回复广播给所有发送者
+    #[cfg(feature = "live")]
+    pub async fn flush_danmaku_batch(&mut self) {
+        let pending = match self.danmaku_batch.as_mut() {
+            Some(batch) => batch.take(),
+            None => return,
+        };
+
+        if pending.is_empty() {
+            return;
+        }
+
+        let prompt = if pending.len() == 1 {
+            pending[0].message.clone()
+        } else {
+            let lines = pending
+                .iter()
+                .map(|item| format!("- {}：{}", item.sender, item.message))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("观众们说：\n{lines}")
+        };
+
+        info!(
+            target: "bilibili::live",
+            batch_size = pending.len(),
+            "合并直播弹幕为一次对话请求"
+        );
+
+        let source = RequestSource {
+            room_id: self
+                .live
+                .as_ref()
+                .and_then(LiveManager::info)
+                .map(|info| info.room_id),
+            ..RequestSource::new("live")
+        };
+        match self.execute(Intent::Conversation, &prompt, &source).await {
+            Ok(outcome) => {
+                // 合并的弹幕从最早收到的一条算起，包含等待合并窗口的时间
+                let latency_ms = pending
+                    .iter()
+                    .map(|item| item.received_at)
+                    .min()
+                    .map(|received_at| received_at.elapsed().as_millis() as u64);
+                let metadata = if pending.len() == 1 {
+                    json!({
+                        "sender": pending[0].sender,
+                        "message": pending[0].message,
+                        "latency_ms": latency_ms,
+                    })
+                } else {
+                    json!({
+                        "batch": pending
+                            .iter()
+                            .map(|item| json!({
+                                "sender": item.sender,
+                                "message": item.message,
+                            }))
+                            .collect::<Vec<_>>(),
+                        "latency_ms": latency_ms,
+                    })
+                };
+                self.broadcast_outcome("live", Some(metadata.clone()), &outcome);
+                self.speak_live_reply(&outcome, metadata, &source).await;
+            }
+            Err(err) => {
+                warn!(
+                    target: "bilibili::live",
+                    error = ?err,
+                    batch_size = pending.len(),
+                    "合并弹幕处理失败"
+                );
+                self.broadcast_error("live", &format!("直播消息处理失败: {err}"));
+            }
+        }
+    }
+
+    #[cfg(feature = "live")]
+    fn report_live_failure(&self, sender: &str, message: &str, err: &AgentError) {
+        warn!(
+            target: "bilibili::live",
+            error = ?err,
+            %sender,
+            message,
+            "直播弹幕处理失败"
+        );
+        self.broadcast_error("live", &format!("直播消息处理失败: {err}"));
+    }
+}
+
+/// 未启用 live 功能时的占位实现：没有直播事件，直播控制一律返回不支持
+#[cfg(not(feature = "live"))]
+impl AgentController {
+    fn live_configured(&self) -> bool {
+        false
+    }
+
+    pub fn has_live_listener(&self) -> bool {
+        false
+    }
+
+    pub async fn recv_live_event(&mut self) -> Option<std::convert::Infallible> {
+        None
+    }
+
+    pub fn live_auto_start_deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    pub fn danmaku_batch_deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    pub async fn flush_danmaku_batch(&mut self) {}
+
+    pub fn live_status(&self) -> Result<Value> {
+        Err(live_unsupported())
+    }
+
+    async fn execute_live_control(
+        &mut self,
+        input: &str,
+        _source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        let action = LiveAction::parse(input);
+        warn!(target: "agent_controller", action = action.as_str(), "当前构建未启用 live 功能");
+        Err(live_unsupported())
+    }
+
+    /// 没有直播会话需要收尾
+    pub async fn shutdown(&mut self, _deadline: Instant) -> Result<ShutdownSummary> {
+        Ok(ShutdownSummary::default())
+    }
+}
+
+/// 未启用 live 功能时直播相关命令返回的错误
+#[cfg(not(feature = "live"))]
+pub fn live_unsupported() -> AgentError {
+    AgentError::unsupported("当前构建未启用 live 功能，无法使用 B 站直播监听")
+}
+
+impl AgentController {
+    pub fn schedule_command(
+        &mut self,
+        input: String,
+        at: Option<&str>,
+        every_seconds: Option<u64>,
+    ) -> Result<Value> {
+        self.scheduler.add(input, at, every_seconds)
+    }
+
+    pub fn cancel_schedule(&mut self, id: &str) -> bool {
+        self.scheduler.cancel(id)
+    }
+
+    pub fn list_schedules(&self) -> Vec<Value> {
+        self.scheduler.list()
+    }
+
+    /// 运行时替换对话人设提示词，对话历史保留；`text` 为空时恢复配置中的提示词。
+    /// 只接受运营端命令（`command` 来源），弹幕等其它来源一律拒绝。返回 `agent.preamble_changed` 的内容
+    pub fn set_preamble(&mut self, text: &str, source: &RequestSource) -> Result<Value> {
+        Self::ensure_operator(source)?;
+        let agent = self
+            .conversation
+            .as_mut()
+            .ok_or(AgentError::MissingConfig("providers.conversation"))?;
+        let text = text.trim();
+        let restored = text.is_empty();
+        let preamble = if restored {
+            self.configured_preamble.clone().unwrap_or_default()
+        } else {
+            text.to_string()
+        };
+        agent.set_preamble(&preamble);
+        self.mood = None;
+        let preview = truncate_chars(&preamble, 80);
+        info!(
+            target: "agent_controller",
+            restored,
+            preamble = %preview,
+            "对话人设提示词已更新"
+        );
+        Ok(json!({
+            "restored": restored,
+            "preview": preview,
+            "chars": preamble.chars().count(),
+        }))
+    }
+
+    /// 切换到 `[[personas]]` 中的命名人设（名称不区分大小写），对话历史保留；
+    /// 与 `set_preamble` 一样只接受运营端命令。返回 `agent.mood.changed` 的内容
+    pub fn set_mood(&mut self, name: &str, source: &RequestSource) -> Result<Value> {
+        Self::ensure_operator(source)?;
+        let name = name.trim();
+        let persona = self
+            .personas
+            .iter()
+            .find(|persona| persona.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let available = self
+                    .personas
+                    .iter()
+                    .map(|persona| persona.name.as_str())
+                    .collect::<Vec<_>>();
+                let message = if available.is_empty() {
+                    format!("未知的人设 {name}，配置中没有 [[personas]]")
+                } else {
+                    format!("未知的人设 {name}，可选：{}", available.join("、"))
+                };
+                AgentError::rejected("unknown_mood", message)
+            })?;
+        let agent = self
+            .conversation
+            .as_mut()
+            .ok_or(AgentError::MissingConfig("providers.conversation"))?;
+        agent.set_preamble(&persona.preamble);
+        let previous = self.mood.replace(persona.name.clone());
+        let preview = truncate_chars(&persona.preamble, 80);
+        info!(
+            target: "agent_controller",
+            mood = %persona.name,
+            previous = ?previous,
+            "对话人设已切换"
+        );
+        Ok(json!({
+            "mood": persona.name,
+            "previous": previous,
+            "preview": preview,
+            "chars": persona.preamble.chars().count(),
+        }))
+    }
+
+    /// 修改人设只接受运营端命令（`command` 来源），弹幕等其它来源一律拒绝
+    fn ensure_operator(source: &RequestSource) -> Result<()> {
+        if source.kind != "command" {
+            return Err(AgentError::rejected(
+                "operator_only",
+                format!("来源 {} 不能修改人设提示词", source.kind),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn next_schedule_deadline(&self) -> Option<Instant> {
+        self.scheduler.next_deadline()
+    }
+
+    pub fn take_due_schedules(&mut self) -> Vec<DueSchedule> {
+        self.scheduler.take_due()
+    }
+
+    /// 只执行意图判定与路由解析，报告请求会被如何处理；不调用生成服务，也不写入产物
+    pub async fn dry_run(&self, input: &str, fallback_only: bool) -> Result<Value> {
+        let classification = if fallback_only {
+            self.classifier.classify_fallback(input)
+        } else {
+            self.classifier.classify_detailed(input).await?
+        };
+        let intent = classification.intent;
+
+        let capability = Self::capability_for(intent).map(|capability| {
+            let route = self.capability_route(capability);
+            let preamble = match capability {
+                Intent::Conversation => self
+                    .conversation
+                    .as_ref()
+                    .and_then(ConversationAgent::preamble),
+                _ => None,
+            };
+            json!({
+                "intent": capability.to_string(),
+                "enabled": self.capability_enabled(capability),
+                "provider": route.map(|route| route.provider.as_str()),
+                "model": route.and_then(|route| route.model.as_deref()),
+                "preamble": preamble,
+            })
+        });
+
+        info!(
+            target: "agent_controller",
+            %intent,
+            source = classification.source.as_str(),
+            "dry run 路由完成"
+        );
+
+        Ok(json!({
+            "input": input,
+            "intent": intent.to_string(),
+            "classification": {
+                "source": classification.source.as_str(),
+                "fallback_only": fallback_only,
+                "intent_route": self.routes.intent.as_ref().map(|route| json!({
+                    "provider": route.provider,
+                    "model": route.model,
+                })),
+                "raw_response": classification.raw_response,
+            },
+            "capability": capability,
+        }))
+    }
+
+    pub async fn handle(
+        &mut self,
+        input: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        if let Some((intents, prompt)) = parse_combo(input)? {
+            reject_override(source, "组合生成")?;
+            return Ok(self.execute_composite(&intents, prompt, source).await);
+        }
+
+        if let Some((intent, prompt)) = self.take_clarification_answer(input, source) {
+            return self.execute(intent, &prompt, source).await;
+        }
+
+        let classification = self.classify(input, source).await?;
+        if !classification.segments.is_empty() {
+            reject_override(source, "拆分为多段的")?;
+            return Ok(self
+                .execute_segments(input, classification.segments, source)
+                .await);
+        }
+
+        let intent = classification.intent;
+        if let Some(outcome) = self.clarify(intent, input, classification.confidence, source) {
+            return Ok(outcome);
+        }
+        self.execute(intent, input, source).await
+    }
+
+    /// 按顺序执行复合请求拆分出的各段，每段结果立即广播并在 `context` 中带上段序号与原始输入；
+    /// 某段失败不影响后续段，拆分出的段不再追问
+    async fn execute_segments(
+        &mut self,
+        input: &str,
+        segments: Vec<IntentSegment>,
+        source: &RequestSource,
+    ) -> ExecutionOutcome {
+        let count = segments.len();
+        info!(target: "agent_controller", count, "复合请求拆分为多段，按顺序执行");
+
+        let mut results = Vec::with_capacity(count);
+        for (index, segment) in segments.into_iter().enumerate() {
+            let context = json!({
+                "input": input,
+                "segment_index": index,
+                "segment_count": count,
+                "segment": segment.segment,
+            });
+            let result = self
+                .execute(segment.intent, &segment.segment, source)
+                .await
+                .map_err(|err| err.to_string());
+            match &result {
+                Ok(outcome) => self.broadcast_outcome(source.kind, Some(context), outcome),
+                Err(error) => {
+                    warn!(
+                        target: "agent_controller",
+                        index,
+                        intent = %segment.intent,
+                        error = %error,
+                        "复合请求中的一段执行失败"
+                    );
+                    let mut payload = json!({ "message": error });
+                    attach_context(&mut payload, source.kind, Some(context));
+                    self.broadcast("agent.error", payload);
+                }
+            }
+            results.push((segment, result));
+        }
+
+        ExecutionOutcome::Segments { results }
+    }
+
+    /// 判定意图并记录路由模型的 token 用量
+    async fn classify(&mut self, input: &str, source: &RequestSource) -> Result<Classification> {
+        let started = Instant::now();
+        let classification = self.classifier.classify_detailed(input).await?;
+        if let Some(provider) = self.classifier.provider_label()
+            && classification.raw_response.is_some()
+        {
+            self.latency.record("intent", started.elapsed());
+            self.usage
+                .record(provider, source.kind, "intent", classification.usage)
+                .await;
+        }
+        Ok(classification)
+    }
+
+    fn take_clarification_answer(
+        &mut self,
+        input: &str,
+        source: &RequestSource,
+    ) -> Option<(Intent, String)> {
+        if !source.is_interactive() {
+            return None;
+        }
+        let key = source.conversation_key();
+        let (intent, prompt) = self.clarifications.take_answer(&key, input)?;
+        info!(target: "agent_controller", %intent, %key, "收到追问的回答，合并为完整请求");
+        Some((intent, prompt))
+    }
+
+    fn clarify(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        confidence: Option<f64>,
+        source: &RequestSource,
+    ) -> Option<ExecutionOutcome> {
+        if !source.is_interactive() {
+            return None;
+        }
+        let key = source.conversation_key();
+        let (question, reason) = self
+            .clarifications
+            .request(&key, intent, input, confidence)?;
+        info!(
+            target: "agent_controller",
+            %intent,
+            %key,
+            reason = reason.as_str(),
+            confidence = ?confidence,
+            "请求内容过少或意图不确定，先向用户追问"
+        );
+        Some(ExecutionOutcome::Clarify {
+            intent,
+            reason,
+            question: question.to_string(),
+            expires_in_seconds: self.clarifications.ttl().as_secs(),
+        })
+    }
+
+    /// 执行已判定的意图，并按意图记录成败
+    async fn execute(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        let result = match &source.model_override {
+            Some(requested) => {
+                self.execute_overridden(intent, input, source, requested)
+                    .await
+            }
+            None => self.execute_intent(intent, input, source).await,
+        };
+        self.outcomes.record(intent, &result);
+        result
+    }
+
+    /// 按命令指定的提供方/模型临时替换对话或绘画模型执行本次请求，结束后恢复原模型；
+    /// 覆盖的请求不读写结果缓存，结果的 `served_by` 为实际使用的提供方与模型
+    async fn execute_overridden(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        source: &RequestSource,
+        requested: &ModelOverride,
+    ) -> Result<ExecutionOutcome> {
+        let image = intent == Intent::ImageGeneration;
+        let (route, served_by) = self.override_route(intent, requested)?;
+        info!(
+            target: "agent_controller",
+            %intent,
+            served_by = %served_by,
+            "本次请求覆盖提供方/模型"
+        );
+
+        let providers = &self.override_providers;
+        let endpoints = OpenAiEndpoints::new(providers.openai.as_ref(), &providers.openai_profiles);
+        let unavailable = || {
+            AgentError::rejected(
+                "model_override_unavailable",
+                format!("提供方 {} 不可用", route.provider),
+            )
+        };
+        let result = if image {
+            let generator = build_image(
+                &route,
+                &endpoints,
+                providers.zhipu.as_ref(),
+                providers.sd_webui.as_ref(),
+            )?
+            .ok_or_else(unavailable)?
+            .with_local_files(providers.allow_local_files);
+            let previous = self.image.replace(generator);
+            let previous_route = self.routes.image.replace(route);
+            let cache = self.result_cache.take();
+            let result = self.execute_intent(intent, input, source).await;
+            self.image = previous;
+            self.routes.image = previous_route;
+            self.result_cache = cache;
+            result
+        } else {
+            let mut agent = build_conversation(
+                &route,
+                &endpoints,
+                providers.zhipu.as_ref(),
+                providers.deepseek.as_ref(),
+            )?
+            .ok_or_else(unavailable)?;
+            if let Some(current) = self.conversation.as_mut() {
+                current.swap_backend(&mut agent);
+            }
+            let previous_route = self.routes.conversation.replace(route);
+            let result = self.execute_intent(intent, input, source).await;
+            self.routes.conversation = previous_route;
+            if let Some(current) = self.conversation.as_mut() {
+                current.swap_backend(&mut agent);
+            }
+            result
+        };
+
+        result.map(|mut outcome| {
+            outcome.set_served_by(served_by);
+            outcome
         })
     }
 
-    pub fn capabilities_overview(&self) -> Vec<(Intent, bool)> {
-        vec![
-            (Intent::Conversation, self.conversation.is_some()),
-            (Intent::ImageGeneration, self.image.is_some()),
-            (Intent::MusicGeneration, self.music.is_some()),
-            (Intent::VideoGeneration, self.video.is_some()),
-        ]
+    /// 校验命令中的提供方/模型覆盖并生成本次请求使用的路由；只支持对话与绘画，
+    /// 提供方必须已配置，换了提供方时不沿用原路由的配置档与模型
+    fn override_route(
+        &self,
+        intent: Intent,
+        requested: &ModelOverride,
+    ) -> Result<(CapabilityRoute, Value)> {
+        let (image, label, base) = match intent {
+            Intent::Conversation | Intent::Unknown => (
+                false,
+                "对话",
+                self.routes
+                    .conversation
+                    .as_ref()
+                    .filter(|_| self.conversation.is_some())
+                    .ok_or_else(|| AgentError::MissingConfig("providers.conversation (聊天)"))?,
+            ),
+            Intent::ImageGeneration => (
+                true,
+                "绘画",
+                self.routes
+                    .image
+                    .as_ref()
+                    .filter(|_| self.image.is_some())
+                    .ok_or_else(|| AgentError::MissingConfig("providers.image (绘画生成)"))?,
+            ),
+            other => {
+                return Err(AgentError::rejected(
+                    "model_override_unsupported",
+                    format!("{other} 请求不支持指定提供方/模型，仅对话与绘画可以覆盖"),
+                ));
+            }
+        };
+
+        let providers = &self.override_providers;
+        let mut route = base.clone();
+        // 当前人设提示词（含 set_preamble/set_mood 的修改）随对话一起带到临时模型
+        route.preamble = self
+            .conversation
+            .as_ref()
+            .filter(|_| !image)
+            .and_then(ConversationAgent::preamble)
+            .map(str::to_string)
+            .or(route.preamble);
+        if let Some(provider) = requested.provider.as_deref() {
+            // 配置档名称即 `providers_profiles` 中的 OpenAI 兼容服务
+            let (name, profile) = if providers.openai_profiles.contains_key(provider) {
+                ("openai", Some(provider.to_string()))
+            } else {
+                (provider, None)
+            };
+            if !providers.available(name, profile.as_deref(), image) {
+                return Err(AgentError::rejected(
+                    "model_override_unavailable",
+                    format!("提供方 {provider} 未配置或不支持{label}"),
+                ));
+            }
+            if route.provider != name || route.profile != profile {
+                route.provider = name.to_string();
+                route.profile = profile;
+                route.model = None;
+            }
+        }
+        if let Some(model) = &requested.model {
+            route.model = Some(model.clone());
+        }
+
+        let provider = match &route.profile {
+            Some(profile) => format!("{}/{profile}", route.provider),
+            None => route.provider.clone(),
+        };
+        let model = route
+            .model
+            .clone()
+            .unwrap_or_else(|| providers.default_model(&route, image));
+        Ok((route, json!({ "provider": provider, "model": model })))
+    }
+
+    async fn execute_intent(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        info!(target: "agent_controller", %intent, "收到用户请求");
+
+        match intent {
+            Intent::Conversation | Intent::Unknown => {
+                let lookups = self.lookup_results();
+                let agent = self
+                    .conversation
+                    .as_mut()
+                    .ok_or_else(|| AgentError::MissingConfig("providers.conversation (聊天)"))?;
+                let language = detect_language(input);
+                *self
+                    .languages
+                    .entry(language.map_or("unknown", |language| language.code()))
+                    .or_default() += 1;
+
+                let preamble = source
+                    .room_id
+                    .and_then(|room_id| self.room_preambles.get(&room_id))
+                    .map(String::as_str)
+                    .or_else(|| agent.preamble())
+                    .unwrap_or_default();
+                // 固定语言优先，否则要求按输入语言回复；无法判断时不追加。注入防护的加固说明放在最后
+                let instructions = reply_language(self.force_language, language)
+                    .map(|reply| reply.reply_instruction())
+                    .into_iter()
+                    .chain(
+                        self.injection_guard
+                            .as_ref()
+                            .and_then(InjectionGuard::hardening),
+                    )
+                    .collect::<Vec<_>>();
+                let preamble = (!instructions.is_empty()).then(|| {
+                    std::iter::once(preamble)
+                        .filter(|preamble| !preamble.is_empty())
+                        .chain(instructions)
+                        .collect::<Vec<_>>()
+                        .join("\n\n")
+                });
+                let model_input = match &self.injection_guard {
+                    Some(guard) => guard.fence(input),
+                    None => Cow::Borrowed(input),
+                };
+                let provider = agent.provider();
+                let started = Instant::now();
+                // 按请求来源覆盖采样参数，例如弹幕回复使用更小的 max_tokens
+                let sampling = self
+                    .routes
+                    .conversation
+                    .as_ref()
+                    .and_then(|route| route.origin_sampling.get(source.kind));
+                let reply = agent
+                    .chat(&model_input, preamble.as_deref(), sampling, &lookups)
+                    .await?;
+                self.latency.record("conversation", started.elapsed());
+                self.usage
+                    .record(provider, source.kind, "conversation", reply.usage)
+                    .await;
+                for lookup in &reply.lookups {
+                    info!(
+                        target: "agent_controller",
+                        tool = %lookup.name,
+                        result = %lookup.result,
+                        "对话模型查询信息"
+                    );
+                }
+                let tools = self.run_tool_calls(input, reply.tool_calls, source).await;
+                let response = self
+                    .moderate_response(&model_input, preamble.as_deref(), source, reply.text)
+                    .await;
+                let response = self.postprocessor.apply(response);
+                let expression = self.classify_expression(&response, source).await;
+                Ok(ExecutionOutcome::Conversation {
+                    response,
+                    language,
+                    tools,
+                    lookups: reply.lookups,
+                    served_by: None,
+                    expression,
+                })
+            }
+            Intent::Help => Ok(ExecutionOutcome::Help {
+                message: self.help_message(),
+            }),
+            Intent::LiveControl => self.execute_live_control(input, source).await,
+            Intent::ImageGeneration | Intent::MusicGeneration | Intent::VideoGeneration => {
+                self.execute_generation(intent, input, source).await
+            }
+        }
+    }
+
+    /// 开启 `[expressions]` 时为回复选择表情；`llm` 模式没有对话能力或请求失败时改用关键词判断
+    async fn classify_expression(
+        &mut self,
+        response: &str,
+        source: &RequestSource,
+    ) -> Option<Expression> {
+        let classifier = self.expressions.as_ref()?;
+        let agent = match (classifier.kind(), self.conversation.as_ref()) {
+            (ExpressionClassifierKind::Llm, Some(agent)) => agent,
+            _ => return Some(classifier.heuristic(response)),
+        };
+        let provider = agent.provider();
+        let expression = match agent
+            .complete_once(response, &classifier.system_prompt())
+            .await
+        {
+            Ok(reply) => {
+                self.usage
+                    .record(provider, source.kind, "expression", reply.usage)
+                    .await;
+                classifier.parse_reply(&reply.text)
+            }
+            Err(err) => {
+                warn!(
+                    target: "agent_controller",
+                    error = %err,
+                    "判断回复表情失败，改用关键词判断"
+                );
+                classifier.heuristic(response)
+            }
+        };
+        debug!(
+            target: "agent_controller",
+            expression = %expression.label,
+            intensity = expression.intensity,
+            "已判断回复表情"
+        );
+        Some(expression)
+    }
+
+    /// 对话回复命中屏蔽词时按 `moderation.response_action` 先重新生成一次，仍然命中（或直接打码）时替换命中片段；
+    /// 在广播与朗读之前执行
+    async fn moderate_response(
+        &mut self,
+        input: &str,
+        preamble: Option<&str>,
+        source: &RequestSource,
+        response: String,
+    ) -> String {
+        let Some(moderation) = &self.moderation else {
+            return response;
+        };
+        let Some(matched) = moderation.find(&response) else {
+            return response;
+        };
+        let action = moderation.action();
+        info!(
+            target: "moderation",
+            matched,
+            action = action.as_str(),
+            "对话回复命中屏蔽词"
+        );
+
+        let mut response = response;
+        if action == ModerationAction::Regenerate
+            && let Some(agent) = self.conversation.as_mut()
+        {
+            let preamble = match preamble.or_else(|| agent.preamble()).unwrap_or_default() {
+                "" => MODERATION_RETRY_INSTRUCTION.to_string(),
+                preamble => format!("{preamble}\n\n{MODERATION_RETRY_INSTRUCTION}"),
+            };
+            let provider = agent.provider();
+            let started = Instant::now();
+            let sampling = self
+                .routes
+                .conversation
+                .as_ref()
+                .and_then(|route| route.origin_sampling.get(source.kind));
+            match agent.regenerate(input, Some(&preamble), sampling).await {
+                Ok(reply) => {
+                    self.latency.record("conversation", started.elapsed());
+                    self.usage
+                        .record(provider, source.kind, "conversation", reply.usage)
+                        .await;
+                    response = reply.text;
+                }
+                Err(err) => warn!(
+                    target: "moderation",
+                    error = ?err,
+                    "重新生成回复失败，改为打码原回复"
+                ),
+            }
+        }
+
+        match self
+            .moderation
+            .as_ref()
+            .and_then(|moderation| moderation.redact(&response))
+        {
+            Some(redacted) => redacted,
+            None => response,
+        }
+    }
+
+    async fn execute_generation(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        if intent == Intent::ImageGeneration
+            && let Some((reference, prompt)) = parse_image_reference(input)
+        {
+            return self.execute_image_edit(reference, &prompt, source).await;
+        }
+        if intent == Intent::VideoGeneration
+            && let Some(config) = self
+                .routes
+                .video
+                .as_ref()
+                .and_then(|route| route.storyboard.clone())
+            && let Some(scenes) = self.write_storyboard(&config, input, source).await
+        {
+            return Ok(self
+                .execute_storyboard(&config, input, scenes, source)
+                .await);
+        }
+        let outcome = match self.cached_outcome(intent, input, source).await {
+            Some(outcome) => outcome,
+            None => {
+                let enhanced = match intent {
+                    Intent::ImageGeneration => self.enhance_image_prompt(input, source).await,
+                    _ => None,
+                };
+                let lyrics = match intent {
+                    Intent::MusicGeneration => self.write_lyrics(input, source).await,
+                    _ => None,
+                };
+                let artifact = match &enhanced {
+                    Some(enhanced) => {
+                        // 参数子句不参与改写，原样接在改写结果之后
+                        let (_, clauses) = split_option_clauses(input);
+                        let mut artifact = self
+                            .generate(intent, &format!("{enhanced}{clauses}"))
+                            .await?;
+                        artifact
+                            .metadata
+                            .insert("original_input".to_string(), json!(input));
+                        artifact
+                            .metadata
+                            .insert("enhanced_prompt".to_string(), json!(enhanced));
+                        artifact
+                    }
+                    None => match &lyrics {
+                        Some(lyrics) => self.compose_with_lyrics(input, lyrics).await?,
+                        None => self.generate(intent, input).await?,
+                    },
+                };
+                let outcome = self.store_artifact(intent, artifact, source).await?;
+                self.remember_outcome(intent, input, &outcome);
+                outcome
+            }
+        };
+        self.remember_artifact(input, &outcome);
+        if intent == Intent::ImageGeneration {
+            self.remember_image(source, &outcome);
+        }
+        Ok(outcome)
+    }
+
+    /// 开启 `providers.image.enhance_prompts` 时用对话模型把输入改写为详细的英文提示词；
+    /// 未开启、没有对话能力或改写失败时返回 `None`，按原始输入生成
+    async fn enhance_image_prompt(
+        &mut self,
+        input: &str,
+        source: &RequestSource,
+    ) -> Option<String> {
+        let route = self
+            .routes
+            .image
+            .as_ref()
+            .filter(|route| route.enhance_prompts)?;
+        let preamble = route
+            .enhance_preamble
+            .as_deref()
+            .unwrap_or(DEFAULT_IMAGE_PROMPT_PREAMBLE);
+        let agent = self.conversation.as_ref()?;
+        let provider = agent.provider();
+        let (request, _) = split_option_clauses(input);
+        let reply = match agent.complete_once(request, preamble).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!(
+                    target: "agent_controller",
+                    error = %err,
+                    "改写绘画提示词失败，使用原始输入"
+                );
+                return None;
+            }
+        };
+        self.usage
+            .record(provider, source.kind, "image_prompt", reply.usage)
+            .await;
+
+        let enhanced = extract_image_prompt(&reply.text);
+        match &enhanced {
+            Some(enhanced) => info!(
+                target: "agent_controller",
+                original = request,
+                enhanced = %truncate_chars(enhanced, 200),
+                "已改写绘画提示词"
+            ),
+            None => warn!(
+                target: "agent_controller",
+                reply = %truncate_chars(&reply.text, 200),
+                "未能从回复中解析出绘画提示词，使用原始输入"
+            ),
+        }
+        enhanced
+    }
+
+    /// 配置了 `providers.music.lyrics` 时先用对话模型为点歌请求写歌词；
+    /// 未配置、没有对话能力、写歌词失败或回复为空时返回 `None`，直接按输入生成音乐
+    async fn write_lyrics(&mut self, input: &str, source: &RequestSource) -> Option<String> {
+        let prompt = self
+            .routes
+            .music
+            .as_ref()
+            .and_then(|route| route.lyrics.as_ref())?
+            .system_prompt();
+        let agent = self.conversation.as_ref()?;
+        let provider = agent.provider();
+        let (request, _) = split_option_clauses(input);
+        let reply = match agent.complete_once(request, &prompt).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!(
+                    target: "agent_controller",
+                    error = %err,
+                    "写歌词失败，直接按输入生成音乐"
+                );
+                return None;
+            }
+        };
+        self.usage
+            .record(provider, source.kind, "lyrics", reply.usage)
+            .await;
+
+        let lyrics = reply.text.trim();
+        if lyrics.is_empty() {
+            warn!(target: "agent_controller", "对话模型没有返回歌词，直接按输入生成音乐");
+            return None;
+        }
+        info!(
+            target: "agent_controller",
+            request,
+            lines = lyrics.lines().filter(|line| !line.trim().is_empty()).count(),
+            "已写好歌词"
+        );
+        Some(lyrics.to_string())
+    }
+
+    /// 两段式点歌的第二段：按歌词谱曲，原始输入作为曲风提示；成功时记录耗时
+    async fn compose_with_lyrics(&self, input: &str, lyrics: &str) -> Result<BinaryArtifact> {
+        let generator = self
+            .music
+            .as_ref()
+            .ok_or_else(|| AgentError::MissingConfig("providers.music (音乐生成)"))?;
+        let _permit = self.generations.acquire("music").await?;
+        let started = Instant::now();
+        let mut artifact = generator.compose_with_lyrics(input, lyrics).await?;
+        self.latency.record("music", started.elapsed());
+        artifact
+            .metadata
+            .insert("original_input".to_string(), json!(input));
+        Ok(artifact)
     }
 
-    pub fn has_live_listener(&self) -> bool {
-        self.live_event_rx.is_some()
-    }
+    /// 开启分镜模式时用对话模型把视频请求拆成多个镜头；
+    /// 没有对话能力、拆分失败或只拆出一个镜头时返回 `None`，按原始输入生成单个视频
+    async fn write_storyboard(
+        &mut self,
+        config: &StoryboardConfig,
+        input: &str,
+        source: &RequestSource,
+    ) -> Option<Vec<String>> {
+        let agent = self.conversation.as_ref()?;
+        let provider = agent.provider();
+        let (request, _) = split_params_clause(input);
+        let (request, _) = split_option_clauses(request);
+        let reply = match agent.complete_once(request, &config.system_prompt()).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!(
+                    target: "agent_controller",
+                    error = %err,
+                    "拆分分镜失败，按原始输入生成视频"
+                );
+                return None;
+            }
+        };
+        self.usage
+            .record(provider, source.kind, "storyboard", reply.usage)
+            .await;
 
-    pub async fn recv_live_event(&mut self) -> Option<LiveEvent> {
-        if self.live_event_rx.is_none() {
+        let scenes = parse_storyboard(&reply.text, config.max_scenes);
+        if scenes.len() < 2 {
+            info!(
+                target: "agent_controller",
+                request,
+                "分镜只有一个镜头，按原始输入生成视频"
+            );
             return None;
         }
+        info!(target: "agent_controller", request, scenes = scenes.len(), "已拆分分镜");
+        Some(scenes)
+    }
 
-        let result = {
-            let receiver = self.live_event_rx.as_mut().expect("checked is_some");
-            receiver.recv().await
+    /// 按分镜逐个（或按 `concurrency` 同时）生成镜头并分别保存，单个镜头失败不影响其它镜头；
+    /// 开启 `concat` 且全部镜头都已落盘时再用 ffmpeg 拼接为一个文件
+    async fn execute_storyboard(
+        &mut self,
+        config: &StoryboardConfig,
+        input: &str,
+        scenes: Vec<String>,
+        source: &RequestSource,
+    ) -> ExecutionOutcome {
+        info!(
+            target: "agent_controller",
+            scenes = scenes.len(),
+            concurrency = config.concurrency,
+            "按分镜分段生成视频"
+        );
+        let controller = &*self;
+        // 原始输入中的 `--params` 对每个镜头都生效
+        let (_, params) = split_params_clause(input);
+        let generated: Vec<Result<ExecutionOutcome>> = stream::iter(scenes.iter().enumerate())
+            .map(|(index, scene)| async move {
+                let prompt = match params {
+                    "" => Cow::Borrowed(scene.as_str()),
+                    params => Cow::Owned(format!("{scene} {params}")),
+                };
+                let mut artifact = controller
+                    .generate(Intent::VideoGeneration, &prompt)
+                    .await?;
+                artifact
+                    .metadata
+                    .insert("original_input".to_string(), json!(input));
+                artifact
+                    .metadata
+                    .insert("scene_index".to_string(), json!(index));
+                controller
+                    .store_artifact(Intent::VideoGeneration, artifact, source)
+                    .await
+            })
+            .buffered(config.concurrency)
+            .collect()
+            .await;
+
+        let storyboard = scenes
+            .iter()
+            .enumerate()
+            .map(|(index, scene)| format!("{}. {scene}", index + 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut results = Vec::with_capacity(scenes.len());
+        for (index, (scene, result)) in scenes.into_iter().zip(generated).enumerate() {
+            match result {
+                Ok(outcome) => {
+                    self.remember_artifact(input, &outcome);
+                    results.push((scene, Ok(outcome)));
+                }
+                Err(err) => {
+                    warn!(target: "agent_controller", error = ?err, index, "分镜中的镜头生成失败");
+                    results.push((scene, Err(err.to_string())));
+                }
+            }
+        }
+
+        let combined = if config.concat {
+            let combined = self
+                .concat_storyboard(config, input, &results, source)
+                .await;
+            if let Ok(outcome) = &combined {
+                self.remember_artifact(input, outcome);
+            }
+            Some(combined.map(Box::new))
+        } else {
+            None
         };
 
-        if result.is_none() {
-            self.live_event_rx = None;
+        ExecutionOutcome::Storyboard {
+            storyboard,
+            scenes: results,
+            combined,
         }
+    }
 
-        result
+    /// 拼接全部镜头并保存；有镜头失败或未落盘时不拼接，错误只作为 `combined` 的说明返回
+    async fn concat_storyboard(
+        &self,
+        config: &StoryboardConfig,
+        input: &str,
+        results: &[(String, std::result::Result<ExecutionOutcome, String>)],
+        source: &RequestSource,
+    ) -> std::result::Result<ExecutionOutcome, String> {
+        let mut paths = Vec::with_capacity(results.len());
+        let mut format = None;
+        for (_, result) in results {
+            let Ok(ExecutionOutcome::Artifact {
+                path: Some(path),
+                media_type,
+                file_extension,
+                ..
+            }) = result
+            else {
+                info!(target: "agent_controller", "有镜头生成失败或未能落盘，跳过拼接");
+                return Err("有镜头生成失败或未能保存到磁盘，未拼接".to_string());
+            };
+            paths.push(path.clone());
+            format.get_or_insert((media_type.as_str(), file_extension.as_str()));
+        }
+        let (media_type, extension) = format.unwrap_or(("video/mp4", "mp4"));
+
+        let started = Instant::now();
+        let stored = match concat_videos(&config.ffmpeg_path, &paths, media_type, extension).await {
+            Ok(mut artifact) => {
+                self.latency.record("ffmpeg", started.elapsed());
+                artifact
+                    .metadata
+                    .insert("original_input".to_string(), json!(input));
+                self.store_artifact(Intent::VideoGeneration, artifact, source)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        stored.map_err(|err| {
+            warn!(target: "agent_controller", error = ?err, "拼接分镜视频失败");
+            err.to_string()
+        })
     }
 
-    pub async fn start_live(&mut self) -> Result<LiveSessionInfo> {
-        let manager = self
-            .live
-            .as_mut()
-            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))?;
-        manager.start().await
+    /// 基于参考图修改或生成变体；参考图不存在或无法读取时返回 `image_source_missing`，结果不进入缓存
+    async fn execute_image_edit(
+        &mut self,
+        reference: ImageReference,
+        prompt: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        let generator = self
+            .image
+            .as_ref()
+            .ok_or_else(|| AgentError::MissingConfig("providers.image (绘画生成)"))?;
+        let image_source = match reference {
+            ImageReference::Previous => {
+                let path = self
+                    .last_images
+                    .get(&source.conversation_key())
+                    .ok_or_else(|| {
+                        AgentError::rejected(
+                            "image_source_missing",
+                            "没有可参考的上一张图，请先生成图片或指明产物文件名",
+                        )
+                    })?;
+                read_image_source(path).await?
+            }
+            ImageReference::Artifact(name) => {
+                let path = self.writer.resolve(&name).ok_or_else(|| {
+                    AgentError::rejected("image_source_missing", format!("产物目录中没有 {name}"))
+                })?;
+                read_image_source(&path).await?
+            }
+            // 地址会由服务端下载，只接受控制台命令与定时任务给出的 URL
+            ImageReference::Url(url) if matches!(source.kind, "command" | "schedule") => {
+                generator.fetch_source(&url).await?
+            }
+            ImageReference::Url(_) => {
+                return Err(AgentError::rejected(
+                    "image_source_url_forbidden",
+                    "只有控制台命令与定时任务可以使用图片 URL 作为参考图",
+                ));
+            }
+        };
+        info!(
+            target: "agent_controller",
+            reference = %image_source.reference.1,
+            prompt,
+            "基于参考图生成图片"
+        );
+
+        let permit = self.generations.acquire("image").await?;
+        let started = Instant::now();
+        let artifact = generator.edit(image_source, prompt).await?;
+        self.latency.record("image", started.elapsed());
+        drop(permit);
+        let outcome = self
+            .store_artifact(Intent::ImageGeneration, artifact, source)
+            .await?;
+        self.remember_artifact(prompt, &outcome);
+        self.remember_image(source, &outcome);
+        Ok(outcome)
     }
 
-    pub async fn stop_live(&mut self) -> Result<Option<LiveSessionInfo>> {
-        let manager = self
-            .live
-            .as_mut()
-            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))?;
-        manager.stop().await
+    /// 已开启的信息查询工具在本轮的结果（按工具名），请求对话模型前准备好，模型调用时回填
+    fn lookup_results(&self) -> Map<String, Value> {
+        let mut results = Map::new();
+        for (tool, name, _) in &LOOKUP_TOOLS {
+            if !self.lookup_tools.contains(tool) {
+                continue;
+            }
+            let result = match tool {
+                ConversationTool::Time => {
+                    let now = now_in_beijing();
+                    json!({
+                        "now": format_beijing(&now, "%Y-%m-%d %H:%M:%S"),
+                        "weekday": WEEKDAYS[now.weekday().num_days_from_monday() as usize],
+                        "timezone": "UTC+8（北京时间）",
+                    })
+                }
+                ConversationTool::LiveStatus => match self.live_status() {
+                    Ok(mut status) => {
+                        // 标识字段对回答没有帮助，不交给模型
+                        if let Value::Object(map) = &mut status {
+                            map.remove("anchor_open_id");
+                            map.remove("game_id");
+                        }
+                        status
+                    }
+                    Err(_) => json!({ "active": false, "configured": false }),
+                },
+                ConversationTool::Artifacts => json!(self.recent_artifacts),
+                ConversationTool::Generate => continue,
+            };
+            results.insert(name.to_string(), result);
+        }
+        results
     }
 
-    pub fn live_status(&self) -> Result<Option<LiveSessionInfo>> {
-        let manager = self
-            .live
+    /// 记录各意图最近一次生成的产物，只保留交给模型回答所需的字段
+    fn remember_artifact(&mut self, input: &str, outcome: &ExecutionOutcome) {
+        let ExecutionOutcome::Artifact {
+            intent,
+            path,
+            description,
+            ..
+        } = outcome
+        else {
+            return;
+        };
+        let file = path
             .as_ref()
-            .ok_or_else(|| AgentError::MissingConfig("live.bilibili"))?;
-        Ok(manager.info())
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        self.recent_artifacts.insert(
+            intent.as_prefix(),
+            json!({
+                "file": file,
+                "prompt": input,
+                "description": description,
+                "created_at": format_beijing(&now_in_beijing(), "%Y-%m-%d %H:%M:%S"),
+            }),
+        );
     }
 
-    pub async fn handle_live_event(&mut self, event: LiveEvent) -> Result<()> {
-        match event.cmd.as_str() {
-            "LIVE_OPEN_PLATFORM_DM" => {
-                let raw_message = match event.field_str(&["msg"]) {
-                    Some(msg) => msg,
-                    None => return Ok(()),
-                };
+    /// 记录会话最近的一张图片；会话数超过上限时淘汰最久未使用的会话
+    fn remember_image(&mut self, source: &RequestSource, outcome: &ExecutionOutcome) {
+        let ExecutionOutcome::Artifact {
+            path: Some(path), ..
+        } = outcome
+        else {
+            return;
+        };
+        self.last_images
+            .insert(source.conversation_key(), path.clone());
+    }
 
-                let trimmed = raw_message.trim();
-                if trimmed.is_empty() {
-                    return Ok(());
+    /// 依次执行对话模型请求的生成工具，每个结果立即广播并在 `context` 中带上工具名与提示词；
+    /// 单个工具失败只广播该工具的 `agent.error`
+    async fn run_tool_calls(
+        &mut self,
+        input: &str,
+        calls: Vec<ToolRequest>,
+        source: &RequestSource,
+    ) -> Vec<(ToolRequest, std::result::Result<ExecutionOutcome, String>)> {
+        let mut results = Vec::with_capacity(calls.len());
+        for call in calls {
+            info!(
+                target: "agent_controller",
+                tool = %call.name,
+                prompt = %call.prompt,
+                "对话模型调用生成工具"
+            );
+            let context = json!({
+                "input": input,
+                "tool": call.name,
+                "prompt": call.prompt,
+            });
+            let intent = CONVERSATION_TOOLS
+                .iter()
+                .find(|(_, name, _)| *name == call.name)
+                .map(|(intent, _, _)| *intent);
+            let result = match intent {
+                Some(_) if call.prompt.is_empty() => {
+                    Err(format!("工具 {} 缺少 prompt 参数", call.name))
                 }
+                Some(intent) => self
+                    .execute_generation(intent, &call.prompt, source)
+                    .await
+                    .map_err(|err| err.to_string()),
+                None => Err(format!("未知的工具: {}", call.name)),
+            };
+            match &result {
+                Ok(outcome) => self.broadcast_outcome(source.kind, Some(context), outcome),
+                Err(error) => {
+                    warn!(
+                        target: "agent_controller",
+                        tool = %call.name,
+                        error = %error,
+                        "生成工具执行失败"
+                    );
+                    let mut payload = json!({ "message": error });
+                    attach_context(&mut payload, source.kind, Some(context));
+                    self.broadcast("agent.error", payload);
+                }
+            }
+            results.push((call, result));
+        }
+        results
+    }
 
-                let sender = event
-                    .field_str(&["uname"])
-                    .filter(|name| !name.is_empty())
-                    .unwrap_or_else(|| "匿名用户".to_string());
-
-                info!(
-                    target: "bilibili::live",
-                    %sender,
-                    message = trimmed,
-                    "收到直播弹幕，准备执行意图判断"
-                );
+    /// 按输入中的动作开始/停止/查询直播监听；弹幕来源只接受当前直播间主播本人的指令
+    #[cfg(feature = "live")]
+    async fn execute_live_control(
+        &mut self,
+        input: &str,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        let action = LiveAction::parse(input);
+        if source.kind == "live" {
+            if !self.live_danmaku_control {
+                return Err(AgentError::unsupported("已禁用通过弹幕控制直播监听"));
+            }
+            let anchor_open_id = self
+                .live
+                .as_ref()
+                .and_then(LiveManager::info)
+                .and_then(|info| info.anchor_open_id);
+            if anchor_open_id.is_none() || anchor_open_id != source.open_id {
+                return Err(AgentError::unsupported(
+                    "只有主播本人可以通过弹幕控制直播监听",
+                ));
+            }
+        }
 
-                match self.handle(trimmed).await {
-                    Ok(outcome) => {
-                        info!(
-                            target: "bilibili::live",
-                            %sender,
-                            message = trimmed,
-                            "直播消息触发自动执行"
-                        );
-                        let metadata = json!({
-                            "sender": sender,
-                            "message": trimmed,
-                        });
-                        self.broadcast_outcome("live", Some(metadata), &outcome);
-                    }
-                    Err(err) => {
-                        warn!(
-                            target: "bilibili::live",
-                            error = ?err,
-                            %sender,
-                            message = trimmed,
-                            "直播弹幕处理失败"
-                        );
-                        self.broadcast_error("live", &format!("直播消息处理失败: {err}"));
+        info!(target: "agent_controller", action = action.as_str(), kind = source.kind, "执行直播监听控制");
+        let (event, payload) = match action {
+            LiveAction::Start => ("live.started", self.start_live().await?.to_payload()),
+            LiveAction::Stop => match self.stop_live().await? {
+                Some((info, usage)) => {
+                    let mut payload = info.to_payload();
+                    if let Value::Object(map) = &mut payload {
+                        map.insert("usage".to_string(), usage_value(&usage));
                     }
+                    ("live.stopped", payload)
+                }
+                None => ("live.stopped", json!({ "active": false })),
+            },
+            LiveAction::Status => ("live.status", self.live_status()?),
+        };
+
+        Ok(ExecutionOutcome::LiveControl {
+            action,
+            event,
+            payload,
+        })
+    }
+
+    /// 同一输入并发交给多个生成能力，单个能力失败不影响其它能力的结果
+    async fn execute_composite(
+        &mut self,
+        intents: &[Intent],
+        input: &str,
+        source: &RequestSource,
+    ) -> ExecutionOutcome {
+        info!(target: "agent_controller", ?intents, "收到组合请求");
+
+        let mut results = Vec::with_capacity(intents.len());
+        let mut pending = Vec::new();
+        for &intent in intents {
+            match self.cached_outcome(intent, input, source).await {
+                Some(outcome) => {
+                    self.outcomes.record(intent, &Ok(()));
+                    self.remember_artifact(input, &outcome);
+                    results.push((intent, Ok(outcome)));
                 }
+                None => pending.push(intent),
             }
-            _ => {}
         }
 
-        Ok(())
+        let controller = &*self;
+        let generated = join_all(pending.iter().map(|&intent| async move {
+            let artifact = controller.generate(intent, input).await?;
+            controller.store_artifact(intent, artifact, source).await
+        }))
+        .await;
+
+        for (intent, result) in pending.into_iter().zip(generated) {
+            self.outcomes.record(intent, &result);
+            match result {
+                Ok(outcome) => {
+                    self.remember_outcome(intent, input, &outcome);
+                    self.remember_artifact(input, &outcome);
+                    results.push((intent, Ok(outcome)));
+                }
+                Err(err) => {
+                    warn!(target: "agent_controller", error = ?err, %intent, "组合请求中的能力执行失败");
+                    results.push((intent, Err(err.to_string())));
+                }
+            }
+        }
+        results.sort_by_key(|(intent, _)| intents.iter().position(|item| item == intent));
+
+        ExecutionOutcome::Composite { results }
     }
 
-    pub async fn handle(&mut self, input: &str) -> Result<ExecutionOutcome> {
-        let intent = self.classifier.classify(input).await?;
-        info!(target: "agent_controller", %intent, "收到用户请求");
+    /// 命中结果缓存且文件仍存在时直接返回已有产物；对话依赖上下文，不参与缓存
+    async fn cached_outcome(
+        &mut self,
+        intent: Intent,
+        input: &str,
+        source: &RequestSource,
+    ) -> Option<ExecutionOutcome> {
+        let model = self
+            .capability_route(intent)
+            .and_then(|route| route.model.clone());
+        let cached = self
+            .result_cache
+            .as_mut()?
+            .get(intent, input, model.as_deref())
+            .await?;
+        info!(target: "agent_controller", %intent, path = ?cached.path, "命中生成结果缓存");
+        let url = self.download_url(&cached.path);
+        Some(ExecutionOutcome::cached_artifact(
+            intent, cached, source, url,
+        ))
+    }
 
-        match intent {
-            Intent::Conversation | Intent::Unknown => {
-                let agent = self
-                    .conversation
-                    .as_mut()
-                    .ok_or_else(|| AgentError::MissingConfig("providers.conversation (聊天)"))?;
-                let response = agent.chat(input).await?;
-                Ok(ExecutionOutcome::Conversation { response })
-            }
+    fn remember_outcome(&mut self, intent: Intent, input: &str, outcome: &ExecutionOutcome) {
+        let model = self
+            .capability_route(intent)
+            .and_then(|route| route.model.clone());
+        if let Some(cache) = self.result_cache.as_mut()
+            && let ExecutionOutcome::Artifact {
+                path: Some(path),
+                description,
+                media_type,
+                file_extension,
+                details,
+                cached: false,
+                ..
+            } = outcome
+        {
+            cache.insert(
+                intent,
+                input,
+                model.as_deref(),
+                CachedArtifact {
+                    path: path.clone(),
+                    description: description.clone(),
+                    media_type: media_type.clone(),
+                    file_extension: file_extension.clone(),
+                    details: details.clone(),
+                },
+            );
+        }
+    }
+
+    /// 调用图像/音乐/视频生成器，只产生内存中的产物，不落盘；占用一个生成名额，成功时记录耗时（不含排队时间）
+    async fn generate(&self, intent: Intent, input: &str) -> Result<BinaryArtifact> {
+        let capability = match intent {
+            Intent::ImageGeneration => "image",
+            Intent::MusicGeneration => "music",
+            Intent::VideoGeneration => "video",
+            other => return Err(AgentError::unsupported(format!("{other} 不是生成类能力"))),
+        };
+        let _permit = self.generations.acquire(capability).await?;
+        let started = Instant::now();
+        let result = match intent {
             Intent::ImageGeneration => {
                 let generator = self
                     .image
                     .as_ref()
                     .ok_or_else(|| AgentError::MissingConfig("providers.image (绘画生成)"))?;
-                let artifact = generator.generate(input, None).await?;
-                let path = self
-                    .writer
-                    .persist(Intent::ImageGeneration, &artifact)
-                    .await?;
-                Ok(ExecutionOutcome::Artifact {
-                    intent: Intent::ImageGeneration,
-                    path,
-                    description: artifact.summary.clone(),
-                })
+                generator.generate(input, None).await
             }
             Intent::MusicGeneration => {
                 let generator = self
                     .music
                     .as_ref()
                     .ok_or_else(|| AgentError::MissingConfig("providers.music (音乐生成)"))?;
-                let artifact = generator.compose(input).await?;
-                let path = self
-                    .writer
-                    .persist(Intent::MusicGeneration, &artifact)
-                    .await?;
-                Ok(ExecutionOutcome::Artifact {
-                    intent: Intent::MusicGeneration,
-                    path,
-                    description: artifact.summary.clone(),
-                })
+                generator.compose(input).await
             }
-            Intent::VideoGeneration => {
+            _ => {
                 let generator = self
                     .video
                     .as_ref()
                     .ok_or_else(|| AgentError::MissingConfig("providers.video (视频生成)"))?;
-                let artifact = generator.generate(input).await?;
-                let path = self
-                    .writer
-                    .persist(Intent::VideoGeneration, &artifact)
-                    .await?;
-                Ok(ExecutionOutcome::Artifact {
-                    intent: Intent::VideoGeneration,
-                    path,
-                    description: artifact.summary.clone(),
-                })
+                let mut coalescer = ProgressCoalescer::new(self.progress_coalesce_window);
+                generator
+                    .generate(input, |progress| {
+                        let Some(coalesced) =
+                            coalescer.admit(progress.status.as_deref(), progress.finished)
+                        else {
+                            return;
+                        };
+                        let mut payload = json!({
+                            "intent": Intent::VideoGeneration.to_string(),
+                            "job_id": progress.job_id,
+                            "attempt": progress.attempt,
+                            "status": progress.status,
+                            "elapsed_seconds": progress.elapsed.as_secs(),
+                            "finished": progress.finished,
+                        });
+                        if coalesced > 0 {
+                            payload["coalesced"] = json!(coalesced);
+                        }
+                        if let Some(retry) = &progress.retry {
+                            payload["retry"] = json!({
+                                "stage": retry.stage,
+                                "max_attempts": retry.max_attempts,
+                                "wait_ms": retry.wait.as_millis() as u64,
+                                "reason": retry.reason,
+                            });
+                        }
+                        self.broadcast("agent.progress", payload);
+                    })
+                    .await
             }
-            Intent::Help => Ok(ExecutionOutcome::Help {
-                message: self.help_message(),
+        };
+        if result.is_ok() {
+            self.latency.record(capability, started.elapsed());
+        }
+        result
+    }
+
+    /// 开启 `live.bilibili.auto_tts` 时朗读弹幕触发的对话回复：合成语音、保存为产物并广播 `agent.audio`；
+    /// 超过字数上限的回复不朗读，合成失败只影响语音，不影响已广播的文字回复
+    #[cfg(feature = "live")]
+    async fn speak_live_reply(
+        &self,
+        outcome: &ExecutionOutcome,
+        metadata: Value,
+        source: &RequestSource,
+    ) {
+        let (Some(max_chars), Some(speech)) = (self.auto_tts_max_chars, self.speech.as_ref())
+        else {
+            return;
+        };
+        let ExecutionOutcome::Conversation {
+            response,
+            expression,
+            ..
+        } = outcome
+        else {
+            return;
+        };
+        let chars = response.chars().count();
+        if chars > max_chars {
+            info!(
+                target: "bilibili::live",
+                chars,
+                max_chars,
+                "回复超过朗读字数上限，跳过语音合成"
+            );
+            return;
+        }
+
+        let started = Instant::now();
+        let stored = match speech.synthesize(response, source.voice.as_deref()).await {
+            Ok(artifact) => {
+                self.latency.record("speech", started.elapsed());
+                let cues = artifact.metadata.get("subtitle_cues").cloned();
+                self.store_artifact(Intent::Conversation, artifact, source)
+                    .await
+                    .map(|outcome| (outcome, cues))
+            }
+            Err(err) => Err(err),
+        };
+        match stored {
+            Ok((outcome, cues)) => {
+                let (_, mut payload) = outcome.as_event_payload();
+                if let Some(expression) = expression {
+                    expression.insert_into(&mut payload);
+                }
+                if let Value::Object(map) = &mut payload {
+                    map.insert("text".to_string(), json!(response));
+                    // 字幕随音频落盘为同名 `.srt`，内联返回时没有字幕文件
+                    if let (
+                        Some(cues),
+                        ExecutionOutcome::Artifact {
+                            path: Some(path), ..
+                        },
+                    ) = (cues, &outcome)
+                    {
+                        let srt = path.with_extension("srt");
+                        map.insert(
+                            "subtitles".to_string(),
+                            json!({
+                                "file": srt.file_name().and_then(|name| name.to_str()),
+                                "url": self.download_url(&srt),
+                                "cues": cues,
+                            }),
+                        );
+                    }
+                }
+                attach_context(&mut payload, "live", Some(metadata));
+                self.broadcast("agent.audio", payload);
+            }
+            Err(err) => {
+                warn!(target: "bilibili::live", error = ?err, "弹幕回复语音合成失败");
+                self.broadcast_error("live", &format!("语音合成失败: {err}"));
+            }
+        }
+    }
+
+    /// 产物文件的短期下载链接
+    fn download_url(&self, path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_str()?;
+        Some(self.artifact_tokens.download_url(name))
+    }
+
+    /// 保存产物；落盘失败时若体积不超过上限则以内联 Base64 返回，避免生成结果丢失
+    async fn store_artifact(
+        &self,
+        intent: Intent,
+        mut artifact: BinaryArtifact,
+        source: &RequestSource,
+    ) -> Result<ExecutionOutcome> {
+        // 生成器自带的 source 字段优先，不覆盖
+        artifact
+            .metadata
+            .entry(SOURCE_METADATA_KEY)
+            .or_insert_with(|| source.to_value());
+        let artifact = &artifact;
+
+        let err = match self.writer.persist(intent, artifact).await {
+            Ok(path) => {
+                let url = self.download_url(&path);
+                return Ok(ExecutionOutcome::artifact(intent, path, artifact, url));
+            }
+            Err(err) => err,
+        };
+
+        let size = artifact.data.len();
+        let inline = size <= self.inline_artifact_max_bytes;
+        warn!(
+            target: "agent_controller",
+            error = ?err,
+            %intent,
+            size,
+            inline,
+            "产物保存失败"
+        );
+        self.broadcast(
+            "agent.artifact.persist_failed",
+            json!({
+                "intent": intent.to_string(),
+                "error": err.to_string(),
+                "size_bytes": size,
+                "inline": inline,
             }),
+        );
+
+        if !inline {
+            return Err(err);
         }
+
+        Ok(ExecutionOutcome::inline_artifact(
+            intent,
+            artifact,
+            format!("产物未能保存到磁盘 ({err})，已内联返回"),
+        ))
     }
 
-    pub async fn shutdown(&mut self) -> Result<()> {
+    /// 按顺序收尾：在截止时间前处理已合并的弹幕，丢弃尚未处理的直播事件，最后结束直播会话。
+    /// 产物在命令执行过程中同步落盘，调用方处理完排队命令后这里不再有待写入的产物
+    #[cfg(feature = "live")]
+    pub async fn shutdown(&mut self, deadline: Instant) -> Result<ShutdownSummary> {
+        let mut summary = ShutdownSummary::default();
+
+        let pending = self
+            .danmaku_batch
+            .as_ref()
+            .map_or(0, |batch| batch.pending.len());
+        if pending > 0 {
+            if tokio::time::timeout_at(deadline, self.flush_danmaku_batch())
+                .await
+                .is_ok()
+            {
+                summary.danmaku_drained = pending;
+            } else {
+                warn!(target: "agent", pending, "退出超时，放弃合并中的弹幕");
+                summary.danmaku_abandoned = pending;
+                summary.timed_out = true;
+            }
+        }
+
+        if let Some(receiver) = self.live_event_rx.as_mut() {
+            receiver.close();
+            while receiver.try_recv().is_ok() {
+                summary.live_events_abandoned += 1;
+            }
+        }
+
         if let Some(manager) = self.live.as_mut() {
-            let _ = manager.stop().await?;
+            summary.live_session = manager.stop().await?.map(|info| info.to_payload());
         }
-        Ok(())
+
+        Ok(summary)
     }
 
     pub fn help_message(&self) -> String {
@@ -431,17 +3626,24 @@ impl AgentController {
             "我可以帮你处理以下任务:".to_string(),
         ];
 
-        for (intent, enabled) in self.capabilities_overview() {
-            let status = if enabled {
-                "✅ 已启用"
-            } else {
-                "⚠️ 待配置"
+        for CapabilityInfo {
+            intent,
+            enabled,
+            health,
+            ..
+        } in self.capabilities_overview()
+        {
+            let status = match (enabled, health) {
+                (false, _) => "⚠️ 待配置",
+                (true, HealthStatus::Degraded(_)) => "⚠️ 自检未通过",
+                (true, _) => "✅ 已启用",
             };
             let description = match intent {
                 Intent::Conversation => "自由对话与问答",
                 Intent::ImageGeneration => "创建插画或设计草图 (OPENAI_API_KEY)",
                 Intent::MusicGeneration => "根据提示生成音乐 (HYPERBOLIC_API_KEY)",
                 Intent::VideoGeneration => "调用自定义视频服务生成短片 (VIDEO_API_ENDPOINT)",
+                Intent::LiveControl => "控制直播监听",
                 Intent::Help | Intent::Unknown => "帮助信息",
             };
             lines.push(format!("- {}: {}", description, status));
@@ -454,6 +3656,8 @@ impl AgentController {
         lines.push("- ‘画一张赛博朋克风格的城市夜景’".to_string());
         lines.push("- ‘写一段轻快的 lofi 风格背景音乐’".to_string());
         lines.push("- ‘制作一个 10 秒的启动动画蓝图’".to_string());
+        lines.push("- ‘/combo image+music: 夏夜的城市天台’（同时生成多种产物）".to_string());
+        lines.push("- ‘开始直播监听’ / ‘停止监听’ / ‘直播监听状态’".to_string());
 
         lines.push(String::new());
         lines.push("通过 WebSocket 发送 JSON 消息即可与我交互。例如：".to_string());
@@ -461,12 +3665,13 @@ impl AgentController {
         lines.push(r#"- {"action":"command","input":"画一只赛博朋克猫娘"}"#.to_string());
         lines.push(r#"- {"action":"command","input":"把下面一段文字读出来……"}"#.to_string());
 
-        if self.live.is_some() {
+        if self.live_configured() {
             lines.push(String::new());
             lines.push("直播相关命令：".to_string());
             lines.push(r#"- {"action":"live_start"} — 使用配置的身份码启动监听"#.to_string());
             lines.push(r#"- {"action":"live_stop"} — 停止监听"#.to_string());
             lines.push(r#"- {"action":"live_status"} — 查询当前状态"#.to_string());
+            lines.push(r#"- {"action":"live_stats"} — 只查询本场统计计数"#.to_string());
         }
 
         lines.join("\n")
@@ -478,6 +3683,7 @@ impl AgentController {
         self.broadcast(event, payload);
     }
 
+    #[cfg(feature = "live")]
     fn broadcast_error(&self, origin: &str, message: &str) {
         self.broadcast(
             "agent.error",
@@ -495,6 +3701,291 @@ impl AgentController {
     }
 }
 
+const COMBO_PREFIX: &str = "/combo";
+
+/// 直播监听控制的具体动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveAction {
+    Start,
+    Stop,
+    Status,
+}
+
+impl LiveAction {
+    /// 依次匹配停止、查询状态与开始，越保守的动作越优先；都未提及时只查询状态
+    pub fn parse(input: &str) -> Self {
+        let normalized = input.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| normalized.contains(word));
+
+        if mentions(&["停止", "结束", "关闭", "stop"]) {
+            LiveAction::Stop
+        } else if mentions(&["状态", "status"]) {
+            LiveAction::Status
+        } else if mentions(&["开始", "启动", "开启", "start"]) {
+            LiveAction::Start
+        } else {
+            LiveAction::Status
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiveAction::Start => "start",
+            LiveAction::Stop => "stop",
+            LiveAction::Status => "status",
+        }
+    }
+}
+
+/// 合并同一任务连续相同状态的进度事件：窗口内重复的状态不广播，任务结束时的进度总是广播
+struct ProgressCoalescer {
+    window: Option<Duration>,
+    /// 上次广播的状态及时间
+    last: Option<(Option<String>, Instant)>,
+    suppressed: u32,
+}
+
+impl ProgressCoalescer {
+    fn new(window: Option<Duration>) -> Self {
+        Self {
+            window,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// 返回 `None` 表示丢弃该进度，否则为自上次广播以来被合并的次数
+    fn admit(&mut self, status: Option<&str>, finished: bool) -> Option<u32> {
+        let now = Instant::now();
+        if let Some(window) = self.window
+            && !finished
+            && let Some((last_status, sent_at)) = &self.last
+            && last_status.as_deref() == status
+            && now.duration_since(*sent_at) < window
+        {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last = Some((status.map(str::to_string), now));
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+/// 从改写提示词的回复中取出提示词：忽略代码块标记与列表符号，带 `Prompt:` / `提示词：` 标签的行优先，
+/// 否则取最长的一行（模型附带的说明通常较短），并去掉包裹的引号
+fn extract_image_prompt(reply: &str) -> Option<String> {
+    let lines: Vec<&str> = reply
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*', '>', '#']).trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("```"))
+        .collect();
+    let labeled = lines.iter().enumerate().find_map(|(index, line)| {
+        let (label, rest) = line.split_once([':', '：'])?;
+        let label = label.to_lowercase();
+        if label.chars().count() > 20 || !(label.contains("prompt") || label.contains("提示词"))
+        {
+            return None;
+        }
+        match rest.trim() {
+            "" => lines.get(index + 1).copied(),
+            rest => Some(rest),
+        }
+    });
+    let prompt = labeled.or_else(|| {
+        lines
+            .iter()
+            .copied()
+            .max_by_key(|line| line.chars().count())
+    })?;
+    let prompt = prompt.trim_matches(|c: char| c.is_whitespace() || "\"'“”*`".contains(c));
+    (!prompt.is_empty()).then(|| prompt.to_string())
+}
+
+/// 图生图的参考来源
+#[derive(Debug, PartialEq)]
+enum ImageReference {
+    /// 同一会话中最近生成的图片
+    Previous,
+    /// 产物目录中的文件名
+    Artifact(String),
+    /// 图片 URL，开启 `processing.allow_local_files` 时也可以是 `file://` 或本地绝对路径
+    Url(String),
+}
+
+/// 读取产物目录中的参考图，元数据中记录文件名
+async fn read_image_source(path: &Path) -> Result<ImageSource> {
+    let bytes = tokio::fs::read(path).await.map_err(|err| {
+        AgentError::rejected(
+            "image_source_missing",
+            format!("读取参考图 {} 失败: {err}", path.display()),
+        )
+    })?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(ImageSource {
+        bytes,
+        reference: ("source_artifact", name),
+    })
+}
+
+/// 记住“上一张图”的会话数上限
+const LAST_IMAGES_MAX: usize = 1024;
+/// “上一张图”的有效期，超过后需要重新生成或指明产物文件名
+const LAST_IMAGES_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// 指代上一张图的说法，按顺序匹配，较长的说法在前
+const PREVIOUS_IMAGE_PHRASES: &[&str] = &[
+    "上一张图片",
+    "刚才那张图",
+    "刚刚那张图",
+    "刚才的图",
+    "上一张图",
+    "刚才那张",
+    "刚刚那张",
+    "上一张",
+];
+
+/// 指代前后的连接词，拆出修改要求时去掉
+const REFERENCE_LEADING_WORDS: &[&str] = &[
+    "基于", "根据", "参考", "照着", "把", "将", "对", "在", "画", "生成", "帮我", "请",
+];
+const REFERENCE_TRAILING_WORDS: &[&str] = &["的基础上", "为基础", "为参考", "的"];
+
+/// 只要求“类似的图”时生成变体而不是按提示词修改
+const VARIATION_PHRASES: &[&str] = &[
+    "变体",
+    "来个变体",
+    "生成变体",
+    "类似的",
+    "类似的图",
+    "再来一张类似的",
+];
+
+/// 识别输入中的参考图（图片 URL、产物文件名或“上一张图”），返回参考来源与去掉指代后的修改要求；
+/// 修改要求为空表示生成变体
+fn parse_image_reference(input: &str) -> Option<(ImageReference, String)> {
+    let is_separator = |c: char| c.is_whitespace() || ",，。、:：".contains(c);
+    // URL 中含有冒号，按空白与中文标点切分
+    let token = input
+        .split(|c: char| c.is_whitespace() || "，。、".contains(c))
+        .find(|token| {
+            let lower = token.to_ascii_lowercase();
+            let is_image_file = [".png", ".jpg", ".jpeg", ".webp"]
+                .iter()
+                .any(|ext| lower.ends_with(ext));
+            lower.starts_with("http://")
+                || lower.starts_with("https://")
+                || lower.starts_with("file://")
+                // 本地路径只在开启 processing.allow_local_files 时可读
+                || (is_image_file && (token.starts_with('/') || !token.contains(['/', '\\'])))
+        });
+    let (reference, prefix, rest) = match token {
+        Some(token) => {
+            let start = token.as_ptr() as usize - input.as_ptr() as usize;
+            let reference = if token.contains("://") || token.starts_with('/') {
+                ImageReference::Url(token.to_string())
+            } else {
+                ImageReference::Artifact(token.to_string())
+            };
+            (reference, &input[..start], &input[start + token.len()..])
+        }
+        None => {
+            let (start, phrase) = PREVIOUS_IMAGE_PHRASES
+                .iter()
+                .find_map(|phrase| input.find(phrase).map(|start| (start, *phrase)))?;
+            (
+                ImageReference::Previous,
+                &input[..start],
+                &input[start + phrase.len()..],
+            )
+        }
+    };
+
+    let mut prefix = prefix.trim_end_matches(is_separator);
+    while let Some(stripped) = REFERENCE_LEADING_WORDS
+        .iter()
+        .find_map(|word| prefix.strip_suffix(word))
+    {
+        prefix = stripped.trim_end_matches(is_separator);
+    }
+    let mut rest = rest.trim_start_matches(is_separator);
+    while let Some(stripped) = REFERENCE_TRAILING_WORDS
+        .iter()
+        .find_map(|word| rest.strip_prefix(word))
+    {
+        rest = stripped.trim_start_matches(is_separator);
+    }
+    let prompt = format!("{prefix}{rest}");
+    let prompt = prompt.trim_matches(is_separator);
+    let prompt = if VARIATION_PHRASES.contains(&prompt) {
+        ""
+    } else {
+        prompt
+    };
+    Some((reference, prompt.to_string()))
+}
+
+/// 从分镜回复中逐行取出镜头描述，去掉 `1.` / `2、` / `- ` 等序号，最多保留 `max_scenes` 个
+fn parse_storyboard(reply: &str, max_scenes: usize) -> Vec<String> {
+    reply
+        .lines()
+        .map(|line| {
+            let line = line.trim().trim_start_matches(['-', '*', '#']).trim_start();
+            // 只去掉后面紧跟分隔符的数字，避免误删“3D 动画”这类描述开头的数字
+            let unnumbered = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            match unnumbered.strip_prefix(['.', '、', ')', '）', ':', '：']) {
+                Some(rest) if unnumbered.len() < line.len() => rest.trim(),
+                _ => line,
+            }
+        })
+        .filter(|scene| !scene.is_empty())
+        .take(max_scenes)
+        .map(str::to_string)
+        .collect()
+}
+
+/// 解析显式组合语法 `/combo image+music: 提示词`，非组合输入返回 `None`
+fn parse_combo(input: &str) -> Result<Option<(Vec<Intent>, &str)>> {
+    let Some(rest) = input.trim().strip_prefix(COMBO_PREFIX) else {
+        return Ok(None);
+    };
+    let (spec, prompt) = rest
+        .split_once([':', '：'])
+        .ok_or_else(|| AgentError::other("组合请求格式应为 /combo image+music: 提示词"))?;
+
+    let mut intents = Vec::new();
+    for name in spec
+        .split('+')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let intent = match name.to_lowercase().as_str() {
+            "image" | "图片" | "图像" => Intent::ImageGeneration,
+            "music" | "音乐" => Intent::MusicGeneration,
+            "video" | "视频" => Intent::VideoGeneration,
+            other => {
+                return Err(AgentError::other(format!(
+                    "组合请求只支持 image / music / video，未知的能力: {other}"
+                )));
+            }
+        };
+        if !intents.contains(&intent) {
+            intents.push(intent);
+        }
+    }
+
+    let prompt = prompt.trim();
+    if intents.is_empty() || prompt.is_empty() {
+        return Err(AgentError::other(
+            "组合请求需要至少一个能力和非空的提示词，例如 /combo image+music: 夏日海边",
+        ));
+    }
+
+    Ok(Some((intents, prompt)))
+}
+
 fn attach_context(payload: &mut Value, origin: &str, metadata: Option<Value>) {
     if let Value::Object(map) = payload {
         map.insert("origin".to_string(), json!(origin));
@@ -503,3 +3994,74 @@ fn attach_context(payload: &mut Value, origin: &str, metadata: Option<Value>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 静态关键字路由加 mock 绘画，产物写入临时目录
+    async fn controller(artifacts_dir: &Path) -> AgentController {
+        let config = AppConfig::from_toml(&format!(
+            r#"
+artifacts_dir = "{}"
+[sse]
+access_key = "a"
+secret_key = "b"
+[providers.intent]
+provider = "static"
+[providers.conversation]
+provider = "mock"
+[providers.image]
+provider = "mock"
+[clarification]
+min_prompt_chars = 2
+[[intent.rules]]
+keywords = ["画"]
+intent = "image_generation"
+"#,
+            artifacts_dir.display()
+        ))
+        .unwrap();
+        AgentController::new(config, None).await.unwrap()
+    }
+
+    /// 产物 `.meta.json` 中记录的提示词
+    fn prompt_of(outcome: ExecutionOutcome) -> String {
+        let ExecutionOutcome::Artifact {
+            path: Some(path), ..
+        } = outcome
+        else {
+            panic!("应当直接生成图片");
+        };
+        let meta = std::fs::read_to_string(path.with_extension("meta.json")).unwrap();
+        serde_json::from_str::<Value>(&meta).unwrap()["prompt"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn batch_items_are_not_merged_by_clarification() {
+        let artifacts_dir = std::env::temp_dir().join(format!("vutber-test-{}", Uuid::new_v4()));
+        let mut controller = controller(&artifacts_dir).await;
+        let batch = RequestSource::new("batch");
+        // 批量与定时命令不追问，内容过少也直接执行，下一条不会被当作回答
+        for input in ["画", "画一只猫"] {
+            let outcome = controller.handle(input, &batch).await.unwrap();
+            assert_eq!(prompt_of(outcome), input);
+        }
+        let outcome = controller
+            .handle("画", &RequestSource::new("schedule"))
+            .await
+            .unwrap();
+        assert_eq!(prompt_of(outcome), "画");
+
+        // 交互来源仍会追问，下一条输入作为回答合并
+        let command = RequestSource::new("command");
+        let outcome = controller.handle("画", &command).await.unwrap();
+        assert!(matches!(outcome, ExecutionOutcome::Clarify { .. }));
+        let outcome = controller.handle("一只猫", &command).await.unwrap();
+        assert_eq!(prompt_of(outcome), "画，一只猫");
+        std::fs::remove_dir_all(artifacts_dir).unwrap();
+    }
+}
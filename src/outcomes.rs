@@ -0,0 +1,60 @@
+use serde_json::{Map, Value, json};
+
+use crate::{
+    errors::Result,
+    intent::Intent,
+    util::{beijing_rfc3339, now_in_beijing},
+};
+
+/// 按意图统计请求的执行结果：成功与失败次数，以及最近一次失败的原因，便于判断哪类生成经常出错
+///
+/// 以 `Intent` 的判别值作为下标；组合请求中的每个能力分别计数
+#[derive(Debug, Default)]
+pub struct OutcomeStats {
+    entries: [OutcomeEntry; Intent::ALL.len()],
+}
+
+#[derive(Debug, Default)]
+struct OutcomeEntry {
+    succeeded: u64,
+    failed: u64,
+    /// 最近一次失败的错误信息与时间（北京时间）
+    last_error: Option<(String, String)>,
+}
+
+impl OutcomeStats {
+    pub fn record<T>(&mut self, intent: Intent, result: &Result<T>) {
+        let entry = &mut self.entries[intent as usize];
+        match result {
+            Ok(_) => entry.succeeded += 1,
+            Err(err) => {
+                entry.failed += 1;
+                entry.last_error = Some((err.to_string(), beijing_rfc3339(&now_in_beijing())));
+            }
+        }
+    }
+
+    /// `{ intent: { succeeded, failed, success_rate, last_error: { message, at } } }`，未执行过的意图不输出
+    pub fn snapshot(&self) -> Value {
+        let mut intents = Map::new();
+        for (intent, entry) in Intent::ALL.iter().zip(&self.entries) {
+            let total = entry.succeeded + entry.failed;
+            if total == 0 {
+                continue;
+            }
+            intents.insert(
+                intent.as_prefix().to_string(),
+                json!({
+                    "succeeded": entry.succeeded,
+                    "failed": entry.failed,
+                    "success_rate": entry.succeeded as f64 / total as f64,
+                    "last_error": entry
+                        .last_error
+                        .as_ref()
+                        .map(|(message, at)| json!({ "message": message, "at": at })),
+                }),
+            );
+        }
+        Value::Object(intents)
+    }
+}
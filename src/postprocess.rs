@@ -0,0 +1,212 @@
+use regex::Regex;
+use tracing::{debug, warn};
+
+use crate::config::PostprocessStep;
+
+/// 句末标点，截断时优先在这里断开
+const SENTENCE_ENDS: &[char] = &['。', '！', '？', '!', '?', '…', '\n'];
+
+/// 对话回复的后处理：去掉模型常见的多余前缀、Markdown 与过多的 emoji，在广播与朗读之前执行
+///
+/// 步骤来自 `[[providers.conversation.postprocess]]`，按配置顺序执行；没有步骤时原样返回
+#[derive(Debug)]
+pub struct ResponsePostprocessor {
+    steps: Vec<PostprocessStep>,
+    /// `[文字](链接)` 与 `![说明](图片)`
+    link: Regex,
+}
+
+impl ResponsePostprocessor {
+    pub fn new(steps: Vec<PostprocessStep>) -> Self {
+        Self {
+            steps,
+            link: Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").expect("链接正则有效"),
+        }
+    }
+
+    /// 依次执行各步骤；处理后为空（例如回复只有 emoji）时返回原文，避免广播空回复
+    ///
+    /// ```
+    /// use vutber_agent::{config::PostprocessStep, postprocess::ResponsePostprocessor};
+    ///
+    /// let postprocessor = ResponsePostprocessor::new(vec![
+    ///     PostprocessStep::TrimPrefixes { prefixes: vec!["回应：".into(), "主播：".into()] },
+    ///     PostprocessStep::StripMarkdown,
+    ///     PostprocessStep::CollapseWhitespace,
+    ///     PostprocessStep::LimitEmoji { max: 1 },
+    /// ]);
+    /// let reply = "回应： 主播：## 今日推荐\n\n\n- **草莓** 蛋糕 🍓🍰\n- 看看[菜单](https://example.com)👍🏻";
+    /// assert_eq!(postprocessor.apply(reply.into()), "今日推荐\n\n草莓 蛋糕 🍓\n看看菜单");
+    ///
+    /// // 截断时优先在句末断开
+    /// let truncate = ResponsePostprocessor::new(vec![PostprocessStep::Truncate { max_chars: 12 }]);
+    /// assert_eq!(truncate.apply("谢谢大家的礼物！今天就播到这里，明天见".into()), "谢谢大家的礼物！");
+    /// assert_eq!(truncate.apply("一二三四五六七八九十一二三四".into()), "一二三四五六七八九十一二…");
+    /// assert_eq!(truncate.apply("不用截断".into()), "不用截断");
+    ///
+    /// // 只剩空白时保留原文
+    /// let strip = ResponsePostprocessor::new(vec![PostprocessStep::LimitEmoji { max: 0 }]);
+    /// assert_eq!(strip.apply("👋👋".into()), "👋👋");
+    /// ```
+    pub fn apply(&self, text: String) -> String {
+        if self.steps.is_empty() {
+            return text;
+        }
+
+        let mut processed = text.clone();
+        for step in &self.steps {
+            processed = match step {
+                PostprocessStep::TrimPrefixes { prefixes } => trim_prefixes(&processed, prefixes),
+                PostprocessStep::CollapseWhitespace => collapse_whitespace(&processed),
+                PostprocessStep::StripMarkdown => self.strip_markdown(&processed),
+                PostprocessStep::LimitEmoji { max } => limit_emoji(&processed, *max),
+                PostprocessStep::Truncate { max_chars } => truncate(&processed, *max_chars),
+            };
+        }
+
+        let processed = processed.trim();
+        if processed.is_empty() {
+            warn!(target: "agent_controller", "对话回复后处理后为空，保留原回复");
+            return text;
+        }
+        if processed != text {
+            debug!(
+                target: "agent_controller",
+                before = %text,
+                after = %processed,
+                "对话回复已后处理"
+            );
+        }
+        processed.to_string()
+    }
+
+    fn strip_markdown(&self, text: &str) -> String {
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            // 代码围栏与分隔线整行去掉，围栏内的内容保留
+            if trimmed.starts_with("```") || is_rule(trimmed) {
+                continue;
+            }
+            let mut line = trimmed;
+            while let Some(rest) = line.strip_prefix('>') {
+                line = rest.trim_start();
+            }
+            let heading = line.trim_start_matches('#');
+            if heading.len() < line.len() && (heading.is_empty() || heading.starts_with(' ')) {
+                line = heading.trim_start();
+            }
+            for marker in ["- ", "* ", "+ "] {
+                if let Some(rest) = line.strip_prefix(marker) {
+                    line = rest.trim_start();
+                    break;
+                }
+            }
+            let line = self.link.replace_all(line, "$1");
+            let line = line
+                .replace("**", "")
+                .replace("__", "")
+                .replace("~~", "")
+                .replace(['`', '*'], "");
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+fn trim_prefixes(text: &str, prefixes: &[String]) -> String {
+    let mut text = text.trim_start();
+    while let Some(rest) = prefixes
+        .iter()
+        .filter(|prefix| !prefix.is_empty())
+        .find_map(|prefix| text.strip_prefix(prefix.as_str()))
+    {
+        text = rest.trim_start();
+    }
+    text.to_string()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim_end().to_string()
+}
+
+/// `---`、`***`、`___` 这类分隔线
+fn is_rule(line: &str) -> bool {
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| line.chars().all(|c| c == *mark || c == ' '))
+}
+
+fn limit_emoji(text: &str, max: usize) -> String {
+    let mut kept = 0;
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            result.push(c);
+            continue;
+        }
+        // 肤色、变体选择符、零宽连接与国旗的第二个区域指示符都属于同一个 emoji
+        let mut emoji = String::from(c);
+        while let Some(&next) = chars.peek() {
+            let joined = matches!(next, '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}')
+                || (is_regional_indicator(c) && is_regional_indicator(next) && emoji.len() == 4);
+            if joined {
+                emoji.push(next);
+                chars.next();
+            } else if next == '\u{200D}' {
+                emoji.push(next);
+                chars.next();
+                if let Some(joined) = chars.next_if(|c| is_emoji(*c)) {
+                    emoji.push(joined);
+                }
+            } else {
+                break;
+            }
+        }
+        if kept < max {
+            result.push_str(&emoji);
+            kept += 1;
+        }
+    }
+    result
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}' | '\u{2600}'..='\u{27BF}' | '\u{2B00}'..='\u{2BFF}'
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let Some((limit, _)) = text.char_indices().nth(max_chars) else {
+        return text.to_string();
+    };
+    let kept = &text[..limit];
+    // 只在后半段找句末，避免截得过短
+    let half = kept
+        .char_indices()
+        .nth(max_chars / 2)
+        .map_or(0, |(index, _)| index);
+    match kept.rfind(SENTENCE_ENDS).filter(|end| *end >= half) {
+        Some(end) => {
+            let end = end + kept[end..].chars().next().map_or(0, char::len_utf8);
+            kept[..end].trim_end().to_string()
+        }
+        None => format!("{}…", kept.trim_end()),
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    config::ZhipuConfig,
+    errors::{AgentError, Result},
+    util::{fetch_bytes, send_with_retry},
+};
+
+/// 智谱 CogView 文生图接口（`/images/generations`），同时兼容 URL 与 Base64 两种返回形式
+#[derive(Clone)]
+pub struct CogViewClient {
+    http: HttpClient,
+    api_key: String,
+    endpoint: String,
+    /// 返回的图像 URL 可以是 `file://` 或本地路径，见 `processing.allow_local_files`
+    allow_local_files: bool,
+}
+
+/// 接口返回的图像内容及其传输形式（`url` 或 `b64_json`）
+pub struct CogViewImage {
+    pub bytes: Vec<u8>,
+    pub response_format: &'static str,
+}
+
+#[derive(Deserialize)]
+struct CogViewResponse {
+    #[serde(default)]
+    data: Vec<CogViewData>,
+}
+
+#[derive(Deserialize)]
+struct CogViewData {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    b64_json: Option<String>,
+}
+
+/// 智谱的错误返回：`{"error": {"code": "1301", "message": "..."}}`
+#[derive(Deserialize)]
+struct CogViewErrorBody {
+    error: CogViewError,
+}
+
+#[derive(Deserialize)]
+struct CogViewError {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl CogViewClient {
+    pub fn from_config(config: &ZhipuConfig) -> Result<Self> {
+        Ok(Self {
+            http: HttpClient::builder()
+                .timeout(Duration::from_secs(180))
+                .build()?,
+            api_key: config.api_key.clone(),
+            endpoint: config.image_api_url.clone(),
+            allow_local_files: false,
+        })
+    }
+
+    /// 允许返回的图像 URL 指向本地文件，仅用于测试与离线演示
+    pub fn set_allow_local_files(&mut self, allow: bool) {
+        self.allow_local_files = allow;
+    }
+
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// `params` 为随请求发送的可选参数（如 CogView-4 的 `quality`）
+    pub async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        (width, height): (u32, u32),
+        params: &Map<String, Value>,
+    ) -> Result<CogViewImage> {
+        let mut body = json!({
+            "model": model,
+            "prompt": prompt,
+            "size": format!("{width}x{height}"),
+        });
+        if let Value::Object(map) = &mut body {
+            map.extend(params.clone());
+        }
+
+        let request = self
+            .http
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(&body);
+        let response = send_with_retry("zhipu", request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reason = match serde_json::from_str::<CogViewErrorBody>(&body) {
+                Ok(CogViewErrorBody {
+                    error:
+                        CogViewError {
+                            code,
+                            message: Some(message),
+                        },
+                }) => match code {
+                    Some(code) => format!("{message}（错误码 {code}）"),
+                    None => message,
+                },
+                _ => body,
+            };
+            return Err(AgentError::other(format!(
+                "{model} 图像生成失败 ({status}): {reason}"
+            )));
+        }
+
+        let response: CogViewResponse = response.json().await?;
+        let data = response
+            .data
+            .into_iter()
+            .find(|data| data.url.is_some() || data.b64_json.is_some())
+            .ok_or_else(|| AgentError::other(format!("{model} 未返回图像数据")))?;
+
+        if let Some(b64_json) = data.b64_json {
+            let bytes = BASE64_STANDARD.decode(b64_json.trim()).map_err(|e| {
+                AgentError::other(format!("解码 {model} 返回的 Base64 图像失败: {e}"))
+            })?;
+            return Ok(CogViewImage {
+                bytes,
+                response_format: "b64_json",
+            });
+        }
+
+        let url = data.url.unwrap_or_default();
+        let bytes = fetch_bytes("zhipu", &self.http, &url, self.allow_local_files)
+            .await
+            .map_err(|err| match err {
+                AgentError::Other(reason) => {
+                    AgentError::other(format!("下载 {model} 生成的图像失败: {reason}"))
+                }
+                other => other,
+            })?;
+        Ok(CogViewImage {
+            bytes,
+            response_format: "url",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json, Router,
+        http::{HeaderMap, StatusCode},
+        response::{IntoResponse, Response},
+        routing::{get, post},
+    };
+
+    use super::*;
+    use crate::{capabilities::ImageGenerator, util::test_support::spawn_stub};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\ncogview";
+
+    /// 模拟智谱的图像接口：按提示词返回 URL、Base64 或错误
+    async fn serve() -> ZhipuConfig {
+        async fn generations(headers: HeaderMap, Json(body): Json<Value>) -> Response {
+            if headers["authorization"] != "Bearer zhipu-key" {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            match body["prompt"].as_str().unwrap_or_default() {
+                "敏感" => (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": { "code": "1301", "message": "输入包含不安全内容" } })),
+                )
+                    .into_response(),
+                "base64" => Json(json!({ "data": [{ "b64_json": BASE64_STANDARD.encode(PNG) }] }))
+                    .into_response(),
+                _ => {
+                    // 下载地址按实际请求的尺寸区分，尺寸没有对齐时下载会失败
+                    let host = headers["host"].to_str().unwrap();
+                    let url = format!("http://{host}/files/{}.png", body["size"].as_str().unwrap());
+                    Json(json!({ "created": 1, "data": [{ "url": url }] })).into_response()
+                }
+            }
+        }
+
+        let app = Router::new()
+            .route("/api/paas/v4/images/generations", post(generations))
+            .route("/files/1024x1024.png", get(|| async { PNG }));
+        let base_url = format!("http://{}", spawn_stub(app).await);
+        ZhipuConfig {
+            api_key: "zhipu-key".to_string(),
+            chat_model: "glm-4-flash".to_string(),
+            agent_preamble: String::new(),
+            api_url: format!("{base_url}/api/paas/v4/chat/completions"),
+            image_model: "cogview-3-flash".to_string(),
+            image_api_url: format!("{base_url}/api/paas/v4/images/generations"),
+        }
+    }
+
+    #[tokio::test]
+    async fn url_result_is_downloaded() {
+        let config = serve().await;
+        let artifact = ImageGenerator::zhipu(&config, None, &[])
+            .unwrap()
+            .generate("画一只猫", Some((1000, 1000)))
+            .await
+            .unwrap();
+        assert_eq!(artifact.data, PNG);
+        assert_eq!(artifact.media_type, "image/png");
+        let metadata = &artifact.metadata;
+        assert_eq!(metadata["model"], "cogview-3-flash");
+        assert_eq!(metadata["prompt"], "画一只猫");
+        assert_eq!(metadata["response_format"], "url");
+        assert_eq!(metadata["width"], 1024);
+    }
+
+    #[tokio::test]
+    async fn base64_result_and_error_body() {
+        let config = serve().await;
+        let client = CogViewClient::from_config(&config).unwrap();
+        let image = client
+            .generate("cogview-4", "base64", (1024, 1024), &Map::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            (image.bytes.as_slice(), image.response_format),
+            (PNG, "b64_json")
+        );
+
+        let Err(err) = client
+            .generate("cogview-4", "敏感", (1024, 1024), &Map::new())
+            .await
+        else {
+            panic!("敏感提示词应当失败");
+        };
+        let err = err.to_string();
+        assert!(err.contains("400"), "{err}");
+        assert!(err.contains("输入包含不安全内容（错误码 1301）"), "{err}");
+    }
+}
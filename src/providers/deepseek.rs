@@ -0,0 +1,369 @@
+use std::time::Duration;
+
+use crate::{config::DeepSeekConfig, usage::PromptCacheUsage, util::send_with_retry};
+use futures_util::{StreamExt, stream};
+use reqwest::Client as HttpClient;
+use rig::completion::{
+    Message,
+    message::{AssistantContent, UserContent},
+    request::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, GetTokenUsage,
+        Usage,
+    },
+};
+use rig::one_or_many::OneOrMany;
+use rig::streaming::{RawStreamingChoice, StreamingCompletionResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// DeepSeek 对话模型的 Completion 实现
+///
+/// 请求与响应沿用 OpenAI 兼容的 chat completions 格式，区别在于：
+/// - `preamble` 作为 system 消息发送
+/// - 用量统计额外包含上下文缓存命中/未命中的 token 数，计费依据与 OpenAI 不同
+#[derive(Clone)]
+pub struct DeepSeekCompletionModel {
+    http_client: HttpClient,
+    api_key: String,
+    model: String,
+    endpoint: String,
+}
+
+impl DeepSeekCompletionModel {
+    pub fn from_config(
+        config: &DeepSeekConfig,
+        model_override: Option<&str>,
+    ) -> Result<Self, CompletionError> {
+        let http_client = HttpClient::builder()
+            .user_agent("VutberAgent/0.1")
+            .timeout(Duration::from_secs(60))
+            .build()
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        Ok(Self {
+            http_client,
+            api_key: config.api_key.clone(),
+            model: model_override
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| config.chat_model.clone()),
+            endpoint: config.api_url.clone(),
+        })
+    }
+
+    fn build_payload<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+        stream: bool,
+    ) -> Result<DeepSeekChatRequest<'a>, CompletionError> {
+        let mut messages = Vec::new();
+
+        if let Some(preamble) = request.preamble.as_deref()
+            && !preamble.is_empty()
+        {
+            messages.push(DeepSeekRequestMessage {
+                role: "system",
+                content: preamble.to_string(),
+            });
+        }
+
+        for message in request.chat_history.iter() {
+            let (role, text) = match message {
+                Message::User { content } => (
+                    "user",
+                    content
+                        .iter()
+                        .filter_map(|c| match c {
+                            UserContent::Text(t) => Some(t.text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                Message::Assistant { content, .. } => (
+                    "assistant",
+                    content
+                        .iter()
+                        .filter_map(|c| match c {
+                            AssistantContent::Text(t) => Some(t.text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+            };
+            if !text.is_empty() {
+                messages.push(DeepSeekRequestMessage {
+                    role,
+                    content: text,
+                });
+            }
+        }
+
+        if messages.iter().all(|message| message.role == "system") {
+            return Err(CompletionError::RequestError(
+                "No valid messages in request".into(),
+            ));
+        }
+
+        Ok(DeepSeekChatRequest {
+            model: &self.model,
+            messages,
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            stream,
+            stream_options: stream.then_some(DeepSeekStreamOptions {
+                include_usage: true,
+            }),
+            extra: request.additional_params.as_ref(),
+        })
+    }
+
+    async fn send(
+        &self,
+        payload: &DeepSeekChatRequest<'_>,
+    ) -> Result<reqwest::Response, CompletionError> {
+        let request = self
+            .http_client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(payload);
+        let response = send_with_retry("deepseek", request)
+            .await
+            .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(CompletionError::ProviderError(format!(
+                "DeepSeek API 请求失败 ({}): {}",
+                status, body
+            )));
+        }
+
+        Ok(response)
+    }
+}
+
+impl CompletionModel for DeepSeekCompletionModel {
+    type Response = DeepSeekChatResponse;
+    type StreamingResponse = DeepSeekChatResponse;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let payload = self.build_payload(&request, false)?;
+        let response: DeepSeekChatResponse = self
+            .send(&payload)
+            .await?
+            .json()
+            .await
+            .map_err(|e| CompletionError::ProviderError(format!("解析响应失败: {}", e)))?;
+
+        let text = response.extract_text().ok_or_else(|| {
+            CompletionError::ProviderError("DeepSeek API 返回结果为空".to_string())
+        })?;
+
+        Ok(CompletionResponse {
+            choice: OneOrMany::one(AssistantContent::Text(text.into())),
+            usage: response.token_usage().unwrap_or_default(),
+            raw_response: response,
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let payload = self.build_payload(&request, true)?;
+        let response = self.send(&payload).await?;
+
+        // SSE 行可能跨网络分片，先按行缓冲再解析
+        let stream = response
+            .bytes_stream()
+            .scan(String::new(), |buffer, chunk| {
+                let items = match chunk {
+                    Ok(bytes) => {
+                        buffer.push_str(&String::from_utf8_lossy(&bytes));
+                        drain_sse_lines(buffer)
+                    }
+                    Err(e) => vec![Err(CompletionError::ProviderError(e.to_string()))],
+                };
+                futures_util::future::ready(Some(stream::iter(items)))
+            })
+            .flatten();
+
+        Ok(StreamingCompletionResponse::stream(Box::pin(stream)))
+    }
+}
+
+type StreamItem = Result<RawStreamingChoice<DeepSeekChatResponse>, CompletionError>;
+
+fn drain_sse_lines(buffer: &mut String) -> Vec<StreamItem> {
+    let mut items = Vec::new();
+
+    while let Some(end) = buffer.find('\n') {
+        let line = buffer[..end].trim().to_string();
+        buffer.drain(..=end);
+
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        if data.is_empty() || data == "[DONE]" {
+            continue;
+        }
+
+        let chunk = match serde_json::from_str::<DeepSeekStreamChunk>(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                items.push(Err(CompletionError::ProviderError(format!(
+                    "解析 DeepSeek 流式响应失败: {}",
+                    e
+                ))));
+                continue;
+            }
+        };
+
+        for choice in &chunk.choices {
+            let Some(delta) = &choice.delta else {
+                continue;
+            };
+            if let Some(reasoning) = delta.reasoning_content.as_ref().filter(|s| !s.is_empty()) {
+                items.push(Ok(RawStreamingChoice::Reasoning {
+                    id: None,
+                    reasoning: reasoning.clone(),
+                    signature: None,
+                }));
+            }
+            if let Some(content) = delta.content.as_ref().filter(|s| !s.is_empty()) {
+                items.push(Ok(RawStreamingChoice::Message(content.clone())));
+            }
+        }
+
+        // 开启 include_usage 后，最后一个块只携带用量统计
+        if chunk.usage.is_some() {
+            items.push(Ok(RawStreamingChoice::FinalResponse(
+                DeepSeekChatResponse {
+                    choices: vec![],
+                    usage: chunk.usage,
+                },
+            )));
+        }
+    }
+
+    items
+}
+
+#[derive(Serialize)]
+struct DeepSeekChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<DeepSeekRequestMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u64>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<DeepSeekStreamOptions>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    extra: Option<&'a Value>,
+}
+
+#[derive(Serialize)]
+struct DeepSeekStreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct DeepSeekRequestMessage<'a> {
+    role: &'a str,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct DeepSeekStreamChunk {
+    #[serde(default)]
+    choices: Vec<DeepSeekStreamChoice>,
+    #[serde(default)]
+    usage: Option<DeepSeekUsage>,
+}
+
+#[derive(Deserialize)]
+struct DeepSeekStreamChoice {
+    #[serde(default)]
+    delta: Option<DeepSeekDelta>,
+}
+
+#[derive(Deserialize)]
+struct DeepSeekDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct DeepSeekChatResponse {
+    #[serde(default)]
+    choices: Vec<DeepSeekChoice>,
+    #[serde(default)]
+    usage: Option<DeepSeekUsage>,
+}
+
+impl DeepSeekChatResponse {
+    fn extract_text(&self) -> Option<String> {
+        self.choices
+            .iter()
+            .find_map(|choice| choice.message.as_ref()?.content.clone())
+            .filter(|text| !text.is_empty())
+    }
+}
+
+impl GetTokenUsage for DeepSeekChatResponse {
+    fn token_usage(&self) -> Option<Usage> {
+        self.usage.as_ref().map(|u| Usage {
+            input_tokens: u.prompt_tokens,
+            output_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+        })
+    }
+}
+
+impl PromptCacheUsage for DeepSeekCompletionModel {
+    fn prompt_cache_tokens(response: &DeepSeekChatResponse) -> Option<(u64, u64)> {
+        let usage = response.usage.as_ref()?;
+        if usage.prompt_cache_hit_tokens.is_none() && usage.prompt_cache_miss_tokens.is_none() {
+            return None;
+        }
+        Some((
+            usage.prompt_cache_hit_tokens.unwrap_or(0),
+            usage.prompt_cache_miss_tokens.unwrap_or(0),
+        ))
+    }
+}
+
+/// DeepSeek 的 token 用量，缓存命中的输入 token 按更低的单价计费
+#[derive(Deserialize, Serialize, Clone)]
+struct DeepSeekUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+    #[serde(default)]
+    prompt_cache_hit_tokens: Option<u64>,
+    #[serde(default)]
+    prompt_cache_miss_tokens: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct DeepSeekChoice {
+    #[serde(default)]
+    message: Option<DeepSeekMessage>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct DeepSeekMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
@@ -0,0 +1,124 @@
+use crate::usage::PromptCacheUsage;
+use futures_util::stream;
+use rig::completion::{
+    Message,
+    message::{AssistantContent, UserContent},
+    request::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, GetTokenUsage,
+        Usage,
+    },
+};
+use rig::one_or_many::OneOrMany;
+use rig::streaming::{RawStreamingChoice, StreamingCompletionResponse};
+use serde::{Deserialize, Serialize};
+
+/// 离线使用的 Completion 实现，不发起网络请求
+///
+/// 回复完全由最后一条用户消息决定，用于在没有 API Key 的环境下以确定的输出跑通命令流程。
+/// token 用量按字符数估算，只为让用量统计有数据可看。
+#[derive(Clone)]
+pub struct MockCompletionModel {
+    reply: fn(&str) -> String,
+}
+
+impl MockCompletionModel {
+    pub fn new(reply: fn(&str) -> String) -> Self {
+        Self { reply }
+    }
+
+    /// 原样回显用户输入
+    pub fn echo() -> Self {
+        Self::new(|input| format!("（mock）收到：{}", input.trim()))
+    }
+
+    fn respond(&self, request: &CompletionRequest) -> (String, Usage) {
+        let input = request
+            .chat_history
+            .iter()
+            .filter_map(|message| match message {
+                Message::User { content } => Some(
+                    content
+                        .iter()
+                        .filter_map(|c| match c {
+                            UserContent::Text(t) => Some(t.text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                _ => None,
+            })
+            .last()
+            .unwrap_or_default();
+        let text = (self.reply)(&input);
+
+        let input_tokens = input.chars().count() as u64;
+        let output_tokens = text.chars().count() as u64;
+        let usage = Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+        };
+        (text, usage)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MockResponse {
+    text: String,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl GetTokenUsage for MockResponse {
+    fn token_usage(&self) -> Option<Usage> {
+        Some(Usage {
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            total_tokens: self.input_tokens + self.output_tokens,
+        })
+    }
+}
+
+impl PromptCacheUsage for MockCompletionModel {}
+
+impl CompletionModel for MockCompletionModel {
+    type Response = MockResponse;
+    type StreamingResponse = MockResponse;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let (text, usage) = self.respond(&request);
+        Ok(CompletionResponse {
+            choice: OneOrMany::one(AssistantContent::Text(text.clone().into())),
+            usage,
+            raw_response: MockResponse {
+                text,
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            },
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let (text, usage) = self.respond(&request);
+        let response = MockResponse {
+            text: text.clone(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        };
+        let items = vec![
+            Ok(RawStreamingChoice::Message(text)),
+            Ok(RawStreamingChoice::FinalResponse(response)),
+        ];
+
+        Ok(StreamingCompletionResponse::stream(Box::pin(stream::iter(
+            items,
+        ))))
+    }
+}
@@ -1 +1,33 @@
+pub mod cogview;
+pub mod deepseek;
+pub mod mock;
+pub mod sd_webui;
 pub mod zhipu;
+
+use rig::{agent::AgentBuilder, completion::CompletionModel};
+use serde_json::Value;
+
+use crate::config::SamplingParams;
+
+/// 将采样参数应用到 rig Agent 上；`extra` 为需要一并发送的其它 `additional_params`
+/// （rig 的 Agent 只保留最后一次设置的 `additional_params`，因此在这里合并）
+pub fn apply_sampling<M: CompletionModel>(
+    mut builder: AgentBuilder<M>,
+    sampling: &SamplingParams,
+    extra: Option<Value>,
+) -> AgentBuilder<M> {
+    if let Some(temperature) = sampling.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(max_tokens) = sampling.max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    let mut params = extra.unwrap_or_else(|| Value::Object(Default::default()));
+    if let Some(top_p) = sampling.top_p {
+        params["top_p"] = top_p.into();
+    }
+    match params.as_object() {
+        Some(map) if map.is_empty() => builder,
+        _ => builder.additional_params(params),
+    }
+}
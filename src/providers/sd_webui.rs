@@ -0,0 +1,291 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use reqwest::Client as HttpClient;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    config::SdWebUiConfig,
+    errors::{AgentError, Result},
+    util::{send_with_retry, truncate_chars},
+};
+
+/// Stable Diffusion WebUI 的 `/sdapi/v1/txt2img`（AUTOMATIC1111 及兼容其 API 的 Forge、SD.Next 等）
+#[derive(Clone)]
+pub struct SdWebUiClient {
+    http: HttpClient,
+    config: SdWebUiConfig,
+}
+
+/// 解码后的第一张图像，以及实际使用的生成参数（含种子）
+pub struct SdWebUiImage {
+    pub bytes: Vec<u8>,
+    pub parameters: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Txt2ImgResponse {
+    #[serde(default)]
+    images: Vec<String>,
+    /// WebUI 以 JSON 字符串返回实际参数（`seed`、`sampler_name` 等）
+    #[serde(default)]
+    info: Option<String>,
+}
+
+impl SdWebUiClient {
+    /// `checkpoint` 为路由的 `model`，覆盖 `sd_webui.checkpoint`
+    pub fn from_config(config: &SdWebUiConfig, checkpoint: Option<&str>) -> Result<Self> {
+        let mut config = config.clone();
+        if let Some(checkpoint) = checkpoint {
+            config.checkpoint = Some(checkpoint.to_string());
+        }
+        Ok(Self {
+            http: HttpClient::builder().timeout(config.timeout).build()?,
+            config,
+        })
+    }
+
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    pub fn default_size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    pub fn checkpoint(&self) -> Option<&str> {
+        self.config.checkpoint.as_deref()
+    }
+
+    /// `negative_prompt` 为空时使用 `sd_webui.negative_prompt`
+    pub async fn txt2img(
+        &self,
+        prompt: &str,
+        negative_prompt: Option<&str>,
+        (width, height): (u32, u32),
+    ) -> Result<SdWebUiImage> {
+        let negative_prompt = negative_prompt
+            .or(self.config.negative_prompt.as_deref())
+            .unwrap_or_default();
+        let mut body = json!({
+            "prompt": prompt,
+            "negative_prompt": negative_prompt,
+            "sampler_name": self.config.sampler,
+            "steps": self.config.steps,
+            "cfg_scale": self.config.cfg_scale,
+            "width": width,
+            "height": height,
+            "seed": -1,
+            "batch_size": 1,
+            "n_iter": 1,
+        });
+        let checkpoint = self.config.checkpoint.as_deref();
+        if let Some(checkpoint) = checkpoint {
+            body["override_settings"] = json!({ "sd_model_checkpoint": checkpoint });
+            body["override_settings_restore_afterwards"] = json!(true);
+        }
+
+        let mut builder = self
+            .http
+            .post(format!("{}/sdapi/v1/txt2img", self.config.endpoint))
+            .json(&body);
+        if let Some(auth) = &self.config.auth {
+            let (user, password) = auth.split_once(':').unwrap_or((auth, ""));
+            builder = builder.basic_auth(user, Some(password));
+        }
+
+        let response = send_with_retry("sd_webui", builder).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AgentError::other(format!(
+                "SD WebUI 返回状态码 {status}: {}",
+                truncate_chars(body.trim(), 300)
+            )));
+        }
+
+        let response: Txt2ImgResponse = response.json().await?;
+        let encoded = response
+            .images
+            .first()
+            .ok_or_else(|| AgentError::other("SD WebUI 未返回图像"))?;
+        // 部分分支返回 data URL
+        let encoded = encoded
+            .split_once("base64,")
+            .map_or(encoded.as_str(), |(_, data)| data);
+        let bytes = BASE64_STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| AgentError::other(format!("解码 SD WebUI 返回的 Base64 图像失败: {e}")))?;
+
+        let mut parameters = Map::new();
+        parameters.insert("sampler".to_string(), json!(self.config.sampler));
+        parameters.insert("steps".to_string(), json!(self.config.steps));
+        parameters.insert("cfg_scale".to_string(), json!(self.config.cfg_scale));
+        if !negative_prompt.is_empty() {
+            parameters.insert("negative_prompt".to_string(), json!(negative_prompt));
+        }
+        if let Some(checkpoint) = checkpoint {
+            parameters.insert("checkpoint".to_string(), json!(checkpoint));
+        }
+        // 以 WebUI 实际使用的参数为准，随机种子只能从这里得到
+        let info = response
+            .info
+            .and_then(|info| serde_json::from_str::<Value>(&info).ok());
+        if let Some(info) = info {
+            for (key, field) in [
+                ("seed", "seed"),
+                ("sampler", "sampler_name"),
+                ("steps", "steps"),
+                ("cfg_scale", "cfg_scale"),
+                ("checkpoint", "sd_model_name"),
+            ] {
+                if let Some(value) = info.get(field).filter(|value| !value.is_null()) {
+                    parameters.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+
+        Ok(SdWebUiImage { bytes, parameters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{
+        Json, Router,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        response::{IntoResponse, Response},
+        routing::post,
+    };
+
+    use super::*;
+    use crate::{capabilities::ImageGenerator, util::test_support::spawn_stub};
+
+    /// 1x1 的 PNG
+    const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+    type Requests = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+    /// 模拟 WebUI 的 txt2img：按提示词返回图像、错误或迟迟不返回，并记录请求
+    async fn serve(auth: Option<&str>, timeout: Duration) -> (SdWebUiConfig, Requests) {
+        async fn txt2img(
+            State(requests): State<Requests>,
+            headers: HeaderMap,
+            Json(body): Json<Value>,
+        ) -> Response {
+            let authorization = headers
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            requests.lock().unwrap().push((authorization, body.clone()));
+            match body["prompt"].as_str().unwrap_or_default() {
+                "oom" => (StatusCode::INTERNAL_SERVER_ERROR, "CUDA out of memory").into_response(),
+                "slow" => {
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    StatusCode::OK.into_response()
+                }
+                _ => {
+                    let info = json!({
+                        "seed": 1234567,
+                        "sampler_name": body["sampler_name"],
+                        "steps": body["steps"],
+                        "cfg_scale": body["cfg_scale"],
+                        "sd_model_name": "sdxl_base",
+                    });
+                    Json(json!({
+                        "images": [format!("data:image/png;base64,{PNG_BASE64}")],
+                        "info": info.to_string(),
+                    }))
+                    .into_response()
+                }
+            }
+        }
+
+        let requests = Requests::default();
+        let app = Router::new()
+            .route("/sdapi/v1/txt2img", post(txt2img))
+            .with_state(requests.clone());
+        let addr = spawn_stub(app).await;
+        let config = SdWebUiConfig {
+            endpoint: format!("http://{addr}"),
+            auth: auth.map(str::to_string),
+            checkpoint: None,
+            sampler: "DPM++ 2M".to_string(),
+            steps: 25,
+            cfg_scale: 6.5,
+            negative_prompt: Some("lowres".to_string()),
+            width: 768,
+            height: 512,
+            timeout,
+        };
+        (config, requests)
+    }
+
+    #[tokio::test]
+    async fn txt2img_decodes_first_image_with_parameters() {
+        let (config, requests) = serve(Some("user:pass"), Duration::from_secs(10)).await;
+        let artifact = ImageGenerator::sd_webui(&config, Some("sdxl_base.safetensors"), &[])
+            .unwrap()
+            .generate("一只猫", None)
+            .await
+            .unwrap();
+        assert_eq!(artifact.data, BASE64_STANDARD.decode(PNG_BASE64).unwrap());
+        let metadata = &artifact.metadata;
+        assert_eq!(metadata["model"], "sdxl_base.safetensors");
+        assert_eq!(
+            (&metadata["width"], &metadata["height"]),
+            (&json!(768), &json!(512))
+        );
+        assert_eq!(metadata["seed"], 1234567);
+        assert_eq!(metadata["sampler"], "DPM++ 2M");
+        assert_eq!(metadata["steps"], 25);
+        assert_eq!(metadata["cfg_scale"], 6.5);
+        assert_eq!(metadata["negative_prompt"], "lowres");
+        // 以 WebUI 返回的实际模型为准
+        assert_eq!(metadata["checkpoint"], "sdxl_base");
+
+        let (authorization, body) = requests.lock().unwrap()[0].clone();
+        assert_eq!(
+            authorization.as_deref(),
+            Some(format!("Basic {}", BASE64_STANDARD.encode("user:pass")).as_str())
+        );
+        assert_eq!(body["seed"], -1);
+        assert_eq!(
+            body["override_settings"]["sd_model_checkpoint"],
+            "sdxl_base.safetensors"
+        );
+
+        // 提示词中的反向提示词覆盖配置的默认值
+        ImageGenerator::sd_webui(&config, None, &[])
+            .unwrap()
+            .generate("一只猫 --negative: 模糊", Some((512, 512)))
+            .await
+            .unwrap();
+        let (authorization, body) = requests.lock().unwrap()[1].clone();
+        assert!(authorization.is_some());
+        assert_eq!(body["negative_prompt"], "模糊");
+        assert_eq!(body["width"], 512);
+        assert!(body.get("override_settings").is_none());
+    }
+
+    #[tokio::test]
+    async fn errors_and_timeouts() {
+        let (config, _) = serve(None, Duration::from_millis(200)).await;
+        let client = SdWebUiClient::from_config(&config, None).unwrap();
+        let Err(err) = client.txt2img("oom", None, (512, 512)).await else {
+            panic!("WebUI 返回 500 时应当失败");
+        };
+        let err = err.to_string();
+        assert!(err.contains("500"), "{err}");
+        assert!(err.contains("CUDA out of memory"), "{err}");
+
+        let started = std::time::Instant::now();
+        assert!(client.txt2img("slow", None, (512, 512)).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
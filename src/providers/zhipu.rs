@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use crate::config::ZhipuConfig;
+use crate::{config::ZhipuConfig, util::send_with_retry};
 use futures_util::StreamExt as FuturesStreamExt;
 use reqwest::Client as HttpClient;
 use rig::completion::{
@@ -22,18 +22,26 @@ use serde::{Deserialize, Serialize};
 /// 
 /// ## 标准模式
 /// ```no_run
-/// use rig::completion::CompletionModel;
+/// use rig::completion::{AssistantContent, CompletionModel};
+/// # use vutber_agent::{config::ZhipuConfig, providers::zhipu::ZhipuCompletionModel};
+/// # async fn run(config: ZhipuConfig) -> Result<(), rig::completion::CompletionError> {
 /// 
 /// let model = ZhipuCompletionModel::from_config(&config, None)?;
 /// let request = model.completion_request("你好").build();
 /// let response = model.completion(request).await?;
-/// println!("{}", response.choice.first().unwrap().text());
+/// if let AssistantContent::Text(text) = response.choice.first() {
+///     println!("{}", text.text);
+/// }
+/// # Ok(())
+/// # }
 /// ```
 /// 
 /// ## 流式模式
 /// ```no_run
 /// use futures_util::StreamExt;
 /// use rig::completion::CompletionModel;
+/// # use vutber_agent::{config::ZhipuConfig, providers::zhipu::ZhipuCompletionModel};
+/// # async fn run(config: ZhipuConfig) -> Result<(), rig::completion::CompletionError> {
 /// 
 /// let model = ZhipuCompletionModel::from_config(&config, None)?;
 /// let request = model.completion_request("讲一个故事").build();
@@ -41,13 +49,12 @@ use serde::{Deserialize, Serialize};
 /// 
 /// // 逐个 token 打印
 /// while let Some(result) = stream.next().await {
-///     match result? {
-///         rig::streaming::StreamedAssistantContent::Text(text) => {
-///             print!("{}", text);
-///         }
-///         _ => {}
+///     if let rig::streaming::StreamedAssistantContent::Text(text) = result? {
+///         print!("{}", text.text);
 ///     }
 /// }
+/// # Ok(())
+/// # }
 /// ```
 #[derive(Clone)]
 pub struct ZhipuCompletionModel {
@@ -74,6 +81,75 @@ impl ZhipuCompletionModel {
             endpoint: config.api_url.clone(),
         })
     }
+
+    /// 构造发送给智谱接口的请求体：`preamble` 作为 `system` 消息放在最前，
+    /// `additional_params` 中只透传 `top_p` 与 `response_format`（如 `{"type": "json_object"}`）
+    ///
+    /// ```
+    /// use rig::completion::{CompletionModel, Message};
+    /// use vutber_agent::{config::ZhipuConfig, providers::zhipu::ZhipuCompletionModel};
+    ///
+    /// let config = ZhipuConfig {
+    ///     api_key: "test".into(),
+    ///     chat_model: "glm-4-flash".into(),
+    ///     agent_preamble: String::new(),
+    ///     api_url: "http://127.0.0.1/chat".into(),
+    ///     image_model: String::new(),
+    ///     image_api_url: String::new(),
+    /// };
+    /// let model = ZhipuCompletionModel::from_config(&config, None).unwrap();
+    /// let request = model
+    ///     .completion_request(Message::user("今天唱什么？"))
+    ///     .preamble("你是虚拟主播".to_string())
+    ///     .messages(vec![Message::user("你好"), Message::assistant("晚上好呀")])
+    ///     .build();
+    ///
+    /// let body = model.request_body(&request, false).unwrap();
+    /// let roles: Vec<_> = body["messages"]
+    ///     .as_array()
+    ///     .unwrap()
+    ///     .iter()
+    ///     .map(|message| message["role"].as_str().unwrap())
+    ///     .collect();
+    /// assert_eq!(roles, ["system", "user", "assistant", "user"]);
+    /// assert_eq!(body["messages"][0]["content"], "你是虚拟主播");
+    /// assert!(body.get("stream").is_none());
+    /// assert_eq!(model.request_body(&request, true).unwrap()["stream"], true);
+    /// // 未配置的采样参数不出现在请求体中
+    /// assert!(body.get("temperature").is_none() && body.get("max_tokens").is_none());
+    ///
+    /// let request = model
+    ///     .completion_request(Message::user("来段弹幕回复"))
+    ///     .temperature(0.3)
+    ///     .max_tokens(80)
+    ///     .additional_params(serde_json::json!({ "top_p": 0.9, "ignored": true }))
+    ///     .build();
+    /// let body = model.request_body(&request, false).unwrap();
+    /// assert_eq!(body["temperature"], 0.3);
+    /// assert_eq!(body["max_tokens"], 80);
+    /// assert_eq!(body["top_p"], 0.9);
+    /// assert!(body.get("ignored").is_none());
+    /// ```
+    pub fn request_body(
+        &self,
+        request: &CompletionRequest,
+        stream: bool,
+    ) -> Result<serde_json::Value, CompletionError> {
+        let messages = request_messages(request)?;
+        let payload = ZhipuChatRequest {
+            model: &self.model,
+            messages: &messages,
+            stream,
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            top_p: top_p(request),
+            response_format: request
+                .additional_params
+                .as_ref()
+                .and_then(|params| params.get("response_format")),
+        };
+        serde_json::to_value(&payload).map_err(CompletionError::JsonError)
+    }
 }
 
 impl CompletionModel for ZhipuCompletionModel {
@@ -84,70 +160,14 @@ impl CompletionModel for ZhipuCompletionModel {
         &self,
         request: CompletionRequest,
     ) -> std::result::Result<CompletionResponse<Self::Response>, CompletionError> {
-        // 转换 rig 的 Message 为 Zhipu 格式
-        let messages: Vec<ZhipuRequestMessage> = request
-            .chat_history
-            .iter()
-            .filter_map(|msg| match msg {
-                Message::User { content } => {
-                    // 提取文本内容
-                    let text = content
-                        .iter()
-                        .filter_map(|c| match c {
-                            rig::completion::message::UserContent::Text(t) => Some(t.text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n");
-                    if text.is_empty() {
-                        None
-                    } else {
-                        Some(ZhipuRequestMessage {
-                            role: "user",
-                            content: text,
-                        })
-                    }
-                }
-                Message::Assistant { content, .. } => {
-                    // 提取助手文本
-                    let text = content
-                        .iter()
-                        .filter_map(|c| match c {
-                            rig::completion::message::AssistantContent::Text(t) => Some(t.text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n");
-                    if text.is_empty() {
-                        None
-                    } else {
-                        Some(ZhipuRequestMessage {
-                            role: "assistant",
-                            content: text,
-                        })
-                    }
-                }
-                // Message 枚举目前只有 User 和 Assistant 两个变体
-            })
-            .collect();
-
-        if messages.is_empty() {
-            return Err(CompletionError::RequestError(
-                "No valid messages in request".into(),
-            ));
-        }
-
-        let payload = ZhipuChatRequest {
-            model: &self.model,
-            messages: &messages,
-        };
+        let payload = self.request_body(&request, false)?;
 
-        let response = self
+        let request = self
             .http_client
             .post(&self.endpoint)
             .bearer_auth(&self.api_key)
-            .json(&payload)
-            .send()
+            .json(&payload);
+        let response = send_with_retry("zhipu", request)
             .await
             .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
 
@@ -181,69 +201,14 @@ impl CompletionModel for ZhipuCompletionModel {
         &self,
         request: CompletionRequest,
     ) -> std::result::Result<rig::streaming::StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
-        // 转换 rig 的 Message 为 Zhipu 格式
-        let messages: Vec<ZhipuRequestMessage> = request
-            .chat_history
-            .iter()
-            .filter_map(|msg| match msg {
-                Message::User { content } => {
-                    let text = content
-                        .iter()
-                        .filter_map(|c| match c {
-                            rig::completion::message::UserContent::Text(t) => Some(t.text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n");
-                    if text.is_empty() {
-                        None
-                    } else {
-                        Some(ZhipuRequestMessage {
-                            role: "user",
-                            content: text,
-                        })
-                    }
-                }
-                Message::Assistant { content, .. } => {
-                    let text = content
-                        .iter()
-                        .filter_map(|c| match c {
-                            rig::completion::message::AssistantContent::Text(t) => Some(t.text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n");
-                    if text.is_empty() {
-                        None
-                    } else {
-                        Some(ZhipuRequestMessage {
-                            role: "assistant",
-                            content: text,
-                        })
-                    }
-                }
-            })
-            .collect();
-
-        if messages.is_empty() {
-            return Err(CompletionError::RequestError(
-                "No valid messages in request".into(),
-            ));
-        }
+        let payload = self.request_body(&request, true)?;
 
-        // 添加 stream: true 参数
-        let payload = ZhipuStreamRequest {
-            model: &self.model,
-            messages: &messages,
-            stream: true,
-        };
-
-        let response = self
+        let request = self
             .http_client
             .post(&self.endpoint)
             .bearer_auth(&self.api_key)
-            .json(&payload)
-            .send()
+            .json(&payload);
+        let response = send_with_retry("zhipu", request)
             .await
             .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
 
@@ -256,61 +221,25 @@ impl CompletionModel for ZhipuCompletionModel {
             )));
         }
 
-        // 创建 SSE 流
-        let stream = response.bytes_stream().map(move |chunk_result| {
-            let chunk = chunk_result.map_err(|e| CompletionError::ProviderError(e.to_string()))?;
-            let text = String::from_utf8_lossy(&chunk);
-            
-            // 解析 SSE 格式: data: {...}
-            for line in text.lines() {
-                let line = line.trim();
-                if line.starts_with("data:") {
-                    let json_str = line.strip_prefix("data:").unwrap().trim();
-                    if json_str == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // 解析智谱 AI 的流式响应
-                    match serde_json::from_str::<ZhipuStreamChunk>(json_str) {
-                        Ok(chunk) => {
-                            // 提取文本增量
-                            if let Some(choice) = chunk.choices.first() {
-                                if let Some(ref delta) = choice.delta {
-                                    if let Some(ref content) = delta.content {
-                                        return Ok(RawStreamingChoice::<ZhipuChatResponse>::Message(content.clone()));
-                                    }
-                                }
-                            }
-                            // 如果是最后一个块，返回完整响应(带 usage 信息)
-                            if chunk.choices.iter().any(|c| c.finish_reason.is_some()) {
-                                // 构造完整响应
-                                let full_response = ZhipuChatResponse {
-                                    choices: vec![],  // 流式响应不需要完整的 choices
-                                    usage: chunk.usage,
-                                };
-                                return Ok(RawStreamingChoice::FinalResponse(full_response));
-                            }
-                        }
-                        Err(e) => {
-                            return Err(CompletionError::ProviderError(format!(
-                                "解析智谱流式响应失败: {}",
-                                e
-                            )));
+        // 一个网络块可能包含多行 data，也可能在行（甚至多字节字符）中间截断，
+        // 因此按字节缓冲到换行再逐行解析
+        let stream = response
+            .bytes_stream()
+            .scan(Vec::new(), |buffer: &mut Vec<u8>, chunk| {
+                let mut items = Vec::new();
+                match chunk {
+                    Ok(chunk) => {
+                        buffer.extend_from_slice(&chunk);
+                        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                            let line: Vec<u8> = buffer.drain(..=end).collect();
+                            parse_stream_line(String::from_utf8_lossy(&line).trim(), &mut items);
                         }
                     }
+                    Err(e) => items.push(Err(CompletionError::ProviderError(e.to_string()))),
                 }
-            }
-            
-            // 如果没有解析到任何内容，跳过此块
-            Err(CompletionError::ProviderError("无效的 SSE 数据块".to_string()))
-        })
-        .filter_map(|result| async move {
-            match result {
-                Ok(choice) => Some(Ok(choice)),
-                Err(e) if e.to_string().contains("无效的 SSE 数据块") => None,
-                Err(e) => Some(Err(e)),
-            }
-        });
+                futures_util::future::ready(Some(futures_util::stream::iter(items)))
+            })
+            .flatten();
 
         Ok(rig::streaming::StreamingCompletionResponse::stream(Box::pin(stream)))
     }
@@ -320,13 +249,114 @@ impl CompletionModel for ZhipuCompletionModel {
 struct ZhipuChatRequest<'a> {
     model: &'a str,
     messages: &'a [ZhipuRequestMessage<'a>],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<&'a serde_json::Value>,
 }
 
-#[derive(Serialize)]
-struct ZhipuStreamRequest<'a> {
-    model: &'a str,
-    messages: &'a [ZhipuRequestMessage<'a>],
-    stream: bool,
+/// 转换 rig 的请求为智谱消息列表：`preamble` 作为第一条 `system` 消息，
+/// 之后按顺序映射历史中的用户与助手文本，跳过没有文本的消息
+fn request_messages(
+    request: &CompletionRequest,
+) -> Result<Vec<ZhipuRequestMessage<'static>>, CompletionError> {
+    let mut messages = Vec::new();
+    if let Some(preamble) = request.preamble.as_deref().filter(|text| !text.is_empty()) {
+        messages.push(ZhipuRequestMessage {
+            role: "system",
+            content: preamble.to_string(),
+        });
+    }
+
+    for msg in request.chat_history.iter() {
+        let (role, text) = match msg {
+            Message::User { content } => (
+                "user",
+                content
+                    .iter()
+                    .filter_map(|c| match c {
+                        rig::completion::message::UserContent::Text(t) => Some(t.text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Message::Assistant { content, .. } => (
+                "assistant",
+                content
+                    .iter()
+                    .filter_map(|c| match c {
+                        AssistantContent::Text(t) => Some(t.text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        };
+        if !text.is_empty() {
+            messages.push(ZhipuRequestMessage {
+                role,
+                content: text,
+            });
+        }
+    }
+
+    if !messages.iter().any(|message| message.role != "system") {
+        return Err(CompletionError::RequestError(
+            "No valid messages in request".into(),
+        ));
+    }
+    Ok(messages)
+}
+
+/// rig 的请求没有 `top_p` 字段，从 `additional_params` 中读取
+fn top_p(request: &CompletionRequest) -> Option<f64> {
+    request.additional_params.as_ref()?.get("top_p")?.as_f64()
+}
+
+/// 解析一行 SSE：文本增量产出 `Message`，带 `finish_reason` 的最后一块产出携带用量的 `FinalResponse`
+fn parse_stream_line(
+    line: &str,
+    items: &mut Vec<Result<RawStreamingChoice<ZhipuChatResponse>, CompletionError>>,
+) {
+    let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+        return;
+    };
+    if data.is_empty() || data == "[DONE]" {
+        return;
+    }
+
+    let chunk = match serde_json::from_str::<ZhipuStreamChunk>(data) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            items.push(Err(CompletionError::ProviderError(format!(
+                "解析智谱流式响应失败: {}",
+                e
+            ))));
+            return;
+        }
+    };
+
+    if let Some(content) = chunk
+        .choices
+        .iter()
+        .filter_map(|choice| choice.delta.as_ref()?.content.as_ref())
+        .find(|content| !content.is_empty())
+    {
+        items.push(Ok(RawStreamingChoice::Message(content.clone())));
+    }
+    if chunk.choices.iter().any(|c| c.finish_reason.is_some()) {
+        items.push(Ok(RawStreamingChoice::FinalResponse(ZhipuChatResponse {
+            choices: vec![], // 流式响应不需要完整的 choices
+            usage: chunk.usage,
+        })));
+    }
 }
 
 #[derive(Deserialize)]
@@ -440,3 +470,124 @@ struct ZhipuMessageSegment {
     #[serde(default)]
     text: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use rig::completion::CompletionModel;
+
+    use super::*;
+
+    fn model() -> ZhipuCompletionModel {
+        ZhipuCompletionModel::from_config(
+            &ZhipuConfig {
+                api_key: "test".into(),
+                chat_model: "glm-4-flash".into(),
+                agent_preamble: String::new(),
+                api_url: "http://127.0.0.1/chat".into(),
+                image_model: String::new(),
+                image_api_url: String::new(),
+            },
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sampling_params_are_serialized_only_when_set() {
+        let model = model();
+        let plain = model.completion_request(Message::user("你好")).build();
+        let body = model.request_body(&plain, false).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "glm-4-flash",
+                "messages": [{ "role": "user", "content": "你好" }],
+            })
+        );
+
+        let tuned = model
+            .completion_request(Message::user("你好"))
+            .temperature(0.3)
+            .max_tokens(80)
+            .additional_params(serde_json::json!({
+                "top_p": 0.9,
+                "response_format": { "type": "json_object" },
+                "ignored": true,
+            }))
+            .build();
+        let body = model.request_body(&tuned, true).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["temperature"], 0.3);
+        assert_eq!(body["max_tokens"], 80);
+        assert_eq!(body["top_p"], 0.9);
+        assert_eq!(
+            body["response_format"],
+            serde_json::json!({ "type": "json_object" })
+        );
+        assert!(body.get("ignored").is_none());
+    }
+
+    #[test]
+    fn response_round_trips_through_serde() {
+        let raw = serde_json::json!({
+            "id": "chatcmpl-1",
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": "晚上好" } }],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 },
+        });
+        let response: ZhipuChatResponse = serde_json::from_value(raw).unwrap();
+        let again: ZhipuChatResponse =
+            serde_json::from_value(serde_json::to_value(&response).unwrap()).unwrap();
+
+        for response in [&response, &again] {
+            assert_eq!(response.extract_text().as_deref(), Some("晚上好"));
+            let usage = response.token_usage().unwrap();
+            assert_eq!(
+                (usage.input_tokens, usage.output_tokens, usage.total_tokens),
+                (12, 3, 15)
+            );
+        }
+    }
+
+    #[test]
+    fn segmented_and_missing_content() {
+        let response: ZhipuChatResponse = serde_json::from_value(serde_json::json!({
+            "choices": [{ "message": { "content": [
+                { "type": "text", "text": "晚上" },
+                { "type": "image_url" },
+                { "type": "text", "text": "好" },
+            ] } }],
+        }))
+        .unwrap();
+        assert_eq!(response.extract_text().as_deref(), Some("晚上好"));
+        assert!(response.token_usage().is_none());
+
+        let again: ZhipuChatResponse =
+            serde_json::from_value(serde_json::to_value(&response).unwrap()).unwrap();
+        assert_eq!(again.extract_text().as_deref(), Some("晚上好"));
+
+        let empty: ZhipuChatResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.extract_text().is_none());
+    }
+
+    #[test]
+    fn stream_lines_yield_text_then_usage() {
+        let mut items = Vec::new();
+        for line in [
+            ": keep-alive",
+            r#"data: {"choices":[{"delta":{"content":"晚上"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":""},"finish_reason":"stop"}],"usage":{"prompt_tokens":1,"completion_tokens":2,"total_tokens":3}}"#,
+            "data: [DONE]",
+        ] {
+            parse_stream_line(line, &mut items);
+        }
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0], Ok(RawStreamingChoice::Message(text)) if text == "晚上"));
+        let Ok(RawStreamingChoice::FinalResponse(last)) = &items[1] else {
+            panic!("最后一块应带用量");
+        };
+        assert_eq!(last.token_usage().unwrap().total_tokens, 3);
+
+        parse_stream_line("data: {not json", &mut items);
+        assert!(items.last().unwrap().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::{Value, json};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{
+    errors::{AgentError, Result},
+    util::{format_beijing, now_in_beijing},
+};
+
+const MIN_RECURRING_INTERVAL_SECONDS: u64 = 10;
+
+/// 进程内的定时命令表。条目只保存在内存中，进程重启后不会恢复。
+#[derive(Default)]
+pub struct Scheduler {
+    entries: Vec<ScheduledEntry>,
+}
+
+struct ScheduledEntry {
+    id: String,
+    input: String,
+    next_fire: Instant,
+    next_fire_at: DateTime<FixedOffset>,
+    every: Option<Duration>,
+}
+
+impl ScheduledEntry {
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "input": self.input,
+            "next_fire_at": format_beijing(&self.next_fire_at, "%Y-%m-%d %H:%M:%S%:z"),
+            "every_seconds": self.every.map(|every| every.as_secs()),
+        })
+    }
+}
+
+/// 到期需要执行的定时命令
+pub struct DueSchedule {
+    pub id: String,
+    pub input: String,
+    pub recurring: bool,
+}
+
+impl Scheduler {
+    /// 新增定时命令：`at` 为 RFC3339 时间（过去的时间会立即执行一次），`every_seconds` 为重复间隔
+    pub fn add(
+        &mut self,
+        input: String,
+        at: Option<&str>,
+        every_seconds: Option<u64>,
+    ) -> Result<Value> {
+        if input.trim().is_empty() {
+            return Err(AgentError::other("定时命令的 input 不能为空"));
+        }
+
+        let every = match every_seconds {
+            Some(seconds) if seconds < MIN_RECURRING_INTERVAL_SECONDS => {
+                return Err(AgentError::other(format!(
+                    "重复间隔不能小于 {MIN_RECURRING_INTERVAL_SECONDS} 秒"
+                )));
+            }
+            Some(seconds) => Some(Duration::from_secs(seconds)),
+            None => None,
+        };
+
+        let now = now_in_beijing();
+        let (next_fire, next_fire_at) = match at {
+            Some(at) => {
+                let at = DateTime::parse_from_rfc3339(at)
+                    .map_err(|err| AgentError::other(format!("解析定时时间 {at} 失败: {err}")))?;
+                let delay = at
+                    .signed_duration_since(now)
+                    .to_std()
+                    .unwrap_or(Duration::ZERO);
+                (Instant::now() + delay, at.with_timezone(now.offset()))
+            }
+            None => {
+                let every = every.ok_or_else(|| {
+                    AgentError::other("定时命令需要提供 at 或 every_seconds 其中之一")
+                })?;
+                (
+                    Instant::now() + every,
+                    now + chrono::Duration::from_std(every).unwrap_or_default(),
+                )
+            }
+        };
+
+        let entry = ScheduledEntry {
+            id: Uuid::new_v4().to_string(),
+            input,
+            next_fire,
+            next_fire_at,
+            every,
+        };
+        let summary = entry.summary();
+        self.entries.push(entry);
+        Ok(summary)
+    }
+
+    pub fn cancel(&mut self, id: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != before
+    }
+
+    pub fn list(&self) -> Vec<Value> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.next_fire);
+        entries.into_iter().map(ScheduledEntry::summary).collect()
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries.iter().map(|entry| entry.next_fire).min()
+    }
+
+    /// 取出所有已到期的命令；一次性条目被移除，重复条目顺延到下一个周期
+    pub fn take_due(&mut self) -> Vec<DueSchedule> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+
+        self.entries.retain_mut(|entry| {
+            if entry.next_fire > now {
+                return true;
+            }
+
+            due.push(DueSchedule {
+                id: entry.id.clone(),
+                input: entry.input.clone(),
+                recurring: entry.every.is_some(),
+            });
+
+            match entry.every {
+                Some(every) => {
+                    entry.next_fire = now + every;
+                    entry.next_fire_at =
+                        now_in_beijing() + chrono::Duration::from_std(every).unwrap_or_default();
+                    true
+                }
+                None => false,
+            }
+        });
+
+        due
+    }
+}
@@ -1,19 +1,28 @@
-use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use std::{convert::Infallible, path::PathBuf, sync::Arc, time::Duration};
 
 use axum::{
-    extract::{Query, State},
-    response::sse::{Event, KeepAlive, Sse},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
     Json, Router,
 };
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
 use futures_util::stream::{Stream, StreamExt};
 use serde::Deserialize;
 use serde_json::{Value, json};
 use tokio::sync::{broadcast, mpsc};
 use tokio_stream::wrappers::BroadcastStream;
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
-use crate::errors::{AgentError, Result};
+use crate::{
+    config::SseConfig,
+    errors::{AgentError, Result},
+};
 
 pub type BroadcastSender = broadcast::Sender<String>;
 
@@ -38,34 +47,148 @@ impl SignatureAuth {
             return false;
         }
 
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
+        let now = unix_now();
 
         let diff = now.abs_diff(params.timestamp);
         if diff > self.max_age.as_secs() {
             return false;
         }
 
-        let canonical = format!("{}:{}:{}", params.access_key, params.timestamp, params.nonce);
         let signature_bytes = match hex::decode(&params.signature) {
             Ok(bytes) => bytes,
             Err(_) => return false,
         };
 
-        use hmac::{Hmac, Mac};
-        use sha2::Sha256;
-
-        let mut mac = match Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes()) {
-            Ok(mac) => mac,
-            Err(_) => return false,
+        let mac = match signature_mac(
+            &self.secret_key,
+            &params.access_key,
+            params.timestamp,
+            &params.nonce,
+        ) {
+            Some(mac) => mac,
+            None => return false,
         };
-        mac.update(canonical.as_bytes());
         mac.verify_slice(&signature_bytes).is_ok()
     }
 }
 
+/// 以当前时间为 `/command` 与 `/events` 生成签名参数，供下游 Rust 客户端直接拼接请求
+///
+/// 签名为 `hex(HMAC-SHA256(secret_key, "access_key:timestamp:nonce"))`，`nonce` 由调用方生成，
+/// 每个请求应使用不同的值
+///
+/// ```
+/// use std::time::Duration;
+/// use vutber_agent::sse::{SignatureAuth, sign};
+///
+/// let params = sign("my-access-key", "my-secret-key", "nonce-123");
+/// let url = reqwest::Url::parse_with_params(
+///     "http://127.0.0.1:9000/events",
+///     params.query_pairs(),
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     url.as_str(),
+///     format!(
+///         "http://127.0.0.1:9000/events?access_key=my-access-key&timestamp={}&nonce=nonce-123&signature={}",
+///         params.timestamp(),
+///         params.signature(),
+///     )
+/// );
+///
+/// let auth = SignatureAuth::new(
+///     "my-access-key".to_string(),
+///     "my-secret-key".to_string(),
+///     Duration::from_secs(300),
+/// );
+/// assert!(auth.verify_params(&params));
+/// ```
+pub fn sign(access_key: &str, secret_key: &str, nonce: &str) -> AuthParams {
+    let timestamp = unix_now();
+    let signature = signature_mac(secret_key, access_key, timestamp, nonce)
+        .map(|mac| hex::encode(mac.finalize().into_bytes()))
+        .unwrap_or_default();
+    AuthParams {
+        access_key: access_key.to_string(),
+        timestamp,
+        nonce: nonce.to_string(),
+        signature,
+    }
+}
+
+fn signature_mac(
+    secret_key: &str,
+    access_key: &str,
+    timestamp: i64,
+    nonce: &str,
+) -> Option<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes()).ok()?;
+    mac.update(format!("{access_key}:{timestamp}:{nonce}").as_bytes());
+    Some(mac)
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// `/artifacts/{name}` 的短期下载令牌，格式为 `过期时间戳.签名`
+///
+/// 签名以 `secret_key` 对 `artifact:文件名:过期时间戳` 计算，令牌只对签发时的文件名有效，
+/// 便于直接放进 `<img src>` 等无法附加完整签名参数的场景
+#[derive(Clone)]
+pub struct ArtifactTokens {
+    secret_key: String,
+    ttl: Duration,
+}
+
+impl ArtifactTokens {
+    pub fn new(secret_key: String, ttl: Duration) -> Self {
+        Self { secret_key, ttl }
+    }
+
+    pub fn mint(&self, name: &str) -> String {
+        let expires = unix_now() + self.ttl.as_secs() as i64;
+        let signature = self
+            .mac(name, expires)
+            .map(|mac| hex::encode(mac.finalize().into_bytes()))
+            .unwrap_or_default();
+        format!("{expires}.{signature}")
+    }
+
+    /// 相对于 SSE 服务地址的下载链接
+    pub fn download_url(&self, name: &str) -> String {
+        format!("/artifacts/{name}?token={}", self.mint(name))
+    }
+
+    pub fn verify(&self, name: &str, token: &str) -> bool {
+        let Some((expires, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<i64>() else {
+            return false;
+        };
+        if expires < unix_now() {
+            return false;
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+
+        self.mac(name, expires)
+            .is_some_and(|mac| mac.verify_slice(&signature).is_ok())
+    }
+
+    fn mac(&self, name: &str, expires: i64) -> Option<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes()).ok()?;
+        mac.update(format!("artifact:{name}:{expires}").as_bytes());
+        Some(mac)
+    }
+}
+
+/// `/command` 与 `/events` 的签名查询参数，客户端可用 [`sign`] 生成
 #[derive(Debug, Deserialize)]
 pub struct AuthParams {
     access_key: String,
@@ -74,30 +197,202 @@ pub struct AuthParams {
     signature: String,
 }
 
+impl AuthParams {
+    pub fn access_key(&self) -> &str {
+        &self.access_key
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// 按 `access_key`、`timestamp`、`nonce`、`signature` 的顺序输出查询参数，值未经 URL 编码
+    pub fn query_pairs(&self) -> [(&'static str, String); 4] {
+        [
+            ("access_key", self.access_key.clone()),
+            ("timestamp", self.timestamp.to_string()),
+            ("nonce", self.nonce.clone()),
+            ("signature", self.signature.clone()),
+        ]
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "action", rename_all = "snake_case")]
 pub enum ClientMessage {
-    Command { input: String },
+    Command {
+        input: String,
+        #[serde(default)]
+        dry_run: bool,
+        #[serde(default)]
+        fallback_only: bool,
+        /// 仅本次请求使用的提供方，如 `openai`、`zhipu` 或 `providers_profiles` 中的配置档名称
+        #[serde(default)]
+        provider: Option<String>,
+        /// 仅本次请求使用的模型，未指定 `provider` 时沿用路由的提供方
+        #[serde(default)]
+        model: Option<String>,
+    },
+    Batch {
+        inputs: Vec<String>,
+    },
+    Schedule {
+        input: String,
+        #[serde(default)]
+        at: Option<String>,
+        #[serde(default)]
+        every_seconds: Option<u64>,
+    },
+    ScheduleList,
+    ScheduleCancel {
+        id: String,
+    },
     LiveStart,
     LiveStop,
     LiveStatus,
+    LiveStats,
+    Stats,
+    /// 查询各能力是否可用及其提供方/模型，与 `system.ready` 中的 `capabilities` 相同
+    Capabilities,
+    /// 运行时替换对话人设提示词，空文本恢复配置中的提示词
+    SetPreamble {
+        #[serde(default)]
+        text: String,
+    },
+    /// 切换到 `[[personas]]` 中的命名人设
+    SetMood {
+        name: String,
+    },
+    /// 兼容旧版 WebSocket 客户端的弹幕消息，按 `command` 执行；`user_id` 可以是字符串或数字
+    Danmaku {
+        content: String,
+        #[serde(default)]
+        user_id: Option<Value>,
+    },
 }
 
 #[derive(Debug)]
 pub enum AgentCommand {
-    Command { input: String },
+    Command {
+        input: String,
+        /// 广播与用量统计中的来源：`command`，或由兼容的 `danmaku` 消息转换而来时为 `danmaku`
+        origin: &'static str,
+        /// 旧版客户端提供的用户标识，作为请求的发送者并写入广播的 `context`
+        user_id: Option<String>,
+        /// 覆盖本次请求的提供方与模型，由控制器按已配置的提供方校验
+        provider: Option<String>,
+        model: Option<String>,
+    },
+    DryRun {
+        input: String,
+        fallback_only: bool,
+    },
+    Batch {
+        inputs: Vec<String>,
+    },
+    Schedule {
+        input: String,
+        at: Option<String>,
+        every_seconds: Option<u64>,
+    },
+    ScheduleList,
+    ScheduleCancel {
+        id: String,
+    },
     LiveStart,
     LiveStop,
     LiveStatus,
+    LiveStats,
+    Stats,
+    Capabilities,
+    SetPreamble {
+        text: String,
+    },
+    SetMood {
+        name: String,
+    },
+}
+
+/// 进入命令通道的命令；`request_id` 在接收时生成并返回给客户端，命令执行时作为请求 ID 写入广播的 `context`
+#[derive(Debug)]
+pub struct QueuedCommand {
+    pub request_id: String,
+    pub command: AgentCommand,
+}
+
+impl QueuedCommand {
+    fn new(message: ClientMessage) -> Self {
+        Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            command: message.into(),
+        }
+    }
 }
 
 impl From<ClientMessage> for AgentCommand {
     fn from(value: ClientMessage) -> Self {
         match value {
-            ClientMessage::Command { input } => AgentCommand::Command { input },
+            ClientMessage::Command {
+                input,
+                dry_run: true,
+                fallback_only,
+                ..
+            } => AgentCommand::DryRun {
+                input,
+                fallback_only,
+            },
+            ClientMessage::Command {
+                input,
+                provider,
+                model,
+                ..
+            } => AgentCommand::Command {
+                input,
+                origin: "command",
+                user_id: None,
+                provider,
+                model,
+            },
+            ClientMessage::Batch { inputs } => AgentCommand::Batch { inputs },
+            ClientMessage::Schedule {
+                input,
+                at,
+                every_seconds,
+            } => AgentCommand::Schedule {
+                input,
+                at,
+                every_seconds,
+            },
+            ClientMessage::ScheduleList => AgentCommand::ScheduleList,
+            ClientMessage::ScheduleCancel { id } => AgentCommand::ScheduleCancel { id },
             ClientMessage::LiveStart => AgentCommand::LiveStart,
             ClientMessage::LiveStop => AgentCommand::LiveStop,
             ClientMessage::LiveStatus => AgentCommand::LiveStatus,
+            ClientMessage::LiveStats => AgentCommand::LiveStats,
+            ClientMessage::Stats => AgentCommand::Stats,
+            ClientMessage::Capabilities => AgentCommand::Capabilities,
+            ClientMessage::SetPreamble { text } => AgentCommand::SetPreamble { text },
+            ClientMessage::SetMood { name } => AgentCommand::SetMood { name },
+            ClientMessage::Danmaku { content, user_id } => AgentCommand::Command {
+                input: content,
+                origin: "danmaku",
+                user_id: user_id
+                    .map(|id| match id {
+                        Value::String(id) => id,
+                        other => other.to_string(),
+                    })
+                    .filter(|id| !id.is_empty() && id != "null"),
+                provider: None,
+                model: None,
+            },
         }
     }
 }
@@ -123,25 +418,40 @@ pub fn broadcast_json(sender: &BroadcastSender, event: &str, payload: Value) {
 struct AppState {
     auth: Arc<SignatureAuth>,
     broadcaster: BroadcastSender,
-    command_tx: mpsc::Sender<AgentCommand>,
+    command_tx: mpsc::Sender<QueuedCommand>,
+    keepalive_interval: Option<Duration>,
+    keepalive_text: Option<String>,
+    artifact_tokens: ArtifactTokens,
+    artifacts_dir: Arc<PathBuf>,
+    max_frame_bytes: usize,
 }
 
 pub async fn run_server(
-    addr: SocketAddr,
+    config: SseConfig,
     auth: Arc<SignatureAuth>,
     broadcaster: BroadcastSender,
-    command_tx: mpsc::Sender<AgentCommand>,
+    command_tx: mpsc::Sender<QueuedCommand>,
+    artifacts_dir: PathBuf,
 ) -> Result<()> {
+    let addr = config.bind_addr;
     let state = AppState {
         auth,
         broadcaster,
         command_tx,
+        keepalive_interval: config.keepalive_interval,
+        keepalive_text: config.keepalive_text.clone(),
+        artifact_tokens: ArtifactTokens::new(config.secret_key.clone(), config.artifact_token_ttl),
+        artifacts_dir: Arc::new(artifacts_dir),
+        max_frame_bytes: config.max_frame_bytes,
     };
 
     let app = Router::new()
         .route("/events", get(sse_handler))
         .route("/command", post(command_handler))
-        .layer(CorsLayer::permissive())
+        .route("/command/batch", post(command_batch_handler))
+        .route("/version", get(version_handler))
+        .route("/artifacts/{name}", get(artifact_handler))
+        .layer(build_cors(&config.allowed_origins))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -154,6 +464,26 @@ pub async fn run_server(
     Ok(())
 }
 
+fn build_cors(allowed_origins: &[String]) -> CorsLayer {
+    if allowed_origins.is_empty() {
+        tracing::warn!(
+            target: "sse",
+            "未配置 sse.allowed_origins，CORS 将允许任意来源访问；对外暴露服务时请务必配置来源白名单"
+        );
+        return CorsLayer::permissive();
+    }
+
+    let origins = allowed_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect::<Vec<_>>();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([header::CONTENT_TYPE, header::ACCEPT])
+}
+
 async fn sse_handler(
     Query(params): Query<AuthParams>,
     State(state): State<AppState>,
@@ -164,18 +494,279 @@ async fn sse_handler(
     }
 
     let rx = state.broadcaster.subscribe();
+    let max_frame_bytes = state.max_frame_bytes;
     let stream = BroadcastStream::new(rx)
-        .filter_map(|result| async move {
-            match result {
-                Ok(message) => {
-                    let event = Event::default().data(message);
-                    Some(Ok(event))
+        .filter_map(|result| async move { result.ok() })
+        .flat_map(move |message| {
+            let frames = fit_frame(message, max_frame_bytes)
+                .into_iter()
+                .map(|frame| Ok(Event::default().data(frame)));
+            futures_util::stream::iter(frames)
+        });
+
+    let mut keep_alive = KeepAlive::new();
+    if let Some(interval) = state.keepalive_interval {
+        keep_alive = keep_alive.interval(interval);
+    }
+    if let Some(text) = state.keepalive_text.as_deref() {
+        keep_alive = keep_alive.text(text);
+    }
+
+    Ok(Sse::new(stream).keep_alive(keep_alive))
+}
+
+/// 负载中超过该长度的字符串字段（如内联的 Base64 产物）会在超限时被移除
+const OVERSIZED_FIELD_BYTES: usize = 1024;
+
+/// 消息超过上限时移除负载中（含嵌套对象与数组）的大字段，依次推送精简后的消息、
+/// 一条 `message.truncated` 与承载被移除内容的 `message.chunk`
+///
+/// 内联 Base64 只在产物落盘失败时出现，没有下载链接可用，因此原值按 `chunk_id` 分片推送，
+/// 客户端按 `field` 与 `index` 拼接后还原
+fn fit_frame(message: String, max_frame_bytes: usize) -> Vec<String> {
+    if message.len() <= max_frame_bytes {
+        return vec![message];
+    }
+
+    let original_bytes = message.len();
+    let Ok(Value::Object(mut envelope)) = serde_json::from_str::<Value>(&message) else {
+        tracing::warn!(
+            target: "sse",
+            original_bytes,
+            max_frame_bytes,
+            "推送消息超过大小上限且无法解析，已丢弃"
+        );
+        return Vec::new();
+    };
+    let event = envelope
+        .get("event")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut dropped = Vec::new();
+    if let Some(payload) = envelope.get_mut("payload") {
+        take_oversized(payload, "", &mut dropped);
+        if let Value::Object(payload) = payload {
+            payload.insert("truncated".to_string(), json!(true));
+        }
+    }
+
+    let url = envelope
+        .get("payload")
+        .and_then(|payload| payload.get("url"))
+        .cloned();
+    let mut reduced = Value::Object(envelope).to_string();
+    if reduced.len() > max_frame_bytes {
+        reduced = encode_message(&event, json!({ "truncated": true, "url": url }));
+    }
+
+    let chunk_id = uuid::Uuid::new_v4().simple().to_string();
+    let mut chunks = Vec::new();
+    let mut fields = Vec::new();
+    for (field, value) in &dropped {
+        let frames = chunk_field(&event, &chunk_id, field, value, max_frame_bytes);
+        fields.push(json!({
+            "field": field,
+            "bytes": value.len(),
+            "chunks": frames.len(),
+        }));
+        chunks.extend(frames);
+    }
+
+    tracing::warn!(
+        target: "sse",
+        %event,
+        original_bytes,
+        max_frame_bytes,
+        dropped_fields = ?fields,
+        chunk_frames = chunks.len(),
+        "推送消息超过大小上限，已移除大字段并分片推送"
+    );
+    let notice = encode_message(
+        "message.truncated",
+        json!({
+            "event": event,
+            "original_bytes": original_bytes,
+            "max_bytes": max_frame_bytes,
+            "dropped_fields": dropped.iter().map(|(field, _)| field).collect::<Vec<_>>(),
+            "chunk_id": chunk_id,
+            "chunked_fields": fields,
+            "url": url,
+        }),
+    );
+
+    let mut frames = vec![reduced, notice];
+    frames.extend(chunks);
+    frames
+}
+
+/// 递归移除超过 `OVERSIZED_FIELD_BYTES` 的字符串，记录以 `.` 连接的字段路径与原值；
+/// 数组中的字符串替换为 `null` 以保留其余元素的下标
+fn take_oversized(value: &mut Value, path: &str, dropped: &mut Vec<(String, String)>) {
+    let child_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        Value::Object(map) => map.retain(|key, child| {
+            let path = child_path(key);
+            match child {
+                Value::String(text) if text.len() > OVERSIZED_FIELD_BYTES => {
+                    dropped.push((path, std::mem::take(text)));
+                    false
+                }
+                _ => {
+                    take_oversized(child, &path, dropped);
+                    true
                 }
-                Err(_) => None,
             }
+        }),
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                let path = child_path(&index.to_string());
+                match item {
+                    Value::String(text) if text.len() > OVERSIZED_FIELD_BYTES => {
+                        dropped.push((path, std::mem::take(text)));
+                        *item = Value::Null;
+                    }
+                    _ => take_oversized(item, &path, dropped),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 把被移除的字段拆成若干条不超过上限的 `message.chunk`；上限连分片的固定部分都放不下时返回空
+fn chunk_field(
+    event: &str,
+    chunk_id: &str,
+    field: &str,
+    value: &str,
+    max_frame_bytes: usize,
+) -> Vec<String> {
+    let frame = |index: usize, count: usize, data: &str| {
+        encode_message(
+            "message.chunk",
+            json!({
+                "event": event,
+                "chunk_id": chunk_id,
+                "field": field,
+                "index": index,
+                "count": count,
+                "data": data,
+            }),
+        )
+    };
+    // 下标与总数按最大可能的位数估算
+    let overhead = frame(value.len(), value.len(), "").len();
+    let Some(budget) = max_frame_bytes
+        .checked_sub(overhead)
+        .filter(|budget| *budget > 0)
+    else {
+        tracing::warn!(target: "sse", field, max_frame_bytes, "推送上限过小，无法分片，字段已丢弃");
+        return Vec::new();
+    };
+
+    let mut parts = Vec::new();
+    let (mut start, mut size) = (0, 0);
+    for (offset, c) in value.char_indices() {
+        // JSON 转义后的长度上限
+        let escaped = match c {
+            '"' | '\\' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        };
+        if size + escaped > budget && offset > start {
+            parts.push(&value[start..offset]);
+            (start, size) = (offset, 0);
+        }
+        size += escaped;
+    }
+    parts.push(&value[start..]);
+
+    let count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| frame(index, count, data))
+        .collect()
+}
+
+/// 构建信息不含敏感数据，无需签名即可访问，便于部署后核对版本
+async fn version_handler() -> Json<Value> {
+    Json(crate::util::build_info())
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtifactQuery {
+    token: String,
+}
+
+/// 只提供产物目录下的文件，文件名不能包含路径分隔符或以 `.` 开头；
+/// 以内容的 SHA-256 作为 `ETag`，`If-None-Match` 命中时返回 304
+async fn artifact_handler(
+    Path(name): Path<String>,
+    Query(query): Query<ArtifactQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> std::result::Result<impl IntoResponse, StatusCode> {
+    let plain_name = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.contains("..");
+    if !plain_name {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !state.artifact_tokens.verify(&name, &query.token) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let data = tokio::fs::read(state.artifacts_dir.join(&name))
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let etag = format!("\"{}\"", hex::encode(Sha256::digest(&data)));
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
         });
+    let headers = [
+        (header::CONTENT_TYPE, content_type_for(&name).to_string()),
+        (header::CACHE_CONTROL, "private, max-age=300".to_string()),
+        (header::ETAG, etag),
+    ];
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers, Vec::new()));
+    }
 
-    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    Ok((StatusCode::OK, headers, data))
+}
+
+fn content_type_for(name: &str) -> &'static str {
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("mp3") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("json") => "application/json",
+        Some("txt" | "md" | "srt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
 }
 
 async fn command_handler(
@@ -187,9 +778,180 @@ async fn command_handler(
         return Err(axum::http::StatusCode::UNAUTHORIZED);
     }
 
-    if state.command_tx.send(message.into()).await.is_err() {
+    let queued = QueuedCommand::new(message);
+    let request_id = queued.request_id.clone();
+    if state.command_tx.send(queued).await.is_err() {
         return Err(axum::http::StatusCode::SERVICE_UNAVAILABLE);
     }
 
-    Ok(Json(json!({ "status": "accepted" })))
+    Ok(Json(
+        json!({ "status": "accepted", "request_id": request_id }),
+    ))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BatchOptions {
+    /// 为 true 时任一条目无法解析则整批拒绝；默认跳过无效条目，其余照常入队
+    #[serde(default)]
+    stop_on_error: bool,
+}
+
+/// 一次提交多条命令：签名只校验一次，有效条目按顺序连续写入命令通道，不会与其它请求的命令交错
+async fn command_batch_handler(
+    Query(params): Query<AuthParams>,
+    Query(options): Query<BatchOptions>,
+    State(state): State<AppState>,
+    Json(messages): Json<Vec<Value>>,
+) -> std::result::Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !state.auth.verify_params(&params) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "status": "unauthorized" })),
+        ));
+    }
+
+    let mut queued = Vec::with_capacity(messages.len());
+    let mut rejected = Vec::new();
+    for (index, message) in messages.into_iter().enumerate() {
+        match serde_json::from_value::<ClientMessage>(message) {
+            Ok(message) => queued.push(QueuedCommand::new(message)),
+            Err(err) if options.stop_on_error => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "status": "rejected",
+                        "rejected": [{ "index": index, "error": err.to_string() }],
+                    })),
+                ));
+            }
+            Err(err) => rejected.push(json!({ "index": index, "error": err.to_string() })),
+        }
+    }
+
+    if queued.len() > state.command_tx.max_capacity() {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({
+                "status": "rejected",
+                "error": format!(
+                    "批量提交 {} 条命令，超过命令队列容量 {}",
+                    queued.len(),
+                    state.command_tx.max_capacity()
+                ),
+            })),
+        ));
+    }
+
+    let request_ids: Vec<String> = queued
+        .iter()
+        .map(|queued| queued.request_id.clone())
+        .collect();
+    if !queued.is_empty() {
+        let Ok(permits) = state.command_tx.reserve_many(queued.len()).await else {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "unavailable" })),
+            ));
+        };
+        for (permit, command) in permits.zip(queued) {
+            permit.send(command);
+        }
+    }
+    tracing::debug!(target: "sse", accepted = request_ids.len(), rejected = rejected.len(), "批量命令已入队");
+
+    Ok(Json(json!({
+        "status": "accepted",
+        "request_ids": request_ids,
+        "rejected": rejected,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 4096;
+
+    /// 按 `message.chunk` 还原被移除的字段
+    fn reassemble(frames: &[Value], field: &str) -> String {
+        let mut chunks: Vec<_> = frames
+            .iter()
+            .filter(|frame| frame["event"] == "message.chunk")
+            .map(|frame| &frame["payload"])
+            .filter(|chunk| chunk["field"] == field)
+            .collect();
+        chunks.sort_by_key(|chunk| chunk["index"].as_u64());
+        assert!(chunks.iter().all(|chunk| chunk["count"] == chunks.len()));
+        chunks
+            .iter()
+            .map(|chunk| chunk["data"].as_str().unwrap())
+            .collect()
+    }
+
+    fn fit(event: &str, payload: Value) -> Vec<Value> {
+        let frames = fit_frame(encode_message(event, payload), MAX);
+        assert!(frames.iter().all(|frame| frame.len() <= MAX));
+        frames
+            .iter()
+            .map(|frame| serde_json::from_str(frame).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn small_messages_pass_through() {
+        let message = encode_message("agent.help", json!({ "message": "hi" }));
+        assert_eq!(fit_frame(message.clone(), MAX), [message]);
+    }
+
+    #[test]
+    fn inline_artifact_is_chunked() {
+        let data = "QUJD".repeat(5000);
+        let frames = fit(
+            "agent.artifact",
+            json!({ "intent": "image_generation", "path": null, "data_base64": data }),
+        );
+        assert_eq!(frames[0]["event"], "agent.artifact");
+        assert_eq!(frames[0]["payload"]["intent"], "image_generation");
+        assert_eq!(frames[0]["payload"]["truncated"], true);
+        assert!(frames[0]["payload"].get("data_base64").is_none());
+
+        let notice = &frames[1]["payload"];
+        assert_eq!(frames[1]["event"], "message.truncated");
+        assert_eq!(notice["dropped_fields"], json!(["data_base64"]));
+        assert_eq!(notice["chunked_fields"][0]["bytes"], data.len());
+        assert_eq!(notice["chunked_fields"][0]["chunks"], frames.len() - 2);
+        assert!(
+            frames[2..]
+                .iter()
+                .all(|frame| frame["payload"]["chunk_id"] == notice["chunk_id"])
+        );
+        assert_eq!(reassemble(&frames, "data_base64"), data);
+    }
+
+    #[test]
+    fn nested_composite_results_are_stripped() {
+        let data = "QUJD".repeat(2000);
+        let text = "“引号\"与\\反斜杠\n".repeat(300);
+        let frames = fit(
+            "agent.composite",
+            json!({
+                "results": [
+                    { "intent": "image_generation", "ok": true, "data_base64": data },
+                    { "intent": "music_generation", "ok": false, "error": "超时" },
+                    text,
+                ],
+            }),
+        );
+        let results = &frames[0]["payload"]["results"];
+        assert_eq!(results[0]["intent"], "image_generation");
+        assert!(results[0].get("data_base64").is_none());
+        assert_eq!(results[1]["error"], "超时");
+        assert_eq!(results[2], Value::Null);
+        assert_eq!(
+            frames[1]["payload"]["dropped_fields"],
+            json!(["results.0.data_base64", "results.2"])
+        );
+        assert_eq!(reassemble(&frames, "results.0.data_base64"), data);
+        assert_eq!(reassemble(&frames, "results.2"), text);
+    }
 }
@@ -0,0 +1,210 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Add, AddAssign},
+    path::{Path, PathBuf},
+};
+
+use rig::{
+    completion::{CompletionModel, CompletionResponse, request::Usage},
+    providers::openai::responses_api::ResponsesCompletionModel,
+};
+use serde_json::{Value, json};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::warn;
+
+use crate::util::{beijing_rfc3339, format_beijing, now_in_beijing};
+
+/// 一次或累计的模型 token 用量
+///
+/// 在 rig 的 [`Usage`] 之外记录 DeepSeek 上下文缓存命中与未命中的输入 token，
+/// 命中部分按更低的单价计费；不返回缓存分项的提供方两者都为 0。
+///
+/// ```
+/// use rig::completion::request::Usage;
+/// use vutber_agent::usage::{TokenUsage, usage_value};
+///
+/// let mut usage = TokenUsage::from(Usage { input_tokens: 10, output_tokens: 2, total_tokens: 12 });
+/// assert_eq!(usage_value(&usage), serde_json::json!({ "input_tokens": 10, "output_tokens": 2, "total_tokens": 12 }));
+///
+/// usage += TokenUsage { prompt_cache_hit_tokens: 64, prompt_cache_miss_tokens: 16, ..TokenUsage::new() };
+/// assert_eq!(usage_value(&usage)["prompt_cache_hit_tokens"], 64);
+/// assert_eq!(usage_value(&usage)["prompt_cache_miss_tokens"], 16);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    pub prompt_cache_hit_tokens: u64,
+    pub prompt_cache_miss_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取补全响应的用量，缓存分项由提供方的 [`PromptCacheUsage`] 给出
+    pub fn of<M: PromptCacheUsage>(response: &CompletionResponse<M::Response>) -> Self {
+        let mut usage = Self::from(response.usage);
+        if let Some((hit, miss)) = M::prompt_cache_tokens(&response.raw_response) {
+            usage.prompt_cache_hit_tokens = hit;
+            usage.prompt_cache_miss_tokens = miss;
+        }
+        usage
+    }
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+            ..Self::default()
+        }
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        self += other;
+        self
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+        self.prompt_cache_hit_tokens += other.prompt_cache_hit_tokens;
+        self.prompt_cache_miss_tokens += other.prompt_cache_miss_tokens;
+    }
+}
+
+/// 从提供方的原始响应中读取上下文缓存用量，默认不返回
+pub trait PromptCacheUsage: CompletionModel {
+    /// 命中与未命中缓存的输入 token
+    fn prompt_cache_tokens(_response: &Self::Response) -> Option<(u64, u64)> {
+        None
+    }
+}
+
+impl PromptCacheUsage for ResponsesCompletionModel {}
+
+/// 按提供方与来源累计 LLM token 用量，用于估算每场直播的成本
+#[derive(Default)]
+pub struct UsageStats {
+    total: TokenUsage,
+    requests: u64,
+    by_provider: BTreeMap<&'static str, TokenUsage>,
+    by_origin: BTreeMap<&'static str, TokenUsage>,
+    /// 直播进行中累计的用量，停止时随 `live.stopped` 汇总
+    live_session: Option<TokenUsage>,
+    /// 按天写入 `usage-YYYYMMDD.jsonl` 的目录，未启用时为 `None`
+    log_dir: Option<PathBuf>,
+}
+
+impl UsageStats {
+    pub fn new(log_dir: Option<PathBuf>) -> Self {
+        Self {
+            log_dir,
+            ..Self::default()
+        }
+    }
+
+    /// 记录一次模型调用；`kind` 为 intent / conversation
+    pub async fn record(
+        &mut self,
+        provider: &'static str,
+        origin: &'static str,
+        kind: &'static str,
+        usage: TokenUsage,
+    ) {
+        self.total += usage;
+        self.requests += 1;
+        *self.by_provider.entry(provider).or_default() += usage;
+        *self.by_origin.entry(origin).or_default() += usage;
+        if let Some(session) = self.live_session.as_mut() {
+            *session += usage;
+        }
+
+        if let Some(dir) = &self.log_dir
+            && let Err(err) = Self::append_log(dir, provider, origin, kind, usage).await
+        {
+            warn!(target: "usage", error = ?err, "写入用量日志失败");
+        }
+    }
+
+    pub fn begin_live_session(&mut self) {
+        self.live_session = Some(TokenUsage::new());
+    }
+
+    pub fn end_live_session(&mut self) -> Option<TokenUsage> {
+        self.live_session.take()
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "requests": self.requests,
+            "total": usage_value(&self.total),
+            "by_provider": self
+                .by_provider
+                .iter()
+                .map(|(provider, usage)| (provider.to_string(), usage_value(usage)))
+                .collect::<serde_json::Map<_, _>>(),
+            "by_origin": self
+                .by_origin
+                .iter()
+                .map(|(origin, usage)| (origin.to_string(), usage_value(usage)))
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    }
+
+    async fn append_log(
+        dir: &Path,
+        provider: &str,
+        origin: &str,
+        kind: &str,
+        usage: TokenUsage,
+    ) -> std::io::Result<()> {
+        fs::create_dir_all(dir).await?;
+        let now = now_in_beijing();
+        let path = dir.join(format!("usage-{}.jsonl", format_beijing(&now, "%Y%m%d")));
+        let mut line = json!({
+            "timestamp": beijing_rfc3339(&now),
+            "provider": provider,
+            "origin": origin,
+            "kind": kind,
+        });
+        if let (Value::Object(line), Value::Object(tokens)) = (&mut line, usage_value(&usage)) {
+            line.extend(tokens);
+        }
+        let mut line = line.to_string();
+        line.push('\n');
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await
+    }
+}
+
+/// 用量的 JSON 表示，没有缓存分项时省略 `prompt_cache_*` 字段
+pub fn usage_value(usage: &TokenUsage) -> Value {
+    let mut value = json!({
+        "input_tokens": usage.input_tokens,
+        "output_tokens": usage.output_tokens,
+        "total_tokens": usage.total_tokens,
+    });
+    if usage.prompt_cache_hit_tokens + usage.prompt_cache_miss_tokens > 0 {
+        value["prompt_cache_hit_tokens"] = json!(usage.prompt_cache_hit_tokens);
+        value["prompt_cache_miss_tokens"] = json!(usage.prompt_cache_miss_tokens);
+    }
+    value
+}
@@ -0,0 +1,20 @@
+use chrono::DateTime;
+use serde_json::{Value, json};
+
+use crate::util::{beijing_rfc3339, now_in_beijing};
+
+/// 当前运行版本的构建信息，提交号与 rustc 版本由 build.rs 在编译期注入
+pub fn build_info() -> Value {
+    let built_at = env!("VUTBER_BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|time| beijing_rfc3339(&time.with_timezone(now_in_beijing().offset())));
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": option_env!("VUTBER_GIT_SHA"),
+        "rustc": option_env!("VUTBER_RUSTC_VERSION"),
+        "built_at": built_at,
+    })
+}
@@ -0,0 +1,284 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
+
+use reqwest::{Url, redirect::Policy};
+use tokio::{fs, net::lookup_host};
+use tracing::{debug, warn};
+
+use crate::{
+    errors::{AgentError, Result},
+    util::send_with_retry,
+};
+
+/// 下载生成结果或参考图的内容，HTTP 请求遇到限流时按 `send_with_retry` 重试
+///
+/// `allow_local` 开启时（`processing.allow_local_files`，仅用于测试与离线演示）`file://` 与本地路径直接读取磁盘；
+/// 未开启时拒绝读取，避免上游返回的地址被用来读取本机文件
+pub async fn fetch_bytes(
+    provider: &'static str,
+    client: &reqwest::Client,
+    url: &str,
+    allow_local: bool,
+) -> Result<Vec<u8>> {
+    let Some(path) = local_path(url) else {
+        let response = send_with_retry(provider, client.get(url)).await?;
+        let status = response.status();
+        if !status.is_success() {
+            // 响应内容可能来自任意站点，只写日志，不放进会广播给客户端的错误
+            let body = response.text().await.unwrap_or_default();
+            warn!(target: "fetch", %url, %status, %body, "下载失败");
+            return Err(AgentError::other(format!("下载 {url} 失败 ({status})")));
+        }
+        return Ok(response.bytes().await?.to_vec());
+    };
+
+    if !allow_local {
+        return Err(AgentError::rejected(
+            "local_file_disabled",
+            format!("未开启 processing.allow_local_files，拒绝读取本地文件 {url}"),
+        ));
+    }
+    debug!(target: "fetch", path = %path.display(), "读取本地文件代替下载");
+    Ok(fs::read(path).await?)
+}
+
+/// `file://` 地址或不带协议的路径
+fn local_path(url: &str) -> Option<&Path> {
+    match url.strip_prefix("file://") {
+        Some(path) => Some(Path::new(path)),
+        None if !url.contains("://") => Some(Path::new(url)),
+        None => None,
+    }
+}
+
+/// 请求方给出的参考图最多下载这么多字节
+pub const MAX_SOURCE_BYTES: usize = 20 * 1024 * 1024;
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 下载请求方给出的地址（如参考图 URL）：只接受 http(s)，主机解析到内网、回环、链路本地等非公网地址时拒绝，
+/// 连接固定到检查过的地址且不跟随重定向，内容超过 `max_bytes` 时放弃
+///
+/// `allow_local` 开启时（`processing.allow_local_files`，仅用于测试与离线演示）与 `fetch_bytes` 一样读取本地文件，
+/// 也不检查内网地址
+pub async fn fetch_source_bytes(url: &str, allow_local: bool, max_bytes: usize) -> Result<Vec<u8>> {
+    if let Some(path) = local_path(url) {
+        if !allow_local {
+            return Err(AgentError::rejected(
+                "local_file_disabled",
+                format!("未开启 processing.allow_local_files，拒绝读取本地文件 {url}"),
+            ));
+        }
+        if fs::metadata(path).await?.len() > max_bytes as u64 {
+            return Err(too_large(url, max_bytes));
+        }
+        debug!(target: "fetch", path = %path.display(), "读取本地文件代替下载");
+        return Ok(fs::read(path).await?);
+    }
+
+    let parsed = Url::parse(url).map_err(|err| {
+        AgentError::rejected("source_url_invalid", format!("无法解析地址 {url}: {err}"))
+    })?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(AgentError::rejected(
+            "source_url_invalid",
+            format!("只支持 http/https 地址: {url}"),
+        ));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| AgentError::rejected("source_url_invalid", format!("地址缺少主机: {url}")))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = parsed.port_or_known_default().unwrap_or(443);
+
+    let mut builder = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .timeout(SOURCE_TIMEOUT);
+    if !allow_local {
+        let addr = public_address(&host, port).await?;
+        builder = builder.resolve(&host, addr);
+    }
+    let client = builder.build()?;
+
+    let mut response = client.get(parsed).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        warn!(target: "fetch", %url, %status, "下载参考来源失败");
+        return Err(AgentError::other(format!("下载 {url} 失败 ({status})")));
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes as u64)
+    {
+        return Err(too_large(url, max_bytes));
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(too_large(url, max_bytes));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// 解析主机并确认所有地址都在公网，返回用于连接的地址
+async fn public_address(host: &str, port: u16) -> Result<SocketAddr> {
+    let addrs = lookup_host((host, port))
+        .await
+        .map_err(|err| {
+            AgentError::rejected("source_url_invalid", format!("无法解析主机 {host}: {err}"))
+        })?
+        .collect::<Vec<_>>();
+    if let Some(blocked) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        warn!(target: "fetch", host, address = %blocked.ip(), "拒绝访问非公网地址");
+        return Err(AgentError::rejected(
+            "source_url_forbidden",
+            format!("{host} 指向非公网地址，拒绝下载"),
+        ));
+    }
+    addrs
+        .into_iter()
+        .next()
+        .ok_or_else(|| AgentError::rejected("source_url_invalid", format!("无法解析主机 {host}")))
+}
+
+fn too_large(url: &str, max_bytes: usize) -> AgentError {
+    AgentError::rejected(
+        "source_too_large",
+        format!("{url} 超过 {} MB，拒绝下载", max_bytes / 1024 / 1024),
+    )
+}
+
+/// 是否为可以从服务端访问的公网地址：回环、私有、链路本地、运营商 NAT、文档、组播与保留地址都不算
+///
+/// ```
+/// use vutber_agent::util::is_public_ip;
+///
+/// for blocked in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+///     assert!(!is_public_ip(blocked.parse().unwrap()), "{blocked}");
+/// }
+/// assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+/// assert!(is_public_ip("2606:2800:220:1::".parse().unwrap()));
+/// ```
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // 运营商级 NAT 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF 协议分配 192.0.0.0/24
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // 基准测试 198.18.0.0/15
+        || (a == 198 && (18..20).contains(&b))
+        // 保留 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        // 文档 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // NAT64 64:ff9b::/96 内嵌的 IPv4 按 IPv4 判断
+        || (segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+            && !is_public_ipv4(Ipv4Addr::new(
+                (segments[6] >> 8) as u8,
+                segments[6] as u8,
+                (segments[7] >> 8) as u8,
+                segments[7] as u8,
+            ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::get};
+
+    use super::*;
+    use crate::util::test_support::spawn_stub;
+
+    async fn serve() -> String {
+        let app = Router::new()
+            .route("/image.png", get(|| async { vec![0u8; 4096] }))
+            .route(
+                "/missing.png",
+                get(|| async { (StatusCode::NOT_FOUND, "secret internal page") }),
+            );
+        format!("http://{}", spawn_stub(app).await)
+    }
+
+    #[tokio::test]
+    async fn rejects_private_hosts() {
+        let base = serve().await;
+        for url in [
+            format!("{base}/image.png"),
+            "http://localhost/image.png".to_string(),
+            "http://[::1]/image.png".to_string(),
+        ] {
+            let err = fetch_source_bytes(&url, false, MAX_SOURCE_BYTES)
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), Some("source_url_forbidden"), "{url}: {err}");
+        }
+        let err = fetch_source_bytes("ftp://example.com/a.png", false, MAX_SOURCE_BYTES)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some("source_url_invalid"));
+        let err = fetch_source_bytes("/etc/passwd", false, MAX_SOURCE_BYTES)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some("local_file_disabled"));
+    }
+
+    #[tokio::test]
+    async fn caps_size_and_hides_error_bodies() {
+        let base = serve().await;
+        let data = fetch_source_bytes(&format!("{base}/image.png"), true, 4096)
+            .await
+            .unwrap();
+        assert_eq!(data.len(), 4096);
+        let err = fetch_source_bytes(&format!("{base}/image.png"), true, 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some("source_too_large"));
+
+        let err = fetch_source_bytes(&format!("{base}/missing.png"), true, 1024)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("404"), "{err}");
+        assert!(!err.to_string().contains("secret"), "{err}");
+        let err = fetch_bytes(
+            "test",
+            &reqwest::Client::new(),
+            &format!("{base}/missing.png"),
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(!err.to_string().contains("secret"), "{err}");
+    }
+}
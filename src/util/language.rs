@@ -0,0 +1,128 @@
+/// 对话输入可识别的语言，用于让回复语言与观众一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Language {
+    Zh,
+    Ja,
+    Ko,
+    En,
+}
+
+impl Language {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::Zh => "zh",
+            Language::Ja => "ja",
+            Language::Ko => "ko",
+            Language::En => "en",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim().to_lowercase().as_str() {
+            "zh" => Some(Language::Zh),
+            "ja" => Some(Language::Ja),
+            "ko" => Some(Language::Ko),
+            "en" => Some(Language::En),
+            _ => None,
+        }
+    }
+
+    /// 追加到人设提示词之后的回复语言要求
+    pub fn reply_instruction(&self) -> &'static str {
+        match self {
+            Language::Zh => "请使用简体中文回复。",
+            Language::Ja => "请使用日语（日本語）回复。",
+            Language::Ko => "请使用韩语（한국어）回复。",
+            Language::En => "请使用英语（English）回复。",
+        }
+    }
+}
+
+/// 回复使用的语言：配置了固定语言时总是使用固定语言，否则跟随输入语言
+pub fn reply_language(forced: Option<Language>, input: Option<Language>) -> Option<Language> {
+    forced.or(input)
+}
+
+/// 按文字区块粗略判断输入语言，没有任何文字（纯表情、数字等）时返回 `None`
+///
+/// 含假名即视为日语，含谚文且不少于汉字时视为韩语；中英混写时按词数比较，
+/// 两个汉字约折合一个英文单词，持平时按中文处理。
+pub fn detect_language(text: &str) -> Option<Language> {
+    let (mut han, mut kana, mut hangul, mut latin_words) = (0usize, 0usize, 0usize, 0usize);
+    let mut in_word = false;
+    for c in text.chars() {
+        let is_latin = c.is_ascii_alphabetic();
+        if is_latin && !in_word {
+            latin_words += 1;
+        }
+        in_word = is_latin;
+
+        match c {
+            '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                kana += 1
+            }
+            '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' => {
+                hangul += 1
+            }
+            '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}' => han += 1,
+            _ => {}
+        }
+    }
+
+    if kana > 0 {
+        return Some(Language::Ja);
+    }
+    if hangul > 0 && hangul >= han {
+        return Some(Language::Ko);
+    }
+    if han == 0 && latin_words == 0 {
+        return None;
+    }
+    if latin_words > han.div_ceil(2) {
+        Some(Language::En)
+    } else {
+        Some(Language::Zh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_script_inputs() {
+        let cases = [
+            ("今天直播几点开始？", Some(Language::Zh)),
+            ("What time does the stream start?", Some(Language::En)),
+            // 含假名即为日语，即使汉字更多
+            ("今日の配信は何時から", Some(Language::Ja)),
+            ("오늘 방송 몇 시에 시작해요?", Some(Language::Ko)),
+            // 谚文不少于汉字时为韩语
+            ("韩国 안녕하세요", Some(Language::Ko)),
+            ("我在学习韩语 한국어", Some(Language::Zh)),
+            // 中英混写按词数比较：两个汉字约折合一个单词，持平时按中文处理
+            ("这首 song 好听", Some(Language::Zh)),
+            ("play 那首 song please", Some(Language::En)),
+            ("好 ok", Some(Language::Zh)),
+            ("233333 ？！😂", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(detect_language(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn forced_language_overrides_input() {
+        let japanese = detect_language("こんにちは");
+        assert_eq!(reply_language(None, japanese), Some(Language::Ja));
+        assert_eq!(
+            reply_language(Some(Language::Zh), japanese),
+            Some(Language::Zh)
+        );
+        assert_eq!(reply_language(Some(Language::En), None), Some(Language::En));
+        assert_eq!(reply_language(None, detect_language("👍")), None);
+        assert_eq!(Language::from_code(" JA "), Some(Language::Ja));
+        assert_eq!(Language::from_code("fr"), None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// 带容量上限与过期时间的 LRU 映射，用于按观众记录的会话状态，避免长时间直播中无限增长
+///
+/// ```
+/// use std::time::Duration;
+/// use vutber_agent::util::TtlLruMap;
+///
+/// let mut map = TtlLruMap::new(2, Duration::from_secs(60));
+/// map.insert("a", 1);
+/// map.insert("b", 2);
+/// // 读取后 a 成为最近使用，再插入时淘汰 b
+/// assert_eq!(map.get(&"a"), Some(&1));
+/// map.insert("c", 3);
+/// assert_eq!(map.get(&"b"), None);
+/// assert_eq!(map.len(), 2);
+/// ```
+pub struct TtlLruMap<K, V> {
+    max_entries: usize,
+    ttl: Duration,
+    entries: HashMap<K, Slot<V>>,
+    /// 访问序号 → 键，序号越小越久未访问
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+struct Slot<V> {
+    value: V,
+    expires_at: Instant,
+    tick: u64,
+}
+
+impl<K, V> TtlLruMap<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// 当前条目数（可能包含尚未清理的过期条目）
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 读取未过期的条目并标记为最近使用；已过期的条目会被移除
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let now = Instant::now();
+        let expired = self.entries.get(key)?.expires_at <= now;
+        if expired {
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+        let slot = self.entries.get_mut(key)?;
+        self.order.remove(&slot.tick);
+        self.order.insert(tick, key.clone());
+        slot.tick = tick;
+        Some(&slot.value)
+    }
+
+    /// 插入或替换条目，过期时间从插入时起算；超过容量时淘汰最久未访问的条目
+    pub fn insert(&mut self, key: K, value: V) {
+        self.insert_with_ttl(key, value, self.ttl);
+    }
+
+    /// 与 `insert` 相同，但使用单独的过期时间
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) {
+        let now = Instant::now();
+        self.remove(&key);
+        self.evict_expired_front(now);
+
+        while self.entries.len() >= self.max_entries {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Slot {
+                value,
+                expires_at: now + ttl,
+                tick: self.tick,
+            },
+        );
+    }
+
+    /// 取出未过期的条目；已过期的条目同样会被移除，但返回 `None`
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.tick);
+        (slot.expires_at > Instant::now()).then_some(slot.value)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.tick);
+        Some(slot.value)
+    }
+
+    /// 从最久未访问的一端清理连续的过期条目，开销与清理数量成正比
+    fn evict_expired_front(&mut self, now: Instant) {
+        while let Some((&tick, key)) = self.order.first_key_value() {
+            let expired = self
+                .entries
+                .get(key)
+                .is_none_or(|slot| slot.expires_at <= now);
+            if !expired {
+                break;
+            }
+            let key = key.clone();
+            self.order.remove(&tick);
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserting_beyond_capacity_evicts_oldest() {
+        let mut map = TtlLruMap::new(3, Duration::from_secs(60));
+        for viewer in 0..10 {
+            map.insert(viewer, viewer * 10);
+        }
+        assert_eq!(map.len(), 3);
+        assert!((0..7).all(|viewer| map.get(&viewer).is_none()));
+        assert_eq!(map.get(&7), Some(&70));
+
+        // 替换已有的键不淘汰其他条目，并把它标记为最近使用
+        map.insert(8, 81);
+        map.insert(10, 100);
+        assert_eq!(map.get(&9), None);
+        assert_eq!(map.get(&8), Some(&81));
+        assert_eq!(map.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn entries_expire_after_ttl() {
+        let mut map = TtlLruMap::new(16, Duration::from_secs(60));
+        map.insert("old", 1);
+        map.insert_with_ttl("short", 2, Duration::from_secs(5));
+        tokio::time::advance(Duration::from_secs(30)).await;
+        map.insert("new", 3);
+        assert_eq!(map.get(&"short"), None);
+        assert_eq!(map.get(&"old"), Some(&1));
+
+        // 读取不会延长过期时间
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(map.get(&"old"), None);
+        assert_eq!(map.take(&"new"), Some(3));
+
+        // 插入时顺带清理最久未访问一端的过期条目
+        map.insert("stale", 4);
+        tokio::time::advance(Duration::from_secs(60)).await;
+        map.insert("fresh", 5);
+        assert_eq!(map.len(), 1);
+    }
+}
@@ -1,5 +1,21 @@
+mod build_info;
+mod fetch;
+mod language;
+mod lru;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod text;
+mod throttle;
 mod time;
 mod writer;
 
+pub use build_info::build_info;
+pub use fetch::{MAX_SOURCE_BYTES, fetch_bytes, fetch_source_bytes, is_public_ip};
+pub use language::{Language, detect_language, reply_language};
+pub use lru::TtlLruMap;
+pub use text::{estimate_tokens, truncate_chars};
+pub use throttle::{
+    MAX_RETRY_AFTER, ThrottleEvent, retry_after, send_with_retry, subscribe_throttle_events,
+};
 pub use time::{beijing_rfc3339, format_beijing, now_in_beijing};
 pub use writer::ArtifactWriter;
@@ -0,0 +1,11 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+
+/// 在 `127.0.0.1` 的随机端口上启动测试用的桩服务，返回其地址
+pub async fn spawn_stub(app: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
@@ -0,0 +1,32 @@
+/// 按字符截断文本，超出部分以省略号代替，用于日志输出
+pub fn truncate_chars(text: &str, max_chars: usize) -> String {
+    let mut chars = text.chars();
+    let truncated: String = chars.by_ref().take(max_chars).collect();
+    if chars.next().is_some() {
+        format!("{truncated}…")
+    } else {
+        truncated
+    }
+}
+
+/// 粗略估算文本的 token 数：ASCII 字符按 4 个一个 token，其它字符（中日韩文字与标点等）按 1.7 个一个 token，结果向上取整
+///
+/// ```
+/// use vutber_agent::util::estimate_tokens;
+///
+/// assert_eq!(estimate_tokens(""), 0);
+/// assert_eq!(estimate_tokens("hello world!"), 3);
+/// assert_eq!(estimate_tokens("你好"), 2);
+/// // 12 个 ASCII 字符（3）+ 5 个汉字（约 2.94）
+/// assert_eq!(estimate_tokens("hello world!晚上好呀！"), 6);
+/// ```
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), ch| {
+        if ch.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    (ascii as f64 / 4.0 + other as f64 / 1.7).ceil() as usize
+}
@@ -0,0 +1,199 @@
+use std::{sync::OnceLock, time::Duration};
+
+use chrono::{DateTime, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode, header::HeaderMap};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// 单次等待的上限，避免服务端给出过长的 Retry-After 时请求长时间挂起
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// 429 后最多重试的次数
+const MAX_THROTTLE_RETRIES: u32 = 2;
+/// 服务端未给出等待时间时的基础退避
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(2);
+
+/// 上游提供方限流时的一次等待记录
+#[derive(Debug, Clone)]
+pub struct ThrottleEvent {
+    pub provider: &'static str,
+    pub wait: Duration,
+    pub attempt: u32,
+    /// 等待时间来自服务端响应头而不是默认退避
+    pub server_hint: bool,
+}
+
+fn throttle_sender() -> &'static broadcast::Sender<ThrottleEvent> {
+    static SENDER: OnceLock<broadcast::Sender<ThrottleEvent>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(32).0)
+}
+
+/// 订阅限流事件；各提供方的 HTTP 调用不持有广播器，通过该通道统一上报
+pub fn subscribe_throttle_events() -> broadcast::Receiver<ThrottleEvent> {
+    throttle_sender().subscribe()
+}
+
+/// 发送请求，遇到 429 时按服务端建议的等待时间重试；请求体无法克隆时不重试
+pub async fn send_with_retry(
+    provider: &'static str,
+    builder: RequestBuilder,
+) -> reqwest::Result<Response> {
+    let mut attempt = 0;
+    let mut builder = builder;
+
+    loop {
+        let retry = builder.try_clone();
+        let response = builder.send().await?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_THROTTLE_RETRIES {
+            return Ok(response);
+        }
+        let Some(next) = retry else {
+            return Ok(response);
+        };
+
+        attempt += 1;
+        let hint = retry_after(response.headers());
+        let wait = hint
+            .unwrap_or(DEFAULT_BACKOFF * 2u32.pow(attempt - 1))
+            .min(MAX_RETRY_AFTER);
+
+        warn!(
+            target: "throttle",
+            provider,
+            attempt,
+            wait_ms = wait.as_millis() as u64,
+            "上游提供方限流，等待后重试"
+        );
+        let _ = throttle_sender().send(ThrottleEvent {
+            provider,
+            wait,
+            attempt,
+            server_hint: hint.is_some(),
+        });
+
+        tokio::time::sleep(wait).await;
+        builder = next;
+    }
+}
+
+/// 解析 `Retry-After`（秒数或 HTTP 日期），其次是 OpenAI 兼容接口的 `x-ratelimit-reset-*`；
+/// 结果不超过 `MAX_RETRY_AFTER`，`inf`、`NaN` 等无法表示的值视为没有给出
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(value) = header_str(headers, "retry-after") {
+        if let Ok(seconds) = value.parse::<f64>() {
+            return clamped_secs(seconds);
+        }
+        if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+            let wait = date.with_timezone(&Utc) - Utc::now();
+            return Some(wait.to_std().unwrap_or_default().min(MAX_RETRY_AFTER));
+        }
+    }
+
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header_str(headers, name).and_then(parse_reset_duration))
+        .max()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+/// 解析形如 `1s`、`6m0s`、`250ms` 的重置时间
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let unit = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total = total
+            .checked_add(clamped_secs(amount * unit)?)?
+            .min(MAX_RETRY_AFTER);
+    }
+
+    number.is_empty().then_some(total)
+}
+
+/// 上游给出的秒数先截断到 `MAX_RETRY_AFTER` 再转换，非有限值返回 `None`
+fn clamped_secs(seconds: f64) -> Option<Duration> {
+    if !seconds.is_finite() {
+        return None;
+    }
+    Duration::try_from_secs_f64(seconds.clamp(0.0, MAX_RETRY_AFTER.as_secs_f64())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let wait = |value: &str| retry_after(&headers(&[("retry-after", value)]));
+        assert_eq!(wait("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(wait("-3"), Some(Duration::ZERO));
+        assert_eq!(wait("Thu, 01 Jan 1970 00:00:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(wait("Fri, 01 Jan 9999 00:00:00 GMT"), Some(MAX_RETRY_AFTER));
+        assert_eq!(wait("soon"), None);
+    }
+
+    #[test]
+    fn hostile_retry_after_values_do_not_panic() {
+        let wait = |value: &str| retry_after(&headers(&[("retry-after", value)]));
+        assert_eq!(wait("inf"), None);
+        assert_eq!(wait("-inf"), None);
+        assert_eq!(wait("NaN"), None);
+        assert_eq!(wait("1e20"), Some(MAX_RETRY_AFTER));
+        assert_eq!(wait(&"9".repeat(400)), None);
+    }
+
+    #[test]
+    fn ratelimit_reset_durations() {
+        let reset = |value: &str| {
+            retry_after(&headers(&[
+                ("x-ratelimit-reset-requests", value),
+                ("x-ratelimit-reset-tokens", "250ms"),
+            ]))
+        };
+        assert_eq!(reset("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(reset("10ms"), Some(Duration::from_millis(250)));
+        assert_eq!(reset("0m30s"), Some(Duration::from_secs(30)));
+        assert_eq!(reset("6m0s"), Some(MAX_RETRY_AFTER));
+        // 超长数字与累加溢出都截断为上限
+        assert_eq!(
+            reset(&format!("{}h", "9".repeat(400))),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            reset(&format!("{}s", "9".repeat(30))),
+            Some(MAX_RETRY_AFTER)
+        );
+        assert_eq!(reset(&"59s".repeat(1000)), Some(MAX_RETRY_AFTER));
+        assert_eq!(parse_reset_duration("5x"), None);
+        assert_eq!(parse_reset_duration("5"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::{
     capabilities::BinaryArtifact,
@@ -7,19 +7,36 @@ use crate::{
     util::{beijing_rfc3339, format_beijing, now_in_beijing},
 };
 use serde_json::{Map, Value, json};
-use tokio::fs;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::warn;
 use uuid::Uuid;
 
+/// 写入中的临时文件后缀，写完并 rename 到最终文件名之前读方看不到完整产物
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
 pub struct ArtifactWriter {
     root: PathBuf,
 }
 
 impl ArtifactWriter {
+    /// 创建产物目录，并清理上次崩溃或写入被取消时残留的 `.partial` 临时文件
     pub async fn new(root: PathBuf) -> Result<Self> {
         fs::create_dir_all(&root).await?;
+        remove_partial_files(&root).await?;
         Ok(Self { root })
     }
 
+    /// 产物目录下的文件，文件名不能包含路径分隔符或以 `.` 开头；文件不存在或仍在写入时返回 `None`
+    pub fn resolve(&self, name: &str) -> Option<PathBuf> {
+        let plain_name = !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\'])
+            && !name.contains("..")
+            && !name.ends_with(PARTIAL_SUFFIX);
+        let path = self.root.join(name);
+        (plain_name && path.is_file()).then_some(path)
+    }
+
     pub async fn persist(&self, intent: Intent, artifact: &BinaryArtifact) -> Result<PathBuf> {
         fs::create_dir_all(&self.root).await?;
         let now = now_in_beijing();
@@ -34,7 +51,7 @@ impl ArtifactWriter {
 
         let file_name = format!("{}.{}", base_name, artifact.file_extension);
         let file_path = self.root.join(&file_name);
-        fs::write(&file_path, &artifact.data).await?;
+        write_atomically(&file_path, &artifact.data).await?;
 
         let mut meta = Map::new();
         meta.insert("intent".to_string(), json!(intent.to_string()));
@@ -42,6 +59,17 @@ impl ArtifactWriter {
         meta.insert("description".to_string(), json!(artifact.summary));
         meta.insert("artifact".to_string(), json!(file_name));
         meta.insert("created_at".to_string(), json!(beijing_rfc3339(&now)));
+        meta.insert("sha256".to_string(), json!(artifact.sha256));
+        meta.insert("size_bytes".to_string(), json!(artifact.size_bytes));
+        // 服务商给出的时长优先，其次是从文件头读出的时长
+        if let Some(duration) = artifact
+            .metadata
+            .get("duration_seconds")
+            .cloned()
+            .or_else(|| artifact.duration_seconds.map(|duration| json!(duration)))
+        {
+            meta.insert("duration_seconds".to_string(), duration);
+        }
 
         if let Some(prompt) = artifact
             .metadata
@@ -51,6 +79,21 @@ impl ArtifactWriter {
             meta.insert("prompt".to_string(), Value::String(prompt.to_string()));
         }
 
+        if let Some(source) = artifact.metadata.get("source") {
+            meta.insert("source".to_string(), source.clone());
+        }
+
+        // 语音的字幕另存为同名 `.srt`，元信息中记录文件名
+        if let Some(srt) = artifact
+            .metadata
+            .get("srt")
+            .and_then(|value| value.as_str())
+        {
+            let srt_name = format!("{}.srt", base_name);
+            write_atomically(&self.root.join(&srt_name), srt.as_bytes()).await?;
+            meta.insert("subtitles".to_string(), json!(srt_name));
+        }
+
         if !artifact.metadata.is_empty() {
             meta.insert(
                 "metadata".to_string(),
@@ -61,8 +104,94 @@ impl ArtifactWriter {
         let meta_value = Value::Object(meta);
 
         let meta_path = self.root.join(format!("{}.meta.json", base_name));
-        fs::write(&meta_path, serde_json::to_vec_pretty(&meta_value)?).await?;
+        write_atomically(&meta_path, &serde_json::to_vec_pretty(&meta_value)?).await?;
+
+        // 两段式点歌的歌词另存一份纯文本，便于直接展示或下载
+        if let Some(lyrics) = artifact
+            .metadata
+            .get("lyrics")
+            .and_then(|value| value.as_str())
+        {
+            let lyrics_path = self.root.join(format!("{}.lyrics.txt", base_name));
+            write_atomically(&lyrics_path, lyrics.as_bytes()).await?;
+        }
 
         Ok(file_path)
     }
 }
+
+/// 先写 `<path>.partial` 并落盘，完整写完后再 rename 到目标路径；
+/// 失败时删除临时文件，任务被取消时残留的临时文件由下次启动时清理
+async fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+
+    let written = async {
+        let mut file = fs::File::create(&partial).await?;
+        file.write_all(data).await?;
+        file.sync_all().await?;
+        fs::rename(&partial, path).await
+    }
+    .await;
+
+    if let Err(err) = written {
+        let _ = fs::remove_file(&partial).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+async fn remove_partial_files(root: &Path) -> Result<()> {
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with(PARTIAL_SUFFIX)
+        {
+            warn!(
+                target: "writer",
+                file = %entry.path().display(),
+                "清理未写完的产物临时文件"
+            );
+            let _ = fs::remove_file(entry.path()).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn interrupted_writes_are_cleaned_up() {
+        let root = std::env::temp_dir().join(format!("artifact-writer-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        // 模拟写入中途被打断：只留下半截的临时文件
+        let partial = "image_20250101_000000_deadbeef.png.partial";
+        std::fs::write(root.join(partial), b"\x89PN").unwrap();
+
+        let writer = ArtifactWriter::new(root.clone()).await.unwrap();
+        assert!(writer.resolve(partial).is_none());
+        assert!(!root.join(partial).exists());
+
+        let artifact = BinaryArtifact::new(b"\x89PNG".to_vec(), "image/png", "png", "测试");
+        let path = writer
+            .persist(Intent::ImageGeneration, &artifact)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x89PNG");
+        assert!(path.with_extension("meta.json").exists());
+        let leftovers = std::fs::read_dir(&root)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(PARTIAL_SUFFIX)
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}